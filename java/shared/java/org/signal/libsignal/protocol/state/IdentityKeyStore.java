@@ -27,7 +27,8 @@ public interface IdentityKeyStore {
   // This must be kept in sync with the Rust enum of the same name.
   public enum IdentityChange {
     NEW_OR_UNCHANGED,
-    REPLACED_EXISTING
+    REPLACED_EXISTING,
+    ROTATED_VERIFIED
   }
 
   /**
@@ -428,6 +428,7 @@ impl IntoFfiError for SignalProtocolError {
             | Self::InvalidMacKeyLength(_)
             | Self::BadKEMKeyType(_)
             | Self::WrongKEMKeyType(_, _)
+            | Self::UnacceptableKEMKeyType(_)
             | Self::BadKEMKeyLength(_, _) => SignalErrorCode::InvalidKey,
             Self::SignatureValidationFailed => SignalErrorCode::InvalidSignature,
             Self::UntrustedIdentity(_) => SignalErrorCode::UntrustedIdentity,
@@ -371,6 +371,7 @@ impl JniError for SignalProtocolError {
             | SignalProtocolError::InvalidMacKeyLength(_)
             | SignalProtocolError::BadKEMKeyType(_)
             | SignalProtocolError::WrongKEMKeyType(_, _)
+            | SignalProtocolError::UnacceptableKEMKeyType(_)
             | SignalProtocolError::BadKEMKeyLength(_, _) => {
                 ClassName("org.signal.libsignal.protocol.InvalidKeyException")
             }
@@ -4,7 +4,7 @@
 //
 
 use criterion::{Criterion, criterion_group, criterion_main};
-use libsignal_core::curve::KeyPair;
+use libsignal_core::curve::{KeyPair, PublicKey};
 use rand::{Rng, rng};
 
 pub fn generation(c: &mut Criterion) {
@@ -39,6 +39,42 @@ pub fn signatures(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, generation, key_agreement, signatures);
+pub fn batch_signatures(c: &mut Criterion) {
+    const BATCH_SIZE: usize = 64;
+
+    let rng = &mut rng();
+    let mut some_data = [0; 1024];
+    rng.fill(&mut some_data);
+    let keys: Vec<_> = (0..BATCH_SIZE).map(|_| KeyPair::generate(rng)).collect();
+    let sigs: Vec<_> = keys
+        .iter()
+        .map(|key| key.calculate_signature(&some_data, rng).unwrap())
+        .collect();
+    let batch: Vec<(&PublicKey, &[u8], &[u8])> = keys
+        .iter()
+        .zip(&sigs)
+        .map(|(key, sig)| (&key.public_key, &some_data[..], &sig[..]))
+        .collect();
+
+    c.bench_function("verify signatures one by one", |b| {
+        b.iter(|| {
+            batch
+                .iter()
+                .all(|(key, message, sig)| key.verify_signature(message, sig))
+        })
+    });
+
+    c.bench_function("verify signatures in batch", |b| {
+        b.iter(|| PublicKey::verify_signature_batch(&batch, rng))
+    });
+}
+
+criterion_group!(
+    benches,
+    generation,
+    key_agreement,
+    signatures,
+    batch_signatures
+);
 
 criterion_main!(benches);
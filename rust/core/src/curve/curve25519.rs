@@ -3,11 +3,12 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
-use curve25519_dalek::constants::ED25519_BASEPOINT_TABLE;
-use curve25519_dalek::edwards::EdwardsPoint;
+use curve25519_dalek::constants::{ED25519_BASEPOINT_POINT, ED25519_BASEPOINT_TABLE};
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
 use curve25519_dalek::montgomery::MontgomeryPoint;
 use curve25519_dalek::scalar;
 use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::{IsIdentity, VartimeMultiscalarMul};
 use rand::{CryptoRng, Rng};
 use sha2::{Digest, Sha512};
 use subtle::ConstantTimeEq;
@@ -122,40 +123,78 @@ impl PrivateKey {
         signature: &[u8; SIGNATURE_LENGTH],
     ) -> bool {
         let _trace = libsignal_debug::trace_block!("PrivateKey::verify_signature");
-        let mont_point = MontgomeryPoint(*their_public_key);
-        let ed_pub_key_point =
-            match mont_point.to_edwards((signature[SIGNATURE_LENGTH - 1] & 0b1000_0000_u8) >> 7) {
-                Some(x) => x,
-                None => return false,
-            };
-        let cap_a = ed_pub_key_point.compress();
-        let mut cap_r = [0u8; 32];
-        cap_r.copy_from_slice(&signature[..32]);
-        let mut s = [0u8; 32];
-        s.copy_from_slice(&signature[32..]);
-        s[31] &= 0b0111_1111_u8;
-        if (s[31] & 0b1110_0000_u8) != 0 {
+        let Some(decoded) = DecodedSignature::new(their_public_key, message, signature) else {
             return false;
-        }
-        let minus_cap_a = -ed_pub_key_point;
-
-        let mut hash = Sha512::new();
-        // Explicitly pass a slice to avoid generating multiple versions of update().
-        hash.update(&cap_r[..]);
-        hash.update(cap_a.as_bytes());
-        for message_piece in message {
-            hash.update(message_piece);
-        }
-        let h = Scalar::from_hash(hash);
+        };
 
         let cap_r_check_point = EdwardsPoint::vartime_double_scalar_mul_basepoint(
-            &h,
-            &minus_cap_a,
-            &Scalar::from_bytes_mod_order(s),
+            &decoded.h,
+            &-decoded.cap_a,
+            &decoded.s,
         );
         let cap_r_check = cap_r_check_point.compress();
 
-        bool::from(cap_r_check.as_bytes().ct_eq(&cap_r))
+        bool::from(cap_r_check.as_bytes().ct_eq(&decoded.cap_r))
+    }
+
+    /// Checks all of `signatures` at once, returning `true` only if every one of them is valid.
+    ///
+    /// This checks a random linear combination of the cofactored verification equations with a
+    /// single multiscalar multiplication, which is considerably cheaper than checking each
+    /// signature separately. Apart from a negligible chance of error, the result is the same as
+    /// checking each signature with [`Self::verify_signature`], with one exception: a signature
+    /// whose R is deliberately built with a small-order component can pass the batch check while
+    /// failing the individual one. Building such a signature requires the signer's private key, so
+    /// this does not allow forgeries. Signatures where R or the public key is itself of small order
+    /// always fail the batch check.
+    pub fn verify_signature_batch<R>(
+        signatures: &[(&[u8; PUBLIC_KEY_LENGTH], &[&[u8]], &[u8; SIGNATURE_LENGTH])],
+        csprng: &mut R,
+    ) -> bool
+    where
+        R: CryptoRng + Rng + ?Sized,
+    {
+        let _trace = libsignal_debug::trace_block!("PrivateKey::verify_signature_batch");
+
+        let mut scalars = Vec::with_capacity(2 * signatures.len() + 1);
+        let mut points = Vec::with_capacity(2 * signatures.len() + 1);
+        let mut basepoint_scalar = Scalar::ZERO;
+
+        for (their_public_key, message, signature) in signatures {
+            let Some(decoded) = DecodedSignature::new(their_public_key, message, signature) else {
+                return false;
+            };
+            // verify_signature compares against the encoding of R, so a non-canonical encoding
+            // can never be accepted.
+            let compressed_cap_r = CompressedEdwardsY(decoded.cap_r);
+            let Some(cap_r) = compressed_cap_r.decompress() else {
+                return false;
+            };
+            if cap_r.compress() != compressed_cap_r {
+                return false;
+            }
+            // The cofactored equation below can't tell a small-order R or A apart from the
+            // identity, so such signatures are left for verify_signature to reject.
+            if cap_r.is_small_order() || decoded.cap_a.is_small_order() {
+                return false;
+            }
+
+            // 128 bits of randomness are enough to make a combination of invalid equations cancel
+            // out with only negligible probability.
+            let z = Scalar::from(csprng.random::<u128>());
+            basepoint_scalar -= z * decoded.s;
+            scalars.push(z);
+            points.push(cap_r);
+            scalars.push(z * decoded.h);
+            points.push(decoded.cap_a);
+        }
+
+        scalars.push(basepoint_scalar);
+        points.push(ED25519_BASEPOINT_POINT);
+
+        EdwardsPoint::vartime_multiscalar_mul(scalars, points)
+            .mul_by_cofactor()
+            .is_identity()
     }
 
     pub fn derive_public_key_bytes(&self) -> [u8; PUBLIC_KEY_LENGTH] {
@@ -174,6 +213,53 @@ impl From<[u8; PRIVATE_KEY_LENGTH]> for PrivateKey {
     }
 }
 
+/// The parts of an XEdDSA signature needed to check it, along with the signer's public key.
+struct DecodedSignature {
+    cap_a: EdwardsPoint,
+    cap_r: [u8; 32],
+    s: Scalar,
+    h: Scalar,
+}
+
+impl DecodedSignature {
+    /// Returns `None` if the public key or signature is malformed, in which case the signature is
+    /// invalid.
+    fn new(
+        their_public_key: &[u8; PUBLIC_KEY_LENGTH],
+        message: &[&[u8]],
+        signature: &[u8; SIGNATURE_LENGTH],
+    ) -> Option<Self> {
+        let mont_point = MontgomeryPoint(*their_public_key);
+        let ed_pub_key_point =
+            mont_point.to_edwards((signature[SIGNATURE_LENGTH - 1] & 0b1000_0000_u8) >> 7)?;
+        let cap_a = ed_pub_key_point.compress();
+        let mut cap_r = [0u8; 32];
+        cap_r.copy_from_slice(&signature[..32]);
+        let mut s = [0u8; 32];
+        s.copy_from_slice(&signature[32..]);
+        s[31] &= 0b0111_1111_u8;
+        if (s[31] & 0b1110_0000_u8) != 0 {
+            return None;
+        }
+
+        let mut hash = Sha512::new();
+        // Explicitly pass a slice to avoid generating multiple versions of update().
+        hash.update(&cap_r[..]);
+        hash.update(cap_a.as_bytes());
+        for message_piece in message {
+            hash.update(message_piece);
+        }
+        let h = Scalar::from_hash(hash);
+
+        Some(Self {
+            cap_a: ed_pub_key_point,
+            cap_r,
+            s: Scalar::from_bytes_mod_order(s),
+            h,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use rand::rngs::OsRng;
@@ -313,4 +399,93 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_signature_batch() {
+        let mut csprng = OsRng.unwrap_err();
+        let mut messages = [[0u8; 64]; 10];
+        let keys: Vec<_> = (0..messages.len())
+            .map(|_| PrivateKey::new(&mut csprng))
+            .collect();
+        let public_keys: Vec<_> = keys.iter().map(|k| k.derive_public_key_bytes()).collect();
+        let mut signatures = Vec::new();
+        for (key, message) in keys.iter().zip(&mut messages) {
+            csprng.fill_bytes(message);
+            signatures.push(key.calculate_signature(&mut csprng, &[&message[..]]));
+        }
+
+        let message_parts: Vec<[&[u8]; 1]> = messages.iter().map(|m| [&m[..]]).collect();
+        let batch: Vec<_> = public_keys
+            .iter()
+            .zip(&message_parts)
+            .zip(&signatures)
+            .map(|((key, message), signature)| (key, &message[..], signature))
+            .collect();
+        assert!(PrivateKey::verify_signature_batch(&batch, &mut csprng));
+        assert!(PrivateKey::verify_signature_batch(&[], &mut csprng));
+
+        for i in 0..batch.len() {
+            let mut bad_signature = *batch[i].2;
+            bad_signature[i] ^= 0x01;
+            let mut bad_batch = batch.clone();
+            bad_batch[i].2 = &bad_signature;
+            assert!(
+                !PrivateKey::verify_signature_batch(&bad_batch, &mut csprng),
+                "batch check passed with signature {i} modified"
+            );
+
+            let mut swapped_batch = batch.clone();
+            swapped_batch[i].0 = batch[(i + 1) % batch.len()].0;
+            assert!(
+                !PrivateKey::verify_signature_batch(&swapped_batch, &mut csprng),
+                "batch check passed with key {i} replaced"
+            );
+        }
+    }
+
+    #[test]
+    fn test_small_order_signature_rejected() {
+        let mut csprng = OsRng.unwrap_err();
+        let key = PrivateKey::new(&mut csprng);
+        let public_key = key.derive_public_key_bytes();
+        let message = b"small order";
+
+        // R is the point (0, -1), which has order 2. With s = h * a, sB - hA is the identity, so
+        // only the cofactored equation is satisfied.
+        let mut cap_r = [0xff; 32];
+        cap_r[0] = 0xec;
+        cap_r[31] = 0x7f;
+        let a = Scalar::from_bytes_mod_order(key.secret.to_bytes());
+        let cap_a = (&a * ED25519_BASEPOINT_TABLE).compress();
+        let mut hash = Sha512::new();
+        hash.update(cap_r);
+        hash.update(cap_a.as_bytes());
+        hash.update(message);
+        let s = Scalar::from_hash(hash) * a;
+
+        let mut signature = [0u8; SIGNATURE_LENGTH];
+        signature[..32].copy_from_slice(&cap_r);
+        signature[32..].copy_from_slice(s.as_bytes());
+        signature[SIGNATURE_LENGTH - 1] |= cap_a.as_bytes()[31] & 0b1000_0000_u8;
+
+        assert!(!PrivateKey::verify_signature(
+            &public_key,
+            &[message],
+            &signature
+        ));
+
+        let valid_message: [&[u8]; 1] = [b"valid"];
+        let valid_signature = key.calculate_signature(&mut csprng, &valid_message);
+        let small_order_message: [&[u8]; 1] = [message];
+        let batch = [
+            (&public_key, &valid_message[..], &valid_signature),
+            (&public_key, &small_order_message[..], &signature),
+        ];
+        assert!(PrivateKey::verify_signature_batch(&batch[..1], &mut csprng));
+        assert!(!PrivateKey::verify_signature_batch(&batch, &mut csprng));
+        assert!(!PrivateKey::verify_signature_batch(
+            &batch[1..],
+            &mut csprng
+        ));
+    }
 }
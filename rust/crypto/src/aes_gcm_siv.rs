@@ -0,0 +1,165 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use aes::Aes256;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, InnerIvInit, KeyInit, StreamCipher};
+use polyval::Polyval;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+use crate::padded_mac::PaddedMac;
+use crate::{Error, Result};
+
+pub const TAG_SIZE: usize = 16;
+pub const NONCE_SIZE: usize = 12;
+
+/// The per-message state of AES-256-GCM-SIV, as described in [RFC 8452][].
+///
+/// [RFC 8452]: https://www.rfc-editor.org/rfc/rfc8452.html#section-4
+struct GcmSiv {
+    encryption_cipher: Aes256,
+    nonce: [u8; NONCE_SIZE],
+    polyval: PaddedMac<Polyval>,
+}
+
+impl GcmSiv {
+    fn new(key: &[u8], nonce: &[u8], associated_data: &[u8]) -> Result<Self> {
+        let nonce: [u8; NONCE_SIZE] = nonce.try_into().map_err(|_| Error::InvalidNonceSize)?;
+        let key_generating_cipher =
+            Aes256::new_from_slice(key).map_err(|_| Error::InvalidKeySize)?;
+
+        // The message authentication key (16 bytes) followed by the message encryption key
+        // (32 bytes), built from the first half of each of six encrypted blocks.
+        let mut derived_keys = [0u8; 48];
+        for (i, derived) in (0u32..).zip(derived_keys.chunks_exact_mut(8)) {
+            let mut block = [0u8; 16];
+            block[..4].copy_from_slice(&i.to_le_bytes());
+            block[4..].copy_from_slice(&nonce);
+            key_generating_cipher.encrypt_block(GenericArray::from_mut_slice(&mut block));
+            derived.copy_from_slice(&block[..8]);
+        }
+        let (authentication_key, encryption_key) = derived_keys.split_at(16);
+
+        let polyval = Polyval::new(GenericArray::from_slice(authentication_key));
+        let encryption_cipher =
+            Aes256::new_from_slice(encryption_key).expect("derived key has the right size");
+        derived_keys.zeroize();
+
+        Ok(Self {
+            encryption_cipher,
+            nonce,
+            polyval: PaddedMac::new(polyval, associated_data),
+        })
+    }
+
+    fn compute_tag(self) -> ([u8; TAG_SIZE], Aes256) {
+        let (ad_len, msg_len) = self.polyval.lengths();
+        let mut length_block = [0u8; 16];
+        length_block[..8].copy_from_slice(&(8 * ad_len).to_le_bytes());
+        length_block[8..].copy_from_slice(&(8 * msg_len).to_le_bytes());
+
+        let mut tag = self.polyval.finalize(length_block);
+        for (t, n) in tag.iter_mut().zip(self.nonce) {
+            *t ^= n;
+        }
+        tag[15] &= 0x7f;
+        self.encryption_cipher
+            .encrypt_block(GenericArray::from_mut_slice(&mut tag));
+        (tag, self.encryption_cipher)
+    }
+}
+
+/// The keystream for a message, derived from its tag.
+fn ctr_for_tag(encryption_cipher: Aes256, tag: &[u8; TAG_SIZE]) -> ctr::Ctr32LE<Aes256> {
+    let mut counter_block = *tag;
+    counter_block[15] |= 0x80;
+    ctr::Ctr32LE::from_core(ctr::CtrCore::inner_iv_init(
+        encryption_cipher,
+        &counter_block.into(),
+    ))
+}
+
+/// Incremental AES-256-GCM-SIV encryption.
+///
+/// Because the tag is also the IV for encryption, the plaintext has to be processed twice: first
+/// passed to [`update`](Self::update) to compute the tag, then to the [`Aes256GcmSivKeystream`]
+/// returned by [`compute_tag`](Self::compute_tag) to encrypt it. The chunking can differ between
+/// the two passes.
+pub struct Aes256GcmSivEncryption {
+    siv: GcmSiv,
+}
+
+impl Aes256GcmSivEncryption {
+    pub const TAG_SIZE: usize = TAG_SIZE;
+    pub const NONCE_SIZE: usize = NONCE_SIZE;
+
+    pub fn new(key: &[u8], nonce: &[u8], associated_data: &[u8]) -> Result<Self> {
+        Ok(Self {
+            siv: GcmSiv::new(key, nonce, associated_data)?,
+        })
+    }
+
+    /// Authenticates the next part of the plaintext, without encrypting it.
+    pub fn update(&mut self, plaintext: &[u8]) {
+        self.siv.polyval.update(plaintext);
+    }
+
+    /// Returns the tag, along with the keystream for encrypting the plaintext in a second pass.
+    pub fn compute_tag(self) -> ([u8; TAG_SIZE], Aes256GcmSivKeystream) {
+        let (tag, encryption_cipher) = self.siv.compute_tag();
+        (
+            tag,
+            Aes256GcmSivKeystream(ctr_for_tag(encryption_cipher, &tag)),
+        )
+    }
+}
+
+/// Encrypts a plaintext after its tag has been computed by [`Aes256GcmSivEncryption`].
+pub struct Aes256GcmSivKeystream(ctr::Ctr32LE<Aes256>);
+
+impl Aes256GcmSivKeystream {
+    pub fn encrypt(&mut self, buf: &mut [u8]) {
+        self.0.apply_keystream(buf);
+    }
+}
+
+/// Incremental AES-256-GCM-SIV decryption.
+///
+/// Unlike [`Aes256GcmDecryption`](crate::Aes256GcmDecryption), the tag is needed up front, since
+/// it is the IV for decryption. As with any incremental decryption, the plaintext must not be
+/// used until [`verify_tag`](Self::verify_tag) succeeds.
+pub struct Aes256GcmSivDecryption {
+    ctr: ctr::Ctr32LE<Aes256>,
+    siv: GcmSiv,
+    tag: [u8; TAG_SIZE],
+}
+
+impl Aes256GcmSivDecryption {
+    pub const TAG_SIZE: usize = TAG_SIZE;
+    pub const NONCE_SIZE: usize = NONCE_SIZE;
+
+    pub fn new(key: &[u8], nonce: &[u8], associated_data: &[u8], tag: &[u8]) -> Result<Self> {
+        let tag: [u8; TAG_SIZE] = tag.try_into().map_err(|_| Error::InvalidTag)?;
+        let siv = GcmSiv::new(key, nonce, associated_data)?;
+        let ctr = ctr_for_tag(siv.encryption_cipher.clone(), &tag);
+        Ok(Self { ctr, siv, tag })
+    }
+
+    pub fn decrypt(&mut self, buf: &mut [u8]) {
+        self.ctr.apply_keystream(buf);
+        self.siv.polyval.update(buf);
+    }
+
+    pub fn verify_tag(self) -> Result<()> {
+        let (computed_tag, _) = self.siv.compute_tag();
+
+        if !bool::from(self.tag[..].ct_eq(&computed_tag[..])) {
+            return Err(Error::InvalidTag);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,113 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use chacha20::ChaCha20;
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use poly1305::Poly1305;
+use poly1305::universal_hash::KeyInit;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+use crate::padded_mac::PaddedMac;
+use crate::{Error, Result};
+
+pub const TAG_SIZE: usize = 16;
+pub const NONCE_SIZE: usize = 12;
+pub const KEY_SIZE: usize = 32;
+
+/// Sets up ChaCha20-Poly1305 as described in [RFC 8439][].
+///
+/// [RFC 8439]: https://www.rfc-editor.org/rfc/rfc8439.html#section-2.8
+fn setup_chacha20_poly1305(
+    key: &[u8],
+    nonce: &[u8],
+    associated_data: &[u8],
+) -> Result<(ChaCha20, PaddedMac<Poly1305>)> {
+    if key.len() != KEY_SIZE {
+        return Err(Error::InvalidKeySize);
+    }
+    if nonce.len() != NONCE_SIZE {
+        return Err(Error::InvalidNonceSize);
+    }
+
+    let mut chacha20 = ChaCha20::new(key.into(), nonce.into());
+
+    // The one-time Poly1305 key is the start of the first keystream block. Consume the whole block
+    // so that encryption starts from the second one.
+    let mut first_block = [0u8; 64];
+    chacha20.apply_keystream(&mut first_block);
+    let poly1305 = Poly1305::new(poly1305::Key::from_slice(
+        &first_block[..poly1305::KEY_SIZE],
+    ));
+    first_block.zeroize();
+
+    Ok((chacha20, PaddedMac::new(poly1305, associated_data)))
+}
+
+fn finalize(mac: PaddedMac<Poly1305>) -> [u8; TAG_SIZE] {
+    let (ad_len, msg_len) = mac.lengths();
+    let mut length_block = [0u8; 16];
+    length_block[..8].copy_from_slice(&ad_len.to_le_bytes());
+    length_block[8..].copy_from_slice(&msg_len.to_le_bytes());
+    mac.finalize(length_block)
+}
+
+pub struct ChaCha20Poly1305Encryption {
+    chacha20: ChaCha20,
+    mac: PaddedMac<Poly1305>,
+}
+
+impl ChaCha20Poly1305Encryption {
+    pub const TAG_SIZE: usize = TAG_SIZE;
+    pub const NONCE_SIZE: usize = NONCE_SIZE;
+
+    pub fn new(key: &[u8], nonce: &[u8], associated_data: &[u8]) -> Result<Self> {
+        let (chacha20, mac) = setup_chacha20_poly1305(key, nonce, associated_data)?;
+        Ok(Self { chacha20, mac })
+    }
+
+    pub fn encrypt(&mut self, buf: &mut [u8]) {
+        self.chacha20.apply_keystream(buf);
+        self.mac.update(buf);
+    }
+
+    pub fn compute_tag(self) -> [u8; TAG_SIZE] {
+        finalize(self.mac)
+    }
+}
+
+pub struct ChaCha20Poly1305Decryption {
+    chacha20: ChaCha20,
+    mac: PaddedMac<Poly1305>,
+}
+
+impl ChaCha20Poly1305Decryption {
+    pub const TAG_SIZE: usize = TAG_SIZE;
+    pub const NONCE_SIZE: usize = NONCE_SIZE;
+
+    pub fn new(key: &[u8], nonce: &[u8], associated_data: &[u8]) -> Result<Self> {
+        let (chacha20, mac) = setup_chacha20_poly1305(key, nonce, associated_data)?;
+        Ok(Self { chacha20, mac })
+    }
+
+    pub fn decrypt(&mut self, buf: &mut [u8]) {
+        self.mac.update(buf);
+        self.chacha20.apply_keystream(buf);
+    }
+
+    pub fn verify_tag(self, tag: &[u8]) -> Result<()> {
+        if tag.len() != TAG_SIZE {
+            return Err(Error::InvalidTag);
+        }
+
+        let computed_tag = finalize(self.mac);
+
+        if !bool::from(tag.ct_eq(&computed_tag)) {
+            return Err(Error::InvalidTag);
+        }
+
+        Ok(())
+    }
+}
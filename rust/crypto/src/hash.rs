@@ -3,28 +3,90 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
+use std::str::FromStr;
+
+use blake2::Blake2b512;
 use hmac::{Hmac, Mac};
 use sha1::Sha1;
 use sha2::{Digest, Sha256, Sha512};
+use sha3::Sha3_256;
 
 use crate::{Error, Result};
 
+/// A MAC supported by [`CryptographicMac`].
+///
+/// The discriminants are stable, so the algorithm can cross the app bridges as a single byte. The
+/// names accepted by [`FromStr`] are the ones historically passed to [`CryptographicMac::new`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, derive_more::TryFrom)]
+#[repr(u8)]
+#[try_from(repr)]
+pub enum MacAlgorithm {
+    HmacSha1 = 1,
+    HmacSha256 = 2,
+    HmacSha512 = 3,
+}
+
+impl From<MacAlgorithm> for u8 {
+    fn from(value: MacAlgorithm) -> Self {
+        value as Self
+    }
+}
+
+impl FromStr for MacAlgorithm {
+    type Err = Error;
+
+    fn from_str(algo: &str) -> Result<Self> {
+        match algo {
+            "HMACSha1" | "HmacSha1" => Ok(Self::HmacSha1),
+            "HMACSha256" | "HmacSha256" => Ok(Self::HmacSha256),
+            "HMACSha512" | "HmacSha512" => Ok(Self::HmacSha512),
+            _ => Err(Error::UnknownAlgorithm("MAC", algo.to_string())),
+        }
+    }
+}
+
+impl MacAlgorithm {
+    /// The length of the MAC in bytes.
+    pub fn output_len(self) -> usize {
+        match self {
+            Self::HmacSha1 => <Sha1 as Digest>::output_size(),
+            Self::HmacSha256 => <Sha256 as Digest>::output_size(),
+            Self::HmacSha512 => <Sha512 as Digest>::output_size(),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum CryptographicMac {
     HmacSha256(Hmac<Sha256>),
     HmacSha1(Hmac<Sha1>),
+    HmacSha512(Hmac<Sha512>),
 }
 
 impl CryptographicMac {
     pub fn new(algo: &str, key: &[u8]) -> Result<Self> {
+        Ok(Self::with_algorithm(algo.parse()?, key))
+    }
+
+    pub fn with_algorithm(algo: MacAlgorithm, key: &[u8]) -> Self {
         match algo {
-            "HMACSha1" | "HmacSha1" => Ok(Self::HmacSha1(
+            MacAlgorithm::HmacSha1 => Self::HmacSha1(
                 Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts any key length"),
-            )),
-            "HMACSha256" | "HmacSha256" => Ok(Self::HmacSha256(
+            ),
+            MacAlgorithm::HmacSha256 => Self::HmacSha256(
                 Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length"),
-            )),
-            _ => Err(Error::UnknownAlgorithm("MAC", algo.to_string())),
+            ),
+            MacAlgorithm::HmacSha512 => Self::HmacSha512(
+                Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts any key length"),
+            ),
+        }
+    }
+
+    pub fn algorithm(&self) -> MacAlgorithm {
+        match self {
+            Self::HmacSha1(_) => MacAlgorithm::HmacSha1,
+            Self::HmacSha256(_) => MacAlgorithm::HmacSha256,
+            Self::HmacSha512(_) => MacAlgorithm::HmacSha512,
         }
     }
 
@@ -32,6 +94,7 @@ impl CryptographicMac {
         match self {
             Self::HmacSha1(sha1) => sha1.update(input),
             Self::HmacSha256(sha256) => sha256.update(input),
+            Self::HmacSha512(sha512) => sha512.update(input),
         }
     }
 
@@ -44,6 +107,57 @@ impl CryptographicMac {
         match self {
             Self::HmacSha1(sha1) => sha1.finalize_reset().into_bytes().to_vec(),
             Self::HmacSha256(sha256) => sha256.finalize_reset().into_bytes().to_vec(),
+            Self::HmacSha512(sha512) => sha512.finalize_reset().into_bytes().to_vec(),
+        }
+    }
+}
+
+/// A digest supported by [`CryptographicHash`].
+///
+/// The discriminants are stable, so the algorithm can cross the app bridges as a single byte. The
+/// names accepted by [`FromStr`] are the ones historically passed to [`CryptographicHash::new`],
+/// plus ones for the newer algorithms.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, derive_more::TryFrom)]
+#[repr(u8)]
+#[try_from(repr)]
+pub enum HashAlgorithm {
+    Sha1 = 1,
+    Sha256 = 2,
+    Sha512 = 3,
+    Sha3_256 = 4,
+    Blake2b512 = 5,
+}
+
+impl From<HashAlgorithm> for u8 {
+    fn from(value: HashAlgorithm) -> Self {
+        value as Self
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    fn from_str(algo: &str) -> Result<Self> {
+        match algo {
+            "SHA-1" | "SHA1" | "Sha1" => Ok(Self::Sha1),
+            "SHA-256" | "SHA256" | "Sha256" => Ok(Self::Sha256),
+            "SHA-512" | "SHA512" | "Sha512" => Ok(Self::Sha512),
+            "SHA3-256" | "Sha3_256" => Ok(Self::Sha3_256),
+            "BLAKE2b-512" | "BLAKE2b" | "Blake2b512" => Ok(Self::Blake2b512),
+            _ => Err(Error::UnknownAlgorithm("digest", algo.to_string())),
+        }
+    }
+}
+
+impl HashAlgorithm {
+    /// The length of the digest in bytes.
+    pub fn output_len(self) -> usize {
+        match self {
+            Self::Sha1 => <Sha1 as Digest>::output_size(),
+            Self::Sha256 => <Sha256 as Digest>::output_size(),
+            Self::Sha512 => <Sha512 as Digest>::output_size(),
+            Self::Sha3_256 => <Sha3_256 as Digest>::output_size(),
+            Self::Blake2b512 => <Blake2b512 as Digest>::output_size(),
         }
     }
 }
@@ -53,15 +167,32 @@ pub enum CryptographicHash {
     Sha1(Sha1),
     Sha256(Sha256),
     Sha512(Sha512),
+    Sha3_256(Sha3_256),
+    Blake2b512(Blake2b512),
 }
 
 impl CryptographicHash {
     pub fn new(algo: &str) -> Result<Self> {
+        Ok(Self::with_algorithm(algo.parse()?))
+    }
+
+    pub fn with_algorithm(algo: HashAlgorithm) -> Self {
         match algo {
-            "SHA-1" | "SHA1" | "Sha1" => Ok(Self::Sha1(Sha1::new())),
-            "SHA-256" | "SHA256" | "Sha256" => Ok(Self::Sha256(Sha256::new())),
-            "SHA-512" | "SHA512" | "Sha512" => Ok(Self::Sha512(Sha512::new())),
-            _ => Err(Error::UnknownAlgorithm("digest", algo.to_string())),
+            HashAlgorithm::Sha1 => Self::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => Self::Sha512(Sha512::new()),
+            HashAlgorithm::Sha3_256 => Self::Sha3_256(Sha3_256::new()),
+            HashAlgorithm::Blake2b512 => Self::Blake2b512(Blake2b512::new()),
+        }
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        match self {
+            Self::Sha1(_) => HashAlgorithm::Sha1,
+            Self::Sha256(_) => HashAlgorithm::Sha256,
+            Self::Sha512(_) => HashAlgorithm::Sha512,
+            Self::Sha3_256(_) => HashAlgorithm::Sha3_256,
+            Self::Blake2b512(_) => HashAlgorithm::Blake2b512,
         }
     }
 
@@ -70,6 +201,8 @@ impl CryptographicHash {
             Self::Sha1(sha1) => sha1.update(input),
             Self::Sha256(sha256) => sha256.update(input),
             Self::Sha512(sha512) => sha512.update(input),
+            Self::Sha3_256(sha3_256) => sha3_256.update(input),
+            Self::Blake2b512(blake2b) => blake2b.update(input),
         }
     }
 
@@ -78,6 +211,82 @@ impl CryptographicHash {
             Self::Sha1(sha1) => sha1.finalize_reset().to_vec(),
             Self::Sha256(sha256) => sha256.finalize_reset().to_vec(),
             Self::Sha512(sha512) => sha512.finalize_reset().to_vec(),
+            Self::Sha3_256(sha3_256) => sha3_256.finalize_reset().to_vec(),
+            Self::Blake2b512(blake2b) => blake2b.finalize_reset().to_vec(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn digest(algo: HashAlgorithm, input: &[u8]) -> String {
+        let mut hash = CryptographicHash::with_algorithm(algo);
+        hash.update(input);
+        let result = hash.finalize();
+        assert_eq!(result.len(), algo.output_len());
+        hex::encode(result)
+    }
+
+    #[test]
+    fn hash_known_answers() {
+        assert_eq!(
+            digest(HashAlgorithm::Sha3_256, b"abc"),
+            "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"
+        );
+        assert_eq!(
+            digest(HashAlgorithm::Blake2b512, b"abc"),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+    }
+
+    #[test]
+    fn hmac_sha512_known_answer() {
+        // RFC 4231, test case 2
+        let mut mac = CryptographicMac::new("HmacSha512", b"Jefe").expect("known algorithm");
+        assert_eq!(mac.algorithm(), MacAlgorithm::HmacSha512);
+        let result = mac
+            .update_and_get(b"what do ya want ")
+            .update_and_get(b"for nothing?")
+            .finalize();
+        assert_eq!(result.len(), MacAlgorithm::HmacSha512.output_len());
+        assert_eq!(
+            hex::encode(result),
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+             9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+        );
+    }
+
+    #[test]
+    fn algorithm_names_and_tags() {
+        for algo in [
+            HashAlgorithm::Sha1,
+            HashAlgorithm::Sha256,
+            HashAlgorithm::Sha512,
+            HashAlgorithm::Sha3_256,
+            HashAlgorithm::Blake2b512,
+        ] {
+            assert_eq!(HashAlgorithm::try_from(u8::from(algo)).ok(), Some(algo));
+            assert_eq!(CryptographicHash::with_algorithm(algo).algorithm(), algo);
+        }
+        for algo in [
+            MacAlgorithm::HmacSha1,
+            MacAlgorithm::HmacSha256,
+            MacAlgorithm::HmacSha512,
+        ] {
+            assert_eq!(MacAlgorithm::try_from(u8::from(algo)).ok(), Some(algo));
+        }
+
+        assert_eq!(
+            "SHA-256".parse::<HashAlgorithm>().ok(),
+            Some(HashAlgorithm::Sha256)
+        );
+        assert!(matches!(
+            CryptographicHash::new("MD5"),
+            Err(Error::UnknownAlgorithm("digest", _))
+        ));
+        assert!(HashAlgorithm::try_from(0).is_err());
+    }
+}
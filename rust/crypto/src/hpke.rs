@@ -4,6 +4,8 @@
 //
 
 use hpke_rs::prelude::*;
+use libsignal_core::curve::{KeyType, PrivateKey, PublicKey};
+use rand_core::CryptoRng;
 
 mod provider;
 
@@ -16,6 +18,15 @@ pub use hpke_rs::HpkeError;
 #[try_from(repr)]
 pub enum SignalHpkeCiphertextType {
     Base_X25519_HkdfSha256_Aes256Gcm = 1,
+    Base_X25519_HkdfSha256_ChaCha20Poly1305 = 2,
+    Psk_X25519_HkdfSha256_Aes256Gcm = 3,
+    Psk_X25519_HkdfSha256_ChaCha20Poly1305 = 4,
+    Auth_X25519_HkdfSha256_Aes256Gcm = 5,
+    Auth_X25519_HkdfSha256_ChaCha20Poly1305 = 6,
+    AuthPsk_X25519_HkdfSha256_Aes256Gcm = 7,
+    AuthPsk_X25519_HkdfSha256_ChaCha20Poly1305 = 8,
+    Base_X25519MlKem1024_HkdfSha256_Aes256Gcm = 9,
+    Base_X25519MlKem1024_HkdfSha256_ChaCha20Poly1305 = 10,
 }
 
 impl From<SignalHpkeCiphertextType> for u8 {
@@ -24,7 +35,104 @@ impl From<SignalHpkeCiphertextType> for u8 {
     }
 }
 
+/// The AEAD used to encrypt an HPKE payload.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum HpkeAead {
+    #[default]
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+/// The post-quantum KEMs that can be combined with X25519 in a hybrid HPKE suite.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HpkeHybridKem {
+    MlKem1024,
+}
+
+impl HpkeHybridKem {
+    /// The length of the KEM ciphertext, which follows the X25519 encapsulated key in a sealed
+    /// message.
+    pub fn ciphertext_len(self) -> usize {
+        match self {
+            HpkeHybridKem::MlKem1024 => 1568,
+        }
+    }
+
+    /// Identifies the KEM shared secret when it is used as the HPKE pre-shared key.
+    fn psk_id(self) -> &'static [u8] {
+        match self {
+            HpkeHybridKem::MlKem1024 => b"Signal_HPKE_X25519_MLKEM1024",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct SuiteParameters {
+    authenticated: bool,
+    psk: bool,
+    hybrid_kem: Option<HpkeHybridKem>,
+    aead: HpkeAead,
+}
+
 impl SignalHpkeCiphertextType {
+    const ALL: [Self; 10] = [
+        Self::Base_X25519_HkdfSha256_Aes256Gcm,
+        Self::Base_X25519_HkdfSha256_ChaCha20Poly1305,
+        Self::Psk_X25519_HkdfSha256_Aes256Gcm,
+        Self::Psk_X25519_HkdfSha256_ChaCha20Poly1305,
+        Self::Auth_X25519_HkdfSha256_Aes256Gcm,
+        Self::Auth_X25519_HkdfSha256_ChaCha20Poly1305,
+        Self::AuthPsk_X25519_HkdfSha256_Aes256Gcm,
+        Self::AuthPsk_X25519_HkdfSha256_ChaCha20Poly1305,
+        Self::Base_X25519MlKem1024_HkdfSha256_Aes256Gcm,
+        Self::Base_X25519MlKem1024_HkdfSha256_ChaCha20Poly1305,
+    ];
+
+    fn parameters(self) -> SuiteParameters {
+        let (authenticated, psk, hybrid_kem, aead) = match self {
+            Self::Base_X25519_HkdfSha256_Aes256Gcm => (false, false, None, HpkeAead::Aes256Gcm),
+            Self::Base_X25519_HkdfSha256_ChaCha20Poly1305 => {
+                (false, false, None, HpkeAead::ChaCha20Poly1305)
+            }
+            Self::Psk_X25519_HkdfSha256_Aes256Gcm => (false, true, None, HpkeAead::Aes256Gcm),
+            Self::Psk_X25519_HkdfSha256_ChaCha20Poly1305 => {
+                (false, true, None, HpkeAead::ChaCha20Poly1305)
+            }
+            Self::Auth_X25519_HkdfSha256_Aes256Gcm => (true, false, None, HpkeAead::Aes256Gcm),
+            Self::Auth_X25519_HkdfSha256_ChaCha20Poly1305 => {
+                (true, false, None, HpkeAead::ChaCha20Poly1305)
+            }
+            Self::AuthPsk_X25519_HkdfSha256_Aes256Gcm => (true, true, None, HpkeAead::Aes256Gcm),
+            Self::AuthPsk_X25519_HkdfSha256_ChaCha20Poly1305 => {
+                (true, true, None, HpkeAead::ChaCha20Poly1305)
+            }
+            Self::Base_X25519MlKem1024_HkdfSha256_Aes256Gcm => (
+                false,
+                false,
+                Some(HpkeHybridKem::MlKem1024),
+                HpkeAead::Aes256Gcm,
+            ),
+            Self::Base_X25519MlKem1024_HkdfSha256_ChaCha20Poly1305 => (
+                false,
+                false,
+                Some(HpkeHybridKem::MlKem1024),
+                HpkeAead::ChaCha20Poly1305,
+            ),
+        };
+        SuiteParameters {
+            authenticated,
+            psk,
+            hybrid_kem,
+            aead,
+        }
+    }
+
+    fn for_parameters(parameters: SuiteParameters) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|ciphertext_type| ciphertext_type.parameters() == parameters)
+    }
+
     fn set_up(self) -> Hpke<provider::CryptoProvider> {
         Hpke::new(
             self.mode(),
@@ -35,132 +143,415 @@ impl SignalHpkeCiphertextType {
     }
 
     fn mode(self) -> HpkeMode {
-        match self {
-            SignalHpkeCiphertextType::Base_X25519_HkdfSha256_Aes256Gcm => HpkeMode::Base,
+        let parameters = self.parameters();
+        // The hybrid suites pass the post-quantum shared secret to HPKE as a pre-shared key.
+        let psk = parameters.psk || parameters.hybrid_kem.is_some();
+        match (parameters.authenticated, psk) {
+            (false, false) => HpkeMode::Base,
+            (false, true) => HpkeMode::Psk,
+            (true, false) => HpkeMode::Auth,
+            (true, true) => HpkeMode::AuthPsk,
         }
     }
 
     fn kem_algorithm(self) -> hpke_types::KemAlgorithm {
-        match self {
-            SignalHpkeCiphertextType::Base_X25519_HkdfSha256_Aes256Gcm => {
-                hpke_types::KemAlgorithm::DhKem25519
-            }
-        }
+        // Every suite uses X25519, including the hybrid ones; the post-quantum KEM runs outside of
+        // hpke-rs.
+        hpke_types::KemAlgorithm::DhKem25519
     }
 
     fn kdf_algorithm(self) -> hpke_types::KdfAlgorithm {
-        match self {
-            SignalHpkeCiphertextType::Base_X25519_HkdfSha256_Aes256Gcm => {
-                hpke_types::KdfAlgorithm::HkdfSha256
-            }
-        }
+        hpke_types::KdfAlgorithm::HkdfSha256
     }
 
     fn aead_algorithm(self) -> hpke_types::AeadAlgorithm {
-        match self {
-            SignalHpkeCiphertextType::Base_X25519_HkdfSha256_Aes256Gcm => {
-                hpke_types::AeadAlgorithm::Aes256Gcm
-            }
+        match self.parameters().aead {
+            HpkeAead::Aes256Gcm => hpke_types::AeadAlgorithm::Aes256Gcm,
+            HpkeAead::ChaCha20Poly1305 => hpke_types::AeadAlgorithm::ChaCha20Poly1305,
         }
     }
 }
 
-/// A thoroughly stripped-down version of [HPKE][] that only supports the "base" mode
-/// (unauthenticated, no pre-shared key).
+/// A pre-shared key and its identifier, for HPKE's PSK modes.
+#[derive(Clone, Copy)]
+pub struct HpkePsk<'a> {
+    pub psk: &'a [u8],
+    pub psk_id: &'a [u8],
+}
+
+/// Options for [`SimpleHpkeSender::seal_with_options`].
 ///
-/// Additionally hardcodes the KDF as HKDF-SHA-256 and the AEAD as AES-256-GCM, as used elsewhere in
-/// libsignal.
+/// Providing `sender` selects one of HPKE's authenticated modes, and providing `psk` one of its
+/// pre-shared key modes; the defaults give the "base" mode with AES-256-GCM.
+#[derive(Clone, Copy, Default)]
+pub struct HpkeSealOptions<'a> {
+    pub aead: HpkeAead,
+    pub sender: Option<&'a PrivateKey>,
+    pub psk: Option<HpkePsk<'a>>,
+}
+
+/// Options for [`SimpleHpkeReceiver::open_with_options`].
 ///
-/// See also [SimpleHpkeReceiver].
+/// These must match the options the message was sealed with. In particular, a receiver that
+/// expects an authenticated sender will not accept an unauthenticated message, and vice versa.
+/// The AEAD is determined by the message.
+#[derive(Clone, Copy, Default)]
+pub struct HpkeOpenOptions<'a> {
+    pub sender: Option<&'a PublicKey>,
+    pub psk: Option<HpkePsk<'a>>,
+}
+
+/// The post-quantum half of a recipient's key for a hybrid HPKE suite.
 ///
-/// [HPKE]: https://www.rfc-editor.org/rfc/rfc9180.html
-pub trait SimpleHpkeSender {
-    fn seal(&self, info: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, HpkeError>;
+/// This crate doesn't implement any post-quantum KEMs itself; this is implemented for the KEM keys
+/// in libsignal-protocol.
+pub trait HpkeKemPublicKey {
+    /// The hybrid suite this key can be used with, if any.
+    fn hpke_kem(&self) -> Option<HpkeHybridKem>;
+
+    /// Returns a new shared secret and the ciphertext encapsulating it.
+    ///
+    /// The ciphertext must be exactly [`HpkeHybridKem::ciphertext_len`] bytes.
+    fn hpke_encapsulate<R: CryptoRng>(
+        &self,
+        csprng: &mut R,
+    ) -> Result<(Box<[u8]>, Box<[u8]>), HpkeError>;
 }
 
-impl SimpleHpkeSender for libsignal_core::curve::PublicKey {
-    fn seal(&self, info: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, HpkeError> {
-        let ciphertext_type = match self.key_type() {
-            libsignal_core::curve::KeyType::Djb => {
-                SignalHpkeCiphertextType::Base_X25519_HkdfSha256_Aes256Gcm
-            }
-        };
+/// The post-quantum half of a recipient's key for a hybrid HPKE suite.
+///
+/// See [`HpkeKemPublicKey`].
+pub trait HpkeKemSecretKey {
+    /// The hybrid suite this key can be used with, if any.
+    fn hpke_kem(&self) -> Option<HpkeHybridKem>;
 
-        let hpke_key = HpkePublicKey::from(self.public_key_bytes());
-        let (encapsulated_secret, mut ciphertext) = ciphertext_type
-            .set_up()
-            .seal(&hpke_key, info, aad, plaintext, None, None, None)?;
-        debug_assert_eq!(
-            encapsulated_secret.len(),
-            ciphertext_type.kem_algorithm().shared_secret_len()
-        );
+    /// Recovers the shared secret from a ciphertext produced by
+    /// [`HpkeKemPublicKey::hpke_encapsulate`].
+    fn hpke_decapsulate(&self, ciphertext: &[u8]) -> Result<Box<[u8]>, HpkeError>;
+}
 
-        // Insert the type byte and the encapsulated secret at the front of the ciphertext. We do
-        // this by mutating the ciphertext rather than creating a new Vec (or appending to the
-        // secret) because we have the best chance of the ciphertext Vec already having extra room
-        // in the buffer, in which case we're just moving bytes around with no new allocations. If
-        // not, this should fall back to effectively creating a new buffer and copying all three
-        // parts into it.
-        ciphertext.splice(
-            0..0,
-            [ciphertext_type.into()]
-                .into_iter()
-                .chain(encapsulated_secret),
-        );
+/// A recipient's X25519 and post-quantum public keys, for sealing with a hybrid HPKE suite.
+///
+/// The post-quantum shared secret is used as HPKE's pre-shared key, so the message stays
+/// confidential as long as either X25519 or the post-quantum KEM is unbroken. The KEM ciphertext
+/// is carried after the X25519 encapsulated key, in the same format as the other suites.
+pub struct HybridHpkePublicKey<'a, K> {
+    pub ec: &'a PublicKey,
+    pub kem: &'a K,
+}
 
-        Ok(ciphertext)
-    }
+/// A recipient's X25519 and post-quantum private keys, for opening messages sealed with
+/// [`HybridHpkePublicKey`].
+///
+/// This only opens messages that used a hybrid suite.
+pub struct HybridHpkePrivateKey<'a, K> {
+    pub ec: &'a PrivateKey,
+    pub kem: &'a K,
 }
 
-/// A thoroughly stripped-down version of [HPKE][] that only supports the "base" mode
-/// (unauthenticated, no pre-shared key).
+/// A stripped-down version of [HPKE][] with a fixed set of suites, identified by a type byte at the
+/// start of each sealed message.
 ///
-/// Additionally hardcodes the KDF as HKDF-SHA-256 and the AEAD as AES-256-GCM, as used elsewhere in
-/// libsignal.
+/// The KEM is always X25519 (optionally combined with a post-quantum KEM, see
+/// [`HybridHpkePublicKey`]) and the KDF is always HKDF-SHA-256. The AEAD can be AES-256-GCM or
+/// ChaCha20-Poly1305.
 ///
 /// See also [SimpleHpkeReceiver].
 ///
 /// [HPKE]: https://www.rfc-editor.org/rfc/rfc9180.html
-pub trait SimpleHpkeReceiver {
-    fn open(&self, info: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, HpkeError>;
+pub trait SimpleHpkeSender {
+    /// Seals `plaintext` using the "base" mode (unauthenticated, no pre-shared key) and
+    /// AES-256-GCM.
+    fn seal(&self, info: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, HpkeError> {
+        self.seal_with_options(&HpkeSealOptions::default(), info, aad, plaintext)
+    }
+
+    /// Seals `plaintext` using the mode and AEAD selected by `options`.
+    fn seal_with_options(
+        &self,
+        options: &HpkeSealOptions<'_>,
+        info: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, HpkeError>;
 }
 
-impl SimpleHpkeReceiver for libsignal_core::curve::PrivateKey {
+/// A stripped-down version of [HPKE][] with a fixed set of suites, identified by a type byte at the
+/// start of each sealed message.
+///
+/// See [SimpleHpkeSender] for the supported suites.
+///
+/// [HPKE]: https://www.rfc-editor.org/rfc/rfc9180.html
+pub trait SimpleHpkeReceiver {
+    /// Opens a message sealed using the "base" mode (unauthenticated, no pre-shared key), with
+    /// either AEAD.
     fn open(&self, info: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, HpkeError> {
-        let (ciphertext_type, ciphertext) = ciphertext
-            .split_at_checked(1)
-            .ok_or(HpkeError::InvalidInput)?;
-        let ciphertext_type = ciphertext_type[0]
-            .try_into()
-            .map_err(|_| HpkeError::UnknownMode)?;
+        self.open_with_options(&HpkeOpenOptions::default(), info, aad, ciphertext)
+    }
+
+    /// Opens a message sealed using the mode selected by `options`, with either AEAD.
+    fn open_with_options(
+        &self,
+        options: &HpkeOpenOptions<'_>,
+        info: &[u8],
+        aad: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, HpkeError>;
+}
+
+impl SimpleHpkeSender for PublicKey {
+    fn seal_with_options(
+        &self,
+        options: &HpkeSealOptions<'_>,
+        info: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, HpkeError> {
+        match self.key_type() {
+            KeyType::Djb => {}
+        }
+        if let Some(sender) = options.sender {
+            match sender.key_type() {
+                KeyType::Djb => {}
+            }
+        }
+
+        let ciphertext_type = SignalHpkeCiphertextType::for_parameters(SuiteParameters {
+            authenticated: options.sender.is_some(),
+            psk: options.psk.is_some(),
+            hybrid_kem: None,
+            aead: options.aead,
+        })
+        .expect("every X25519 mode and AEAD has a ciphertext type");
+
+        seal(
+            ciphertext_type,
+            self,
+            options.sender,
+            options.psk,
+            &[],
+            info,
+            aad,
+            plaintext,
+        )
+    }
+}
+
+impl SimpleHpkeReceiver for PrivateKey {
+    fn open_with_options(
+        &self,
+        options: &HpkeOpenOptions<'_>,
+        info: &[u8],
+        aad: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, HpkeError> {
+        let (ciphertext_type, ciphertext) = split_ciphertext_type(ciphertext)?;
+        let parameters = ciphertext_type.parameters();
 
         // Check for a ciphertext using a non-Curve25519 key.
-        // This code will need to be updated if there are other key types or ciphertext types in the future.
-        match (ciphertext_type, self.key_type()) {
-            (
-                SignalHpkeCiphertextType::Base_X25519_HkdfSha256_Aes256Gcm,
-                libsignal_core::curve::KeyType::Djb,
-            ) => {}
+        // This code will need to be updated if there are other key types in the future.
+        match self.key_type() {
+            KeyType::Djb => {}
+        }
+        if parameters.hybrid_kem.is_some()
+            || parameters.authenticated != options.sender.is_some()
+            || parameters.psk != options.psk.is_some()
+        {
+            return Err(HpkeError::UnknownMode);
         }
 
         let (encapsulated_secret, ciphertext) = ciphertext
             .split_at_checked(ciphertext_type.kem_algorithm().shared_secret_len())
             .ok_or(HpkeError::InvalidInput)?;
 
-        let hpke_key = HpkePrivateKey::from(self.serialize());
-        ciphertext_type.set_up().open(
+        open(
+            ciphertext_type,
+            self,
             encapsulated_secret,
-            &hpke_key,
+            options.sender,
+            options.psk,
             info,
             aad,
             ciphertext,
+        )
+    }
+}
+
+impl<K: HpkeKemPublicKey> SimpleHpkeSender for HybridHpkePublicKey<'_, K> {
+    fn seal_with_options(
+        &self,
+        options: &HpkeSealOptions<'_>,
+        info: &[u8],
+        aad: &[u8],
+        plaintext: &[u8],
+    ) -> Result<Vec<u8>, HpkeError> {
+        // The pre-shared key slot is taken by the post-quantum shared secret.
+        if options.sender.is_some() || options.psk.is_some() {
+            return Err(HpkeError::InvalidConfig);
+        }
+        match self.ec.key_type() {
+            KeyType::Djb => {}
+        }
+        let hybrid_kem = self.kem.hpke_kem().ok_or(HpkeError::InvalidConfig)?;
+
+        let ciphertext_type = SignalHpkeCiphertextType::for_parameters(SuiteParameters {
+            authenticated: false,
+            psk: false,
+            hybrid_kem: Some(hybrid_kem),
+            aead: options.aead,
+        })
+        .expect("every hybrid KEM and AEAD has a ciphertext type");
+
+        let (shared_secret, kem_ciphertext) =
+            self.kem.hpke_encapsulate(&mut provider::Rng::default())?;
+        if kem_ciphertext.len() != hybrid_kem.ciphertext_len() {
+            return Err(HpkeError::InvalidConfig);
+        }
+
+        seal(
+            ciphertext_type,
+            self.ec,
             None,
+            Some(HpkePsk {
+                psk: &shared_secret,
+                psk_id: hybrid_kem.psk_id(),
+            }),
+            &kem_ciphertext,
+            info,
+            aad,
+            plaintext,
+        )
+    }
+}
+
+impl<K: HpkeKemSecretKey> SimpleHpkeReceiver for HybridHpkePrivateKey<'_, K> {
+    fn open_with_options(
+        &self,
+        options: &HpkeOpenOptions<'_>,
+        info: &[u8],
+        aad: &[u8],
+        ciphertext: &[u8],
+    ) -> Result<Vec<u8>, HpkeError> {
+        if options.sender.is_some() || options.psk.is_some() {
+            return Err(HpkeError::InvalidConfig);
+        }
+        match self.ec.key_type() {
+            KeyType::Djb => {}
+        }
+
+        let (ciphertext_type, ciphertext) = split_ciphertext_type(ciphertext)?;
+        // Don't accept messages without post-quantum protection.
+        let hybrid_kem = ciphertext_type
+            .parameters()
+            .hybrid_kem
+            .filter(|kem| Some(*kem) == self.kem.hpke_kem())
+            .ok_or(HpkeError::UnknownMode)?;
+
+        let (encapsulated_secret, ciphertext) = ciphertext
+            .split_at_checked(ciphertext_type.kem_algorithm().shared_secret_len())
+            .ok_or(HpkeError::InvalidInput)?;
+        let (kem_ciphertext, ciphertext) = ciphertext
+            .split_at_checked(hybrid_kem.ciphertext_len())
+            .ok_or(HpkeError::InvalidInput)?;
+        let shared_secret = self.kem.hpke_decapsulate(kem_ciphertext)?;
+
+        open(
+            ciphertext_type,
+            self.ec,
+            encapsulated_secret,
             None,
-            None,
+            Some(HpkePsk {
+                psk: &shared_secret,
+                psk_id: hybrid_kem.psk_id(),
+            }),
+            info,
+            aad,
+            ciphertext,
         )
     }
 }
 
+fn split_ciphertext_type(
+    ciphertext: &[u8],
+) -> Result<(SignalHpkeCiphertextType, &[u8]), HpkeError> {
+    let (ciphertext_type, ciphertext) = ciphertext
+        .split_at_checked(1)
+        .ok_or(HpkeError::InvalidInput)?;
+    let ciphertext_type = ciphertext_type[0]
+        .try_into()
+        .map_err(|_| HpkeError::UnknownMode)?;
+    Ok((ciphertext_type, ciphertext))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn seal(
+    ciphertext_type: SignalHpkeCiphertextType,
+    recipient: &PublicKey,
+    sender: Option<&PrivateKey>,
+    psk: Option<HpkePsk<'_>>,
+    kem_ciphertext: &[u8],
+    info: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, HpkeError> {
+    let hpke_key = HpkePublicKey::from(recipient.public_key_bytes());
+    let sender_key = sender.map(|key| HpkePrivateKey::from(key.serialize()));
+    let (encapsulated_secret, mut ciphertext) = ciphertext_type.set_up().seal(
+        &hpke_key,
+        info,
+        aad,
+        plaintext,
+        psk.map(|psk| psk.psk),
+        psk.map(|psk| psk.psk_id),
+        sender_key.as_ref(),
+    )?;
+    debug_assert_eq!(
+        encapsulated_secret.len(),
+        ciphertext_type.kem_algorithm().shared_secret_len()
+    );
+
+    // Insert the type byte, the encapsulated secret, and the KEM ciphertext (if any) at the front
+    // of the ciphertext. We do this by mutating the ciphertext rather than creating a new Vec (or
+    // appending to the secret) because we have the best chance of the ciphertext Vec already
+    // having extra room in the buffer, in which case we're just moving bytes around with no new
+    // allocations. If not, this should fall back to effectively creating a new buffer and copying
+    // all the parts into it.
+    ciphertext.splice(
+        0..0,
+        [ciphertext_type.into()]
+            .into_iter()
+            .chain(encapsulated_secret)
+            .chain(kem_ciphertext.iter().copied()),
+    );
+
+    Ok(ciphertext)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn open(
+    ciphertext_type: SignalHpkeCiphertextType,
+    recipient: &PrivateKey,
+    encapsulated_secret: &[u8],
+    sender: Option<&PublicKey>,
+    psk: Option<HpkePsk<'_>>,
+    info: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, HpkeError> {
+    let hpke_key = HpkePrivateKey::from(recipient.serialize());
+    let sender_key = sender.map(|key| HpkePublicKey::from(key.public_key_bytes()));
+    ciphertext_type.set_up().open(
+        encapsulated_secret,
+        &hpke_key,
+        info,
+        aad,
+        ciphertext,
+        psk.map(|psk| psk.psk),
+        psk.map(|psk| psk.psk_id),
+        sender_key.as_ref(),
+    )
+}
+
 #[cfg(test)]
 mod test {
     use assert_matches::assert_matches;
@@ -194,4 +585,120 @@ mod test {
             HpkeError::OpenError
         );
     }
+
+    #[test]
+    fn modes_and_aeads() {
+        let recipient = KeyPair::generate(&mut rand::rng());
+        let sender = KeyPair::generate(&mut rand::rng());
+        let psk = HpkePsk {
+            psk: &[0x42; 32],
+            psk_id: b"psk id",
+        };
+        let info = b"info";
+        let aad = b"extra";
+        let contents = b"message";
+
+        for aead in [HpkeAead::Aes256Gcm, HpkeAead::ChaCha20Poly1305] {
+            for (sender_private, sender_public) in [
+                (None, None),
+                (Some(&sender.private_key), Some(&sender.public_key)),
+            ] {
+                for psk in [None, Some(psk)] {
+                    let seal_options = HpkeSealOptions {
+                        aead,
+                        sender: sender_private,
+                        psk,
+                    };
+                    let open_options = HpkeOpenOptions {
+                        sender: sender_public,
+                        psk,
+                    };
+
+                    let ciphertext = recipient
+                        .public_key
+                        .seal_with_options(&seal_options, info, aad, contents)
+                        .expect("can seal");
+                    let ciphertext_type =
+                        SignalHpkeCiphertextType::try_from(ciphertext[0]).expect("valid type byte");
+                    assert_eq!(ciphertext_type.parameters().aead, aead);
+
+                    let unsealed = recipient
+                        .private_key
+                        .open_with_options(&open_options, info, aad, &ciphertext)
+                        .expect("can open");
+                    assert_eq!(&contents[..], unsealed);
+
+                    // Receivers must agree on the mode.
+                    let mismatched_options = HpkeOpenOptions {
+                        sender: sender_public.xor(Some(&sender.public_key)),
+                        psk,
+                    };
+                    assert_matches!(
+                        recipient.private_key.open_with_options(
+                            &mismatched_options,
+                            info,
+                            aad,
+                            &ciphertext
+                        ),
+                        Err(HpkeError::UnknownMode)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn wrong_sender_or_psk() {
+        let recipient = KeyPair::generate(&mut rand::rng());
+        let sender = KeyPair::generate(&mut rand::rng());
+        let impostor = KeyPair::generate(&mut rand::rng());
+        let psk = HpkePsk {
+            psk: &[0x42; 32],
+            psk_id: b"psk id",
+        };
+        let info = b"info";
+        let aad = b"extra";
+
+        let ciphertext = recipient
+            .public_key
+            .seal_with_options(
+                &HpkeSealOptions {
+                    aead: HpkeAead::ChaCha20Poly1305,
+                    sender: Some(&sender.private_key),
+                    psk: Some(psk),
+                },
+                info,
+                aad,
+                b"message",
+            )
+            .expect("can seal");
+
+        assert_matches!(
+            recipient.private_key.open_with_options(
+                &HpkeOpenOptions {
+                    sender: Some(&impostor.public_key),
+                    psk: Some(psk),
+                },
+                info,
+                aad,
+                &ciphertext
+            ),
+            Err(HpkeError::OpenError)
+        );
+        assert_matches!(
+            recipient.private_key.open_with_options(
+                &HpkeOpenOptions {
+                    sender: Some(&sender.public_key),
+                    psk: Some(HpkePsk {
+                        psk: &[0x43; 32],
+                        ..psk
+                    }),
+                },
+                info,
+                aad,
+                &ciphertext
+            ),
+            Err(HpkeError::OpenError)
+        );
+    }
 }
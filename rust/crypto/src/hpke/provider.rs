@@ -3,6 +3,8 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
+use chacha20poly1305::ChaCha20Poly1305;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
 use hkdf::Hkdf;
 use hpke_rs_crypto::error::Error as HpkeError;
 use hpke_rs_crypto::types::{AeadAlgorithm, KdfAlgorithm, KemAlgorithm};
@@ -37,7 +39,7 @@ impl HpkeCrypto for CryptoProvider {
 
     fn supports_aead(alg: AeadAlgorithm) -> Result<(), HpkeError> {
         match alg {
-            AeadAlgorithm::Aes256Gcm => Ok(()),
+            AeadAlgorithm::Aes256Gcm | AeadAlgorithm::ChaCha20Poly1305 => Ok(()),
             _ => Err(HpkeError::UnknownAeadAlgorithm),
         }
     }
@@ -129,8 +131,13 @@ impl HpkeCrypto for CryptoProvider {
         unimplemented!("unused with DH-based KEM");
     }
 
-    fn dh_validate_sk(_alg: KemAlgorithm, _sk: &[u8]) -> Result<Vec<u8>, HpkeError> {
-        unimplemented!("unused with unauthenticated (Base) mode");
+    fn dh_validate_sk(alg: KemAlgorithm, sk: &[u8]) -> Result<Vec<u8>, HpkeError> {
+        match alg {
+            KemAlgorithm::DhKem25519 => {}
+            _ => return Err(HpkeError::UnknownKemAlgorithm),
+        }
+        let sk = PrivateKey::deserialize(sk).map_err(|_| HpkeError::KemInvalidSecretKey)?;
+        Ok(sk.serialize())
     }
 
     fn aead_seal(
@@ -142,6 +149,14 @@ impl HpkeCrypto for CryptoProvider {
     ) -> Result<Vec<u8>, HpkeError> {
         match alg {
             AeadAlgorithm::Aes256Gcm => {}
+            AeadAlgorithm::ChaCha20Poly1305 => {
+                return chacha20poly1305_cipher(key, nonce)?
+                    .encrypt(
+                        chacha20poly1305::Nonce::from_slice(nonce),
+                        Payload { msg, aad },
+                    )
+                    .map_err(|_| HpkeError::CryptoLibraryError("encryption failed".into()));
+            }
             _ => return Err(HpkeError::UnknownAeadAlgorithm),
         }
 
@@ -164,6 +179,14 @@ impl HpkeCrypto for CryptoProvider {
     ) -> Result<Vec<u8>, HpkeError> {
         match alg {
             AeadAlgorithm::Aes256Gcm => {}
+            AeadAlgorithm::ChaCha20Poly1305 => {
+                return chacha20poly1305_cipher(key, nonce)?
+                    .decrypt(
+                        chacha20poly1305::Nonce::from_slice(nonce),
+                        Payload { msg, aad },
+                    )
+                    .map_err(|_| HpkeError::AeadOpenError);
+            }
             _ => return Err(HpkeError::UnknownAeadAlgorithm),
         }
 
@@ -179,6 +202,14 @@ impl HpkeCrypto for CryptoProvider {
     }
 }
 
+fn chacha20poly1305_cipher(key: &[u8], nonce: &[u8]) -> Result<ChaCha20Poly1305, HpkeError> {
+    // Check the length up front; Nonce::from_slice panics on a mismatch.
+    if nonce.len() != 12 {
+        return Err(HpkeError::AeadInvalidNonce);
+    }
+    ChaCha20Poly1305::new_from_slice(key).map_err(|e| HpkeError::CryptoLibraryError(e.to_string()))
+}
+
 // Matching https://github.com/cryspen/hpke-rs/blob/v0.6.0/rust_crypto_provider/src/lib.rs#L40
 type RngImpl = rand_chacha::ChaCha20Rng;
 
@@ -0,0 +1,179 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use std::str::FromStr;
+
+use hkdf::Hkdf;
+use sha2::{Sha256, Sha512};
+
+use crate::{Error, Result};
+
+/// The hash function used with [HKDF][].
+///
+/// Like [`HashAlgorithm`](crate::HashAlgorithm), the discriminants are stable so the algorithm can
+/// cross the app bridges as a single byte.
+///
+/// [HKDF]: https://www.rfc-editor.org/rfc/rfc5869.html
+#[derive(Clone, Copy, PartialEq, Eq, Debug, derive_more::TryFrom)]
+#[repr(u8)]
+#[try_from(repr)]
+pub enum HkdfAlgorithm {
+    HkdfSha256 = 1,
+    HkdfSha512 = 2,
+}
+
+impl From<HkdfAlgorithm> for u8 {
+    fn from(value: HkdfAlgorithm) -> Self {
+        value as Self
+    }
+}
+
+impl FromStr for HkdfAlgorithm {
+    type Err = Error;
+
+    fn from_str(algo: &str) -> Result<Self> {
+        match algo {
+            "HKDFSha256" | "HkdfSha256" => Ok(Self::HkdfSha256),
+            "HKDFSha512" | "HkdfSha512" => Ok(Self::HkdfSha512),
+            _ => Err(Error::UnknownAlgorithm("KDF", algo.to_string())),
+        }
+    }
+}
+
+/// The extract step of HKDF, taking the input keying material in pieces.
+#[derive(Clone)]
+pub enum HkdfExtractor {
+    HkdfSha256(hkdf::HkdfExtract<Sha256>),
+    HkdfSha512(hkdf::HkdfExtract<Sha512>),
+}
+
+impl HkdfExtractor {
+    /// Starts extraction; a missing salt is equivalent to a salt of all zeros.
+    pub fn new(algo: HkdfAlgorithm, salt: Option<&[u8]>) -> Self {
+        match algo {
+            HkdfAlgorithm::HkdfSha256 => Self::HkdfSha256(hkdf::HkdfExtract::new(salt)),
+            HkdfAlgorithm::HkdfSha512 => Self::HkdfSha512(hkdf::HkdfExtract::new(salt)),
+        }
+    }
+
+    pub fn update(&mut self, ikm: &[u8]) {
+        match self {
+            Self::HkdfSha256(extract) => extract.input_ikm(ikm),
+            Self::HkdfSha512(extract) => extract.input_ikm(ikm),
+        }
+    }
+
+    /// Returns the pseudorandom key, along with an expander that uses it.
+    pub fn finalize(self) -> (Vec<u8>, HkdfExpander) {
+        match self {
+            Self::HkdfSha256(extract) => {
+                let (prk, hkdf) = extract.finalize();
+                (prk.to_vec(), HkdfExpander::HkdfSha256(hkdf))
+            }
+            Self::HkdfSha512(extract) => {
+                let (prk, hkdf) = extract.finalize();
+                (prk.to_vec(), HkdfExpander::HkdfSha512(hkdf))
+            }
+        }
+    }
+}
+
+/// The expand step of HKDF, which can produce any number of outputs from one pseudorandom key.
+#[derive(Clone)]
+pub enum HkdfExpander {
+    HkdfSha256(Hkdf<Sha256>),
+    HkdfSha512(Hkdf<Sha512>),
+}
+
+impl HkdfExpander {
+    /// Uses an existing pseudorandom key, which must be at least as long as the hash output.
+    pub fn from_prk(algo: HkdfAlgorithm, prk: &[u8]) -> Result<Self> {
+        let result = match algo {
+            HkdfAlgorithm::HkdfSha256 => Hkdf::from_prk(prk).map(Self::HkdfSha256),
+            HkdfAlgorithm::HkdfSha512 => Hkdf::from_prk(prk).map(Self::HkdfSha512),
+        };
+        result.map_err(|_| Error::InvalidKeySize)
+    }
+
+    pub fn algorithm(&self) -> HkdfAlgorithm {
+        match self {
+            Self::HkdfSha256(_) => HkdfAlgorithm::HkdfSha256,
+            Self::HkdfSha512(_) => HkdfAlgorithm::HkdfSha512,
+        }
+    }
+
+    /// Fills `okm`, failing if it is longer than 255 times the hash output.
+    pub fn expand(&self, info: &[u8], okm: &mut [u8]) -> Result<()> {
+        self.expand_multi_info(&[info], okm)
+    }
+
+    /// Like [`expand`](Self::expand), with `info` given in pieces to be concatenated.
+    pub fn expand_multi_info(&self, info_components: &[&[u8]], okm: &mut [u8]) -> Result<()> {
+        let result = match self {
+            Self::HkdfSha256(hkdf) => hkdf.expand_multi_info(info_components, okm),
+            Self::HkdfSha512(hkdf) => hkdf.expand_multi_info(info_components, okm),
+        };
+        result.map_err(|_| Error::InvalidInputSize)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use const_str::hex;
+
+    use super::*;
+
+    #[test]
+    fn rfc5869_test_case_1() {
+        let ikm = [0x0b; 22];
+        let salt = hex!("000102030405060708090a0b0c");
+        let info = hex!("f0f1f2f3f4f5f6f7f8f9");
+
+        let mut extractor = HkdfExtractor::new(HkdfAlgorithm::HkdfSha256, Some(&salt[..]));
+        extractor.update(&ikm[..10]);
+        extractor.update(&ikm[10..]);
+        let (prk, expander) = extractor.finalize();
+        assert_eq!(
+            hex::encode(&prk),
+            "077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5"
+        );
+
+        let mut okm = [0; 42];
+        expander
+            .expand_multi_info(&[&info[..4], &info[4..]], &mut okm)
+            .expect("valid length");
+        assert_eq!(
+            hex::encode(okm),
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+        );
+
+        let expander =
+            HkdfExpander::from_prk(HkdfAlgorithm::HkdfSha256, &prk).expect("valid PRK length");
+        let mut okm_again = [0; 42];
+        expander
+            .expand(&info, &mut okm_again)
+            .expect("valid length");
+        assert_eq!(okm, okm_again);
+    }
+
+    #[test]
+    fn invalid_lengths() {
+        assert!(matches!(
+            HkdfExpander::from_prk(HkdfAlgorithm::HkdfSha512, &[0; 32]),
+            Err(Error::InvalidKeySize)
+        ));
+
+        let (_, expander) = HkdfExtractor::new(HkdfAlgorithm::HkdfSha512, None).finalize();
+        assert_eq!(expander.algorithm(), HkdfAlgorithm::HkdfSha512);
+        let mut okm = vec![0; 255 * 64 + 1];
+        assert!(matches!(
+            expander.expand(b"", &mut okm),
+            Err(Error::InvalidInputSize)
+        ));
+        expander
+            .expand(b"", &mut okm[..255 * 64])
+            .expect("maximum length is allowed");
+    }
+}
@@ -8,14 +8,25 @@
 mod error;
 mod hash;
 mod hpke;
+mod kdf;
 
 mod aes_cbc;
 mod aes_ctr;
 mod aes_gcm;
+mod aes_gcm_siv;
+mod chacha20_poly1305;
+mod padded_mac;
 
 pub use aes_cbc::{DecryptionError, EncryptionError, aes_256_cbc_decrypt, aes_256_cbc_encrypt};
 pub use aes_ctr::Aes256Ctr32;
 pub use aes_gcm::{Aes256GcmDecryption, Aes256GcmEncryption};
+pub use aes_gcm_siv::{Aes256GcmSivDecryption, Aes256GcmSivEncryption, Aes256GcmSivKeystream};
+pub use chacha20_poly1305::{ChaCha20Poly1305Decryption, ChaCha20Poly1305Encryption};
 pub use error::{Error, Result};
-pub use hash::{CryptographicHash, CryptographicMac};
-pub use hpke::{HpkeError, SimpleHpkeReceiver, SimpleHpkeSender};
+pub use hash::{CryptographicHash, CryptographicMac, HashAlgorithm, MacAlgorithm};
+pub use hpke::{
+    HpkeAead, HpkeError, HpkeHybridKem, HpkeKemPublicKey, HpkeKemSecretKey, HpkeOpenOptions,
+    HpkePsk, HpkeSealOptions, HybridHpkePrivateKey, HybridHpkePublicKey, SignalHpkeCiphertextType,
+    SimpleHpkeReceiver, SimpleHpkeSender,
+};
+pub use kdf::{HkdfAlgorithm, HkdfExpander, HkdfExtractor};
@@ -0,0 +1,76 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use ghash::universal_hash::UniversalHash;
+use ghash::universal_hash::consts::U16;
+use ghash::universal_hash::generic_array::GenericArray;
+
+const BLOCK_SIZE: usize = 16;
+
+/// Incrementally feeds associated data and a message to a universal hash, each zero-padded to a
+/// whole number of blocks, as done by the Poly1305 and POLYVAL based AEADs.
+///
+/// The caller supplies the final length block, since its encoding differs between constructions.
+#[derive(Clone)]
+pub(crate) struct PaddedMac<H> {
+    hash: H,
+    msg_buf: [u8; BLOCK_SIZE],
+    msg_buf_offset: usize,
+    ad_len: u64,
+    msg_len: u64,
+}
+
+impl<H: UniversalHash<BlockSize = U16>> PaddedMac<H> {
+    pub(crate) fn new(mut hash: H, associated_data: &[u8]) -> Self {
+        hash.update_padded(associated_data);
+        Self {
+            hash,
+            msg_buf: [0; BLOCK_SIZE],
+            msg_buf_offset: 0,
+            ad_len: associated_data.len() as u64,
+            msg_len: 0,
+        }
+    }
+
+    pub(crate) fn update(&mut self, mut msg: &[u8]) {
+        self.msg_len += msg.len() as u64;
+
+        if self.msg_buf_offset > 0 {
+            let taking = std::cmp::min(msg.len(), BLOCK_SIZE - self.msg_buf_offset);
+            self.msg_buf[self.msg_buf_offset..][..taking].copy_from_slice(&msg[..taking]);
+            self.msg_buf_offset += taking;
+            msg = &msg[taking..];
+
+            if self.msg_buf_offset < BLOCK_SIZE {
+                return;
+            }
+            self.hash
+                .update(std::slice::from_ref(GenericArray::from_slice(
+                    &self.msg_buf,
+                )));
+            self.msg_buf_offset = 0;
+        }
+
+        // A whole number of blocks is processed without any padding.
+        let full_blocks_len = msg.len() - msg.len() % BLOCK_SIZE;
+        self.hash.update_padded(&msg[..full_blocks_len]);
+
+        let leftover = &msg[full_blocks_len..];
+        self.msg_buf[..leftover.len()].copy_from_slice(leftover);
+        self.msg_buf_offset = leftover.len();
+    }
+
+    /// The number of bytes of associated data and message, respectively.
+    pub(crate) fn lengths(&self) -> (u64, u64) {
+        (self.ad_len, self.msg_len)
+    }
+
+    pub(crate) fn finalize(mut self, length_block: [u8; BLOCK_SIZE]) -> [u8; BLOCK_SIZE] {
+        self.hash
+            .update_padded(&self.msg_buf[..self.msg_buf_offset]);
+        self.hash.update(&[length_block.into()]);
+        self.hash.finalize().into()
+    }
+}
@@ -0,0 +1,85 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use aes_gcm_siv::aead::{Aead, KeyInit, Payload};
+use rand::{Rng, TryRngCore as _};
+
+#[test]
+fn aes_gcm_siv_matches_one_shot() -> Result<(), signal_crypto::Error> {
+    let mut rng = rand::rngs::OsRng.unwrap_err();
+
+    for len in [0, 1, 15, 16, 17, 63, 64, 65, 1000] {
+        let key: [u8; 32] = rng.random();
+        let nonce: [u8; 12] = rng.random();
+        let aad = vec![0xAD; len % 37];
+        let mut plaintext = vec![0; len];
+        rng.fill(&mut plaintext[..]);
+
+        let expected = aes_gcm_siv::Aes256GcmSiv::new(&key.into())
+            .encrypt(
+                &nonce.into(),
+                Payload {
+                    msg: &plaintext,
+                    aad: &aad,
+                },
+            )
+            .expect("can encrypt");
+        let (expected_ct, expected_tag) = expected.split_at(len);
+
+        // The two encryption passes can be chunked differently.
+        let mut enc = signal_crypto::Aes256GcmSivEncryption::new(&key, &nonce, &aad)?;
+        for chunk in plaintext.chunks(rng.random_range(1..=len.max(1))) {
+            enc.update(chunk);
+        }
+        let (tag, mut keystream) = enc.compute_tag();
+        assert_eq!(hex::encode(tag), hex::encode(expected_tag));
+
+        let mut enc_buf = plaintext.clone();
+        for chunk in enc_buf.chunks_mut(rng.random_range(1..=len.max(1))) {
+            keystream.encrypt(chunk);
+        }
+        assert_eq!(hex::encode(&enc_buf), hex::encode(expected_ct));
+
+        let mut dec = signal_crypto::Aes256GcmSivDecryption::new(&key, &nonce, &aad, &tag)?;
+        for chunk in enc_buf.chunks_mut(rng.random_range(1..=len.max(1))) {
+            dec.decrypt(chunk);
+        }
+        dec.verify_tag()?;
+        assert_eq!(enc_buf, plaintext);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn aes_gcm_siv_rejects_bad_tag() -> Result<(), signal_crypto::Error> {
+    let key = [0x42; 32];
+    let nonce = [0x24; 12];
+
+    let mut enc = signal_crypto::Aes256GcmSivEncryption::new(&key, &nonce, b"aad")?;
+    enc.update(b"message");
+    let (mut tag, mut keystream) = enc.compute_tag();
+    let mut buf = b"message".to_vec();
+    keystream.encrypt(&mut buf);
+
+    tag[0] ^= 1;
+    let mut dec = signal_crypto::Aes256GcmSivDecryption::new(&key, &nonce, b"aad", &tag)?;
+    dec.decrypt(&mut buf);
+    assert!(matches!(
+        dec.verify_tag(),
+        Err(signal_crypto::Error::InvalidTag)
+    ));
+
+    assert!(matches!(
+        signal_crypto::Aes256GcmSivDecryption::new(&key, &nonce, b"aad", &tag[..8]),
+        Err(signal_crypto::Error::InvalidTag)
+    ));
+    assert!(matches!(
+        signal_crypto::Aes256GcmSivEncryption::new(&key, &nonce[..8], b""),
+        Err(signal_crypto::Error::InvalidNonceSize)
+    ));
+
+    Ok(())
+}
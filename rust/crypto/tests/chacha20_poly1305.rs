@@ -0,0 +1,104 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use const_str::hex;
+use rand::{Rng, TryRngCore as _};
+
+#[test]
+fn chacha20_poly1305_rfc8439_test_vector() -> Result<(), signal_crypto::Error> {
+    // RFC 8439, section 2.8.2
+    let key = hex!("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f");
+    let nonce = hex!("070000004041424344454647");
+    let aad = hex!("50515253c0c1c2c3c4c5c6c7");
+    let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+    let tag = hex!("1ae10b594f09e26a7e902ecbd0600691");
+
+    let mut enc = signal_crypto::ChaCha20Poly1305Encryption::new(&key, &nonce, &aad)?;
+    let mut buf = plaintext.to_vec();
+    enc.encrypt(&mut buf);
+    assert_eq!(hex::encode(enc.compute_tag()), hex::encode(tag));
+
+    let mut dec = signal_crypto::ChaCha20Poly1305Decryption::new(&key, &nonce, &aad)?;
+    dec.decrypt(&mut buf);
+    dec.verify_tag(&tag)?;
+    assert_eq!(buf, plaintext);
+
+    Ok(())
+}
+
+#[test]
+fn chacha20_poly1305_matches_one_shot() -> Result<(), signal_crypto::Error> {
+    let mut rng = rand::rngs::OsRng.unwrap_err();
+
+    for len in [0, 1, 15, 16, 17, 63, 64, 65, 1000] {
+        let key: [u8; 32] = rng.random();
+        let nonce: [u8; 12] = rng.random();
+        let aad = vec![0xAD; len % 37];
+        let mut plaintext = vec![0; len];
+        rng.fill(&mut plaintext[..]);
+
+        let expected = chacha20poly1305::ChaCha20Poly1305::new(&key.into())
+            .encrypt(
+                &nonce.into(),
+                Payload {
+                    msg: &plaintext,
+                    aad: &aad,
+                },
+            )
+            .expect("can encrypt");
+        let (expected_ct, expected_tag) = expected.split_at(len);
+
+        let mut enc = signal_crypto::ChaCha20Poly1305Encryption::new(&key, &nonce, &aad)?;
+        let mut dec = signal_crypto::ChaCha20Poly1305Decryption::new(&key, &nonce, &aad)?;
+        let mut enc_buf = plaintext.clone();
+        let mut dec_buf = expected_ct.to_vec();
+
+        // Process the message in randomly sized pieces.
+        let mut processed = 0;
+        while processed != len {
+            let this_time = rng.random_range(1..=len - processed);
+            enc.encrypt(&mut enc_buf[processed..processed + this_time]);
+            dec.decrypt(&mut dec_buf[processed..processed + this_time]);
+            processed += this_time;
+        }
+
+        assert_eq!(hex::encode(&enc_buf), hex::encode(expected_ct));
+        assert_eq!(hex::encode(enc.compute_tag()), hex::encode(expected_tag));
+        dec.verify_tag(expected_tag)?;
+        assert_eq!(dec_buf, plaintext);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn chacha20_poly1305_rejects_bad_tag() -> Result<(), signal_crypto::Error> {
+    let key = [0x42; 32];
+    let nonce = [0x24; 12];
+
+    let mut enc = signal_crypto::ChaCha20Poly1305Encryption::new(&key, &nonce, b"aad")?;
+    let mut buf = b"message".to_vec();
+    enc.encrypt(&mut buf);
+    let tag = enc.compute_tag();
+
+    let mut dec = signal_crypto::ChaCha20Poly1305Decryption::new(&key, &nonce, b"other aad")?;
+    dec.decrypt(&mut buf);
+    assert!(matches!(
+        dec.verify_tag(&tag),
+        Err(signal_crypto::Error::InvalidTag)
+    ));
+
+    assert!(matches!(
+        signal_crypto::ChaCha20Poly1305Encryption::new(&key, &nonce[..8], b""),
+        Err(signal_crypto::Error::InvalidNonceSize)
+    ));
+    assert!(matches!(
+        signal_crypto::ChaCha20Poly1305Encryption::new(&key[..16], &nonce, b""),
+        Err(signal_crypto::Error::InvalidKeySize)
+    ));
+
+    Ok(())
+}
@@ -21,6 +21,8 @@ use crate::backup::call::{AdHocCall, CallError};
 use crate::backup::chat::chat_style::{CustomChatColor, CustomColorId};
 use crate::backup::chat::{ChatData, ChatError, ChatItemData, ChatItemError, PinOrder};
 use crate::backup::chat_folder::{ChatFolder, ChatFolderError};
+pub use crate::backup::filter::BackupFilter;
+use crate::backup::filter::FrameFilter;
 use crate::backup::frame::{ChatId, RecipientId};
 use crate::backup::hashutil::{AssumedRandomInputHasher, HashBytesAllAtOnce};
 use crate::backup::method::{Lookup, LookupPair, Method};
@@ -41,7 +43,10 @@ mod account_data;
 mod call;
 mod chat;
 mod chat_folder;
+#[cfg(feature = "json")]
+pub mod diff;
 mod file;
+mod filter;
 mod frame;
 mod hashutil;
 pub(crate) mod method;
@@ -96,6 +101,7 @@ pub struct PartialBackup<M: Method + ReferencedTypes> {
     chat_folders: Vec<ChatFolder<M::RecipientReference>>,
     /// Stored here so PartialBackup can be the only context necessary for processing backup frames.
     unusual_timestamp_tracker: RefCell<UnusualTimestampTracker>,
+    filter: Option<FrameFilter>,
 }
 
 #[derive_where(Debug)]
@@ -226,6 +232,7 @@ impl<M: Method + ReferencedTypes> TryFrom<PartialBackup<M>> for CompletedBackup<
             notification_profiles,
             chat_folders,
             unusual_timestamp_tracker: _,
+            filter: _,
         } = value;
 
         let account_data = account_data.ok_or(CompletionError::MissingAccountData)?;
@@ -668,13 +675,29 @@ impl<M: Method + ReferencedTypes> PartialBackup<M> {
             notification_profiles: Default::default(),
             chat_folders: Default::default(),
             unusual_timestamp_tracker,
+            filter: None,
         })
     }
 
+    /// Skips or trims subsequent frames according to `filter`.
+    ///
+    /// Must be called before any frames are added.
+    pub fn set_filter(&mut self, filter: BackupFilter) {
+        self.filter = Some(FrameFilter::new(filter));
+    }
+
     pub fn add_frame(&mut self, frame: proto::Frame) -> Result<(), ValidationError> {
-        self.add_frame_item(frame.item.ok_or_else(|| {
+        let mut item = frame.item.ok_or_else(|| {
             ValidationError::EmptyFrame(HasUnknownFields::check(&frame.special_fields))
-        })?)
+        })?;
+        if self
+            .filter
+            .as_mut()
+            .is_some_and(|filter| !filter.keep(&mut item))
+        {
+            return Ok(());
+        }
+        self.add_frame_item(item)
     }
 
     fn add_frame_item(&mut self, item: FrameItem) -> Result<(), ValidationError> {
@@ -0,0 +1,528 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Structured comparison of two [`Backup`]s.
+//!
+//! The IDs used inside a backup file are only meaningful within that file, so entries are matched
+//! by their stable identities instead: recipients by ACI, PNI, E164, group master key, and so on;
+//! chats by their recipient; chat items by their author and sent timestamp; sticker packs,
+//! notification profiles, and chat folders by their IDs. Matched entries are then compared field
+//! by field using the same canonical representation as [`serialize::Backup`](super::serialize::Backup).
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use crate::backup::chat::{ChatData, ChatItemData};
+use crate::backup::chat_folder::ChatFolder;
+use crate::backup::method::Store;
+use crate::backup::recipient::{Destination, DistributionListItem, FullRecipientData};
+use crate::backup::{Backup, ChatsData, CompletedBackup};
+use crate::unknown::{FormatPath, PathPart};
+
+/// A single difference between two backups.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Difference {
+    pub path: Vec<PathPart>,
+    pub change: Change,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    Added(Value),
+    Removed(Value),
+    Changed { before: Value, after: Value },
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self { path, change } = self;
+        write!(f, "{}: ", FormatPath(path.as_slice()))?;
+        match change {
+            Change::Added(value) => write!(f, "added {value}"),
+            Change::Removed(value) => write!(f, "removed {value}"),
+            Change::Changed { before, after } => write!(f, "changed from {before} to {after}"),
+        }
+    }
+}
+
+/// Compares two backups, returning every difference found in `after` relative to `before`.
+///
+/// Differences are reported in a deterministic order: by top-level section, then by entry key.
+pub fn diff(before: &Backup, after: &Backup) -> Vec<Difference> {
+    let before = Entries::from(before);
+    let after = Entries::from(after);
+
+    let mut differ = Differ::default();
+    differ.field("meta", Some(&before.meta), Some(&after.meta));
+    differ.field(
+        "account_data",
+        Some(&before.account_data),
+        Some(&after.account_data),
+    );
+    differ.keyed("recipients", &before.recipients, &after.recipients);
+    differ.chats(&before.chats, &after.chats);
+    differ.field(
+        "pinned_chats",
+        Some(&before.pinned_chats),
+        Some(&after.pinned_chats),
+    );
+    differ.keyed("ad_hoc_calls", &before.ad_hoc_calls, &after.ad_hoc_calls);
+    differ.keyed("sticker_packs", &before.sticker_packs, &after.sticker_packs);
+    differ.keyed(
+        "notification_profiles",
+        &before.notification_profiles,
+        &after.notification_profiles,
+    );
+    differ.keyed("chat_folders", &before.chat_folders, &after.chat_folders);
+    differ.differences
+}
+
+/// The entries of a backup, keyed by stable identity.
+struct Entries {
+    meta: Value,
+    account_data: Value,
+    recipients: BTreeMap<String, Value>,
+    chats: BTreeMap<String, ChatEntry>,
+    pinned_chats: Value,
+    ad_hoc_calls: BTreeMap<String, Value>,
+    sticker_packs: BTreeMap<String, Value>,
+    notification_profiles: BTreeMap<String, Value>,
+    chat_folders: BTreeMap<String, Value>,
+}
+
+/// A chat, with its items split out so they can be matched individually.
+struct ChatEntry {
+    chat: Value,
+    items: BTreeMap<String, Value>,
+}
+
+impl From<&Backup> for Entries {
+    fn from(value: &Backup) -> Self {
+        let CompletedBackup {
+            meta,
+            account_data,
+            recipients,
+            chats:
+                ChatsData {
+                    items: chats,
+                    pinned,
+                    chat_items_count: _,
+                },
+            ad_hoc_calls,
+            sticker_packs,
+            notification_profiles,
+            chat_folders,
+        } = value;
+
+        Self {
+            meta: to_value(meta),
+            account_data: to_value(account_data),
+            recipients: keyed_entries(
+                recipients
+                    .iter()
+                    .map(|(_, recipient)| (recipient_key(recipient), to_value(recipient))),
+            ),
+            chats: keyed_entries(
+                chats
+                    .iter()
+                    .map(|(_, chat)| (recipient_key(&chat.recipient), ChatEntry::from(chat))),
+            ),
+            pinned_chats: pinned
+                .iter()
+                .map(|(_, recipient)| Value::String(recipient_key(recipient)))
+                .collect(),
+            ad_hoc_calls: keyed_entries(
+                ad_hoc_calls
+                    .iter()
+                    .map(|call| (key_from_value(to_value(call.id)), to_value(call))),
+            ),
+            sticker_packs: keyed_entries(
+                sticker_packs
+                    .iter()
+                    .map(|(id, pack)| (key_from_value(to_value(id)), to_value(pack))),
+            ),
+            notification_profiles: keyed_entries(
+                notification_profiles
+                    .iter()
+                    .map(|profile| (hex::encode(profile.id()), to_value(profile))),
+            ),
+            chat_folders: keyed_entries(chat_folders.iter().map(|folder| {
+                let key = match folder {
+                    ChatFolder::All => "ALL".to_owned(),
+                    ChatFolder::Custom { id, .. } => hex::encode(id),
+                };
+                (key, to_value(folder))
+            })),
+        }
+    }
+}
+
+impl From<&ChatData<Store>> for ChatEntry {
+    fn from(value: &ChatData<Store>) -> Self {
+        let mut chat = to_value(value);
+        let object = chat
+            .as_object_mut()
+            .expect("chats are serialized as structs");
+        // The recipient is already part of the key, and the items are compared separately.
+        object.remove("recipient");
+        object.remove("items");
+
+        let items = keyed_entries(value.items.iter().map(|item| {
+            let key = format!(
+                "{}@{}",
+                recipient_key(&item.author),
+                item.sent_at.as_millis()
+            );
+            (key, chat_item_value(item))
+        }));
+
+        Self { chat, items }
+    }
+}
+
+fn chat_item_value(item: &ChatItemData<Store>) -> Value {
+    let mut value = to_value(item);
+    let object = value
+        .as_object_mut()
+        .expect("chat items are serialized as structs");
+    // The author is already part of the key, and the position in the file isn't meaningful on its
+    // own (it changes whenever an earlier item is added or removed).
+    object.remove("author");
+    object.remove("total_chat_item_order_index");
+    value
+}
+
+/// A recipient's identity, stable across exports of the same account.
+fn recipient_key(recipient: &FullRecipientData) -> String {
+    match &**recipient {
+        Destination::Contact(contact) => {
+            if let Some(aci) = contact.aci {
+                aci.service_id_string()
+            } else if let Some(pni) = contact.pni {
+                pni.service_id_string()
+            } else if let Some(e164) = contact.e164 {
+                e164.to_string()
+            } else if let Some(username) = &contact.username {
+                format!("username:{username}")
+            } else {
+                "contact".to_owned()
+            }
+        }
+        Destination::Group(group) => format!("group:{}", hex::encode(group.master_key)),
+        Destination::DistributionList(
+            DistributionListItem::Deleted {
+                distribution_id, ..
+            }
+            | DistributionListItem::List {
+                distribution_id, ..
+            },
+        ) => format!("distribution_list:{distribution_id}"),
+        Destination::Self_(_) => "self".to_owned(),
+        Destination::ReleaseNotes => "release_notes".to_owned(),
+        Destination::CallLink(call_link) => {
+            format!("call_link:{}", hex::encode(&call_link.root_key))
+        }
+    }
+}
+
+fn to_value(value: impl serde::Serialize) -> Value {
+    serde_json::to_value(value).expect("can't fail serialization")
+}
+
+/// Uses a serialized ID as a key, without the quotes if it's a string.
+fn key_from_value(value: Value) -> String {
+    match value {
+        Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+/// Collects entries into a map, disambiguating any duplicate keys with a numeric suffix.
+///
+/// Duplicates are numbered in iteration order, so they only line up between backups if the
+/// entries were written in the same order.
+fn keyed_entries<T>(entries: impl IntoIterator<Item = (String, T)>) -> BTreeMap<String, T> {
+    let mut map = BTreeMap::new();
+    for (key, value) in entries {
+        let key = if map.contains_key(&key) {
+            (2..)
+                .map(|n| format!("{key}#{n}"))
+                .find(|key| !map.contains_key(key))
+                .expect("unbounded")
+        } else {
+            key
+        };
+        map.insert(key, value);
+    }
+    map
+}
+
+#[derive(Default)]
+struct Differ {
+    path: Vec<PathPart>,
+    differences: Vec<Difference>,
+}
+
+impl Differ {
+    fn with_part(&mut self, part: PathPart, f: impl FnOnce(&mut Self)) {
+        self.path.push(part);
+        f(self);
+        self.path.pop();
+    }
+
+    fn report(&mut self, change: Change) {
+        self.differences.push(Difference {
+            path: self.path.clone(),
+            change,
+        });
+    }
+
+    fn field(&mut self, field_name: &str, before: Option<&Value>, after: Option<&Value>) {
+        if before == after {
+            return;
+        }
+        if let (Some(Value::Array(before)), Some(Value::Array(after))) = (before, after) {
+            for index in 0..before.len().max(after.len()) {
+                let part = PathPart::Repeated {
+                    field_name: field_name.to_owned(),
+                    index,
+                };
+                self.with_part(part, |differ| {
+                    differ.value(before.get(index), after.get(index))
+                });
+            }
+            return;
+        }
+        let part = PathPart::Field {
+            field_name: field_name.to_owned(),
+        };
+        self.with_part(part, |differ| differ.value(before, after));
+    }
+
+    fn value(&mut self, before: Option<&Value>, after: Option<&Value>) {
+        match (before, after) {
+            (None, None) => {}
+            (Some(before), None) => self.report(Change::Removed(before.clone())),
+            (None, Some(after)) => self.report(Change::Added(after.clone())),
+            (Some(before), Some(after)) if before == after => {}
+            (Some(Value::Object(before)), Some(Value::Object(after))) => {
+                for (name, before_value) in before {
+                    self.field(name, Some(before_value), after.get(name));
+                }
+                for (name, after_value) in after {
+                    if !before.contains_key(name) {
+                        self.field(name, None, Some(after_value));
+                    }
+                }
+            }
+            (Some(before), Some(after)) => self.report(Change::Changed {
+                before: before.clone(),
+                after: after.clone(),
+            }),
+        }
+    }
+
+    fn keyed(
+        &mut self,
+        field_name: &str,
+        before: &BTreeMap<String, Value>,
+        after: &BTreeMap<String, Value>,
+    ) {
+        self.keyed_with(field_name, before, after, Self::value);
+    }
+
+    fn keyed_with<T>(
+        &mut self,
+        field_name: &str,
+        before: &BTreeMap<String, T>,
+        after: &BTreeMap<String, T>,
+        mut compare: impl FnMut(&mut Self, Option<&T>, Option<&T>),
+    ) {
+        let mut keys = before.keys().chain(after.keys()).collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let part = PathPart::MapValue {
+                field_name: field_name.to_owned(),
+                key: key.clone(),
+            };
+            self.with_part(part, |differ| {
+                compare(differ, before.get(key), after.get(key))
+            });
+        }
+    }
+
+    fn chats(&mut self, before: &BTreeMap<String, ChatEntry>, after: &BTreeMap<String, ChatEntry>) {
+        let no_items = BTreeMap::new();
+        self.keyed_with("chats", before, after, |differ, before, after| {
+            differ.value(
+                before.map(|entry| &entry.chat),
+                after.map(|entry| &entry.chat),
+            );
+            // Report every item of an added or removed chat too, so that no message goes missing
+            // without a mention.
+            differ.keyed(
+                "items",
+                before.map_or(&no_items, |entry| &entry.items),
+                after.map_or(&no_items, |entry| &entry.items),
+            );
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backup::{PartialBackup, Purpose};
+    use crate::proto::backup as proto;
+
+    fn backup_with(frames: impl IntoIterator<Item = proto::frame::Item>) -> Backup {
+        let info = proto::BackupInfo {
+            mediaRootBackupKey: vec![0; libsignal_account_keys::BACKUP_KEY_LEN],
+            ..Default::default()
+        };
+        let mut partial = PartialBackup::new_store(info, Purpose::RemoteBackup).expect("valid");
+        for frame in frames {
+            partial.add_frame_item(frame).expect("valid frame");
+        }
+        partial.try_into().expect("valid backup")
+    }
+
+    fn frames() -> Vec<proto::frame::Item> {
+        vec![
+            proto::AccountData::test_data().into(),
+            proto::Recipient::test_data_contact().into(),
+            proto::Recipient::test_data().into(),
+            proto::Chat::test_data().into(),
+            proto::ChatItem::test_data().into(),
+        ]
+    }
+
+    const CONTACT_KEY: &str = "aaaaaaaa-aaaa-aaaa-aaaa-aaaaaaaaaaaa";
+
+    #[test]
+    fn identical_backups_have_no_differences() {
+        assert_eq!(diff(&backup_with(frames()), &backup_with(frames())), []);
+    }
+
+    #[test]
+    fn reports_field_level_changes() {
+        let before = backup_with(frames());
+
+        let mut after_frames = frames();
+        for frame in &mut after_frames {
+            match frame {
+                proto::frame::Item::Chat(chat) => chat.archived = true,
+                proto::frame::Item::ChatItem(item) => {
+                    let Some(proto::chat_item::Item::StandardMessage(message)) = &mut item.item
+                    else {
+                        unreachable!("test data is a standard message");
+                    };
+                    message.text.as_mut().expect("has text").body = "TEST MESSAGE TEXT".to_owned();
+                }
+                _ => {}
+            }
+        }
+        let after = backup_with(after_frames);
+
+        let differences = diff(&before, &after);
+        let formatted = differences
+            .iter()
+            .map(|difference| FormatPath(difference.path.as_slice()).to_string())
+            .collect::<Vec<_>>();
+        let item_key = format!("{CONTACT_KEY}@{}", proto::ChatItem::test_data().dateSent);
+        assert_eq!(
+            formatted,
+            [
+                format!("chats[{CONTACT_KEY}].archived"),
+                format!("chats[{CONTACT_KEY}].items[{item_key}].message.Standard.text.text"),
+            ]
+        );
+        assert_eq!(
+            differences[0].change,
+            Change::Changed {
+                before: Value::Bool(false),
+                after: Value::Bool(true),
+            }
+        );
+        assert_eq!(
+            differences[1].to_string(),
+            format!(
+                "{}: changed from \"test message text\" to \"TEST MESSAGE TEXT\"",
+                formatted[1]
+            )
+        );
+    }
+
+    #[test]
+    fn reports_added_and_removed_entries() {
+        let before = backup_with(frames());
+
+        let mut after_frames = frames();
+        let proto::frame::Item::ChatItem(item) = after_frames.last_mut().expect("non-empty") else {
+            unreachable!("last frame is the chat item");
+        };
+        item.dateSent += 1;
+        let after = backup_with(after_frames);
+
+        let date_sent = proto::ChatItem::test_data().dateSent;
+        let changes = diff(&before, &after)
+            .into_iter()
+            .map(|Difference { path, change }| {
+                let kind = match change {
+                    Change::Added(_) => "added",
+                    Change::Removed(_) => "removed",
+                    Change::Changed { .. } => "changed",
+                };
+                (FormatPath(path.as_slice()).to_string(), kind)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            [
+                (
+                    format!("chats[{CONTACT_KEY}].items[{CONTACT_KEY}@{date_sent}]"),
+                    "removed"
+                ),
+                (
+                    format!(
+                        "chats[{CONTACT_KEY}].items[{CONTACT_KEY}@{}]",
+                        date_sent + 1
+                    ),
+                    "added"
+                ),
+            ]
+        );
+
+        let empty = backup_with([proto::AccountData::test_data().into()]);
+        let removed = diff(&before, &empty);
+        assert!(
+            removed.iter().any(|difference| {
+                FormatPath(difference.path.as_slice()).to_string()
+                    == format!("recipients[{CONTACT_KEY}]")
+                    && matches!(difference.change, Change::Removed(_))
+            }),
+            "{removed:#?}"
+        );
+    }
+
+    #[test]
+    fn duplicate_keys_are_numbered() {
+        let map = keyed_entries([
+            ("a".to_owned(), 1),
+            ("a".to_owned(), 2),
+            ("a#2".to_owned(), 3),
+        ]);
+        assert_eq!(
+            map.into_iter().collect::<Vec<_>>(),
+            [
+                ("a".to_owned(), 1),
+                ("a#2".to_owned(), 2),
+                ("a#2#2".to_owned(), 3)
+            ]
+        );
+    }
+}
@@ -0,0 +1,370 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
+use crate::proto::backup as proto;
+use crate::proto::backup::frame::Item as FrameItem;
+
+/// Which parts of a backup to keep while reading it.
+///
+/// Everything is kept by default. Recipients, account data, sticker packs, notification profiles,
+/// and chat folders are always kept, since they can be referenced from anywhere in the backup; this
+/// guarantees that the filtered backup is still valid.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackupFilter {
+    /// If set, only chats (and ad hoc calls) with these recipients are kept.
+    ///
+    /// These are the IDs used within the backup file, not ACIs or other stable identifiers.
+    pub recipient_ids: Option<HashSet<u64>>,
+    /// If set, only chats with these IDs are kept.
+    pub chat_ids: Option<HashSet<u64>>,
+    /// The range of chat item sent timestamps and ad hoc call timestamps to keep, in milliseconds
+    /// since the epoch.
+    pub time_range: RangeInclusive<u64>,
+    /// If unset, attachments are removed from messages, and messages that had nothing but
+    /// attachments are dropped.
+    ///
+    /// Attachments here include long message text, link preview images, quoted attachment
+    /// thumbnails, stickers, and shared contacts' avatars.
+    pub include_attachments: bool,
+    /// If unset, ad hoc calls and individual and group call updates are dropped.
+    pub include_call_logs: bool,
+}
+
+impl Default for BackupFilter {
+    fn default() -> Self {
+        Self {
+            recipient_ids: None,
+            chat_ids: None,
+            time_range: 0..=u64::MAX,
+            include_attachments: true,
+            include_call_logs: true,
+        }
+    }
+}
+
+/// Applies a [`BackupFilter`] to a stream of frames.
+///
+/// Chat items can only be kept if their chat was, so this has to see every frame in order.
+#[derive(Debug)]
+pub(crate) struct FrameFilter {
+    filter: BackupFilter,
+    kept_chat_ids: HashSet<u64>,
+}
+
+impl FrameFilter {
+    pub(crate) fn new(filter: BackupFilter) -> Self {
+        Self {
+            filter,
+            kept_chat_ids: HashSet::new(),
+        }
+    }
+
+    /// Returns whether `item` should be kept, removing any filtered-out parts of it first.
+    pub(crate) fn keep(&mut self, item: &mut FrameItem) -> bool {
+        let BackupFilter {
+            recipient_ids,
+            chat_ids,
+            time_range,
+            include_attachments,
+            include_call_logs,
+        } = &self.filter;
+        let is_allowed_recipient =
+            |id: u64| recipient_ids.as_ref().is_none_or(|ids| ids.contains(&id));
+
+        match item {
+            FrameItem::Account(_)
+            | FrameItem::Recipient(_)
+            | FrameItem::StickerPack(_)
+            | FrameItem::NotificationProfile(_)
+            | FrameItem::ChatFolder(_) => true,
+            FrameItem::Chat(chat) => {
+                let keep = is_allowed_recipient(chat.recipientId)
+                    && chat_ids.as_ref().is_none_or(|ids| ids.contains(&chat.id));
+                if keep {
+                    self.kept_chat_ids.insert(chat.id);
+                }
+                keep
+            }
+            FrameItem::ChatItem(chat_item) => {
+                if !self.kept_chat_ids.contains(&chat_item.chatId)
+                    || !time_range.contains(&chat_item.dateSent)
+                    || (!include_call_logs && is_call(chat_item))
+                {
+                    return false;
+                }
+                if !include_attachments {
+                    if !remove_attachments(chat_item) {
+                        return false;
+                    }
+                    chat_item.revisions.retain_mut(remove_attachments);
+                }
+                true
+            }
+            FrameItem::AdHocCall(call) => {
+                *include_call_logs
+                    && is_allowed_recipient(call.recipientId)
+                    && time_range.contains(&call.callTimestamp)
+            }
+        }
+    }
+}
+
+fn is_call(chat_item: &proto::ChatItem) -> bool {
+    use proto::chat_update_message::Update;
+
+    let Some(proto::chat_item::Item::UpdateMessage(message)) = &chat_item.item else {
+        return false;
+    };
+    matches!(
+        message.update,
+        Some(Update::IndividualCall(_) | Update::GroupCall(_))
+    )
+}
+
+/// Removes attachments from `chat_item`, returning whether anything is left.
+///
+/// Besides message attachments, this removes long text, link preview images, quoted attachment
+/// thumbnails, and shared contacts' avatars. A sticker is nothing but an attachment, so sticker
+/// messages are always dropped.
+fn remove_attachments(chat_item: &mut proto::ChatItem) -> bool {
+    use proto::chat_item::Item;
+    use proto::direct_story_reply_message::Reply;
+
+    match &mut chat_item.item {
+        Some(Item::StandardMessage(message)) => {
+            message.attachments.clear();
+            message.longText.clear();
+            for preview in &mut message.linkPreview {
+                preview.image.clear();
+            }
+            if let Some(quote) = message.quote.as_mut() {
+                for attachment in &mut quote.attachments {
+                    attachment.thumbnail.clear();
+                }
+            }
+            message.text.is_some()
+        }
+        Some(Item::ContactMessage(message)) => {
+            if let Some(contact) = message.contact.as_mut() {
+                contact.avatar.clear();
+            }
+            true
+        }
+        Some(Item::DirectStoryReplyMessage(message)) => {
+            if let Some(Reply::TextReply(reply)) = &mut message.reply {
+                reply.longText.clear();
+            }
+            true
+        }
+        Some(Item::StickerMessage(_)) => false,
+        Some(Item::ViewOnceMessage(message)) => {
+            // This is how an already-viewed message is represented.
+            message.attachment.clear();
+            true
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+    use crate::backup::method::Store;
+    use crate::backup::{CompletedBackup, PartialBackup, Purpose};
+
+    fn chat_item_without_text() -> proto::ChatItem {
+        let mut chat_item = proto::ChatItem::test_data();
+        let Some(proto::chat_item::Item::StandardMessage(message)) = &mut chat_item.item else {
+            unreachable!("test data is a standard message");
+        };
+        message.text.clear();
+        message.longText.clear();
+        chat_item
+    }
+
+    fn read_filtered(
+        filter: BackupFilter,
+        frames: impl IntoIterator<Item = FrameItem>,
+    ) -> CompletedBackup<Store> {
+        let info = proto::BackupInfo {
+            mediaRootBackupKey: vec![0; libsignal_account_keys::BACKUP_KEY_LEN],
+            ..Default::default()
+        };
+        let mut partial = PartialBackup::new_store(info, Purpose::RemoteBackup).expect("valid");
+        partial.set_filter(filter);
+        for frame in frames {
+            partial
+                .add_frame(proto::Frame {
+                    item: Some(frame),
+                    ..Default::default()
+                })
+                .expect("valid frame");
+        }
+        partial.try_into().expect("valid backup")
+    }
+
+    fn frames() -> Vec<FrameItem> {
+        vec![
+            proto::AccountData::test_data().into(),
+            proto::Recipient::test_data_contact().into(),
+            proto::Recipient::test_data().into(),
+            proto::Chat::test_data().into(),
+            proto::ChatItem::test_data().into(),
+            chat_item_without_text().into(),
+        ]
+    }
+
+    #[test]
+    fn default_keeps_everything() {
+        let backup = read_filtered(BackupFilter::default(), frames());
+        assert_eq!(backup.chats.chat_items_count, 2);
+        assert_eq!(backup.recipients.len(), 2);
+    }
+
+    #[test_case(BackupFilter { chat_ids: Some(HashSet::from([proto::Chat::TEST_ID])), ..Default::default() }, 2; "matching chat ID")]
+    #[test_case(BackupFilter { chat_ids: Some(HashSet::new()), ..Default::default() }, 0; "no chat IDs")]
+    #[test_case(BackupFilter { recipient_ids: Some(HashSet::from([proto::Recipient::TEST_ID])), ..Default::default() }, 0; "other recipient")]
+    fn filters_chats(filter: BackupFilter, expected_items: usize) {
+        let backup = read_filtered(filter, frames());
+        assert_eq!(backup.chats.chat_items_count, expected_items);
+        assert_eq!(backup.chats.items.len(), usize::from(expected_items > 0));
+        // Recipients are kept regardless.
+        assert_eq!(backup.recipients.len(), 2);
+    }
+
+    #[test]
+    fn filters_chat_items_by_time() {
+        let sent = proto::ChatItem::test_data().dateSent;
+        let mut later = proto::ChatItem::test_data();
+        later.dateSent = sent + 1000;
+        let mut frames = frames();
+        frames.push(later.into());
+
+        let backup = read_filtered(
+            BackupFilter {
+                time_range: sent + 1..=u64::MAX,
+                ..Default::default()
+            },
+            frames,
+        );
+        assert_eq!(backup.chats.chat_items_count, 1);
+    }
+
+    #[test]
+    fn removes_attachments() {
+        let backup = read_filtered(
+            BackupFilter {
+                include_attachments: false,
+                ..Default::default()
+            },
+            frames(),
+        );
+        assert_eq!(backup.chats.chat_items_count, 1);
+
+        let mut chat_item = proto::ChatItem::test_data();
+        let Some(proto::chat_item::Item::StandardMessage(message)) = &mut chat_item.item else {
+            unreachable!("test data is a standard message");
+        };
+        message.linkPreview.push(proto::LinkPreview {
+            url: "https://signal.org".into(),
+            image: Some(proto::FilePointer::minimal_test_data()).into(),
+            ..Default::default()
+        });
+        let mut chat_item = FrameItem::from(chat_item);
+        let mut filter = FrameFilter::new(BackupFilter {
+            include_attachments: false,
+            ..Default::default()
+        });
+        assert!(filter.keep(&mut proto::Chat::test_data().into()));
+        assert!(filter.keep(&mut chat_item));
+        let FrameItem::ChatItem(proto::ChatItem {
+            item: Some(proto::chat_item::Item::StandardMessage(message)),
+            ..
+        }) = chat_item
+        else {
+            unreachable!("still a standard message");
+        };
+        assert_eq!(message.attachments, []);
+        assert_eq!(message.longText.as_ref(), None);
+        assert_eq!(message.linkPreview[0].image.as_ref(), None);
+        let quote = message.quote.as_ref().expect("still quoted");
+        assert_eq!(quote.attachments[0].thumbnail.as_ref(), None);
+        // The text is kept.
+        assert!(message.text.is_some());
+    }
+
+    #[test]
+    fn removes_long_text_from_story_replies() {
+        use proto::direct_story_reply_message::{Reply, TextReply};
+
+        let mut chat_item = proto::ChatItem {
+            item: Some(proto::chat_item::Item::DirectStoryReplyMessage(
+                proto::DirectStoryReplyMessage {
+                    reply: Some(Reply::TextReply(TextReply {
+                        text: Some(proto::Text::test_data()).into(),
+                        longText: Some(proto::FilePointer::minimal_test_data()).into(),
+                        ..Default::default()
+                    })),
+                    ..Default::default()
+                },
+            )),
+            ..proto::ChatItem::test_data()
+        };
+        assert!(remove_attachments(&mut chat_item));
+        let Some(proto::chat_item::Item::DirectStoryReplyMessage(proto::DirectStoryReplyMessage {
+            reply: Some(Reply::TextReply(reply)),
+            ..
+        })) = chat_item.item
+        else {
+            unreachable!("still a text reply");
+        };
+        assert_eq!(reply.longText.as_ref(), None);
+        assert!(reply.text.is_some());
+    }
+
+    #[test]
+    fn drops_sticker_messages_without_attachments() {
+        let mut chat_item = proto::ChatItem {
+            item: Some(proto::chat_item::Item::StickerMessage(Default::default())),
+            ..proto::ChatItem::test_data()
+        };
+        assert!(!remove_attachments(&mut chat_item));
+    }
+
+    #[test]
+    fn removes_call_logs() {
+        let mut call = FrameItem::from(proto::ChatItem {
+            item: Some(proto::chat_item::Item::UpdateMessage(
+                proto::ChatUpdateMessage {
+                    update: Some(proto::chat_update_message::Update::IndividualCall(
+                        Default::default(),
+                    )),
+                    ..Default::default()
+                },
+            )),
+            ..proto::ChatItem::test_data()
+        });
+        let mut ad_hoc_call = FrameItem::from(proto::AdHocCall::default());
+
+        let mut filter = FrameFilter::new(BackupFilter::default());
+        assert!(filter.keep(&mut proto::Chat::test_data().into()));
+        assert!(filter.keep(&mut call));
+        assert!(filter.keep(&mut ad_hoc_call));
+
+        let mut filter = FrameFilter::new(BackupFilter {
+            include_call_logs: false,
+            ..Default::default()
+        });
+        assert!(filter.keep(&mut proto::Chat::test_data().into()));
+        assert!(!filter.keep(&mut call));
+        assert!(!filter.keep(&mut ad_hoc_call));
+        assert!(filter.keep(&mut proto::ChatItem::test_data().into()));
+    }
+}
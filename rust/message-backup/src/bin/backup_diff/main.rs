@@ -0,0 +1,116 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+use clap::Parser;
+use libsignal_message_backup::backup::diff::diff;
+use libsignal_message_backup::backup::{Backup, Purpose};
+use libsignal_message_backup::frame::ReaderFactory as _;
+use libsignal_message_backup::key::MessageBackupKey;
+use libsignal_message_backup::{BackupReader, ReadResult};
+
+#[path = "../support/mod.rs"]
+mod support;
+use support::{AsyncReaderFactory, FilenameOrContents, KeyArgs};
+
+/// Compares two message backup files, printing each difference to stdout.
+///
+/// Recipients, chats, chat items, sticker packs, notification profiles, and
+/// chat folders are matched by their identities rather than by their IDs
+/// within each file, so a backup can be compared with one re-exported after
+/// restoring it. Exits with a nonzero status if any differences were found.
+///
+/// If no keys are provided, both backups are assumed to be sequences of
+/// varint-delimited protos. Otherwise, both are decrypted with the same keys.
+#[derive(Debug, Parser)]
+struct Cli {
+    /// the original backup
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    before: String,
+
+    /// the backup to compare against the original
+    #[arg(value_hint = clap::ValueHint::FilePath)]
+    after: String,
+
+    /// the purpose the backups are intended for
+    #[arg(long, default_value_t=Purpose::RemoteBackup)]
+    purpose: Purpose,
+
+    #[command(flatten)]
+    key_args: KeyArgs,
+}
+
+fn main() {
+    futures::executor::block_on(async_main())
+}
+
+async fn async_main() {
+    let Cli {
+        before,
+        after,
+        purpose,
+        key_args,
+    } = Cli::parse();
+    env_logger::init();
+
+    let key = key_args.into_key();
+
+    let before = read_backup(before, key.as_ref(), purpose).await;
+    let after = read_backup(after, key.as_ref(), purpose).await;
+
+    let differences = diff(&before, &after);
+    for difference in &differences {
+        println!("{difference}");
+    }
+    if !differences.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+async fn read_backup(filename: String, key: Option<&MessageBackupKey>, purpose: Purpose) -> Backup {
+    let contents = FilenameOrContents::Filename(filename);
+    let mut factory = AsyncReaderFactory::from(&contents);
+
+    let ReadResult {
+        result,
+        found_unknown_fields,
+    } = if let Some(key) = key {
+        BackupReader::new_encrypted_compressed(key, factory, purpose)
+            .await
+            .unwrap_or_else(|e| panic!("invalid encrypted backup: {e:#}"))
+            .read_all()
+            .await
+    } else {
+        BackupReader::new_unencrypted(factory.make_reader().expect("failed to read"), purpose)
+            .read_all()
+            .await
+    };
+
+    for field in found_unknown_fields {
+        log::warn!("unknown field: {field}");
+    }
+    result.unwrap_or_else(|e| panic!("backup error: {e:#}"))
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+
+    use super::*;
+
+    const EXECUTABLE_NAME: &str = "backup_diff_bin";
+
+    #[test]
+    fn cli_parse_requires_two_files() {
+        let e = assert_matches!(Cli::try_parse_from([EXECUTABLE_NAME, "before"]), Err(e) => e);
+        assert_eq!(e.kind(), clap::error::ErrorKind::MissingRequiredArgument);
+        assert!(e.to_string().contains("<AFTER>"), "{e}");
+
+        let (before, after) = assert_matches!(
+            Cli::try_parse_from([EXECUTABLE_NAME, "before", "after"]),
+            Ok(Cli { before, after, purpose: Purpose::RemoteBackup, key_args: _ }) => (before, after)
+        );
+        assert_eq!((before.as_str(), after.as_str()), ("before", "after"));
+    }
+}
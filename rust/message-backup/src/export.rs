@@ -5,7 +5,8 @@
 
 //! Utilities for exporting backups.
 //!
-//! See `encrypt_backup` or `generation/mod.rs` for how they fit together.
+//! See `encrypt_backup` or `generation/mod.rs` for how they fit together, or
+//! [`BackupWriter`](crate::BackupWriter) to do all of this incrementally.
 
 use aes::cipher::{BlockEncryptMut as _, BlockSizeUser as _, KeyIvInit as _};
 use async_compression::futures::bufread::GzipEncoder;
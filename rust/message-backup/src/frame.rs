@@ -26,10 +26,11 @@ mod mac_read;
 mod reader_factory;
 mod unpad;
 
+pub use aes_read::AES_IV_SIZE;
 #[cfg(feature = "test-util")]
 pub use aes_read::AES_KEY_SIZE;
 #[cfg_attr(feature = "test-util", visibility::make(pub))]
-use aes_read::{AES_IV_SIZE, Aes256CbcReader};
+use aes_read::Aes256CbcReader;
 #[cfg_attr(feature = "test-util", visibility::make(pub))]
 use mac_read::MacReader;
 pub use reader_factory::{CursorFactory, FileReaderFactory, LimitedReaderFactory, ReaderFactory};
@@ -5,7 +5,7 @@
 
 //! Signal remote message backup utilities.
 //!
-//! Contains code to read and validate message backup files.
+//! Contains code to read, validate, and write message backup files.
 
 #![warn(clippy::unwrap_used)]
 
@@ -16,7 +16,7 @@ use mediasan_common::AsyncSkip;
 use protobuf::Message as _;
 
 use crate::backup::method::{Store, ValidateOnly};
-use crate::backup::{CompletedBackup, Purpose};
+use crate::backup::{BackupFilter, CompletedBackup, Purpose};
 use crate::frame::{
     HmacMismatchError, ReaderFactory, UnvalidatedHmacReader, VerifyHmac, VerifyHmacError,
 };
@@ -28,8 +28,13 @@ pub mod args;
 pub mod backup;
 pub mod frame;
 pub mod key;
+pub mod merge;
 pub mod parse;
+pub mod stats;
 pub mod unknown;
+pub mod writer;
+
+pub use writer::{BackupWriter, WriteError};
 
 #[cfg(feature = "json")]
 pub mod json;
@@ -50,6 +55,8 @@ pub struct BackupReader<R> {
     purpose: Purpose,
     reader: VarintDelimitedReader<R>,
     pub visitor: fn(&dyn std::fmt::Debug),
+    /// If set, only the matching parts of the backup are read.
+    pub filter: Option<BackupFilter>,
 }
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
@@ -184,10 +191,12 @@ impl<R: AsyncRead + Unpin + VerifyHmac> BackupReader<R> {
             reader,
             visitor,
             purpose,
+            filter,
         } = self;
 
         let mut found_unknown_fields = Vec::new();
-        let result = read_all_frames(purpose, reader, visitor, &mut found_unknown_fields).await;
+        let result =
+            read_all_frames(purpose, reader, visitor, filter, &mut found_unknown_fields).await;
         ReadResult {
             found_unknown_fields,
             result,
@@ -202,6 +211,7 @@ impl<R: AsyncRead + Unpin> BackupReader<UnvalidatedHmacReader<R>> {
             reader,
             purpose,
             visitor: |_| (),
+            filter: None,
         }
     }
 }
@@ -217,6 +227,7 @@ impl<R: AsyncRead + AsyncSkip + Unpin> BackupReader<frame::FramesReader<R>> {
             reader: VarintDelimitedReader::new(reader),
             purpose,
             visitor: |_| (),
+            filter: None,
         })
     }
 }
@@ -225,6 +236,7 @@ async fn read_all_frames<M: backup::method::Method + backup::ReferencedTypes>(
     purpose: Purpose,
     mut reader: VarintDelimitedReader<impl AsyncRead + Unpin + VerifyHmac>,
     mut visitor: impl FnMut(&dyn std::fmt::Debug) + Send + 'static,
+    filter: Option<BackupFilter>,
     unknown_fields: &mut Vec<FoundUnknownField>,
 ) -> Result<backup::PartialBackup<M>, Error>
 where
@@ -249,6 +261,9 @@ where
     add_found_unknown(unknown_fields, backup_info.collect_unknown_fields(), 0);
 
     let mut backup = backup::PartialBackup::new(backup_info, purpose)?;
+    if let Some(filter) = filter {
+        backup.set_filter(filter);
+    }
 
     // From here on we split the work into two separate threads:
     // - this thread, which reads frames from the reader
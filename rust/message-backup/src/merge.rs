@@ -0,0 +1,1247 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Merging two backups of the same account into one.
+//!
+//! This is for users who ended up with two backups, for example by using two devices or by
+//! re-registering. The main entry point is [`merge`].
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use futures::AsyncRead;
+use protobuf::Message as _;
+
+use crate::backup::method::ValidateOnly;
+use crate::backup::{CompletedBackup, PartialBackup, Purpose};
+use crate::parse::VarintDelimitedReader;
+use crate::proto::backup as proto;
+use crate::proto::backup::frame::Item as FrameItem;
+
+/// Which backup's [`AccountData`](proto::AccountData) to keep.
+///
+/// Account data can't be combined field by field, so one backup's copy is kept as a whole. The
+/// only exception is custom chat colors, which are combined so that chats from either backup keep
+/// their colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccountDataPolicy {
+    First,
+    Second,
+    /// Keep the account data from the backup with the later `backupTimeMs`, preferring the first
+    /// backup if they're the same.
+    Newer,
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum MergeError {
+    /// first backup is invalid: {0}
+    InvalidFirst(crate::Error),
+    /// second backup is invalid: {0}
+    InvalidSecond(crate::Error),
+    /// merged backup is invalid: {0}
+    InvalidOutput(crate::Error),
+}
+
+/// Merges two plaintext backups, producing a plaintext backup with the contents of both.
+///
+/// Both inputs must be valid for `purpose`. The output has the same format (a varint-delimited
+/// `BackupInfo` followed by varint-delimited frames), and is checked with the same validation used
+/// when reading a backup before it's returned.
+///
+/// - Recipients in the second backup are matched to those in the first by ACI, PNI, E164, or
+///   username for contacts (in that order), by master key for groups, by distribution ID for
+///   distribution lists, and by root key for call links. Contacts that both have ACIs are only
+///   matched by ACI, and contacts that both have PNIs are only matched by ACI or PNI. Unmatched
+///   recipients are added with new IDs, without any PNI, E164, or username that already belongs to
+///   another recipient.
+/// - Chats are matched by their (merged) recipient, keeping the first backup's chat settings.
+///   Pinned chats from the second backup are ordered after those from the first.
+/// - Chat items are interleaved by `dateSent`, keeping the relative order of each backup's items.
+///   Items with the same chat, author, and `dateSent` as one in the first backup are dropped.
+/// - Ad hoc calls, sticker packs, notification profiles, and chat folders are deduplicated by
+///   their identifiers.
+/// - The `BackupInfo` comes from the same backup as the account data (see [`AccountDataPolicy`]),
+///   with the later of the two backup times. In particular, this means media from the other
+///   backup will only be found if both backups use the same media root key.
+pub async fn merge(
+    first: impl AsyncRead + Unpin,
+    second: impl AsyncRead + Unpin,
+    purpose: Purpose,
+    policy: AccountDataPolicy,
+) -> Result<Vec<u8>, MergeError> {
+    let first = ParsedBackup::read(first, purpose)
+        .await
+        .map_err(MergeError::InvalidFirst)?;
+    let second = ParsedBackup::read(second, purpose)
+        .await
+        .map_err(MergeError::InvalidSecond)?;
+
+    let (backup_info, frames) = merge_parsed(first, second, policy);
+    write_validated(backup_info, frames, purpose).map_err(MergeError::InvalidOutput)
+}
+
+/// The frames of a validated backup, grouped by type.
+struct ParsedBackup {
+    backup_info: proto::BackupInfo,
+    account_data: proto::AccountData,
+    recipients: Vec<proto::Recipient>,
+    chats: Vec<proto::Chat>,
+    chat_items: Vec<proto::ChatItem>,
+    ad_hoc_calls: Vec<proto::AdHocCall>,
+    sticker_packs: Vec<proto::StickerPack>,
+    notification_profiles: Vec<proto::NotificationProfile>,
+    chat_folders: Vec<proto::ChatFolder>,
+}
+
+impl ParsedBackup {
+    async fn read(reader: impl AsyncRead + Unpin, purpose: Purpose) -> Result<Self, crate::Error> {
+        let mut reader = VarintDelimitedReader::new(reader);
+        let raw_backup_info = reader
+            .read_next()
+            .await
+            .map_err(crate::Error::Parse)?
+            .ok_or(crate::Error::NoFrames)?;
+        let backup_info = proto::BackupInfo::parse_from_bytes(&raw_backup_info)?;
+        let mut validator = PartialBackup::<ValidateOnly>::new(backup_info.clone(), purpose)?;
+
+        let mut account_data = None;
+        let mut recipients = vec![];
+        let mut chats = vec![];
+        let mut chat_items = vec![];
+        let mut ad_hoc_calls = vec![];
+        let mut sticker_packs = vec![];
+        let mut notification_profiles = vec![];
+        let mut chat_folders = vec![];
+
+        while let Some(raw_frame) = reader.read_next().await.map_err(crate::Error::Parse)? {
+            // Any unknown fields are preserved in the merged output.
+            let frame = proto::Frame::parse_from_bytes(&raw_frame)?;
+            let item = frame.item.clone();
+            validator.add_frame(frame)?;
+
+            match item.expect("validated frames are not empty") {
+                FrameItem::Account(item) => account_data = Some(item),
+                FrameItem::Recipient(item) => recipients.push(item),
+                FrameItem::Chat(item) => chats.push(item),
+                FrameItem::ChatItem(item) => chat_items.push(item),
+                FrameItem::AdHocCall(item) => ad_hoc_calls.push(item),
+                FrameItem::StickerPack(item) => sticker_packs.push(item),
+                FrameItem::NotificationProfile(item) => notification_profiles.push(item),
+                FrameItem::ChatFolder(item) => chat_folders.push(item),
+            }
+        }
+
+        let _: CompletedBackup<ValidateOnly> = validator.try_into()?;
+
+        Ok(Self {
+            backup_info,
+            account_data: account_data.expect("completed backups have account data"),
+            recipients,
+            chats,
+            chat_items,
+            ad_hoc_calls,
+            sticker_packs,
+            notification_profiles,
+            chat_folders,
+        })
+    }
+}
+
+fn merge_parsed(
+    first: ParsedBackup,
+    second: ParsedBackup,
+    policy: AccountDataPolicy,
+) -> (proto::BackupInfo, Vec<FrameItem>) {
+    let mut first_ids = IdMap::default();
+    let mut second_ids = IdMap::default();
+
+    let use_second = match policy {
+        AccountDataPolicy::First => false,
+        AccountDataPolicy::Second => true,
+        AccountDataPolicy::Newer => {
+            second.backup_info.backupTimeMs > first.backup_info.backupTimeMs
+        }
+    };
+    let backup_time_ms = first
+        .backup_info
+        .backupTimeMs
+        .max(second.backup_info.backupTimeMs);
+    let (mut backup_info, account_data) = if use_second {
+        let (backup_info, mut account_data) = (second.backup_info, second.account_data);
+        first_ids.custom_colors = merge_custom_colors(&mut account_data, &first.account_data);
+        (backup_info, account_data)
+    } else {
+        let (backup_info, mut account_data) = (first.backup_info, first.account_data);
+        second_ids.custom_colors = merge_custom_colors(&mut account_data, &second.account_data);
+        (backup_info, account_data)
+    };
+    backup_info.backupTimeMs = backup_time_ms;
+
+    // The first backup's recipients and chats keep their IDs; the second's are either matched to
+    // existing ones or added after them.
+    let mut recipients = first.recipients;
+    let mut recipients_by_key = HashMap::new();
+    let mut keys_by_id = HashMap::new();
+    for recipient in &recipients {
+        let keys = RecipientKey::all_for(recipient);
+        for key in &keys {
+            recipients_by_key.entry(key.clone()).or_insert(recipient.id);
+        }
+        keys_by_id.insert(recipient.id, keys);
+    }
+    let mut next_recipient_id = next_id(recipients.iter().map(|r| r.id));
+    let mut new_recipients = vec![];
+    for mut recipient in second.recipients {
+        let original_id = recipient.id;
+        let keys = RecipientKey::all_for(&recipient);
+        let existing_id = keys.iter().find_map(|key| {
+            recipients_by_key
+                .get(key)
+                .filter(|id| key.can_match(&keys, &keys_by_id[*id]))
+        });
+        let merged_id = match existing_id {
+            Some(&existing_id) => existing_id,
+            None => {
+                let new_id = next_recipient_id;
+                next_recipient_id += 1;
+                // A contact that wasn't matched can still share a weaker key with an existing
+                // one, which the output isn't allowed to have twice.
+                for key in &keys {
+                    if recipients_by_key.contains_key(key) {
+                        key.remove_from(&mut recipient);
+                    }
+                }
+                let keys = RecipientKey::all_for(&recipient);
+                for key in &keys {
+                    recipients_by_key.insert(key.clone(), new_id);
+                }
+                keys_by_id.insert(new_id, keys);
+                new_recipients.push(recipient);
+                new_id
+            }
+        };
+        second_ids.recipients.insert(original_id, merged_id);
+    }
+    // Distribution lists refer to other recipients, so IDs can only be remapped once every
+    // recipient has been assigned one.
+    recipients.remap_ids(&first_ids);
+    new_recipients.remap_ids(&second_ids);
+    recipients.extend(new_recipients);
+
+    let mut chats = first.chats;
+    let mut chats_by_recipient: HashMap<u64, u64> = chats
+        .iter()
+        .map(|chat| (chat.recipientId, chat.id))
+        .collect();
+    let mut next_chat_id = next_id(chats.iter().map(|chat| chat.id));
+    let last_pinned_order = chats
+        .iter()
+        .filter_map(|chat| chat.pinnedOrder)
+        .max()
+        .unwrap_or(0);
+    let mut new_chats = vec![];
+    for mut chat in second.chats {
+        let original_id = chat.id;
+        let mut recipient_id = chat.recipientId;
+        second_ids.recipient(&mut recipient_id);
+        let merged_id = match chats_by_recipient.entry(recipient_id) {
+            Entry::Occupied(existing) => *existing.get(),
+            Entry::Vacant(entry) => {
+                let new_id = *entry.insert(next_chat_id);
+                next_chat_id += 1;
+                if let Some(pinned_order) = &mut chat.pinnedOrder {
+                    *pinned_order = pinned_order.saturating_add(last_pinned_order);
+                }
+                new_chats.push(chat);
+                new_id
+            }
+        };
+        second_ids.chats.insert(original_id, merged_id);
+    }
+    chats.remap_ids(&first_ids);
+    new_chats.remap_ids(&second_ids);
+    chats.extend(new_chats);
+
+    let chat_items = {
+        let mut first_items = first.chat_items;
+        first_items.remap_ids(&first_ids);
+        let mut second_items = second.chat_items;
+        second_items.remap_ids(&second_ids);
+        let second_items = without_duplicates(&first_items, second_items, |item| {
+            (item.chatId, item.authorId, item.dateSent)
+        });
+        interleave_by_date_sent(first_items, second_items)
+    };
+
+    let ad_hoc_calls = merge_lists(
+        first.ad_hoc_calls,
+        second.ad_hoc_calls,
+        (&first_ids, &second_ids),
+        |call| (call.callId, call.recipientId),
+    );
+    let sticker_packs = merge_lists(
+        first.sticker_packs,
+        second.sticker_packs,
+        (&first_ids, &second_ids),
+        |pack| pack.packId.clone(),
+    );
+    let notification_profiles = merge_lists(
+        first.notification_profiles,
+        second.notification_profiles,
+        (&first_ids, &second_ids),
+        |profile| profile.id.clone(),
+    );
+    // There can only be one "All chats" folder.
+    let chat_folders = merge_lists(
+        first.chat_folders,
+        second.chat_folders,
+        (&first_ids, &second_ids),
+        |folder| {
+            (folder.folderType.enum_value_or_default() != proto::chat_folder::FolderType::ALL)
+                .then(|| folder.id.clone())
+        },
+    );
+
+    let frames = std::iter::once(FrameItem::from(account_data))
+        .chain(recipients.into_iter().map(FrameItem::from))
+        .chain(chats.into_iter().map(FrameItem::from))
+        .chain(chat_items.into_iter().map(FrameItem::from))
+        .chain(ad_hoc_calls.into_iter().map(FrameItem::from))
+        .chain(sticker_packs.into_iter().map(FrameItem::from))
+        .chain(notification_profiles.into_iter().map(FrameItem::from))
+        .chain(chat_folders.into_iter().map(FrameItem::from))
+        .collect();
+
+    (backup_info, frames)
+}
+
+fn write_validated(
+    backup_info: proto::BackupInfo,
+    frames: Vec<FrameItem>,
+    purpose: Purpose,
+) -> Result<Vec<u8>, crate::Error> {
+    let mut output = vec![];
+    backup_info.write_length_delimited_to_vec(&mut output)?;
+    let mut validator = PartialBackup::<ValidateOnly>::new(backup_info, purpose)?;
+
+    for item in frames {
+        let frame = proto::Frame {
+            item: Some(item),
+            special_fields: Default::default(),
+        };
+        frame.write_length_delimited_to_vec(&mut output)?;
+        validator.add_frame(frame)?;
+    }
+
+    let _: CompletedBackup<ValidateOnly> = validator.try_into()?;
+    Ok(output)
+}
+
+/// Adds the other backup's custom chat colors to `chosen`, returning how their IDs changed.
+///
+/// Colors that `chosen` already has are reused rather than added again.
+fn merge_custom_colors(
+    chosen: &mut proto::AccountData,
+    other: &proto::AccountData,
+) -> HashMap<u64, u64> {
+    let (Some(chosen_settings), Some(other_settings)) = (
+        chosen.accountSettings.as_mut(),
+        other.accountSettings.as_ref(),
+    ) else {
+        return HashMap::new();
+    };
+    let colors = &mut chosen_settings.customChatColors;
+    let mut next_color_id = next_id(colors.iter().map(|color| color.id));
+
+    let mut id_map = HashMap::new();
+    for other_color in &other_settings.customChatColors {
+        let merged_id = match colors.iter().find(|color| color.color == other_color.color) {
+            Some(existing) => existing.id,
+            None => {
+                let new_id = next_color_id;
+                next_color_id += 1;
+                colors.push(proto::chat_style::CustomChatColor {
+                    id: new_id,
+                    ..other_color.clone()
+                });
+                new_id
+            }
+        };
+        id_map.insert(other_color.id, merged_id);
+    }
+    id_map
+}
+
+fn next_id(ids: impl Iterator<Item = u64>) -> u64 {
+    ids.max().unwrap_or(0) + 1
+}
+
+/// Remaps IDs in both lists, then appends the items from `second` that aren't already in `first`.
+fn merge_lists<T: RemapIds, K: Eq + Hash>(
+    mut first: Vec<T>,
+    mut second: Vec<T>,
+    (first_ids, second_ids): (&IdMap, &IdMap),
+    key: impl Fn(&T) -> K,
+) -> Vec<T> {
+    first.remap_ids(first_ids);
+    second.remap_ids(second_ids);
+    let second = without_duplicates(&first, second, key);
+    first.extend(second);
+    first
+}
+
+fn without_duplicates<T, K: Eq + Hash>(
+    existing: &[T],
+    new: Vec<T>,
+    key: impl Fn(&T) -> K,
+) -> Vec<T> {
+    let mut seen: HashSet<K> = existing.iter().map(&key).collect();
+    new.into_iter()
+        .filter(|item| seen.insert(key(item)))
+        .collect()
+}
+
+/// Merges two lists of chat items, each of which is already in order.
+///
+/// Ties go to `first`.
+fn interleave_by_date_sent(
+    first: Vec<proto::ChatItem>,
+    second: Vec<proto::ChatItem>,
+) -> Vec<proto::ChatItem> {
+    let mut result = Vec::with_capacity(first.len() + second.len());
+    let mut second = second.into_iter().peekable();
+    for item in first {
+        while let Some(earlier) = second.next_if(|next| next.dateSent < item.dateSent) {
+            result.push(earlier);
+        }
+        result.push(item);
+    }
+    result.extend(second);
+    result
+}
+
+/// A stable identifier for a recipient, for matching recipients across backups.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum RecipientKey {
+    Aci(Vec<u8>),
+    Pni(Vec<u8>),
+    E164(u64),
+    Username(String),
+    Group(Vec<u8>),
+    DistributionList(Vec<u8>),
+    Self_,
+    ReleaseNotes,
+    CallLink(Vec<u8>),
+}
+
+impl RecipientKey {
+    /// Returns every key for `recipient`, most specific first.
+    fn all_for(recipient: &proto::Recipient) -> Vec<Self> {
+        use proto::recipient::Destination;
+        match &recipient.destination {
+            Some(Destination::Contact(contact)) => [
+                contact.aci.clone().map(Self::Aci),
+                contact.pni.clone().map(Self::Pni),
+                contact.e164.map(Self::E164),
+                contact.username.clone().map(Self::Username),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+            Some(Destination::Group(group)) => vec![Self::Group(group.masterKey.clone())],
+            Some(Destination::DistributionList(list)) => {
+                vec![Self::DistributionList(list.distributionId.clone())]
+            }
+            Some(Destination::Self_(_)) => vec![Self::Self_],
+            Some(Destination::ReleaseNotes(_)) => vec![Self::ReleaseNotes],
+            Some(Destination::CallLink(call_link)) => {
+                vec![Self::CallLink(call_link.rootKey.clone())]
+            }
+            None => vec![],
+        }
+    }
+
+    /// Whether this key is enough to match a recipient with `keys` to one with `existing_keys`.
+    ///
+    /// ACIs and PNIs take precedence over the keys after them: two contacts that both have ACIs
+    /// can only be matched by ACI, and two that both have PNIs can only be matched by ACI or PNI.
+    fn can_match(&self, keys: &[Self], existing_keys: &[Self]) -> bool {
+        let both_have = |is_kind: fn(&Self) -> bool| {
+            keys.iter().any(is_kind) && existing_keys.iter().any(is_kind)
+        };
+        let both_have_aci = || both_have(|key| matches!(key, Self::Aci(_)));
+        let both_have_pni = || both_have(|key| matches!(key, Self::Pni(_)));
+        match self {
+            Self::Pni(_) => !both_have_aci(),
+            Self::E164(_) | Self::Username(_) => !both_have_aci() && !both_have_pni(),
+            Self::Aci(_)
+            | Self::Group(_)
+            | Self::DistributionList(_)
+            | Self::Self_
+            | Self::ReleaseNotes
+            | Self::CallLink(_) => true,
+        }
+    }
+
+    /// Clears the field of `recipient` that this key came from, if it's one that can be cleared.
+    ///
+    /// Only a contact's PNI, E164, and username can be removed; everything else is required to
+    /// identify the recipient at all.
+    fn remove_from(&self, recipient: &mut proto::Recipient) {
+        let Some(proto::recipient::Destination::Contact(contact)) = &mut recipient.destination
+        else {
+            return;
+        };
+        match self {
+            Self::Pni(_) => contact.pni = None,
+            Self::E164(_) => contact.e164 = None,
+            Self::Username(_) => contact.username = None,
+            Self::Aci(_)
+            | Self::Group(_)
+            | Self::DistributionList(_)
+            | Self::Self_
+            | Self::ReleaseNotes
+            | Self::CallLink(_) => {}
+        }
+    }
+}
+
+/// How IDs from one input backup map to IDs in the merged backup.
+///
+/// IDs that aren't present are left unchanged.
+#[derive(Debug, Default)]
+struct IdMap {
+    recipients: HashMap<u64, u64>,
+    chats: HashMap<u64, u64>,
+    custom_colors: HashMap<u64, u64>,
+}
+
+impl IdMap {
+    fn recipient(&self, id: &mut u64) {
+        Self::apply(&self.recipients, id)
+    }
+
+    fn chat(&self, id: &mut u64) {
+        Self::apply(&self.chats, id)
+    }
+
+    fn custom_color(&self, id: &mut u64) {
+        Self::apply(&self.custom_colors, id)
+    }
+
+    fn apply(map: &HashMap<u64, u64>, id: &mut u64) {
+        if let Some(new_id) = map.get(id) {
+            *id = *new_id;
+        }
+    }
+}
+
+/// Rewrites the IDs in a message according to an [`IdMap`].
+///
+/// Like the scrambler, this destructures messages exhaustively, so that new ID fields aren't
+/// missed.
+trait RemapIds {
+    fn remap_ids(&mut self, ids: &IdMap);
+}
+
+impl<T: RemapIds> RemapIds for Vec<T> {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        self.iter_mut().for_each(|x| x.remap_ids(ids));
+    }
+}
+
+impl<T: RemapIds> RemapIds for protobuf::MessageField<T> {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        if let Some(x) = self.as_mut() {
+            x.remap_ids(ids);
+        }
+    }
+}
+
+impl RemapIds for proto::Recipient {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            id,
+            destination,
+            special_fields: _,
+        } = self;
+        ids.recipient(id);
+
+        use proto::recipient::Destination;
+        match destination {
+            Some(Destination::DistributionList(proto::DistributionListItem {
+                distributionId: _,
+                item,
+                special_fields: _,
+            })) => {
+                use proto::distribution_list_item::Item;
+                match item {
+                    Some(Item::DistributionList(proto::DistributionList {
+                        name: _,
+                        allowReplies: _,
+                        privacyMode: _,
+                        memberRecipientIds,
+                        special_fields: _,
+                    })) => memberRecipientIds
+                        .iter_mut()
+                        .for_each(|id| ids.recipient(id)),
+                    Some(Item::DeletionTimestamp(_)) | None => {}
+                }
+            }
+            Some(
+                Destination::Contact(_)
+                | Destination::Group(_)
+                | Destination::Self_(_)
+                | Destination::ReleaseNotes(_)
+                | Destination::CallLink(_),
+            )
+            | None => {}
+        }
+    }
+}
+
+impl RemapIds for proto::Chat {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            id,
+            recipientId,
+            archived: _,
+            pinnedOrder: _,
+            expirationTimerMs: _,
+            muteUntilMs: _,
+            markedUnread: _,
+            dontNotifyForMentionsIfMuted: _,
+            style,
+            expireTimerVersion: _,
+            special_fields: _,
+        } = self;
+        ids.chat(id);
+        ids.recipient(recipientId);
+        style.remap_ids(ids);
+    }
+}
+
+impl RemapIds for proto::ChatStyle {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            dimWallpaperInDarkMode: _,
+            wallpaper: _,
+            bubbleColor,
+            special_fields: _,
+        } = self;
+
+        use proto::chat_style::BubbleColor;
+        match bubbleColor {
+            Some(BubbleColor::CustomColorId(id)) => ids.custom_color(id),
+            Some(BubbleColor::AutoBubbleColor(_) | BubbleColor::BubbleColorPreset(_)) | None => {}
+        }
+    }
+}
+
+impl RemapIds for proto::ChatItem {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            chatId,
+            authorId,
+            dateSent: _,
+            expireStartDate: _,
+            expiresInMs: _,
+            revisions,
+            sms: _,
+            pinDetails: _,
+            directionalDetails,
+            item,
+            special_fields: _,
+        } = self;
+        ids.chat(chatId);
+        ids.recipient(authorId);
+        revisions.remap_ids(ids);
+
+        use proto::chat_item::DirectionalDetails;
+        match directionalDetails {
+            Some(DirectionalDetails::Outgoing(proto::chat_item::OutgoingMessageDetails {
+                sendStatus,
+                dateReceived: _,
+                special_fields: _,
+            })) => sendStatus.remap_ids(ids),
+            Some(DirectionalDetails::Incoming(_) | DirectionalDetails::Directionless(_)) | None => {
+            }
+        }
+
+        use proto::chat_item::Item;
+        match item {
+            Some(Item::StandardMessage(message)) => message.remap_ids(ids),
+            Some(Item::ContactMessage(proto::ContactMessage {
+                contact: _,
+                reactions,
+                special_fields: _,
+            }))
+            | Some(Item::StickerMessage(proto::StickerMessage {
+                sticker: _,
+                reactions,
+                special_fields: _,
+            }))
+            | Some(Item::ViewOnceMessage(proto::ViewOnceMessage {
+                attachment: _,
+                reactions,
+                special_fields: _,
+            }))
+            | Some(Item::DirectStoryReplyMessage(proto::DirectStoryReplyMessage {
+                reply: _,
+                reactions,
+                special_fields: _,
+            })) => reactions.remap_ids(ids),
+            Some(Item::UpdateMessage(message)) => message.remap_ids(ids),
+            Some(Item::Poll(poll)) => poll.remap_ids(ids),
+            Some(Item::AdminDeletedMessage(proto::AdminDeletedMessage {
+                adminId,
+                special_fields: _,
+            })) => ids.recipient(adminId),
+            Some(
+                Item::RemoteDeletedMessage(_) | Item::PaymentNotification(_) | Item::GiftBadge(_),
+            )
+            | None => {}
+        }
+    }
+}
+
+impl RemapIds for proto::SendStatus {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            recipientId,
+            timestamp: _,
+            deliveryStatus: _,
+            special_fields: _,
+        } = self;
+        ids.recipient(recipientId);
+    }
+}
+
+impl RemapIds for proto::StandardMessage {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            quote,
+            text: _,
+            attachments: _,
+            linkPreview: _,
+            longText: _,
+            reactions,
+            special_fields: _,
+        } = self;
+        quote.remap_ids(ids);
+        reactions.remap_ids(ids);
+    }
+}
+
+impl RemapIds for proto::Quote {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            targetSentTimestamp: _,
+            authorId,
+            text: _,
+            attachments: _,
+            type_: _,
+            special_fields: _,
+        } = self;
+        ids.recipient(authorId);
+    }
+}
+
+impl RemapIds for proto::Reaction {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            emoji: _,
+            authorId,
+            sentTimestamp: _,
+            sortOrder: _,
+            special_fields: _,
+        } = self;
+        ids.recipient(authorId);
+    }
+}
+
+impl RemapIds for proto::Poll {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            question: _,
+            allowMultiple: _,
+            options,
+            hasEnded: _,
+            reactions,
+            special_fields: _,
+        } = self;
+        for proto::poll::PollOption {
+            option: _,
+            votes,
+            special_fields: _,
+        } in options
+        {
+            for proto::poll::poll_option::PollVote {
+                voterId,
+                voteCount: _,
+                special_fields: _,
+            } in votes
+            {
+                ids.recipient(voterId);
+            }
+        }
+        reactions.remap_ids(ids);
+    }
+}
+
+impl RemapIds for proto::ChatUpdateMessage {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            update,
+            special_fields: _,
+        } = self;
+
+        use proto::chat_update_message::Update;
+        match update {
+            Some(Update::GroupCall(proto::GroupCall {
+                callId: _,
+                state: _,
+                ringerRecipientId,
+                startedCallRecipientId,
+                startedCallTimestamp: _,
+                endedCallTimestamp: _,
+                read: _,
+                special_fields: _,
+            })) => {
+                if let Some(id) = ringerRecipientId {
+                    ids.recipient(id);
+                }
+                if let Some(id) = startedCallRecipientId {
+                    ids.recipient(id);
+                }
+            }
+            Some(Update::PinMessage(proto::PinMessageUpdate {
+                targetSentTimestamp: _,
+                authorId,
+                special_fields: _,
+            })) => ids.recipient(authorId),
+            Some(
+                Update::SimpleUpdate(_)
+                | Update::GroupChange(_)
+                | Update::ExpirationTimerChange(_)
+                | Update::ProfileChange(_)
+                | Update::ThreadMerge(_)
+                | Update::SessionSwitchover(_)
+                | Update::IndividualCall(_)
+                | Update::LearnedProfileChange(_)
+                | Update::PollTerminate(_),
+            )
+            | None => {}
+        }
+    }
+}
+
+impl RemapIds for proto::AdHocCall {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            callId: _,
+            recipientId,
+            state: _,
+            callTimestamp: _,
+            special_fields: _,
+        } = self;
+        ids.recipient(recipientId);
+    }
+}
+
+impl RemapIds for proto::StickerPack {
+    fn remap_ids(&mut self, _ids: &IdMap) {
+        // Sticker packs don't refer to any other frames.
+    }
+}
+
+impl RemapIds for proto::NotificationProfile {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            name: _,
+            emoji: _,
+            color: _,
+            createdAtMs: _,
+            allowAllCalls: _,
+            allowAllMentions: _,
+            allowedMembers,
+            scheduleEnabled: _,
+            scheduleStartTime: _,
+            scheduleEndTime: _,
+            scheduleDaysEnabled: _,
+            id: _,
+            special_fields: _,
+        } = self;
+        allowedMembers.iter_mut().for_each(|id| ids.recipient(id));
+    }
+}
+
+impl RemapIds for proto::ChatFolder {
+    fn remap_ids(&mut self, ids: &IdMap) {
+        let Self {
+            name: _,
+            showOnlyUnread: _,
+            showMutedChats: _,
+            includeAllIndividualChats: _,
+            includeAllGroupChats: _,
+            folderType: _,
+            includedRecipientIds,
+            excludedRecipientIds,
+            id: _,
+            special_fields: _,
+        } = self;
+        includedRecipientIds
+            .iter_mut()
+            .chain(excludedRecipientIds)
+            .for_each(|id| ids.recipient(id));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use futures::executor::block_on;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::BackupReader;
+
+    const FIRST_BACKUP_TIME_MS: u64 = 1000;
+    const SECOND_BACKUP_TIME_MS: u64 = 2000;
+
+    fn serialize(backup_time_ms: u64, frames: Vec<FrameItem>) -> Vec<u8> {
+        let mut output = vec![];
+        proto::BackupInfo {
+            backupTimeMs: backup_time_ms,
+            mediaRootBackupKey: vec![0; libsignal_account_keys::BACKUP_KEY_LEN],
+            ..Default::default()
+        }
+        .write_length_delimited_to_vec(&mut output)
+        .expect("can serialize");
+        for item in frames {
+            proto::Frame {
+                item: Some(item),
+                ..Default::default()
+            }
+            .write_length_delimited_to_vec(&mut output)
+            .expect("can serialize");
+        }
+        output
+    }
+
+    fn deserialize(backup: &[u8]) -> (proto::BackupInfo, Vec<FrameItem>) {
+        block_on(async {
+            let mut reader = VarintDelimitedReader::new(backup);
+            let raw_backup_info = reader
+                .read_next()
+                .await
+                .expect("can read")
+                .expect("has backup info");
+            let backup_info = proto::BackupInfo::parse_from_bytes(&raw_backup_info).expect("valid");
+            let mut frames = vec![];
+            while let Some(raw_frame) = reader.read_next().await.expect("can read") {
+                let frame = proto::Frame::parse_from_bytes(&raw_frame).expect("valid");
+                frames.push(frame.item.expect("not empty"));
+            }
+            (backup_info, frames)
+        })
+    }
+
+    fn chat_item_at(chat_item: proto::ChatItem, offset_ms: u64) -> proto::ChatItem {
+        proto::ChatItem {
+            dateSent: chat_item.dateSent + offset_ms,
+            ..chat_item
+        }
+    }
+
+    fn first_backup() -> Vec<u8> {
+        serialize(
+            FIRST_BACKUP_TIME_MS,
+            vec![
+                proto::AccountData::test_data().into(),
+                proto::Recipient::test_data().into(),
+                proto::Recipient::test_data_contact().into(),
+                proto::Chat::test_data().into(),
+                proto::ChatItem::test_data().into(),
+                chat_item_at(proto::ChatItem::test_data(), 2000).into(),
+            ],
+        )
+    }
+
+    /// The same account, with its own IDs, one more contact, and one more message from each
+    /// contact.
+    fn second_backup() -> Vec<u8> {
+        const OTHER_CONTACT_ID: u64 = 3;
+        const OTHER_CHAT_ID: u64 = 2;
+        // Everything is built from the same test data as the first backup, then given new IDs.
+        let to_second_ids = IdMap {
+            recipients: HashMap::from([
+                (proto::Recipient::TEST_ID, 1),
+                (proto::Recipient::test_data_contact().id, 2),
+            ]),
+            chats: HashMap::from([(proto::Chat::test_data().id, 1)]),
+            custom_colors: HashMap::new(),
+        };
+
+        let mut other_contact = proto::Recipient {
+            id: OTHER_CONTACT_ID,
+            ..proto::Recipient::test_data_contact()
+        };
+        let Some(proto::recipient::Destination::Contact(contact)) = &mut other_contact.destination
+        else {
+            unreachable!("test data is a contact");
+        };
+        contact.aci = Some([0xbb; 16].into());
+
+        let mut recipients = vec![
+            proto::Recipient::test_data(),
+            proto::Recipient::test_data_contact(),
+            other_contact,
+        ];
+        recipients.remap_ids(&to_second_ids);
+        let mut chats = vec![
+            proto::Chat::test_data(),
+            proto::Chat {
+                id: OTHER_CHAT_ID,
+                recipientId: OTHER_CONTACT_ID,
+                ..proto::Chat::test_data()
+            },
+        ];
+        chats.remap_ids(&to_second_ids);
+        let mut chat_items = vec![
+            // A duplicate of the first backup's first message.
+            proto::ChatItem::test_data(),
+            chat_item_at(
+                proto::ChatItem {
+                    chatId: OTHER_CHAT_ID,
+                    authorId: OTHER_CONTACT_ID,
+                    ..proto::ChatItem::test_data()
+                },
+                1000,
+            ),
+        ];
+        chat_items.remap_ids(&to_second_ids);
+
+        let account_data = proto::AccountData {
+            username: Some("abc.456".to_owned()),
+            ..proto::AccountData::test_data()
+        };
+        let frames = std::iter::once(account_data.into())
+            .chain(recipients.into_iter().map(FrameItem::from))
+            .chain(chats.into_iter().map(FrameItem::from))
+            .chain(chat_items.into_iter().map(FrameItem::from))
+            .collect();
+        serialize(SECOND_BACKUP_TIME_MS, frames)
+    }
+
+    fn merge_test_backups(policy: AccountDataPolicy) -> Vec<u8> {
+        block_on(merge(
+            first_backup().as_slice(),
+            second_backup().as_slice(),
+            Purpose::RemoteBackup,
+            policy,
+        ))
+        .expect("can merge")
+    }
+
+    #[test]
+    fn merged_backup_is_valid() {
+        let merged = merge_test_backups(AccountDataPolicy::First);
+        block_on(
+            BackupReader::new_unencrypted(merged.as_slice(), Purpose::RemoteBackup).validate_all(),
+        )
+        .result
+        .expect("valid");
+    }
+
+    #[test]
+    fn merges_recipients_chats_and_items() {
+        let (_, frames) = deserialize(&merge_test_backups(AccountDataPolicy::First));
+
+        let recipient_ids: Vec<u64> = frames
+            .iter()
+            .filter_map(|frame| match frame {
+                FrameItem::Recipient(recipient) => Some(recipient.id),
+                _ => None,
+            })
+            .collect();
+        let new_recipient_id = proto::Recipient::TEST_ID + 1;
+        assert_eq!(
+            recipient_ids,
+            [
+                proto::Recipient::TEST_ID,
+                proto::Recipient::test_data_contact().id,
+                new_recipient_id,
+            ]
+        );
+
+        let chats: Vec<(u64, u64)> = frames
+            .iter()
+            .filter_map(|frame| match frame {
+                FrameItem::Chat(chat) => Some((chat.id, chat.recipientId)),
+                _ => None,
+            })
+            .collect();
+        let chat = proto::Chat::test_data();
+        let new_chat_id = chat.id + 1;
+        assert_eq!(
+            chats,
+            [(chat.id, chat.recipientId), (new_chat_id, new_recipient_id)]
+        );
+
+        let sent = proto::ChatItem::test_data().dateSent;
+        let chat_items: Vec<(u64, u64, u64)> = frames
+            .iter()
+            .filter_map(|frame| match frame {
+                FrameItem::ChatItem(item) => Some((item.chatId, item.authorId, item.dateSent)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            chat_items,
+            [
+                (chat.id, chat.recipientId, sent),
+                (new_chat_id, new_recipient_id, sent + 1000),
+                (chat.id, chat.recipientId, sent + 2000),
+            ]
+        );
+    }
+
+    fn contact_with_e164(id: u64, aci: [u8; 16]) -> proto::Recipient {
+        let mut recipient = proto::Recipient {
+            id,
+            ..proto::Recipient::test_data_contact()
+        };
+        let Some(proto::recipient::Destination::Contact(contact)) = &mut recipient.destination
+        else {
+            unreachable!("test data is a contact");
+        };
+        contact.aci = Some(aci.into());
+        contact.e164 = Some(proto::Contact::TEST_E164.into());
+        recipient
+    }
+
+    #[test]
+    fn does_not_match_contacts_with_different_acis() {
+        let first = serialize(
+            FIRST_BACKUP_TIME_MS,
+            vec![
+                proto::AccountData::test_data().into(),
+                proto::Recipient::test_data().into(),
+                contact_with_e164(2, proto::Contact::TEST_ACI).into(),
+            ],
+        );
+        let second = serialize(
+            SECOND_BACKUP_TIME_MS,
+            vec![
+                proto::AccountData::test_data().into(),
+                proto::Recipient::test_data().into(),
+                contact_with_e164(2, [0xbb; 16]).into(),
+            ],
+        );
+        let merged = block_on(merge(
+            first.as_slice(),
+            second.as_slice(),
+            Purpose::RemoteBackup,
+            AccountDataPolicy::First,
+        ))
+        .expect("can merge");
+
+        let (_, frames) = deserialize(&merged);
+        let contacts: Vec<(u64, Option<Vec<u8>>, Option<u64>)> = frames
+            .iter()
+            .filter_map(|frame| match frame {
+                FrameItem::Recipient(proto::Recipient {
+                    id,
+                    destination: Some(proto::recipient::Destination::Contact(contact)),
+                    ..
+                }) => Some((*id, contact.aci.clone(), contact.e164)),
+                _ => None,
+            })
+            .collect();
+        // The second contact is kept separately, but can't keep the E164 that now belongs to the
+        // first.
+        let e164: u64 = proto::Contact::TEST_E164.into();
+        assert_eq!(
+            contacts,
+            [
+                (2, Some(proto::Contact::TEST_ACI.to_vec()), Some(e164)),
+                (proto::Recipient::TEST_ID + 1, Some(vec![0xbb; 16]), None),
+            ]
+        );
+    }
+
+    #[test_case(AccountDataPolicy::First, "abc.123")]
+    #[test_case(AccountDataPolicy::Second, "abc.456")]
+    #[test_case(AccountDataPolicy::Newer, "abc.456")]
+    fn account_data_policy(policy: AccountDataPolicy, expected_username: &str) {
+        let (backup_info, frames) = deserialize(&merge_test_backups(policy));
+        assert_eq!(backup_info.backupTimeMs, SECOND_BACKUP_TIME_MS);
+
+        let Some(FrameItem::Account(account_data)) = frames.first() else {
+            panic!("account data should come first");
+        };
+        assert_eq!(account_data.username.as_deref(), Some(expected_username));
+        // The two backups have the same custom colors, so they aren't duplicated.
+        assert_eq!(
+            account_data
+                .accountSettings
+                .as_ref()
+                .expect("present")
+                .customChatColors,
+            [proto::chat_style::CustomChatColor::test_data()]
+        );
+    }
+
+    #[test]
+    fn remaps_custom_colors() {
+        let mut chosen = proto::AccountData::test_data();
+        let mut other = proto::AccountData::test_data();
+        let other_colors = &mut other
+            .accountSettings
+            .as_mut()
+            .expect("present")
+            .customChatColors;
+        let existing_color = other_colors[0].clone();
+        other_colors[0].id += 1;
+        other_colors.push(proto::chat_style::CustomChatColor {
+            color: Some(proto::chat_style::custom_chat_color::Color::Solid(
+                0xff00ff00,
+            )),
+            ..existing_color.clone()
+        });
+
+        let id_map = merge_custom_colors(&mut chosen, &other);
+        assert_eq!(
+            id_map,
+            HashMap::from([
+                (existing_color.id + 1, existing_color.id),
+                (existing_color.id, existing_color.id + 1),
+            ])
+        );
+        assert_eq!(
+            chosen
+                .accountSettings
+                .as_ref()
+                .expect("present")
+                .customChatColors
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        let error = block_on(merge(
+            first_backup().as_slice(),
+            b"".as_slice(),
+            Purpose::RemoteBackup,
+            AccountDataPolicy::First,
+        ))
+        .expect_err("invalid");
+        assert_matches!(error, MergeError::InvalidSecond(crate::Error::NoFrames));
+    }
+}
@@ -0,0 +1,608 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Summarizing what takes up space in a backup.
+//!
+//! The main entry point is [`collect_stats`]. Unlike [`BackupReader`](crate::BackupReader), this
+//! doesn't validate the backup beyond what's needed to parse it, so it can also be used to look at
+//! backups that fail validation.
+
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll, ready};
+
+use futures::AsyncRead;
+use futures::io::Cursor;
+use mediasan_common::AsyncSkip;
+use protobuf::reflect::{ReflectFieldRef, ReflectValueRef};
+use protobuf::{Message as _, MessageDyn};
+
+use crate::frame::{FramesReader, LimitedReaderFactory, UnvalidatedHmacReader, VerifyHmac};
+use crate::key::MessageBackupKey;
+use crate::parse::VarintDelimitedReader;
+use crate::proto::backup as proto;
+use crate::proto::backup::frame::Item as FrameItem;
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum StatsError {
+    /// {0}
+    Frames(#[from] crate::frame::ValidationError),
+    /// {0}
+    Read(#[from] crate::Error),
+}
+
+/// What a backup contains, and how much space each part of it takes up.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct BackupStats {
+    /// Bytes before the first frame, such as the unencrypted metadata and the IV.
+    pub header_bytes: u64,
+    pub backup_info: ByteCounts,
+    /// Frames by type, such as "Recipient" or "ChatItem".
+    pub frames: BTreeMap<&'static str, ByteCounts>,
+    /// Chat items by type, such as "StandardMessage" or "UpdateMessage".
+    pub chat_item_types: BTreeMap<&'static str, ByteCounts>,
+    /// Recipient frames by kind, such as "Contact" or "Group".
+    pub recipient_kinds: BTreeMap<&'static str, ByteCounts>,
+    /// Chats by their ID within the backup.
+    pub chats: BTreeMap<u64, ChatStats>,
+    /// Recipients by their ID within the backup.
+    pub recipients: BTreeMap<u64, RecipientStats>,
+    /// File pointers anywhere in the backup, including wallpapers and long message text.
+    pub attachments: AttachmentStats,
+    /// The number of chat items sent in each month (UTC), formatted as "YYYY-MM".
+    pub chat_items_by_month: BTreeMap<String, u64>,
+    /// Bytes after the last frame, including padding and the HMAC.
+    pub trailing_bytes: u64,
+}
+
+/// Sizes attributed to a group of protos.
+///
+/// Compressed sizes come from how far the underlying file was read while parsing each proto.
+/// Decryption and decompression read ahead, so these are only accurate in aggregate.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ByteCounts {
+    pub count: u64,
+    pub compressed_bytes: u64,
+    pub uncompressed_bytes: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ChatStats {
+    /// The chat's recipient, if its Chat frame was found.
+    pub recipient_id: Option<u64>,
+    pub items: ByteCounts,
+    /// The number of chat items of each type.
+    pub item_types: BTreeMap<&'static str, u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct RecipientStats {
+    /// The kind of recipient, such as "Contact" or "Group", if its Recipient frame was found.
+    pub kind: Option<&'static str>,
+    /// Chat items with this recipient as their author, across all chats.
+    pub authored_items: ByteCounts,
+}
+
+/// Counts of file pointers by which locators they have.
+///
+/// A single file pointer can have several locators, so these don't add up to `total`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize)]
+pub struct AttachmentStats {
+    pub total: u64,
+    /// File pointers with no locator info, for files that can't be downloaded.
+    pub invalid: u64,
+    /// File pointers with a transit tier CDN key.
+    pub transit_tier: u64,
+    /// File pointers with a media tier CDN number.
+    pub media_tier: u64,
+    /// File pointers with a plaintext hash, which is needed to find a file on the media tier.
+    pub plaintext_hash: u64,
+    /// File pointers with a key for a local backup.
+    pub local: u64,
+    /// The total plaintext size of the files, as recorded in their locators.
+    pub plaintext_bytes: u64,
+}
+
+/// Reads the backup in `contents` and reports what it contains.
+///
+/// If `key` is provided, `contents` is decrypted and decompressed as with
+/// [`BackupReader::new_encrypted_compressed`](crate::BackupReader::new_encrypted_compressed), and
+/// its HMAC is checked; otherwise it's read as a plaintext series of varint-delimited protos.
+pub async fn collect_stats(
+    contents: &[u8],
+    key: Option<&MessageBackupKey>,
+) -> Result<BackupStats, StatsError> {
+    let position = Arc::<AtomicU64>::default();
+    let tracking_reader = |position: Arc<AtomicU64>| TrackingReader {
+        inner: Cursor::new(contents),
+        position,
+    };
+    let total_bytes = u64::try_from(contents.len()).expect("u64 can hold usize");
+
+    match key {
+        Some(key) => {
+            let reader = FramesReader::new(
+                key,
+                LimitedReaderFactory::new([
+                    // FramesReader consumes the first reader to validate the HMAC...
+                    tracking_reader(Default::default()),
+                    // ...then uses the second reader for the actual file contents.
+                    tracking_reader(position.clone()),
+                ]),
+            )
+            .await?;
+            collect_from_reader(reader, &position, total_bytes).await
+        }
+        None => {
+            let reader = UnvalidatedHmacReader::new(tracking_reader(position.clone()));
+            collect_from_reader(reader, &position, total_bytes).await
+        }
+    }
+}
+
+async fn collect_from_reader(
+    reader: impl AsyncRead + Unpin + VerifyHmac,
+    position: &AtomicU64,
+    total_bytes: u64,
+) -> Result<BackupStats, StatsError> {
+    let mut reader = VarintDelimitedReader::new(reader);
+    let mut stats = BackupStats {
+        header_bytes: position.load(Ordering::Relaxed),
+        ..Default::default()
+    };
+    let mut last_position = stats.header_bytes;
+    let mut advance = || {
+        let new_position = position.load(Ordering::Relaxed);
+        let compressed_bytes = new_position - last_position;
+        last_position = new_position;
+        compressed_bytes
+    };
+
+    let backup_info = reader
+        .read_next()
+        .await
+        .map_err(crate::Error::Parse)?
+        .ok_or(crate::Error::NoFrames)?;
+    stats.backup_info.add(advance(), &backup_info);
+
+    while let Some(frame) = reader.read_next().await.map_err(crate::Error::Parse)? {
+        let compressed_bytes = advance();
+        let frame_proto = proto::Frame::parse_from_bytes(&frame).map_err(crate::Error::from)?;
+        stats.add_frame(&frame_proto, compressed_bytes, &frame);
+    }
+
+    // Anything that hasn't been read yet is after the last frame.
+    stats.trailing_bytes = total_bytes - last_position;
+
+    reader
+        .into_inner()
+        .verify_hmac()
+        .await
+        .map_err(crate::Error::from)?;
+
+    Ok(stats)
+}
+
+impl BackupStats {
+    fn add_frame(&mut self, frame: &proto::Frame, compressed_bytes: u64, raw_frame: &[u8]) {
+        self.attachments.add_from(frame);
+
+        let frame_type = match &frame.item {
+            None => "unknown",
+            Some(FrameItem::Account(_)) => "Account",
+            Some(FrameItem::Recipient(recipient)) => {
+                let kind = recipient_kind(recipient);
+                self.recipients.entry(recipient.id).or_default().kind = Some(kind);
+                self.recipient_kinds
+                    .entry(kind)
+                    .or_default()
+                    .add(compressed_bytes, raw_frame);
+                "Recipient"
+            }
+            Some(FrameItem::Chat(chat)) => {
+                self.chats.entry(chat.id).or_default().recipient_id = Some(chat.recipientId);
+                "Chat"
+            }
+            Some(FrameItem::ChatItem(chat_item)) => {
+                self.add_chat_item(chat_item, compressed_bytes, raw_frame);
+                "ChatItem"
+            }
+            Some(FrameItem::StickerPack(_)) => "StickerPack",
+            Some(FrameItem::AdHocCall(_)) => "AdHocCall",
+            Some(FrameItem::NotificationProfile(_)) => "NotificationProfile",
+            Some(FrameItem::ChatFolder(_)) => "ChatFolder",
+        };
+        self.frames
+            .entry(frame_type)
+            .or_default()
+            .add(compressed_bytes, raw_frame);
+    }
+
+    fn add_chat_item(
+        &mut self,
+        chat_item: &proto::ChatItem,
+        compressed_bytes: u64,
+        raw_frame: &[u8],
+    ) {
+        let item_type = chat_item_type(chat_item);
+        self.chat_item_types
+            .entry(item_type)
+            .or_default()
+            .add(compressed_bytes, raw_frame);
+
+        let chat = self.chats.entry(chat_item.chatId).or_default();
+        chat.items.add(compressed_bytes, raw_frame);
+        *chat.item_types.entry(item_type).or_default() += 1;
+
+        self.recipients
+            .entry(chat_item.authorId)
+            .or_default()
+            .authored_items
+            .add(compressed_bytes, raw_frame);
+
+        *self
+            .chat_items_by_month
+            .entry(month_of(chat_item.dateSent))
+            .or_default() += 1;
+    }
+}
+
+impl ByteCounts {
+    fn add(&mut self, compressed_bytes: u64, raw_proto: &[u8]) {
+        self.count += 1;
+        self.compressed_bytes += compressed_bytes;
+        self.uncompressed_bytes += u64::try_from(raw_proto.len()).expect("u64 can hold usize");
+    }
+}
+
+impl AttachmentStats {
+    /// Adds every file pointer within `message`, at any depth.
+    fn add_from(&mut self, message: &dyn MessageDyn) {
+        if let Some(file_pointer) = message.downcast_ref::<proto::FilePointer>() {
+            self.add(file_pointer);
+            return;
+        }
+
+        for field in message.descriptor_dyn().fields() {
+            match field.get_reflect(message) {
+                ReflectFieldRef::Optional(value) => {
+                    if let Some(ReflectValueRef::Message(child)) = value.value() {
+                        self.add_from(&*child);
+                    }
+                }
+                ReflectFieldRef::Repeated(values) => {
+                    for value in values {
+                        if let ReflectValueRef::Message(child) = value {
+                            self.add_from(&*child);
+                        }
+                    }
+                }
+                // backup.proto doesn't have any maps.
+                ReflectFieldRef::Map(_) => {}
+            }
+        }
+    }
+
+    fn add(&mut self, file_pointer: &proto::FilePointer) {
+        self.total += 1;
+
+        let Some(locator) = file_pointer.locatorInfo.as_ref() else {
+            self.invalid += 1;
+            return;
+        };
+        // The "invalid" locator is encoded as an empty message.
+        if *locator == proto::file_pointer::LocatorInfo::default() {
+            self.invalid += 1;
+            return;
+        }
+
+        let proto::file_pointer::LocatorInfo {
+            key: _,
+            integrityCheck,
+            size,
+            transitCdnKey,
+            transitCdnNumber: _,
+            transitTierUploadTimestamp: _,
+            mediaTierCdnNumber,
+            localKey,
+            special_fields: _,
+        } = locator;
+
+        use proto::file_pointer::locator_info::IntegrityCheck;
+        self.transit_tier += u64::from(transitCdnKey.is_some());
+        self.media_tier += u64::from(mediaTierCdnNumber.is_some());
+        self.plaintext_hash += u64::from(matches!(
+            integrityCheck,
+            Some(IntegrityCheck::PlaintextHash(_))
+        ));
+        self.local += u64::from(localKey.is_some());
+        self.plaintext_bytes += u64::from(*size);
+    }
+}
+
+fn recipient_kind(recipient: &proto::Recipient) -> &'static str {
+    use proto::recipient::Destination;
+    match &recipient.destination {
+        Some(Destination::Contact(_)) => "Contact",
+        Some(Destination::Group(_)) => "Group",
+        Some(Destination::DistributionList(_)) => "DistributionList",
+        Some(Destination::Self_(_)) => "Self",
+        Some(Destination::ReleaseNotes(_)) => "ReleaseNotes",
+        Some(Destination::CallLink(_)) => "CallLink",
+        None => "unknown",
+    }
+}
+
+fn chat_item_type(chat_item: &proto::ChatItem) -> &'static str {
+    use proto::chat_item::Item;
+    match &chat_item.item {
+        Some(Item::StandardMessage(_)) => "StandardMessage",
+        Some(Item::ContactMessage(_)) => "ContactMessage",
+        Some(Item::StickerMessage(_)) => "StickerMessage",
+        Some(Item::RemoteDeletedMessage(_)) => "RemoteDeletedMessage",
+        Some(Item::UpdateMessage(_)) => "UpdateMessage",
+        Some(Item::PaymentNotification(_)) => "PaymentNotification",
+        Some(Item::GiftBadge(_)) => "GiftBadge",
+        Some(Item::ViewOnceMessage(_)) => "ViewOnceMessage",
+        Some(Item::DirectStoryReplyMessage(_)) => "DirectStoryReplyMessage",
+        Some(Item::Poll(_)) => "Poll",
+        Some(Item::AdminDeletedMessage(_)) => "AdminDeletedMessage",
+        None => "unknown",
+    }
+}
+
+/// Formats the UTC month containing `millis_since_epoch` as "YYYY-MM".
+fn month_of(millis_since_epoch: u64) -> String {
+    const MILLIS_PER_DAY: u64 = 24 * 60 * 60 * 1000;
+    // Howard Hinnant's `civil_from_days`, restricted to dates after the epoch.
+    // See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+    let days_since_march_1_0000 = millis_since_epoch / MILLIS_PER_DAY + 719_468;
+    let era = days_since_march_1_0000 / 146_097;
+    let day_of_era = days_since_march_1_0000 - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let (month, year_offset) = if month_from_march < 10 {
+        (month_from_march + 3, 0)
+    } else {
+        (month_from_march - 9, 1)
+    };
+    let year = era * 400 + year_of_era + year_offset;
+    format!("{year:04}-{month:02}")
+}
+
+/// A reader that reports how far it's gotten through a shared counter.
+struct TrackingReader<R> {
+    inner: R,
+    position: Arc<AtomicU64>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for TrackingReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let count = ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        self.position.fetch_add(
+            u64::try_from(count).expect("u64 can hold usize"),
+            Ordering::Relaxed,
+        );
+        Poll::Ready(Ok(count))
+    }
+}
+
+impl<R: AsyncSkip + Unpin> AsyncSkip for TrackingReader<R> {
+    fn poll_skip(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        amount: u64,
+    ) -> Poll<std::io::Result<()>> {
+        ready!(Pin::new(&mut self.inner).poll_skip(cx, amount))?;
+        self.position.fetch_add(amount, Ordering::Relaxed);
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_stream_position(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<u64>> {
+        Pin::new(&mut self.inner).poll_stream_position(cx)
+    }
+
+    fn poll_stream_len(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<u64>> {
+        Pin::new(&mut self.inner).poll_stream_len(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use test_case::test_case;
+
+    use super::*;
+    use crate::BackupWriter;
+    use crate::frame::AES_IV_SIZE;
+    use crate::key::test::FAKE_MESSAGE_BACKUP_KEY;
+
+    const CANONICAL_BACKUP: &[u8] = include_bytes!("../tests/res/canonical-backup.binproto");
+
+    fn encrypted_canonical_backup() -> Vec<u8> {
+        block_on(async {
+            let mut reader = VarintDelimitedReader::new(CANONICAL_BACKUP);
+            let backup_info = reader
+                .read_next()
+                .await
+                .expect("can read")
+                .expect("has backup info");
+            let mut writer = BackupWriter::new_encrypted_compressed(
+                &FAKE_MESSAGE_BACKUP_KEY,
+                [0x11; AES_IV_SIZE],
+                None,
+                &proto::BackupInfo::parse_from_bytes(&backup_info).expect("valid"),
+                None,
+                Vec::new(),
+            )
+            .await
+            .expect("valid header");
+            while let Some(frame) = reader.read_next().await.expect("can read") {
+                writer.write_raw_frame(&frame).await.expect("can write");
+            }
+            writer.finish().await.expect("can finish")
+        })
+    }
+
+    fn assert_consistent(stats: &BackupStats, contents: &[u8]) {
+        let BackupStats {
+            header_bytes,
+            backup_info,
+            frames,
+            chat_item_types,
+            recipient_kinds,
+            chats,
+            recipients,
+            attachments: _,
+            chat_items_by_month,
+            trailing_bytes,
+        } = stats;
+
+        let frame_bytes: u64 = frames.values().map(|f| f.compressed_bytes).sum();
+        assert_eq!(
+            header_bytes + backup_info.compressed_bytes + frame_bytes + trailing_bytes,
+            u64::try_from(contents.len()).expect("small"),
+        );
+
+        let chat_item_count = frames["ChatItem"].count;
+        assert_eq!(
+            chat_item_types.values().map(|t| t.count).sum::<u64>(),
+            chat_item_count
+        );
+        assert_eq!(
+            chats.values().map(|c| c.items.count).sum::<u64>(),
+            chat_item_count
+        );
+        assert_eq!(
+            recipients
+                .values()
+                .map(|r| r.authored_items.count)
+                .sum::<u64>(),
+            chat_item_count
+        );
+        assert_eq!(chat_items_by_month.values().sum::<u64>(), chat_item_count);
+        assert_eq!(
+            recipient_kinds.values().map(|k| k.count).sum::<u64>(),
+            frames["Recipient"].count
+        );
+        assert_eq!(
+            recipients.values().filter(|r| r.kind.is_some()).count(),
+            usize::try_from(frames["Recipient"].count).expect("small"),
+        );
+    }
+
+    #[test]
+    fn plaintext_stats() {
+        let stats = block_on(collect_stats(CANONICAL_BACKUP, None)).expect("valid");
+        assert_consistent(&stats, CANONICAL_BACKUP);
+
+        assert_eq!(stats.header_bytes, 0);
+        assert_eq!(stats.trailing_bytes, 0);
+        assert_eq!(stats.frames["Account"].count, 1);
+        assert!(stats.chat_item_types.contains_key("StandardMessage"));
+        assert!(stats.attachments.total > 0);
+    }
+
+    #[test]
+    fn encrypted_stats() {
+        let encrypted = encrypted_canonical_backup();
+        let stats =
+            block_on(collect_stats(&encrypted, Some(&FAKE_MESSAGE_BACKUP_KEY))).expect("valid");
+        assert_consistent(&stats, &encrypted);
+
+        let plaintext_stats = block_on(collect_stats(CANONICAL_BACKUP, None)).expect("valid");
+        assert_eq!(
+            stats.backup_info.uncompressed_bytes,
+            plaintext_stats.backup_info.uncompressed_bytes
+        );
+        for (frame_type, counts) in &stats.frames {
+            let plaintext_counts = &plaintext_stats.frames[frame_type];
+            assert_eq!(counts.count, plaintext_counts.count, "{frame_type}");
+            assert_eq!(
+                counts.uncompressed_bytes, plaintext_counts.uncompressed_bytes,
+                "{frame_type}"
+            );
+        }
+        assert_eq!(stats.attachments, plaintext_stats.attachments);
+        assert_eq!(
+            stats.chat_items_by_month,
+            plaintext_stats.chat_items_by_month
+        );
+        // The IV comes before the first frame, and the HMAC after the last.
+        assert!(stats.header_bytes >= u64::try_from(AES_IV_SIZE).expect("small"));
+        assert!(stats.trailing_bytes >= 32);
+    }
+
+    #[test]
+    fn rejects_wrong_key() {
+        let encrypted = encrypted_canonical_backup();
+        let wrong_key = MessageBackupKey {
+            hmac_key: [0; MessageBackupKey::HMAC_KEY_LEN],
+            ..FAKE_MESSAGE_BACKUP_KEY
+        };
+        assert!(block_on(collect_stats(&encrypted, Some(&wrong_key))).is_err());
+    }
+
+    #[test]
+    fn counts_attachments_by_locator() {
+        let mut stats = AttachmentStats::default();
+        let mut chat_item = proto::ChatItem::test_data();
+        stats.add_from(&chat_item);
+        assert!(stats.total > 0);
+
+        let Some(proto::chat_item::Item::StandardMessage(message)) = &mut chat_item.item else {
+            unreachable!("test data is a standard message");
+        };
+        message.attachments.clear();
+        message.quote.clear();
+        message.longText = Some(proto::FilePointer {
+            locatorInfo: Some(proto::file_pointer::LocatorInfo {
+                transitCdnKey: Some("key".to_owned()),
+                mediaTierCdnNumber: Some(2),
+                size: 100,
+                ..Default::default()
+            })
+            .into(),
+            ..Default::default()
+        })
+        .into();
+
+        let mut stats = AttachmentStats::default();
+        stats.add_from(&chat_item);
+        assert_eq!(
+            stats,
+            AttachmentStats {
+                total: 1,
+                transit_tier: 1,
+                media_tier: 1,
+                plaintext_bytes: 100,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test_case(0, "1970-01")]
+    #[test_case(951_782_400_000, "2000-02"; "leap day")]
+    #[test_case(1_709_251_199_999, "2024-02"; "end of month")]
+    #[test_case(1_709_251_200_000, "2024-03"; "start of month")]
+    #[test_case(1_735_689_599_999, "2024-12"; "end of year")]
+    fn month_buckets(millis_since_epoch: u64, expected: &str) {
+        assert_eq!(month_of(millis_since_epoch), expected);
+    }
+}
@@ -0,0 +1,390 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Writing backups incrementally.
+//!
+//! [`BackupWriter`] is the counterpart to [`BackupReader`](crate::BackupReader): it produces the
+//! same format that [`FramesReader`] consumes, without ever holding more than a frame's worth of
+//! the backup in memory.
+
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncryptMut as _, BlockSizeUser, KeyIvInit as _, Unsigned};
+use async_compression::futures::write::GzipEncoder;
+use futures::{AsyncWrite, AsyncWriteExt as _};
+use hmac::{Hmac, Mac as _};
+use libsignal_svrb::proto::backup_metadata::MetadataPb;
+use protobuf::Message as _;
+use sha2::Sha256;
+
+use crate::backup::method::ValidateOnly;
+use crate::backup::{CompletedBackup, PartialBackup, Purpose};
+use crate::frame::{AES_IV_SIZE, FramesReader, forward_secrecy};
+use crate::key::MessageBackupKey;
+use crate::{FoundUnknownField, proto};
+
+const AES_BLOCK_SIZE: usize = <<aes::Aes256 as BlockSizeUser>::BlockSize as Unsigned>::USIZE;
+
+/// How many zero bytes of padding are encrypted at once.
+const PADDING_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum WriteError {
+    /// {0}
+    Io(#[from] std::io::Error),
+    /// invalid unencrypted metadata: {0}
+    InvalidMetadata(crate::frame::ValidationError),
+    /// {0}
+    Validation(#[from] crate::Error),
+}
+
+/// Writes an encrypted, compressed backup one frame at a time.
+///
+/// Frames are varint-delimited, gzip-compressed, padded to a bucketed length, encrypted with
+/// AES-256-CBC, and followed by an HMAC-SHA256 of the IV and ciphertext, exactly as expected by
+/// [`BackupReader::new_encrypted_compressed`](crate::BackupReader::new_encrypted_compressed).
+/// Compressed output is encrypted and passed on to the underlying writer as soon as a whole block
+/// is available.
+pub struct BackupWriter<W> {
+    writer: W,
+    encoder: GzipEncoder<Vec<u8>>,
+    encryptor: cbc::Encryptor<aes::Aes256>,
+    hmac: Hmac<Sha256>,
+    /// Compressed bytes that don't yet make up a whole AES block.
+    partial_block: Vec<u8>,
+    compressed_len: u64,
+    validator: Option<PartialBackup<ValidateOnly>>,
+    frame_index: usize,
+}
+
+impl<W: AsyncWrite + Unpin> BackupWriter<W> {
+    /// Starts a backup, writing the header and `backup_info`.
+    ///
+    /// If `metadata` is provided, the backup starts with the forward secrecy magic number and
+    /// metadata, which are checked the same way [`FramesReader`] checks them; otherwise the legacy
+    /// format is written. If `validation` is provided, each frame is validated as it's written, as
+    /// it would be when reading a backup for that purpose.
+    pub async fn new_encrypted_compressed(
+        key: &MessageBackupKey,
+        iv: [u8; AES_IV_SIZE],
+        metadata: Option<&MetadataPb>,
+        backup_info: &proto::backup::BackupInfo,
+        validation: Option<Purpose>,
+        mut writer: W,
+    ) -> Result<Self, WriteError> {
+        let MessageBackupKey { hmac_key, aes_key } = key;
+
+        if let Some(metadata) = metadata {
+            let metadata = metadata
+                .write_length_delimited_to_bytes()
+                .map_err(|e| WriteError::Validation(e.into()))?;
+            FramesReader::verify_metadata(&mut futures::io::Cursor::new(&metadata))
+                .await
+                .map_err(WriteError::InvalidMetadata)?;
+            writer.write_all(forward_secrecy::MAGIC_NUMBER).await?;
+            writer.write_all(&metadata).await?;
+        }
+
+        let mut hmac = Hmac::<Sha256>::new_from_slice(hmac_key).expect("correct key size");
+        hmac.update(&iv);
+        writer.write_all(&iv).await?;
+
+        let validator = validation
+            .map(|purpose| PartialBackup::new_validator(backup_info.clone(), purpose))
+            .transpose()
+            .map_err(crate::Error::from)?;
+
+        let mut this = Self {
+            writer,
+            encoder: GzipEncoder::new(Vec::new()),
+            encryptor: cbc::Encryptor::new(aes_key.into(), (&iv).into()),
+            hmac,
+            partial_block: Vec::with_capacity(AES_BLOCK_SIZE),
+            compressed_len: 0,
+            validator,
+            frame_index: 1,
+        };
+        this.write_delimited(
+            &backup_info
+                .write_length_delimited_to_bytes()
+                .map_err(crate::Error::from)?,
+        )
+        .await?;
+        Ok(this)
+    }
+
+    /// Validates (if requested) and writes a single frame.
+    pub async fn write_frame(&mut self, frame: proto::backup::Frame) -> Result<(), WriteError> {
+        let serialized = frame
+            .write_length_delimited_to_bytes()
+            .map_err(crate::Error::from)?;
+        if let Some(validator) = &mut self.validator {
+            validator
+                .add_frame(frame)
+                .map_err(|e| WriteError::Validation(e.into()))?;
+        }
+        self.frame_index += 1;
+        self.write_delimited(&serialized).await
+    }
+
+    /// Like [`write_frame`](Self::write_frame), but for a frame that has already been serialized.
+    ///
+    /// Any unknown fields found while validating are logged.
+    pub async fn write_raw_frame(&mut self, frame: &[u8]) -> Result<(), WriteError> {
+        if let Some(validator) = &mut self.validator {
+            let unknown_fields = validator.parse_and_add_frame(frame, |_| ())?;
+            for entry in unknown_fields
+                .into_iter()
+                .map(FoundUnknownField::in_frame(self.frame_index))
+            {
+                log::warn!("{entry}");
+            }
+        }
+        self.frame_index += 1;
+
+        let length = u64::try_from(frame.len()).expect("usize fits in u64");
+        let mut delimited = Vec::with_capacity(frame.len() + 10);
+        {
+            let mut output = protobuf::CodedOutputStream::vec(&mut delimited);
+            output
+                .write_raw_varint64(length)
+                .and_then(|()| output.flush())
+                .map_err(crate::Error::from)?;
+        }
+        delimited.extend_from_slice(frame);
+        self.write_delimited(&delimited).await
+    }
+
+    /// Pads, encrypts, and authenticates the remaining contents, returning the underlying writer.
+    ///
+    /// If frames were being validated, the backup as a whole is checked before anything more is
+    /// written.
+    pub async fn finish(mut self) -> Result<W, WriteError> {
+        if let Some(validator) = self.validator.take() {
+            let _: CompletedBackup<ValidateOnly> =
+                validator.try_into().map_err(crate::Error::from)?;
+        }
+
+        self.encoder.close().await?;
+        self.encrypt_compressed_output().await?;
+
+        let compressed_len = u32::try_from(self.compressed_len).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "backup is too large to pad",
+            )
+        })?;
+        let mut padding_len =
+            usize::try_from(crate::padded_length(compressed_len).saturating_sub(compressed_len))
+                .expect("usize >= u32");
+        while padding_len > 0 {
+            let chunk_len = padding_len.min(PADDING_CHUNK_SIZE);
+            self.encrypt_and_write(vec![0; chunk_len]).await?;
+            padding_len -= chunk_len;
+        }
+
+        let Self {
+            mut writer,
+            encryptor,
+            mut hmac,
+            partial_block,
+            ..
+        } = self;
+
+        let mut last_block = [0; AES_BLOCK_SIZE];
+        last_block[..partial_block.len()].copy_from_slice(&partial_block);
+        let last_block = encryptor
+            .encrypt_padded_mut::<Pkcs7>(&mut last_block, partial_block.len())
+            .expect("fewer than a block's worth of bytes left");
+        hmac.update(last_block);
+        writer.write_all(last_block).await?;
+
+        writer.write_all(&hmac.finalize().into_bytes()).await?;
+        writer.flush().await?;
+        Ok(writer)
+    }
+
+    async fn write_delimited(&mut self, delimited: &[u8]) -> Result<(), WriteError> {
+        self.encoder.write_all(delimited).await?;
+        self.encrypt_compressed_output().await?;
+        Ok(())
+    }
+
+    /// Moves whatever the compressor has produced so far on to the underlying writer.
+    async fn encrypt_compressed_output(&mut self) -> std::io::Result<()> {
+        let compressed = std::mem::take(self.encoder.get_mut());
+        self.compressed_len += u64::try_from(compressed.len()).expect("usize fits in u64");
+        self.encrypt_and_write(compressed).await
+    }
+
+    /// Encrypts and writes all of `contents` that, along with any previous partial block, makes up
+    /// whole blocks, saving the rest for next time.
+    async fn encrypt_and_write(&mut self, contents: Vec<u8>) -> std::io::Result<()> {
+        let mut buf = std::mem::take(&mut self.partial_block);
+        buf.extend_from_slice(&contents);
+
+        let whole_blocks_len = buf.len() - buf.len() % AES_BLOCK_SIZE;
+        self.partial_block = buf.split_off(whole_blocks_len);
+        if buf.is_empty() {
+            return Ok(());
+        }
+
+        for block in buf.chunks_exact_mut(AES_BLOCK_SIZE) {
+            self.encryptor
+                .encrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+        self.hmac.update(&buf);
+        self.writer.write_all(&buf).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::BackupReader;
+    use crate::frame::CursorFactory;
+    use crate::key::test::FAKE_MESSAGE_BACKUP_KEY;
+    use crate::parse::VarintDelimitedReader;
+
+    const CANONICAL_BACKUP: &[u8] = include_bytes!("../tests/res/canonical-backup.binproto");
+
+    fn canonical_frames() -> (proto::backup::BackupInfo, Vec<Box<[u8]>>) {
+        let mut reader = VarintDelimitedReader::new(CANONICAL_BACKUP);
+        let backup_info = block_on(reader.read_next())
+            .expect("can read")
+            .expect("has backup info");
+        let mut frames = vec![];
+        while let Some(frame) = block_on(reader.read_next()).expect("can read") {
+            frames.push(frame);
+        }
+        (
+            proto::backup::BackupInfo::parse_from_bytes(&backup_info).expect("valid"),
+            frames,
+        )
+    }
+
+    fn write_canonical(metadata: Option<&MetadataPb>, reserialize: bool) -> Vec<u8> {
+        let (backup_info, frames) = canonical_frames();
+        block_on(async {
+            let mut writer = BackupWriter::new_encrypted_compressed(
+                &FAKE_MESSAGE_BACKUP_KEY,
+                [0x11; AES_IV_SIZE],
+                metadata,
+                &backup_info,
+                Some(Purpose::RemoteBackup),
+                Vec::new(),
+            )
+            .await
+            .expect("valid header");
+            for frame in frames {
+                if reserialize {
+                    let frame = proto::backup::Frame::parse_from_bytes(&frame).expect("valid");
+                    writer.write_frame(frame).await
+                } else {
+                    writer.write_raw_frame(&frame).await
+                }
+                .expect("valid frame");
+            }
+            writer.finish().await.expect("valid backup")
+        })
+    }
+
+    fn read_back(written: &[u8]) -> Vec<u8> {
+        let mut reader = block_on(FramesReader::new(
+            &FAKE_MESSAGE_BACKUP_KEY,
+            CursorFactory::new(&written),
+        ))
+        .expect("valid HMAC");
+        let mut contents = vec![];
+        block_on(futures::AsyncReadExt::read_to_end(
+            &mut reader,
+            &mut contents,
+        ))
+        .expect("can read");
+
+        let reader = block_on(BackupReader::new_encrypted_compressed(
+            &FAKE_MESSAGE_BACKUP_KEY,
+            CursorFactory::new(&written),
+            Purpose::RemoteBackup,
+        ))
+        .expect("valid HMAC");
+        block_on(reader.validate_all())
+            .result
+            .expect("valid backup");
+
+        contents
+    }
+
+    #[test]
+    fn round_trip() {
+        let metadata = forward_secrecy::test::test_metadata();
+        for metadata in [None, Some(&metadata)] {
+            let written = write_canonical(metadata, false);
+            assert_eq!(
+                metadata.is_some(),
+                written.starts_with(forward_secrecy::MAGIC_NUMBER)
+            );
+            assert_eq!(read_back(&written), CANONICAL_BACKUP);
+        }
+    }
+
+    #[test]
+    fn round_trip_from_protos() {
+        // Re-serializing isn't guaranteed to produce the same bytes, so just check validity.
+        let _ = read_back(&write_canonical(None, true));
+    }
+
+    #[test]
+    fn rejects_invalid_frames() {
+        let (backup_info, _frames) = canonical_frames();
+        let mut writer = block_on(BackupWriter::new_encrypted_compressed(
+            &FAKE_MESSAGE_BACKUP_KEY,
+            [0x11; AES_IV_SIZE],
+            None,
+            &backup_info,
+            Some(Purpose::RemoteBackup),
+            Vec::new(),
+        ))
+        .expect("valid header");
+        assert_matches!(
+            block_on(writer.write_frame(proto::backup::Frame::new())),
+            Err(WriteError::Validation(crate::Error::BackupValidation(_)))
+        );
+
+        // Without validation, anything goes.
+        let mut writer = block_on(BackupWriter::new_encrypted_compressed(
+            &FAKE_MESSAGE_BACKUP_KEY,
+            [0x11; AES_IV_SIZE],
+            None,
+            &backup_info,
+            None,
+            Vec::new(),
+        ))
+        .expect("valid header");
+        block_on(writer.write_frame(proto::backup::Frame::new())).expect("not validated");
+        block_on(writer.finish()).expect("not validated");
+    }
+
+    #[test]
+    fn rejects_invalid_metadata() {
+        let (backup_info, _frames) = canonical_frames();
+        assert_matches!(
+            block_on(BackupWriter::new_encrypted_compressed(
+                &FAKE_MESSAGE_BACKUP_KEY,
+                [0x11; AES_IV_SIZE],
+                Some(&MetadataPb::new()),
+                &backup_info,
+                None,
+                Vec::new(),
+            )),
+            Err(WriteError::InvalidMetadata(
+                crate::frame::ValidationError::MissingMetadataField("pair")
+            ))
+        );
+    }
+}
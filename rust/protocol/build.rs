@@ -9,6 +9,7 @@ fn main() {
         "src/proto/sealed_sender.proto",
         "src/proto/service.proto",
         "src/proto/storage.proto",
+        "src/proto/transcript.proto",
         "src/proto/wire.proto",
     ];
     let mut prost_build = prost_build::Config::new();
@@ -0,0 +1,535 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Streaming encryption and decryption of attachments.
+//!
+//! An encrypted attachment is `IV || AES-256-CBC(plaintext) || HMAC-SHA256(IV || ciphertext)`,
+//! where the AES and HMAC keys are the two halves of a single attachment key. The encryptor derives
+//! the IV from the attachment key as well, so a key must only ever be used for one attachment; the
+//! decryptor reads the IV from the start of the encrypted attachment.
+//!
+//! Before encryption the plaintext is zero-padded up to a size bucket (see [`padded_size`]), so
+//! that the encrypted size reveals less about the original. Alongside the encrypted blob, the
+//! sender produces a SHA-256 digest of the whole blob and an
+//! [incremental MAC](crate::incremental_mac) over it, which lets the receiver validate each chunk
+//! before decrypting it rather than only after downloading everything.
+
+use aes::Aes256;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use futures_util::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::incremental_mac::{Incremental, calculate_chunk_size};
+
+/// The length of the combined attachment key: a 32-byte AES key followed by a 32-byte HMAC key.
+pub const ATTACHMENT_KEY_LEN: usize = 64;
+/// The length of the AES-CBC IV at the start of an encrypted attachment.
+pub const ATTACHMENT_IV_LEN: usize = 16;
+/// The length of [`EncryptedAttachmentInfo::digest`].
+pub const ATTACHMENT_DIGEST_LEN: usize = 32;
+
+const AES_KEY_LEN: usize = 32;
+const AES_BLOCK_LEN: usize = 16;
+const HMAC_LEN: usize = 32;
+const BUFFER_LEN: usize = 64 * 1024;
+
+#[derive(Debug, displaydoc::Display, thiserror::Error)]
+pub enum AttachmentCipherError {
+    /// IO error: {0}
+    Io(#[from] std::io::Error),
+    /// plaintext length mismatch: expected {expected} bytes, read {actual}
+    PlaintextLengthMismatch { expected: u64, actual: u64 },
+    /// invalid incremental MAC parameters
+    InvalidIncrementalMac,
+    /// incremental MAC verification failed
+    ChunkMacMismatch,
+    /// attachment MAC verification failed
+    MacMismatch,
+    /// attachment digest mismatch
+    DigestMismatch,
+    /// invalid encrypted attachment: {0}
+    InvalidCiphertext(&'static str),
+}
+
+/// Integrity information produced when encrypting an attachment.
+///
+/// Everything here except [`encrypted_len`](Self::encrypted_len) is needed to decrypt the
+/// attachment again; it is normally sent along with the attachment key.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncryptedAttachmentInfo {
+    /// The length of the attachment before padding and encryption.
+    pub plaintext_len: u64,
+    /// The length of the encrypted attachment, including the IV and MAC.
+    pub encrypted_len: u64,
+    /// SHA-256 of the entire encrypted attachment.
+    pub digest: [u8; ATTACHMENT_DIGEST_LEN],
+    /// The chunk size used for the incremental MAC.
+    pub incremental_mac_chunk_size: usize,
+    /// The concatenated incremental MAC digests.
+    pub incremental_mac: Vec<u8>,
+}
+
+/// Returns the size an attachment of `plaintext_len` bytes is padded to before encryption.
+///
+/// Sizes are rounded up to the next power of 1.05, with a minimum of 541 bytes.
+#[expect(clippy::cast_possible_truncation)]
+pub fn padded_size(plaintext_len: u64) -> u64 {
+    let bucket = 1.05f64
+        .powf((plaintext_len as f64).log(1.05).ceil())
+        .floor() as u64;
+    bucket.max(541).max(plaintext_len)
+}
+
+/// Returns the size of the encrypted attachment for `plaintext_len` bytes of plaintext.
+pub fn encrypted_size(plaintext_len: u64) -> u64 {
+    let block_len = AES_BLOCK_LEN as u64;
+    // PKCS7 padding always adds at least one byte.
+    let ciphertext_len = (padded_size(plaintext_len) / block_len + 1) * block_len;
+    ATTACHMENT_IV_LEN as u64 + ciphertext_len + HMAC_LEN as u64
+}
+
+fn split_key(key: &[u8; ATTACHMENT_KEY_LEN]) -> (&[u8; AES_KEY_LEN], &[u8]) {
+    let (aes_key, hmac_key) = key.split_first_chunk::<AES_KEY_LEN>().expect("long enough");
+    (aes_key, hmac_key)
+}
+
+fn derive_iv(key: &[u8; ATTACHMENT_KEY_LEN]) -> [u8; ATTACHMENT_IV_LEN] {
+    const INFO: &[u8] = b"Signal_Attachment_IV";
+    let mut iv = [0; ATTACHMENT_IV_LEN];
+    hkdf::Hkdf::<Sha256>::new(None, key)
+        .expand(INFO, &mut iv)
+        .expect("valid output length");
+    iv
+}
+
+fn new_hmac(key: &[u8]) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(key).expect("HMAC-SHA256 should accept any size key")
+}
+
+/// Encrypts `plaintext_len` bytes read from `plaintext`, writing the result to `output`.
+///
+/// `key` should be freshly generated for each attachment, since the IV is derived from it.
+///
+/// Fails with [`AttachmentCipherError::PlaintextLengthMismatch`] if `plaintext` does not produce
+/// exactly `plaintext_len` bytes; by then some of the encrypted attachment may already have been
+/// written.
+pub async fn encrypt_attachment<R, W>(
+    key: &[u8; ATTACHMENT_KEY_LEN],
+    plaintext_len: u64,
+    mut plaintext: R,
+    output: W,
+) -> Result<EncryptedAttachmentInfo, AttachmentCipherError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let (aes_key, hmac_key) = split_key(key);
+    let iv = derive_iv(key);
+    let encrypted_len = encrypted_size(plaintext_len);
+    let chunk_size =
+        calculate_chunk_size::<Sha256>(usize::try_from(encrypted_len).unwrap_or(usize::MAX));
+
+    let mut writer = EncryptingWriter {
+        output,
+        encryptor: cbc::Encryptor::new(aes_key.into(), &iv.into()),
+        hmac: new_hmac(hmac_key),
+        digest: Sha256::new(),
+        incremental: Incremental::new(new_hmac(hmac_key), chunk_size),
+        incremental_mac: Vec::new(),
+        partial_block: Vec::with_capacity(AES_BLOCK_LEN),
+    };
+    writer.hmac.update(&iv);
+    writer.write_raw(&iv).await?;
+
+    let mut buffer = vec![0; BUFFER_LEN];
+    let mut actual_len = 0u64;
+    loop {
+        let read = plaintext.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        actual_len += read as u64;
+        if actual_len > plaintext_len {
+            return Err(AttachmentCipherError::PlaintextLengthMismatch {
+                expected: plaintext_len,
+                actual: actual_len,
+            });
+        }
+        writer.write_encrypted(&buffer[..read]).await?;
+    }
+    if actual_len != plaintext_len {
+        return Err(AttachmentCipherError::PlaintextLengthMismatch {
+            expected: plaintext_len,
+            actual: actual_len,
+        });
+    }
+
+    buffer.fill(0);
+    let mut padding_len = padded_size(plaintext_len) - plaintext_len;
+    while padding_len > 0 {
+        let chunk_len = usize::try_from(padding_len).map_or(BUFFER_LEN, |n| n.min(BUFFER_LEN));
+        writer.write_encrypted(&buffer[..chunk_len]).await?;
+        padding_len -= chunk_len as u64;
+    }
+
+    let pkcs7_len = AES_BLOCK_LEN - writer.partial_block.len();
+    let pkcs7_byte = u8::try_from(pkcs7_len).expect("block size fits in a byte");
+    writer.write_encrypted(&vec![pkcs7_byte; pkcs7_len]).await?;
+    debug_assert!(writer.partial_block.is_empty());
+
+    let mac = writer.hmac.clone().finalize().into_bytes();
+    writer.write_raw(&mac).await?;
+    writer.output.flush().await?;
+
+    let EncryptingWriter {
+        digest,
+        incremental,
+        mut incremental_mac,
+        ..
+    } = writer;
+    incremental_mac.extend_from_slice(&incremental.finalize());
+
+    Ok(EncryptedAttachmentInfo {
+        plaintext_len,
+        encrypted_len,
+        digest: digest.finalize().into(),
+        incremental_mac_chunk_size: chunk_size,
+        incremental_mac,
+    })
+}
+
+struct EncryptingWriter<W> {
+    output: W,
+    encryptor: cbc::Encryptor<Aes256>,
+    hmac: Hmac<Sha256>,
+    digest: Sha256,
+    incremental: Incremental<Hmac<Sha256>>,
+    incremental_mac: Vec<u8>,
+    /// Plaintext that doesn't yet fill a whole block.
+    partial_block: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> EncryptingWriter<W> {
+    async fn write_encrypted(&mut self, plaintext: &[u8]) -> std::io::Result<()> {
+        let mut blocks = std::mem::take(&mut self.partial_block);
+        blocks.extend_from_slice(plaintext);
+        let whole_blocks_len = blocks.len() - blocks.len() % AES_BLOCK_LEN;
+        self.partial_block = blocks.split_off(whole_blocks_len);
+
+        for block in blocks.chunks_exact_mut(AES_BLOCK_LEN) {
+            self.encryptor
+                .encrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+        self.hmac.update(&blocks);
+        self.write_raw(&blocks).await
+    }
+
+    async fn write_raw(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.digest.update(bytes);
+        for mac in self.incremental.update(bytes) {
+            self.incremental_mac.extend_from_slice(&mac);
+        }
+        self.output.write_all(bytes).await
+    }
+}
+
+/// Decrypts an attachment read from `ciphertext`, writing the plaintext to `output`.
+///
+/// Each chunk of `ciphertext` is checked against the incremental MAC in `info` before any of it is
+/// decrypted, so only authenticated plaintext is ever written to `output`. The attachment's own
+/// MAC and digest are checked as well before the final block is written; if any check fails,
+/// `output` may hold a prefix of the plaintext that should be discarded.
+pub async fn decrypt_attachment<R, W>(
+    key: &[u8; ATTACHMENT_KEY_LEN],
+    info: &EncryptedAttachmentInfo,
+    mut ciphertext: R,
+    mut output: W,
+) -> Result<(), AttachmentCipherError>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let (aes_key, hmac_key) = split_key(key);
+
+    if info.incremental_mac_chunk_size == 0
+        || info.incremental_mac.is_empty()
+        || info.incremental_mac.len() % HMAC_LEN != 0
+    {
+        return Err(AttachmentCipherError::InvalidIncrementalMac);
+    }
+    let expected_macs = info
+        .incremental_mac
+        .chunks_exact(HMAC_LEN)
+        .map(|mac| <&[u8; HMAC_LEN]>::try_from(mac).expect("chunks_exact produces exact chunks"));
+    let mut validating = Incremental::new(new_hmac(hmac_key), info.incremental_mac_chunk_size)
+        .validating(expected_macs);
+
+    let mut reader = DecryptingReader {
+        aes_key,
+        decryptor: None,
+        hmac: new_hmac(hmac_key),
+        validated: Vec::new(),
+        plaintext_remaining: info.plaintext_len,
+    };
+    let mut digest = Sha256::new();
+    // Bytes that have been read but are not yet covered by a complete incremental MAC chunk.
+    let mut unvalidated = Vec::new();
+
+    let mut buffer = vec![0; BUFFER_LEN];
+    loop {
+        let read = ciphertext.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        digest.update(&buffer[..read]);
+        unvalidated.extend_from_slice(&buffer[..read]);
+        let validated_len = validating
+            .update(&buffer[..read])
+            .map_err(|_| AttachmentCipherError::ChunkMacMismatch)?;
+        reader.validated.extend(unvalidated.drain(..validated_len));
+
+        // Keep back the MAC and the last block, which has to be unpadded.
+        let decrypted = reader.decrypt_validated(HMAC_LEN + AES_BLOCK_LEN);
+        let plaintext = reader.take_plaintext(decrypted);
+        output.write_all(&plaintext).await?;
+    }
+
+    validating
+        .finalize()
+        .map_err(|_| AttachmentCipherError::ChunkMacMismatch)?;
+    reader.validated.append(&mut unvalidated);
+
+    let mut last_blocks = reader.decrypt_validated(HMAC_LEN);
+    if reader.decryptor.is_none() || reader.validated.len() != HMAC_LEN {
+        return Err(AttachmentCipherError::InvalidCiphertext(
+            "ciphertext is not a whole number of blocks",
+        ));
+    }
+    let our_mac = reader.hmac.clone().finalize().into_bytes();
+    if !bool::from(our_mac.ct_eq(&reader.validated)) {
+        return Err(AttachmentCipherError::MacMismatch);
+    }
+    if !bool::from(digest.finalize().ct_eq(&info.digest)) {
+        return Err(AttachmentCipherError::DigestMismatch);
+    }
+
+    let pkcs7_len = usize::from(*last_blocks.last().ok_or(
+        AttachmentCipherError::InvalidCiphertext("missing final block"),
+    )?);
+    if pkcs7_len == 0
+        || pkcs7_len > AES_BLOCK_LEN
+        || last_blocks[last_blocks.len() - pkcs7_len..]
+            .iter()
+            .any(|&b| usize::from(b) != pkcs7_len)
+    {
+        return Err(AttachmentCipherError::InvalidCiphertext("invalid padding"));
+    }
+    last_blocks.truncate(last_blocks.len() - pkcs7_len);
+    let last_plaintext = reader.take_plaintext(last_blocks);
+    if reader.plaintext_remaining != 0 {
+        return Err(AttachmentCipherError::InvalidCiphertext(
+            "attachment is shorter than its plaintext length",
+        ));
+    }
+    output.write_all(&last_plaintext).await?;
+    output.flush().await?;
+
+    Ok(())
+}
+
+struct DecryptingReader<'a> {
+    aes_key: &'a [u8; AES_KEY_LEN],
+    /// Set up once the IV has been read.
+    decryptor: Option<cbc::Decryptor<Aes256>>,
+    hmac: Hmac<Sha256>,
+    /// Bytes that have passed incremental MAC validation but have not been decrypted yet.
+    validated: Vec<u8>,
+    plaintext_remaining: u64,
+}
+
+impl DecryptingReader<'_> {
+    /// Decrypts as many whole blocks of validated ciphertext as possible, leaving at least
+    /// `hold_back` bytes undecrypted.
+    fn decrypt_validated(&mut self, hold_back: usize) -> Vec<u8> {
+        if self.decryptor.is_none() {
+            let Some(iv) = self.validated.first_chunk::<ATTACHMENT_IV_LEN>() else {
+                return Vec::new();
+            };
+            self.hmac.update(iv);
+            self.decryptor = Some(cbc::Decryptor::new(self.aes_key.into(), iv.into()));
+            self.validated.drain(..ATTACHMENT_IV_LEN);
+        }
+        let decryptor = self.decryptor.as_mut().expect("set up above");
+
+        let available = self.validated.len().saturating_sub(hold_back);
+        let mut blocks: Vec<u8> = self
+            .validated
+            .drain(..available - available % AES_BLOCK_LEN)
+            .collect();
+        self.hmac.update(&blocks);
+        for block in blocks.chunks_exact_mut(AES_BLOCK_LEN) {
+            decryptor.decrypt_block_mut(GenericArray::from_mut_slice(block));
+        }
+        blocks
+    }
+
+    /// Drops anything past the end of the plaintext, which can only be zero padding.
+    fn take_plaintext(&mut self, mut decrypted: Vec<u8>) -> Vec<u8> {
+        let len = usize::try_from(self.plaintext_remaining)
+            .map_or(decrypted.len(), |remaining| remaining.min(decrypted.len()));
+        decrypted.truncate(len);
+        self.plaintext_remaining -= len as u64;
+        decrypted
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use futures_util::FutureExt as _;
+    use rand::{Rng as _, TryRngCore as _};
+
+    use super::*;
+    use crate::crypto::hmac_sha256;
+
+    const TEST_KEY: [u8; ATTACHMENT_KEY_LEN] = [0x42; ATTACHMENT_KEY_LEN];
+
+    fn encrypt(plaintext: &[u8]) -> (Vec<u8>, EncryptedAttachmentInfo) {
+        let mut ciphertext = Vec::new();
+        let info = encrypt_attachment(
+            &TEST_KEY,
+            plaintext.len() as u64,
+            plaintext,
+            &mut ciphertext,
+        )
+        .now_or_never()
+        .expect("sync")
+        .expect("can encrypt");
+        (ciphertext, info)
+    }
+
+    fn decrypt(
+        ciphertext: &[u8],
+        info: &EncryptedAttachmentInfo,
+    ) -> Result<Vec<u8>, AttachmentCipherError> {
+        let mut plaintext = Vec::new();
+        decrypt_attachment(&TEST_KEY, info, ciphertext, &mut plaintext)
+            .now_or_never()
+            .expect("sync")?;
+        Ok(plaintext)
+    }
+
+    fn random_plaintext(len: usize) -> Vec<u8> {
+        let mut plaintext = vec![0; len];
+        rand::rngs::OsRng.unwrap_err().fill(&mut plaintext[..]);
+        plaintext
+    }
+
+    #[test]
+    fn round_trip() {
+        for len in [0, 1, 15, 16, 541, 542, 100_000, 1_000_000] {
+            let plaintext = random_plaintext(len);
+            let (ciphertext, info) = encrypt(&plaintext);
+
+            assert_eq!(ciphertext.len() as u64, info.encrypted_len);
+            assert_eq!(info.encrypted_len, encrypted_size(len as u64));
+            assert_eq!(
+                info.digest,
+                <[u8; 32]>::from(Sha256::digest(&ciphertext)),
+                "len {len}"
+            );
+            assert_eq!(decrypt(&ciphertext, &info).expect("valid"), plaintext);
+        }
+    }
+
+    #[test]
+    fn matches_one_shot_encryption() {
+        let plaintext = random_plaintext(1000);
+        let (ciphertext, _info) = encrypt(&plaintext);
+
+        let (iv, rest) = ciphertext.split_at(ATTACHMENT_IV_LEN);
+        let (body, mac) = rest.split_at(rest.len() - HMAC_LEN);
+        assert_eq!(iv, derive_iv(&TEST_KEY));
+        assert_eq!(
+            mac,
+            hmac_sha256(
+                &TEST_KEY[AES_KEY_LEN..],
+                &ciphertext[..ciphertext.len() - HMAC_LEN]
+            )
+        );
+
+        let decrypted =
+            signal_crypto::aes_256_cbc_decrypt(body, &TEST_KEY[..AES_KEY_LEN], iv).expect("valid");
+        assert_eq!(decrypted.len() as u64, padded_size(1000));
+        assert_eq!(&decrypted[..1000], plaintext);
+        assert!(decrypted[1000..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn tampering_is_detected_before_decryption() {
+        let plaintext = random_plaintext(300_000);
+        let (mut ciphertext, info) = encrypt(&plaintext);
+        let chunk_size = info.incremental_mac_chunk_size;
+        ciphertext[chunk_size + 1] ^= 1;
+
+        let mut output = Vec::new();
+        let result = decrypt_attachment(&TEST_KEY, &info, &ciphertext[..], &mut output)
+            .now_or_never()
+            .expect("sync");
+        assert_matches!(result, Err(AttachmentCipherError::ChunkMacMismatch));
+        // Only plaintext from the first, untouched chunk may have been written.
+        assert!(output.len() < chunk_size);
+        assert_eq!(output, plaintext[..output.len()]);
+    }
+
+    #[test]
+    fn wrong_digest_or_length() {
+        let plaintext = random_plaintext(5000);
+        let (ciphertext, info) = encrypt(&plaintext);
+
+        let mut wrong_digest = info.clone();
+        wrong_digest.digest[0] ^= 1;
+        assert_matches!(
+            decrypt(&ciphertext, &wrong_digest),
+            Err(AttachmentCipherError::DigestMismatch)
+        );
+
+        let mut truncated_mac = info.clone();
+        truncated_mac.incremental_mac.pop();
+        assert_matches!(
+            decrypt(&ciphertext, &truncated_mac),
+            Err(AttachmentCipherError::InvalidIncrementalMac)
+        );
+
+        let mut too_long = info.clone();
+        too_long.plaintext_len = padded_size(5000) + 1;
+        assert_matches!(
+            decrypt(&ciphertext, &too_long),
+            Err(AttachmentCipherError::InvalidCiphertext(_))
+        );
+
+        assert_matches!(
+            decrypt(&ciphertext[..ciphertext.len() - 1], &info),
+            Err(AttachmentCipherError::ChunkMacMismatch)
+        );
+    }
+
+    #[test]
+    fn plaintext_length_must_match() {
+        let result = encrypt_attachment(&TEST_KEY, 10, &[0u8; 9][..], Vec::new())
+            .now_or_never()
+            .expect("sync");
+        assert_matches!(
+            result,
+            Err(AttachmentCipherError::PlaintextLengthMismatch {
+                expected: 10,
+                actual: 9
+            })
+        );
+    }
+}
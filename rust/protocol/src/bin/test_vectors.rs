@@ -0,0 +1,661 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Generates and checks deterministic test vectors for the protocol wire formats.
+//!
+//! Every key, nonce, and signature is drawn from a ChaCha20 RNG seeded from the command line, and
+//! every timestamp is fixed, so the same seed always produces byte-identical output. The vectors
+//! include the private keys of both parties, which lets another implementation decrypt each
+//! message and check that it produces the same plaintext, even if it consumes randomness in a
+//! different order than this one.
+
+use std::io::{Read as _, Write as _};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+
+use clap::Parser;
+use futures_util::FutureExt as _;
+use libsignal_protocol::*;
+use rand::{CryptoRng, Rng, SeedableRng as _};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+const ALICE_UUID: &str = "9d0652a3-dcc3-4d11-975f-74d61598733f";
+const ALICE_E164: &str = "+14151111111";
+const ALICE_REGISTRATION_ID: u32 = 1111;
+const BOB_UUID: &str = "796abedb-ca4e-4f18-8803-1fde5b921f9f";
+const BOB_REGISTRATION_ID: u32 = 2222;
+
+const NOW_MILLIS: u64 = 1_700_000_000_000;
+const SENDER_CERTIFICATE_EXPIRATION_MILLIS: u64 = NOW_MILLIS + 24 * 60 * 60 * 1000;
+const SERVER_KEY_ID: u32 = 1;
+
+const FINGERPRINT_VERSION: u32 = 2;
+const FINGERPRINT_ITERATIONS: u32 = 5200;
+
+/// Generates or verifies test vectors for SignalMessage, PreKeySignalMessage, SenderKeyMessage,
+/// sealed sender v1 and v2, and Fingerprint.
+///
+/// Vectors are written as JSON, with all binary values hex-encoded.
+#[derive(clap::Parser)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Writes a set of vectors to stdout.
+    Generate {
+        /// the seed for the RNG that every random value is drawn from
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Reads a set of vectors on stdin and checks that regenerating them from the same seed
+    /// produces identical output.
+    Verify,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TestVectors {
+    seed: u64,
+    timestamp: u64,
+    alice: Party,
+    bob: Party,
+    bob_pre_keys: PreKeys,
+    session: SessionVector,
+    sealed_sender: SealedSenderVector,
+    sender_key: SenderKeyVector,
+    fingerprint: FingerprintVector,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Party {
+    uuid: String,
+    device_id: u32,
+    registration_id: u32,
+    identity_key_pair: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PreKeys {
+    pre_key_id: u32,
+    pre_key_private: String,
+    signed_pre_key_id: u32,
+    signed_pre_key_private: String,
+    signed_pre_key_signature: String,
+    kyber_pre_key_id: u32,
+    kyber_pre_key_public: String,
+    kyber_pre_key_secret: String,
+    kyber_pre_key_signature: String,
+}
+
+/// Alice starts a session with Bob, and Bob replies.
+#[derive(Serialize, Deserialize)]
+struct SessionVector {
+    plaintext: String,
+    pre_key_signal_message: String,
+    reply_plaintext: String,
+    signal_message: String,
+}
+
+/// Alice sends sealed sender messages to Bob over the session established in [`SessionVector`].
+#[derive(Serialize, Deserialize)]
+struct SealedSenderVector {
+    trust_root_public: String,
+    server_certificate: String,
+    sender_certificate: String,
+    v1_plaintext: String,
+    v1_ciphertext: String,
+    v2_plaintext: String,
+    v2_group_id: String,
+    v2_sent_message: String,
+    v2_received_message: String,
+}
+
+/// Alice distributes a sender key to Bob and sends a group message.
+#[derive(Serialize, Deserialize)]
+struct SenderKeyVector {
+    distribution_id: String,
+    sender_key_distribution_message: String,
+    plaintext: String,
+    sender_key_message: String,
+}
+
+/// The safety number Alice would display for Bob.
+#[derive(Serialize, Deserialize)]
+struct FingerprintVector {
+    version: u32,
+    iterations: u32,
+    local_identifier: String,
+    remote_identifier: String,
+    display: String,
+    scannable: String,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    env_logger::init();
+
+    match cli.command {
+        Command::Generate { seed } => {
+            let vectors = generate(seed).expect("can generate vectors");
+            std::io::stdout()
+                .write_all(to_json(&vectors).as_bytes())
+                .expect("can write to stdout");
+            ExitCode::SUCCESS
+        }
+        Command::Verify => {
+            let mut input = String::new();
+            std::io::stdin()
+                .read_to_string(&mut input)
+                .expect("can read stdin");
+            let expected: serde_json::Value =
+                serde_json::from_str(&input).expect("input is valid JSON");
+            let seed = serde_json::from_value::<TestVectors>(expected.clone())
+                .expect("input has the expected structure")
+                .seed;
+
+            let actual =
+                serde_json::to_value(generate(seed).expect("can generate vectors")).expect("valid");
+
+            let mut mismatches = vec![];
+            find_mismatches("", &expected, &actual, &mut mismatches);
+            if mismatches.is_empty() {
+                eprintln!("all vectors match");
+                return ExitCode::SUCCESS;
+            }
+            for path in mismatches {
+                eprintln!("mismatch at {path}");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Formats `vectors` exactly as the `generate` command writes them.
+fn to_json(vectors: &TestVectors) -> String {
+    let mut json = serde_json::to_string_pretty(vectors).expect("can serialize");
+    json.push('\n');
+    json
+}
+
+fn find_mismatches(
+    path: &str,
+    expected: &serde_json::Value,
+    actual: &serde_json::Value,
+    mismatches: &mut Vec<String>,
+) {
+    match (expected, actual) {
+        (serde_json::Value::Object(expected), serde_json::Value::Object(actual)) => {
+            for (key, expected_value) in expected {
+                let path = format!("{path}/{key}");
+                match actual.get(key) {
+                    Some(actual_value) => {
+                        find_mismatches(&path, expected_value, actual_value, mismatches)
+                    }
+                    None => mismatches.push(path),
+                }
+            }
+            mismatches.extend(
+                actual
+                    .keys()
+                    .filter(|key| !expected.contains_key(*key))
+                    .map(|key| format!("{path}/{key}")),
+            );
+        }
+        _ if expected == actual => {}
+        _ => mismatches.push(path.to_owned()),
+    }
+}
+
+/// Runs every step in a fixed order, so that each one draws the same values from `rng`.
+fn generate(seed: u64) -> Result<TestVectors, SignalProtocolError> {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    let now = SystemTime::UNIX_EPOCH + Duration::from_millis(NOW_MILLIS);
+
+    let alice_address = ProtocolAddress::new(ALICE_UUID.to_owned(), DeviceId::new(1).unwrap());
+    let bob_address = ProtocolAddress::new(BOB_UUID.to_owned(), DeviceId::new(1).unwrap());
+
+    let alice_identity = IdentityKeyPair::generate(&mut rng);
+    let bob_identity = IdentityKeyPair::generate(&mut rng);
+    let mut alice_store = InMemSignalProtocolStore::new(alice_identity, ALICE_REGISTRATION_ID)?;
+    let mut bob_store = InMemSignalProtocolStore::new(bob_identity, BOB_REGISTRATION_ID)?;
+
+    let (bob_bundle, bob_pre_keys) = create_pre_keys(&mut bob_store, &bob_address, &mut rng)?;
+
+    // PreKeySignalMessage and SignalMessage
+    process_prekey_bundle(
+        &bob_address,
+        &alice_address,
+        &mut alice_store.session_store,
+        &mut alice_store.identity_store,
+        &bob_bundle,
+        now,
+        &mut rng,
+    )
+    .now_or_never()
+    .expect("sync")?;
+
+    let plaintext = b"Hi Bob, this is Alice";
+    let pre_key_signal_message = encrypt(
+        &mut alice_store,
+        &bob_address,
+        &alice_address,
+        plaintext,
+        &mut rng,
+    )?;
+    assert_eq!(
+        pre_key_signal_message.message_type(),
+        CiphertextMessageType::PreKey
+    );
+    let decrypted = decrypt(
+        &mut bob_store,
+        &alice_address,
+        &bob_address,
+        &pre_key_signal_message,
+        &mut rng,
+    )?;
+    assert_eq!(decrypted, plaintext);
+
+    let reply_plaintext = b"Hi Alice, this is Bob";
+    let signal_message = encrypt(
+        &mut bob_store,
+        &alice_address,
+        &bob_address,
+        reply_plaintext,
+        &mut rng,
+    )?;
+    assert_eq!(
+        signal_message.message_type(),
+        CiphertextMessageType::Whisper
+    );
+    let decrypted = decrypt(
+        &mut alice_store,
+        &bob_address,
+        &alice_address,
+        &signal_message,
+        &mut rng,
+    )?;
+    assert_eq!(decrypted, reply_plaintext);
+
+    let session = SessionVector {
+        plaintext: hex::encode(plaintext),
+        pre_key_signal_message: hex::encode(pre_key_signal_message.serialize()),
+        reply_plaintext: hex::encode(reply_plaintext),
+        signal_message: hex::encode(signal_message.serialize()),
+    };
+
+    // Sealed sender v1 and v2
+    let trust_root = KeyPair::generate(&mut rng);
+    let server_key = KeyPair::generate(&mut rng);
+    let server_certificate = ServerCertificate::new(
+        SERVER_KEY_ID,
+        server_key.public_key,
+        &trust_root.private_key,
+        &mut rng,
+    )?;
+    let sender_certificate = SenderCertificate::new(
+        ALICE_UUID.to_owned(),
+        Some(ALICE_E164.to_owned()),
+        *alice_identity.public_key(),
+        alice_address.device_id(),
+        Timestamp::from_epoch_millis(SENDER_CERTIFICATE_EXPIRATION_MILLIS),
+        server_certificate.clone(),
+        &server_key.private_key,
+        &mut rng,
+    )?;
+
+    let v1_plaintext = b"A sealed sender v1 message";
+    let v1_ciphertext = sealed_sender_encrypt(
+        &bob_address,
+        &sender_certificate,
+        v1_plaintext,
+        &mut alice_store.session_store,
+        &mut alice_store.identity_store,
+        now,
+        &mut rng,
+    )
+    .now_or_never()
+    .expect("sync")?;
+    let decrypted = sealed_decrypt(&mut bob_store, &bob_address, &trust_root, &v1_ciphertext)?;
+    assert_eq!(decrypted, v1_plaintext);
+
+    let v2_plaintext = b"A sealed sender v2 message";
+    let v2_group_id: [u8; 32] = rng.random();
+    let v2_contents = encrypt(
+        &mut alice_store,
+        &bob_address,
+        &alice_address,
+        v2_plaintext,
+        &mut rng,
+    )?;
+    let usmc = UnidentifiedSenderMessageContent::new(
+        v2_contents.message_type(),
+        sender_certificate.clone(),
+        v2_contents.serialize().to_vec(),
+        ContentHint::Resendable,
+        Some(v2_group_id.to_vec()),
+    )?;
+    let bob_session = alice_store
+        .session_store
+        .load_session(&bob_address)
+        .now_or_never()
+        .expect("sync")?
+        .expect("session established above");
+    let v2_sent_message = sealed_sender_multi_recipient_encrypt(
+        &[&bob_address],
+        &[&bob_session],
+        [],
+        &usmc,
+        &alice_store.identity_store,
+        &mut rng,
+    )
+    .now_or_never()
+    .expect("sync")?;
+    let v2_received_message = {
+        let sent = SealedSenderV2SentMessage::parse(&v2_sent_message)?;
+        let (_service_id, recipient) = sent.recipients.first().expect("one recipient");
+        sent.received_message_parts_for_recipient(recipient)
+            .as_ref()
+            .concat()
+    };
+    let decrypted = sealed_decrypt(
+        &mut bob_store,
+        &bob_address,
+        &trust_root,
+        &v2_received_message,
+    )?;
+    assert_eq!(decrypted, v2_plaintext);
+
+    let sealed_sender = SealedSenderVector {
+        trust_root_public: hex::encode(trust_root.public_key.serialize()),
+        server_certificate: hex::encode(server_certificate.serialized()?),
+        sender_certificate: hex::encode(sender_certificate.serialized()?),
+        v1_plaintext: hex::encode(v1_plaintext),
+        v1_ciphertext: hex::encode(&v1_ciphertext),
+        v2_plaintext: hex::encode(v2_plaintext),
+        v2_group_id: hex::encode(v2_group_id),
+        v2_sent_message: hex::encode(&v2_sent_message),
+        v2_received_message: hex::encode(&v2_received_message),
+    };
+
+    // SenderKeyDistributionMessage and SenderKeyMessage
+    let distribution_id = uuid::Uuid::from_bytes(rng.random());
+    let skdm = create_sender_key_distribution_message(
+        &alice_address,
+        distribution_id,
+        &mut alice_store.sender_key_store,
+        &mut rng,
+    )
+    .now_or_never()
+    .expect("sync")?;
+    process_sender_key_distribution_message(&alice_address, &skdm, &mut bob_store.sender_key_store)
+        .now_or_never()
+        .expect("sync")?;
+
+    let group_plaintext = b"A message to the group";
+    let sender_key_message = group_encrypt(
+        &mut alice_store.sender_key_store,
+        &alice_address,
+        distribution_id,
+        group_plaintext,
+        &mut rng,
+    )
+    .now_or_never()
+    .expect("sync")?;
+    let decrypted = group_decrypt(
+        sender_key_message.serialized(),
+        &mut bob_store.sender_key_store,
+        &alice_address,
+    )
+    .now_or_never()
+    .expect("sync")?;
+    assert_eq!(decrypted, group_plaintext);
+
+    let sender_key = SenderKeyVector {
+        distribution_id: distribution_id.to_string(),
+        sender_key_distribution_message: hex::encode(skdm.serialized()),
+        plaintext: hex::encode(group_plaintext),
+        sender_key_message: hex::encode(sender_key_message.serialized()),
+    };
+
+    // Fingerprint
+    let alice_aci = Aci::parse_from_service_id_string(ALICE_UUID).expect("valid");
+    let bob_aci = Aci::parse_from_service_id_string(BOB_UUID).expect("valid");
+    let local_identifier = alice_aci.service_id_binary();
+    let remote_identifier = bob_aci.service_id_binary();
+    let fingerprint = Fingerprint::new(
+        FINGERPRINT_VERSION,
+        FINGERPRINT_ITERATIONS,
+        &local_identifier,
+        alice_identity.identity_key(),
+        &remote_identifier,
+        bob_identity.identity_key(),
+    )
+    .expect("valid fingerprint inputs");
+    let fingerprint = FingerprintVector {
+        version: FINGERPRINT_VERSION,
+        iterations: FINGERPRINT_ITERATIONS,
+        local_identifier: hex::encode(local_identifier),
+        remote_identifier: hex::encode(remote_identifier),
+        display: fingerprint.display_string().expect("can display"),
+        scannable: hex::encode(fingerprint.scannable.serialize().expect("can serialize")),
+    };
+
+    Ok(TestVectors {
+        seed,
+        timestamp: NOW_MILLIS,
+        alice: Party {
+            uuid: ALICE_UUID.to_owned(),
+            device_id: alice_address.device_id().into(),
+            registration_id: ALICE_REGISTRATION_ID,
+            identity_key_pair: hex::encode(alice_identity.serialize()),
+        },
+        bob: Party {
+            uuid: BOB_UUID.to_owned(),
+            device_id: bob_address.device_id().into(),
+            registration_id: BOB_REGISTRATION_ID,
+            identity_key_pair: hex::encode(bob_identity.serialize()),
+        },
+        bob_pre_keys,
+        session,
+        sealed_sender,
+        sender_key,
+        fingerprint,
+    })
+}
+
+/// Generates one of each kind of pre-key for `store`, saving the private halves.
+fn create_pre_keys<R: Rng + CryptoRng>(
+    store: &mut InMemSignalProtocolStore,
+    address: &ProtocolAddress,
+    rng: &mut R,
+) -> Result<(PreKeyBundle, PreKeys), SignalProtocolError> {
+    let identity = store
+        .identity_store
+        .get_identity_key_pair()
+        .now_or_never()
+        .expect("sync")?;
+    let timestamp = Timestamp::from_epoch_millis(NOW_MILLIS);
+
+    let pre_key_id = PreKeyId::from(rng.random_range(1..0xFFFFFF));
+    let pre_key = KeyPair::generate(rng);
+
+    let signed_pre_key_id = SignedPreKeyId::from(rng.random_range(1..0xFFFFFF));
+    let signed_pre_key = KeyPair::generate(rng);
+    let signed_pre_key_signature = identity
+        .private_key()
+        .calculate_signature(&signed_pre_key.public_key.serialize(), rng)?;
+
+    let kyber_pre_key_id = KyberPreKeyId::from(rng.random_range(1..0xFFFFFF));
+    let kyber_pre_key = kem::KeyPair::generate(kem::KeyType::Kyber1024, rng);
+    let kyber_pre_key_signature = identity
+        .private_key()
+        .calculate_signature(&kyber_pre_key.public_key.serialize(), rng)?;
+
+    let bundle = PreKeyBundle::new(
+        store
+            .identity_store
+            .get_local_registration_id()
+            .now_or_never()
+            .expect("sync")?,
+        address.device_id(),
+        Some((pre_key_id, pre_key.public_key)),
+        signed_pre_key_id,
+        signed_pre_key.public_key,
+        signed_pre_key_signature.to_vec(),
+        kyber_pre_key_id,
+        kyber_pre_key.public_key.clone(),
+        kyber_pre_key_signature.to_vec(),
+        *identity.identity_key(),
+    )?;
+
+    store
+        .pre_key_store
+        .save_pre_key(pre_key_id, &PreKeyRecord::new(pre_key_id, &pre_key))
+        .now_or_never()
+        .expect("sync")?;
+    store
+        .signed_pre_key_store
+        .save_signed_pre_key(
+            signed_pre_key_id,
+            &SignedPreKeyRecord::new(
+                signed_pre_key_id,
+                timestamp,
+                &signed_pre_key,
+                &signed_pre_key_signature,
+            ),
+        )
+        .now_or_never()
+        .expect("sync")?;
+    store
+        .kyber_pre_key_store
+        .save_kyber_pre_key(
+            kyber_pre_key_id,
+            &KyberPreKeyRecord::new(
+                kyber_pre_key_id,
+                timestamp,
+                &kyber_pre_key,
+                &kyber_pre_key_signature,
+            ),
+        )
+        .now_or_never()
+        .expect("sync")?;
+
+    let pre_keys = PreKeys {
+        pre_key_id: pre_key_id.into(),
+        pre_key_private: hex::encode(pre_key.private_key.serialize()),
+        signed_pre_key_id: signed_pre_key_id.into(),
+        signed_pre_key_private: hex::encode(signed_pre_key.private_key.serialize()),
+        signed_pre_key_signature: hex::encode(&signed_pre_key_signature),
+        kyber_pre_key_id: kyber_pre_key_id.into(),
+        kyber_pre_key_public: hex::encode(kyber_pre_key.public_key.serialize()),
+        kyber_pre_key_secret: hex::encode(kyber_pre_key.secret_key.serialize()),
+        kyber_pre_key_signature: hex::encode(&kyber_pre_key_signature),
+    };
+    Ok((bundle, pre_keys))
+}
+
+fn encrypt<R: Rng + CryptoRng>(
+    store: &mut InMemSignalProtocolStore,
+    remote_address: &ProtocolAddress,
+    local_address: &ProtocolAddress,
+    plaintext: &[u8],
+    rng: &mut R,
+) -> Result<CiphertextMessage, SignalProtocolError> {
+    message_encrypt(
+        plaintext,
+        remote_address,
+        local_address,
+        &mut store.session_store,
+        &mut store.identity_store,
+        SystemTime::UNIX_EPOCH + Duration::from_millis(NOW_MILLIS),
+        rng,
+    )
+    .now_or_never()
+    .expect("sync")
+}
+
+fn decrypt<R: Rng + CryptoRng>(
+    store: &mut InMemSignalProtocolStore,
+    remote_address: &ProtocolAddress,
+    local_address: &ProtocolAddress,
+    message: &CiphertextMessage,
+    rng: &mut R,
+) -> Result<Vec<u8>, SignalProtocolError> {
+    message_decrypt(
+        message,
+        remote_address,
+        local_address,
+        &mut store.session_store,
+        &mut store.identity_store,
+        &mut store.pre_key_store,
+        &store.signed_pre_key_store,
+        &mut store.kyber_pre_key_store,
+        rng,
+    )
+    .now_or_never()
+    .expect("sync")
+}
+
+fn sealed_decrypt(
+    store: &mut InMemSignalProtocolStore,
+    local_address: &ProtocolAddress,
+    trust_root: &KeyPair,
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, SignalProtocolError> {
+    let result = sealed_sender_decrypt(
+        ciphertext,
+        &trust_root.public_key,
+        Timestamp::from_epoch_millis(NOW_MILLIS),
+        None,
+        local_address.name().to_owned(),
+        local_address.device_id(),
+        &mut store.identity_store,
+        &mut store.session_store,
+        &mut store.pre_key_store,
+        &store.signed_pre_key_store,
+        &mut store.kyber_pre_key_store,
+    )
+    .now_or_never()
+    .expect("sync")?;
+    assert_eq!(result.sender_uuid, ALICE_UUID);
+    Ok(result.message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checked-in output of `generate --seed 0`. To update it after an intentional change to the
+    /// wire format, run
+    ///
+    /// ```sh
+    /// cargo run -p libsignal-protocol --features test-vectors --bin test_vectors -- generate \
+    ///     > rust/protocol/tests/data/test_vectors.json
+    /// ```
+    const VECTORS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/test_vectors.json");
+
+    #[test]
+    fn generate_matches_checked_in_vectors() {
+        let expected = std::fs::read_to_string(VECTORS_PATH).expect("can read checked-in vectors");
+        let seed = serde_json::from_str::<TestVectors>(&expected)
+            .expect("checked-in vectors have the expected structure")
+            .seed;
+        let actual = to_json(&generate(seed).expect("can generate vectors"));
+        assert!(
+            actual == expected,
+            "generated vectors differ from {VECTORS_PATH}; regenerate it if the change is intended"
+        );
+    }
+
+    #[test]
+    fn generate_is_deterministic() {
+        let first = to_json(&generate(0).expect("can generate vectors"));
+        let second = to_json(&generate(0).expect("can generate vectors"));
+        assert_eq!(first, second);
+    }
+}
@@ -16,7 +16,7 @@ use rand::{CryptoRng, Rng};
 
 use crate::proto::storage::{SessionStructure, session_structure};
 use crate::ratchet::{ChainKey, MessageKeyGenerator, RootKey};
-use crate::state::InvalidSessionError;
+use crate::state::{InvalidSessionError, SessionPolicy};
 use crate::{
     CiphertextMessageType, KeyPair, PrivateKey, PublicKey, Result, SignalProtocolError, consts,
 };
@@ -35,10 +35,12 @@ pub(crate) struct RatchetState {
     pub receiver_chains: Vec<session_structure::Chain>,
     pub previous_counter: u32,
     /// Maximum number of messages we'll skip ahead in a single chain.
-    /// Set to `consts::MAX_FORWARD_JUMPS` for normal sessions,
+    /// Taken from the caller's [`SessionPolicy`] for normal sessions,
     /// `usize::MAX` for self-sessions (note-to-self), following the
     /// same pattern as SPQR's `max_jump` chain parameter.
     pub max_forward_jumps: usize,
+    /// Maximum number of skipped message keys cached per receiver chain.
+    pub max_message_keys: usize,
 }
 
 /// The sending side of a ratchet: our current ephemeral key pair and
@@ -67,11 +69,13 @@ impl RatchetState {
     /// state are owned by higher layers and are not touched here.
     ///
     /// `self_session` must be computed by the caller (requires identity
-    /// key comparison, which is not ratchet-layer knowledge).
+    /// key comparison, which is not ratchet-layer knowledge). The limits
+    /// in `policy` are not serialized.
     pub(crate) fn from_pb(
         session: &SessionStructure,
         self_session: bool,
         receiver_chains: Vec<session_structure::Chain>,
+        policy: &SessionPolicy,
     ) -> std::result::Result<Self, InvalidSessionError> {
         let root_key_bytes: [u8; 32] = session
             .root_key
@@ -93,8 +97,9 @@ impl RatchetState {
             max_forward_jumps: if self_session {
                 usize::MAX
             } else {
-                consts::MAX_FORWARD_JUMPS
+                policy.max_forward_jumps
             },
+            max_message_keys: policy.max_message_keys,
         })
     }
 
@@ -109,6 +114,7 @@ impl RatchetState {
             receiver_chains,
             previous_counter,
             max_forward_jumps: _, // not serialized; derived from session context
+            max_message_keys: _,  // not serialized; derived from session policy
         } = self;
         session.root_key = root_key.key().to_vec();
         session.previous_counter = previous_counter;
@@ -234,7 +240,7 @@ impl RatchetState {
                 "message from too far into the future".to_owned(),
             ));
         } else if jump > consts::MAX_FORWARD_JUMPS {
-            // This only happens if it is a session with self
+            // This only happens if it is a session with self, or the policy raised the limit
             log::info!(
                 "{remote_address_for_logging} Jumping ahead {jump} messages (index: {chain_index}, counter: {counter})"
             );
@@ -355,9 +361,7 @@ impl RatchetState {
         // O(1) per key. Deferred because it changes serialized key order,
         // breaking bit-for-bit compatibility with the legacy implementation.
         keys.insert(0, key.into_pb());
-        if keys.len() > consts::MAX_MESSAGE_KEYS {
-            keys.pop();
-        }
+        keys.truncate(self.max_message_keys);
     }
 
     fn set_receiver_chain_key(&mut self, their_ephemeral: &PublicKey, chain_key: ChainKey) {
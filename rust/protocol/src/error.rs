@@ -100,6 +100,8 @@ pub enum SignalProtocolError {
     BadKEMKeyType(u8),
     /// unexpected KEM key type <{0:#04x}> (expected <{1:#04x}>)
     WrongKEMKeyType(u8, u8),
+    /// KEM key type <{0}> is not accepted by the KEM policy
+    UnacceptableKEMKeyType(kem::KeyType),
     /// bad KEM key length <{1}> for key with type <{0}>
     BadKEMKeyLength(kem::KeyType, usize),
     /// bad KEM ciphertext length <{1}> for key with type <{0}>
@@ -11,7 +11,7 @@ use sha2::Sha512;
 use sha2::digest::Digest;
 use subtle::ConstantTimeEq;
 
-use crate::{IdentityKey, proto};
+use crate::{Aci, IdentityKey, Pni, proto};
 
 #[derive(Debug, displaydoc::Display)]
 pub enum Error {
@@ -112,9 +112,11 @@ impl ScannableFingerprint {
             version: Some(self.version),
             local_fingerprint: Some(proto::fingerprint::LogicalFingerprint {
                 content: Some(self.local_fingerprint.to_owned()),
+                pni_content: None,
             }),
             remote_fingerprint: Some(proto::fingerprint::LogicalFingerprint {
                 content: Some(self.remote_fingerprint.to_owned()),
+                pni_content: None,
             }),
         };
 
@@ -213,6 +215,201 @@ impl Fingerprint {
     }
 }
 
+// ── Multi-identity fingerprints ─────────────────────────────────────────────
+
+/// The identities of one account covered by a [`MultiIdentityFingerprint`].
+#[derive(Debug, Clone, Copy)]
+pub struct AccountIdentities {
+    pub aci: Aci,
+    pub aci_identity_key: IdentityKey,
+    /// The account's PNI and its identity key, if known.
+    pub pni: Option<(Pni, IdentityKey)>,
+}
+
+/// One of the identity keys committed to by a [`MultiIdentityFingerprint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FingerprintComponent {
+    LocalAci,
+    LocalPni,
+    RemoteAci,
+    RemotePni,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScannableMultiIdentityFingerprint {
+    local_aci: Vec<u8>,
+    local_pni: Option<Vec<u8>>,
+    remote_aci: Vec<u8>,
+    remote_pni: Option<Vec<u8>>,
+}
+
+impl ScannableMultiIdentityFingerprint {
+    pub fn deserialize(protobuf: &[u8]) -> Result<Self, Error> {
+        let fingerprint = proto::fingerprint::CombinedFingerprints::decode(protobuf)
+            .map_err(|_| Error::ParsingError("failed to decode protobuf"))?;
+
+        let version = fingerprint
+            .version
+            .ok_or(Error::ParsingError("missing version"))?;
+        if version != MultiIdentityFingerprint::VERSION {
+            return Err(Error::VersionMismatch {
+                theirs: version,
+                ours: MultiIdentityFingerprint::VERSION,
+            });
+        }
+        let local = fingerprint
+            .local_fingerprint
+            .ok_or(Error::ParsingError("missing local fingerprint"))?;
+        let remote = fingerprint
+            .remote_fingerprint
+            .ok_or(Error::ParsingError("missing remote fingerprint"))?;
+
+        Ok(Self {
+            local_aci: local
+                .content
+                .ok_or(Error::ParsingError("missing local ACI fingerprint"))?,
+            local_pni: local.pni_content,
+            remote_aci: remote
+                .content
+                .ok_or(Error::ParsingError("missing remote ACI fingerprint"))?,
+            remote_pni: remote.pni_content,
+        })
+    }
+
+    pub fn serialize(&self) -> Result<Vec<u8>, Error> {
+        let combined_fingerprints = proto::fingerprint::CombinedFingerprints {
+            version: Some(MultiIdentityFingerprint::VERSION),
+            local_fingerprint: Some(proto::fingerprint::LogicalFingerprint {
+                content: Some(self.local_aci.clone()),
+                pni_content: self.local_pni.clone(),
+            }),
+            remote_fingerprint: Some(proto::fingerprint::LogicalFingerprint {
+                content: Some(self.remote_aci.clone()),
+                pni_content: self.remote_pni.clone(),
+            }),
+        };
+
+        Ok(combined_fingerprints.encode_to_vec())
+    }
+
+    /// Compares against the fingerprint scanned from the other party's device.
+    ///
+    /// Returns the components that don't match, from this side's point of view; an empty list means
+    /// the fingerprints match. A PNI that is known to only one side counts as a mismatch.
+    pub fn compare(&self, combined: &[u8]) -> Result<Vec<FingerprintComponent>, Error> {
+        let theirs = Self::deserialize(combined)?;
+
+        fn same_pni(ours: &Option<Vec<u8>>, theirs: &Option<Vec<u8>>) -> bool {
+            match (ours, theirs) {
+                (Some(ours), Some(theirs)) => ours.ct_eq(theirs).into(),
+                (None, None) => true,
+                _ => false,
+            }
+        }
+
+        // Their "local" identities are our "remote" ones and vice versa.
+        let checks = [
+            (
+                FingerprintComponent::LocalAci,
+                bool::from(self.local_aci.ct_eq(&theirs.remote_aci)),
+            ),
+            (
+                FingerprintComponent::LocalPni,
+                same_pni(&self.local_pni, &theirs.remote_pni),
+            ),
+            (
+                FingerprintComponent::RemoteAci,
+                bool::from(self.remote_aci.ct_eq(&theirs.local_aci)),
+            ),
+            (
+                FingerprintComponent::RemotePni,
+                same_pni(&self.remote_pni, &theirs.local_pni),
+            ),
+        ];
+
+        Ok(checks
+            .into_iter()
+            .filter_map(|(component, same)| (!same).then_some(component))
+            .collect())
+    }
+}
+
+/// A safety number that commits to both the ACI and PNI identity keys of each party.
+#[derive(Debug, Clone)]
+pub struct MultiIdentityFingerprint {
+    pub display: DisplayableFingerprint,
+    pub scannable: ScannableMultiIdentityFingerprint,
+}
+
+impl MultiIdentityFingerprint {
+    /// The scannable format version, distinct from the single-identity versions used with
+    /// [`Fingerprint`].
+    pub const VERSION: u32 = 3;
+
+    pub fn new(
+        iterations: u32,
+        local: &AccountIdentities,
+        remote: &AccountIdentities,
+    ) -> Result<Self, Error> {
+        let (local_aci, local_pni) = Self::get_fingerprints(iterations, local)?;
+        let (remote_aci, remote_pni) = Self::get_fingerprints(iterations, remote)?;
+
+        Ok(Self {
+            display: DisplayableFingerprint::new(
+                &Self::combine_for_display(&local_aci, local_pni.as_deref()),
+                &Self::combine_for_display(&remote_aci, remote_pni.as_deref()),
+            )?,
+            scannable: ScannableMultiIdentityFingerprint {
+                local_aci: local_aci[..32].to_vec(),
+                local_pni: local_pni.map(|fprint| fprint[..32].to_vec()),
+                remote_aci: remote_aci[..32].to_vec(),
+                remote_pni: remote_pni.map(|fprint| fprint[..32].to_vec()),
+            },
+        })
+    }
+
+    /// Hashes one account's per-identity fingerprints into the value its half of the safety number
+    /// is derived from.
+    ///
+    /// The hash input starts with a label and the version, and each part is length-prefixed, so no
+    /// two different sets of parts (including with and without a PNI) hash the same input.
+    fn combine_for_display(aci: &[u8], pni: Option<&[u8]>) -> Vec<u8> {
+        const LABEL: &[u8] = b"Signal_MultiIdentityFingerprint_Display";
+
+        let mut sha512 = Sha512::new();
+        sha512.update(LABEL);
+        sha512.update(Self::VERSION.to_be_bytes());
+        for part in std::iter::once(aci).chain(pni) {
+            let len = u32::try_from(part.len()).expect("fingerprints are short");
+            sha512.update(len.to_be_bytes());
+            sha512.update(part);
+        }
+        sha512.finalize().to_vec()
+    }
+
+    fn get_fingerprints(
+        iterations: u32,
+        identities: &AccountIdentities,
+    ) -> Result<(Vec<u8>, Option<Vec<u8>>), Error> {
+        let aci = Fingerprint::get_fingerprint(
+            iterations,
+            &identities.aci.service_id_binary(),
+            &identities.aci_identity_key,
+        )?;
+        let pni = identities
+            .pni
+            .map(|(pni, key)| {
+                Fingerprint::get_fingerprint(iterations, &pni.service_id_binary(), &key)
+            })
+            .transpose()?;
+        Ok((aci, pni))
+    }
+
+    pub fn display_string(&self) -> Result<String, Error> {
+        Ok(self.display.to_string())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use const_str::hex;
@@ -570,4 +767,129 @@ mod test {
 
         Ok(())
     }
+
+    fn test_account(aci: u128, pni: Option<u128>) -> AccountIdentities {
+        use rand::rngs::OsRng;
+
+        use crate::IdentityKeyPair;
+
+        let mut rng = OsRng.unwrap_err();
+        AccountIdentities {
+            aci: Aci::from(uuid::Uuid::from_u128(aci)),
+            aci_identity_key: *IdentityKeyPair::generate(&mut rng).identity_key(),
+            pni: pni.map(|pni| {
+                (
+                    Pni::from(uuid::Uuid::from_u128(pni)),
+                    *IdentityKeyPair::generate(&mut rng).identity_key(),
+                )
+            }),
+        }
+    }
+
+    #[test]
+    fn multi_identity_fingerprint_matching() -> Result<(), Error> {
+        let alice = test_account(0xa11ce, Some(0xa11ce_0001));
+        let bob = test_account(0xb0b, None);
+
+        let a_fprint = MultiIdentityFingerprint::new(5200, &alice, &bob)?;
+        let b_fprint = MultiIdentityFingerprint::new(5200, &bob, &alice)?;
+
+        assert_eq!(a_fprint.display_string()?, b_fprint.display_string()?);
+        assert_eq!(a_fprint.display_string()?.len(), 60);
+
+        assert!(
+            a_fprint
+                .scannable
+                .compare(&b_fprint.scannable.serialize()?)?
+                .is_empty()
+        );
+        assert!(
+            b_fprint
+                .scannable
+                .compare(&a_fprint.scannable.serialize()?)?
+                .is_empty()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn multi_identity_fingerprint_mismatching_component() -> Result<(), Error> {
+        let alice = test_account(0xa11ce, None);
+        let bob = test_account(0xb0b, Some(0xb0b_0001));
+        let mut bob_with_new_pni_key = bob;
+        bob_with_new_pni_key.pni = test_account(0xb0b, Some(0xb0b_0001)).pni;
+
+        let a_fprint = MultiIdentityFingerprint::new(5200, &alice, &bob_with_new_pni_key)?;
+        let b_fprint = MultiIdentityFingerprint::new(5200, &bob, &alice)?;
+
+        assert_ne!(a_fprint.display_string()?, b_fprint.display_string()?);
+        assert_eq!(
+            a_fprint
+                .scannable
+                .compare(&b_fprint.scannable.serialize()?)?,
+            [FingerprintComponent::RemotePni]
+        );
+        assert_eq!(
+            b_fprint
+                .scannable
+                .compare(&a_fprint.scannable.serialize()?)?,
+            [FingerprintComponent::LocalPni]
+        );
+
+        // A PNI known to only one side is also a mismatch.
+        let mut bob_without_pni = bob;
+        bob_without_pni.pni = None;
+        let a_fprint = MultiIdentityFingerprint::new(5200, &alice, &bob_without_pni)?;
+        assert_eq!(
+            a_fprint
+                .scannable
+                .compare(&b_fprint.scannable.serialize()?)?,
+            [FingerprintComponent::RemotePni]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn multi_identity_fingerprint_separates_parts() {
+        let parts = [0x11; 64];
+        let combine = |split: usize| {
+            let (aci, pni) = parts.split_at(split);
+            MultiIdentityFingerprint::combine_for_display(aci, Some(pni))
+        };
+        assert_ne!(combine(30), combine(32));
+        assert_ne!(
+            combine(32),
+            MultiIdentityFingerprint::combine_for_display(&parts, None)
+        );
+    }
+
+    #[test]
+    fn multi_identity_fingerprint_rejects_single_identity_version() -> Result<(), Error> {
+        let alice = test_account(0xa11ce, None);
+        let bob = test_account(0xb0b, None);
+        let a_fprint = MultiIdentityFingerprint::new(5200, &alice, &bob)?;
+
+        let b_fprint_v2 = Fingerprint::new(
+            2,
+            5200,
+            &bob.aci.service_id_binary(),
+            &bob.aci_identity_key,
+            &alice.aci.service_id_binary(),
+            &alice.aci_identity_key,
+        )?;
+
+        assert!(matches!(
+            a_fprint
+                .scannable
+                .compare(&b_fprint_v2.scannable.serialize()?),
+            Err(Error::VersionMismatch {
+                theirs: 2,
+                ours: MultiIdentityFingerprint::VERSION,
+            })
+        ));
+
+        Ok(())
+    }
 }
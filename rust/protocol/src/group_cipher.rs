@@ -3,6 +3,8 @@
 // SPDX-License-Identifier: AGPL-3.0-only
 //
 
+use std::time::SystemTime;
+
 use rand::{CryptoRng, Rng};
 use uuid::Uuid;
 
@@ -225,45 +227,34 @@ pub async fn process_sender_key_distribution_message(
     Ok(())
 }
 
-pub async fn create_sender_key_distribution_message<R: Rng + CryptoRng>(
-    sender: &ProtocolAddress,
+/// Adds a fresh sending chain with a random chain ID and signing key to `record`.
+fn add_new_sending_state<R: Rng + CryptoRng>(
+    record: &mut SenderKeyRecord,
     distribution_id: Uuid,
-    sender_key_store: &mut dyn SenderKeyStore,
     csprng: &mut R,
-) -> Result<SenderKeyDistributionMessage> {
-    let sender_key_record = sender_key_store
-        .load_sender_key(sender, distribution_id)
-        .await?;
-
-    let sender_key_record = match sender_key_record {
-        Some(record) => record,
-        None => {
-            // libsignal-protocol-java uses 31-bit integers for sender key chain IDs
-            let chain_id = (csprng.random::<u32>()) >> 1;
-            log::info!(
-                "Creating SenderKey for distribution {distribution_id} with chain ID {chain_id}"
-            );
-
-            let iteration = 0;
-            let sender_key: [u8; 32] = csprng.random();
-            let signing_key = KeyPair::generate(csprng);
-            let mut record = SenderKeyRecord::new_empty();
-            record.add_sender_key_state(
-                SENDERKEY_MESSAGE_CURRENT_VERSION,
-                chain_id,
-                iteration,
-                &sender_key,
-                signing_key.public_key,
-                Some(signing_key.private_key),
-            );
-            sender_key_store
-                .store_sender_key(sender, distribution_id, &record)
-                .await?;
-            record
-        }
-    };
+) {
+    // libsignal-protocol-java uses 31-bit integers for sender key chain IDs
+    let chain_id = (csprng.random::<u32>()) >> 1;
+    log::info!("Creating SenderKey for distribution {distribution_id} with chain ID {chain_id}");
+
+    let iteration = 0;
+    let sender_key: [u8; 32] = csprng.random();
+    let signing_key = KeyPair::generate(csprng);
+    record.add_sender_key_state(
+        SENDERKEY_MESSAGE_CURRENT_VERSION,
+        chain_id,
+        iteration,
+        &sender_key,
+        signing_key.public_key,
+        Some(signing_key.private_key),
+    );
+}
 
-    let state = sender_key_record
+fn distribution_message_for_current_state(
+    record: &SenderKeyRecord,
+    distribution_id: Uuid,
+) -> Result<SenderKeyDistributionMessage> {
+    let state = record
         .sender_key_state()
         .map_err(|_| SignalProtocolError::InvalidSenderKeySession { distribution_id })?;
     let sender_chain_key = state
@@ -285,3 +276,129 @@ pub async fn create_sender_key_distribution_message<R: Rng + CryptoRng>(
             .map_err(|_| SignalProtocolError::InvalidSenderKeySession { distribution_id })?,
     )
 }
+
+pub async fn create_sender_key_distribution_message<R: Rng + CryptoRng>(
+    sender: &ProtocolAddress,
+    distribution_id: Uuid,
+    sender_key_store: &mut dyn SenderKeyStore,
+    csprng: &mut R,
+) -> Result<SenderKeyDistributionMessage> {
+    let sender_key_record = sender_key_store
+        .load_sender_key(sender, distribution_id)
+        .await?;
+
+    let sender_key_record = match sender_key_record {
+        // A retired record can't be used for sending, so start a new chain in its place.
+        Some(record)
+            if record
+                .sender_key_state()
+                .is_ok_and(|state| !state.is_receive_only()) =>
+        {
+            record
+        }
+        existing => {
+            let mut record = existing.unwrap_or_else(SenderKeyRecord::new_empty);
+            add_new_sending_state(&mut record, distribution_id, csprng);
+            sender_key_store
+                .store_sender_key(sender, distribution_id, &record)
+                .await?;
+            record
+        }
+    };
+
+    distribution_message_for_current_state(&sender_key_record, distribution_id)
+}
+
+/// Replaces the sending chain for `distribution_id` with a new one, for example after a member
+/// has left the group.
+///
+/// Previous chains become receive-only and may be discarded with
+/// [`SenderKeyRecord::remove_expired_states`] once `previous_states_expire_at` has passed. The
+/// returned distribution message must be sent to every remaining member before they can decrypt
+/// messages on the new chain; see [`recipients_needing_sender_key`].
+pub async fn rotate_sender_key<R: Rng + CryptoRng>(
+    sender: &ProtocolAddress,
+    distribution_id: Uuid,
+    sender_key_store: &mut dyn SenderKeyStore,
+    previous_states_expire_at: SystemTime,
+    csprng: &mut R,
+) -> Result<SenderKeyDistributionMessage> {
+    let mut record = sender_key_store
+        .load_sender_key(sender, distribution_id)
+        .await?
+        .unwrap_or_else(SenderKeyRecord::new_empty);
+
+    record.mark_receive_only(previous_states_expire_at);
+    add_new_sending_state(&mut record, distribution_id, csprng);
+    sender_key_store
+        .store_sender_key(sender, distribution_id, &record)
+        .await?;
+
+    distribution_message_for_current_state(&record, distribution_id)
+}
+
+/// Stops sending with `distribution_id` entirely, for clients that move a group to a new
+/// distribution ID instead of rotating the chain.
+///
+/// Every state for `distribution_id` becomes receive-only until `expires_at`. Does nothing if
+/// there is no record for `distribution_id`.
+pub async fn retire_sender_key(
+    sender: &ProtocolAddress,
+    distribution_id: Uuid,
+    sender_key_store: &mut dyn SenderKeyStore,
+    expires_at: SystemTime,
+) -> Result<()> {
+    let Some(mut record) = sender_key_store
+        .load_sender_key(sender, distribution_id)
+        .await?
+    else {
+        return Ok(());
+    };
+
+    record.mark_receive_only(expires_at);
+    sender_key_store
+        .store_sender_key(sender, distribution_id, &record)
+        .await
+}
+
+/// Returns the members of `recipients` that have not yet been sent the distribution message for
+/// the current sending chain of `distribution_id`.
+///
+/// Use [`mark_sender_key_distributed`] to record successful deliveries.
+pub async fn recipients_needing_sender_key(
+    sender: &ProtocolAddress,
+    distribution_id: Uuid,
+    sender_key_store: &mut dyn SenderKeyStore,
+    recipients: &[ProtocolAddress],
+) -> Result<Vec<ProtocolAddress>> {
+    let record = sender_key_store
+        .load_sender_key(sender, distribution_id)
+        .await?;
+    Ok(match record {
+        Some(record) => record
+            .recipients_needing_distribution(recipients)
+            .into_iter()
+            .cloned()
+            .collect(),
+        None => recipients.to_vec(),
+    })
+}
+
+/// Records that the distribution message for the current sending chain of `distribution_id` has
+/// been delivered to each of `recipients`.
+pub async fn mark_sender_key_distributed(
+    sender: &ProtocolAddress,
+    distribution_id: Uuid,
+    sender_key_store: &mut dyn SenderKeyStore,
+    recipients: &[ProtocolAddress],
+) -> Result<()> {
+    let mut record = sender_key_store
+        .load_sender_key(sender, distribution_id)
+        .await?
+        .ok_or(SignalProtocolError::NoSenderKeyState { distribution_id })?;
+
+    record.mark_distributed_to(recipients)?;
+    sender_key_store
+        .store_sender_key(sender, distribution_id, &record)
+        .await
+}
@@ -16,6 +16,9 @@ use crate::{KeyPair, PrivateKey, PublicKey, Result, SignalProtocolError, proto};
 // Used for domain separation between alternate-identity signatures and other key-to-key signatures.
 const ALTERNATE_IDENTITY_SIGNATURE_PREFIX_1: &[u8] = &[0xFF; 32];
 const ALTERNATE_IDENTITY_SIGNATURE_PREFIX_2: &[u8] = b"Signal_PNI_Signature";
+// Separates rotation statements from alternate-identity signatures, so that neither can be
+// presented as the other.
+const IDENTITY_ROTATION_SIGNATURE_PREFIX_2: &[u8] = b"Signal_Identity_Rotation";
 
 /// A public key that represents the identity of a user.
 ///
@@ -69,6 +72,24 @@ impl IdentityKey {
         ))
     }
 
+    /// Given a trusted identity `self`, verify that it was replaced by `new_identity`.
+    ///
+    /// `signature` must be calculated from [`IdentityKeyPair::sign_identity_rotation`].
+    pub fn verify_identity_rotation(
+        &self,
+        new_identity: &IdentityKey,
+        signature: &[u8],
+    ) -> Result<bool> {
+        Ok(self.public_key.verify_signature_for_multipart_message(
+            &[
+                ALTERNATE_IDENTITY_SIGNATURE_PREFIX_1,
+                IDENTITY_ROTATION_SIGNATURE_PREFIX_2,
+                &new_identity.serialize(),
+            ],
+            signature,
+        ))
+    }
+
     /// Do two (identity key, protocol address) pairs map to the same account
     ///
     /// This function will always return false if the names in the protocol addresses aren't valid
@@ -176,6 +197,118 @@ impl IdentityKeyPair {
             rng,
         )?)
     }
+
+    /// Generate a signature claiming that `new_identity` replaces `self`.
+    ///
+    /// This is distinct from [`Self::sign_alternate_identity`]: an alternate identity is used
+    /// alongside this one, while a rotation retires this one.
+    pub fn sign_identity_rotation<R: Rng + CryptoRng>(
+        &self,
+        new_identity: &IdentityKey,
+        rng: &mut R,
+    ) -> Result<Box<[u8]>> {
+        Ok(self.private_key.calculate_signature_for_multipart_message(
+            &[
+                ALTERNATE_IDENTITY_SIGNATURE_PREFIX_1,
+                IDENTITY_ROTATION_SIGNATURE_PREFIX_2,
+                &new_identity.serialize(),
+            ],
+            rng,
+        )?)
+    }
+
+    /// Generate a new identity to replace this one, along with a statement signed by this identity
+    /// vouching for the new one.
+    pub fn rotate<R: Rng + CryptoRng>(
+        &self,
+        rng: &mut R,
+    ) -> Result<(IdentityKeyPair, IdentityKeyTransition)> {
+        let new_identity_key_pair = Self::generate(rng);
+        let signature = self.sign_identity_rotation(new_identity_key_pair.identity_key(), rng)?;
+        let transition = IdentityKeyTransition {
+            old_identity_key: self.identity_key,
+            new_identity_key: new_identity_key_pair.identity_key,
+            signature,
+        };
+        Ok((new_identity_key_pair, transition))
+    }
+}
+
+/// A statement by an old identity key that a new identity key replaces it.
+///
+/// Produced by [`IdentityKeyPair::rotate`] and sent to peers, who pass it to
+/// [`IdentityKeyStore::save_identity_with_transition`](crate::IdentityKeyStore::save_identity_with_transition)
+/// so the new key can be trusted without the warnings an unexplained change would get.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityKeyTransition {
+    old_identity_key: IdentityKey,
+    new_identity_key: IdentityKey,
+    signature: Box<[u8]>,
+}
+
+impl IdentityKeyTransition {
+    /// Assemble a transition from its parts, without checking the signature.
+    pub fn new(
+        old_identity_key: IdentityKey,
+        new_identity_key: IdentityKey,
+        signature: Box<[u8]>,
+    ) -> Self {
+        Self {
+            old_identity_key,
+            new_identity_key,
+            signature,
+        }
+    }
+
+    /// Return the identity being replaced.
+    pub fn old_identity_key(&self) -> &IdentityKey {
+        &self.old_identity_key
+    }
+
+    /// Return the replacement identity.
+    pub fn new_identity_key(&self) -> &IdentityKey {
+        &self.new_identity_key
+    }
+
+    /// Return the old identity's signature over the new one.
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    /// Check that the old identity signed the transition.
+    pub fn verify(&self) -> Result<bool> {
+        self.old_identity_key
+            .verify_identity_rotation(&self.new_identity_key, &self.signature)
+    }
+
+    /// Return a byte slice which can later be deserialized with [`Self::deserialize`].
+    pub fn serialize(&self) -> Box<[u8]> {
+        proto::wire::IdentityKeyTransition {
+            old_identity_key: Some(self.old_identity_key.serialize().into_vec()),
+            new_identity_key: Some(self.new_identity_key.serialize().into_vec()),
+            signature: Some(self.signature.to_vec()),
+        }
+        .encode_to_vec()
+        .into_boxed_slice()
+    }
+
+    /// Deserialize a transition from a byte slice, without checking the signature.
+    pub fn deserialize(value: &[u8]) -> Result<Self> {
+        let structure = proto::wire::IdentityKeyTransition::decode(value)
+            .map_err(|_| SignalProtocolError::InvalidProtobufEncoding)?;
+        let (Some(old_identity_key), Some(new_identity_key), Some(signature)) = (
+            structure.old_identity_key,
+            structure.new_identity_key,
+            structure.signature,
+        ) else {
+            return Err(SignalProtocolError::InvalidProtobufEncoding);
+        };
+        Ok(Self {
+            old_identity_key: IdentityKey::decode(&old_identity_key)?,
+            new_identity_key: IdentityKey::decode(&new_identity_key)?,
+            signature: signature.into_boxed_slice(),
+        })
+    }
 }
 
 impl TryFrom<&[u8]> for IdentityKeyPair {
@@ -293,4 +426,42 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_identity_rotation() -> Result<()> {
+        let mut rng = OsRng.unwrap_err();
+        let old = IdentityKeyPair::generate(&mut rng);
+        let (new, transition) = old.rotate(&mut rng)?;
+
+        assert_eq!(transition.old_identity_key(), old.identity_key());
+        assert_eq!(transition.new_identity_key(), new.identity_key());
+        assert!(transition.verify()?);
+
+        let deserialized = IdentityKeyTransition::deserialize(&transition.serialize())?;
+        assert_eq!(deserialized, transition);
+        assert!(deserialized.verify()?);
+
+        // Rotation statements and alternate-identity signatures are not interchangeable.
+        assert!(
+            !old.identity_key()
+                .verify_alternate_identity(new.identity_key(), transition.signature())?
+        );
+        let alternate_signature = old.sign_alternate_identity(new.identity_key(), &mut rng)?;
+        let forged = IdentityKeyTransition::new(
+            *old.identity_key(),
+            *new.identity_key(),
+            alternate_signature,
+        );
+        assert!(!forged.verify()?);
+
+        // Not symmetric.
+        let reversed = IdentityKeyTransition::new(
+            *new.identity_key(),
+            *old.identity_key(),
+            transition.signature().into(),
+        );
+        assert!(!reversed.verify()?);
+
+        Ok(())
+    }
 }
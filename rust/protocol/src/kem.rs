@@ -12,7 +12,9 @@
 //! `SecretKey::decapsulate(ct: Ciphertext)` to construct the same `SharedSecret`.
 //!
 //! # Supported KEMs
-//! The NIST standardized Kyber1024 and Kyber768 KEMs are currently supported.
+//! Kyber1024 and its NIST standardized form ML-KEM-1024 are always supported; Kyber768 is
+//! available with the `kyber768` feature. A [`KemPolicy`] decides which of them are used for
+//! PQXDH.
 //!
 //! # Serialization
 //! `PublicKey`s and `SecretKey`s have serialization functions that encode the
@@ -55,7 +57,6 @@
 mod kyber1024;
 #[cfg(feature = "kyber768")]
 mod kyber768;
-#[cfg(feature = "mlkem1024")]
 mod mlkem1024;
 
 use std::fmt;
@@ -205,17 +206,15 @@ pub enum KeyType {
     /// Kyber1024 key
     Kyber1024,
     /// ML-KEM 1024 key
-    #[cfg(feature = "mlkem1024")]
     MLKEM1024,
 }
 
 impl KeyType {
-    fn value(&self) -> u8 {
+    pub(crate) fn value(&self) -> u8 {
         match self {
             #[cfg(feature = "kyber768")]
             KeyType::Kyber768 => 0x07,
             KeyType::Kyber1024 => 0x08,
-            #[cfg(feature = "mlkem1024")]
             KeyType::MLKEM1024 => 0x0A,
         }
     }
@@ -228,7 +227,6 @@ impl KeyType {
             #[cfg(feature = "kyber768")]
             KeyType::Kyber768 => &kyber768::Parameters,
             KeyType::Kyber1024 => &kyber1024::Parameters,
-            #[cfg(feature = "mlkem1024")]
             KeyType::MLKEM1024 => &mlkem1024::Parameters,
         }
     }
@@ -242,13 +240,79 @@ impl TryFrom<u8> for KeyType {
             #[cfg(feature = "kyber768")]
             0x07 => Ok(KeyType::Kyber768),
             0x08 => Ok(KeyType::Kyber1024),
-            #[cfg(feature = "mlkem1024")]
             0x0A => Ok(KeyType::MLKEM1024),
             t => Err(SignalProtocolError::BadKEMKeyType(t)),
         }
     }
 }
 
+/// Which KEMs to use when establishing PQXDH sessions.
+///
+/// The preferred KEM is used for newly generated Kyber pre-keys. Pre-key bundles are accepted if
+/// their Kyber pre-key uses any acceptable KEM.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KemPolicy {
+    /// The acceptable KEMs, starting with the preferred one.
+    acceptable: Vec<KeyType>,
+}
+
+impl KemPolicy {
+    /// Creates a policy preferring `preferred` and also accepting `acceptable`.
+    ///
+    /// `preferred` is always acceptable, whether or not it appears in `acceptable`.
+    pub fn new(preferred: KeyType, acceptable: impl IntoIterator<Item = KeyType>) -> Self {
+        let mut all_acceptable = vec![preferred];
+        for key_type in acceptable {
+            if !all_acceptable.contains(&key_type) {
+                all_acceptable.push(key_type);
+            }
+        }
+        Self {
+            acceptable: all_acceptable,
+        }
+    }
+
+    /// Creates a policy that accepts only `key_type`.
+    pub fn only(key_type: KeyType) -> Self {
+        Self::new(key_type, [])
+    }
+
+    pub fn preferred(&self) -> KeyType {
+        self.acceptable[0]
+    }
+
+    /// The acceptable KEMs in order of preference.
+    pub fn acceptable(&self) -> &[KeyType] {
+        &self.acceptable
+    }
+
+    pub fn is_acceptable(&self, key_type: KeyType) -> bool {
+        self.acceptable.contains(&key_type)
+    }
+
+    /// Picks the most preferred KEM among `offered`, or `None` if none of them are acceptable.
+    pub fn select(&self, offered: &[KeyType]) -> Option<KeyType> {
+        self.acceptable
+            .iter()
+            .copied()
+            .find(|key_type| offered.contains(key_type))
+    }
+}
+
+impl Default for KemPolicy {
+    /// Prefers Kyber1024, and accepts every KEM compiled in.
+    fn default() -> Self {
+        Self::new(
+            KeyType::Kyber1024,
+            [
+                KeyType::MLKEM1024,
+                #[cfg(feature = "kyber768")]
+                KeyType::Kyber768,
+            ],
+        )
+    }
+}
+
 pub trait KeyKind {
     fn key_length(key_type: KeyType) -> usize;
 }
@@ -477,6 +541,56 @@ impl KeyPair {
     }
 }
 
+impl KeyType {
+    fn hpke_kem(self) -> Option<signal_crypto::HpkeHybridKem> {
+        match self {
+            #[cfg(feature = "kyber768")]
+            KeyType::Kyber768 => None,
+            KeyType::Kyber1024 => None,
+            KeyType::MLKEM1024 => Some(signal_crypto::HpkeHybridKem::MlKem1024),
+        }
+    }
+}
+
+impl signal_crypto::HpkeKemPublicKey for PublicKey {
+    fn hpke_kem(&self) -> Option<signal_crypto::HpkeHybridKem> {
+        self.key_type.hpke_kem()
+    }
+
+    fn hpke_encapsulate<R: CryptoRng>(
+        &self,
+        csprng: &mut R,
+    ) -> std::result::Result<(Box<[u8]>, Box<[u8]>), signal_crypto::HpkeError> {
+        let (ss, ct) = self
+            .key_type
+            .parameters()
+            .encapsulate(&self.key_data, csprng)
+            .map_err(|_| signal_crypto::HpkeError::InvalidConfig)?;
+        // The HPKE ciphertext type already identifies the KEM, so this is the raw ciphertext
+        // without a type byte.
+        Ok((ss, ct))
+    }
+}
+
+impl signal_crypto::HpkeKemSecretKey for SecretKey {
+    fn hpke_kem(&self) -> Option<signal_crypto::HpkeHybridKem> {
+        self.key_type.hpke_kem()
+    }
+
+    fn hpke_decapsulate(
+        &self,
+        ciphertext: &[u8],
+    ) -> std::result::Result<Box<[u8]>, signal_crypto::HpkeError> {
+        if ciphertext.len() != self.key_type.parameters().ciphertext_length() {
+            return Err(signal_crypto::HpkeError::InvalidInput);
+        }
+        self.key_type
+            .parameters()
+            .decapsulate(&self.key_data, ciphertext)
+            .map_err(|_| signal_crypto::HpkeError::InvalidInput)
+    }
+}
+
 /// Utility type to handle serialization and deserialization of ciphertext data
 struct Ciphertext<'a> {
     key_type: KeyType,
@@ -577,7 +691,6 @@ mod tests {
         assert_eq!(ss_for_sender, ss_for_recipient);
     }
 
-    #[cfg(feature = "mlkem1024")]
     #[test]
     fn test_mlkem1024_kem() {
         // test data for kyber1024
@@ -646,7 +759,6 @@ mod tests {
         assert_eq!(ss_for_recipient, ss_for_sender);
     }
 
-    #[cfg(feature = "mlkem1024")]
     #[test]
     fn test_mlkem1024_keypair() {
         let mut rng = rand::rngs::OsRng.unwrap_err();
@@ -672,6 +784,72 @@ mod tests {
         assert_eq!(ss_for_recipient, ss_for_sender);
     }
 
+    #[test]
+    fn test_hybrid_hpke() {
+        use assert_matches::assert_matches;
+        use signal_crypto::{
+            HpkeAead, HpkeError, HpkeSealOptions, HybridHpkePrivateKey, HybridHpkePublicKey,
+            SimpleHpkeReceiver as _, SimpleHpkeSender as _,
+        };
+
+        let mut rng = rand::rngs::OsRng.unwrap_err();
+        let ec_pair = libsignal_core::curve::KeyPair::generate(&mut rng);
+        let kem_pair = KeyPair::generate(KeyType::MLKEM1024, &mut rng);
+        let recipient = HybridHpkePublicKey {
+            ec: &ec_pair.public_key,
+            kem: &kem_pair.public_key,
+        };
+        let receiver = HybridHpkePrivateKey {
+            ec: &ec_pair.private_key,
+            kem: &kem_pair.secret_key,
+        };
+
+        for aead in [HpkeAead::Aes256Gcm, HpkeAead::ChaCha20Poly1305] {
+            let ciphertext = recipient
+                .seal_with_options(
+                    &HpkeSealOptions {
+                        aead,
+                        ..Default::default()
+                    },
+                    b"info",
+                    b"aad",
+                    b"message",
+                )
+                .expect("can seal");
+            assert_eq!(
+                receiver
+                    .open(b"info", b"aad", &ciphertext)
+                    .expect("can open"),
+                b"message"
+            );
+            // A plain X25519 receiver won't open a hybrid message...
+            assert_matches!(
+                ec_pair.private_key.open(b"info", b"aad", &ciphertext),
+                Err(HpkeError::UnknownMode)
+            );
+        }
+
+        // ...and a hybrid receiver won't open a message without post-quantum protection.
+        let downgraded = ec_pair
+            .public_key
+            .seal(b"info", b"aad", b"message")
+            .expect("can seal");
+        assert_matches!(
+            receiver.open(b"info", b"aad", &downgraded),
+            Err(HpkeError::UnknownMode)
+        );
+
+        let kyber_pair = KeyPair::generate(KeyType::Kyber1024, &mut rng);
+        assert_matches!(
+            HybridHpkePublicKey {
+                ec: &ec_pair.public_key,
+                kem: &kyber_pair.public_key,
+            }
+            .seal(b"info", b"aad", b"message"),
+            Err(HpkeError::InvalidConfig)
+        );
+    }
+
     #[test]
     fn test_dyn_parameters_consts() {
         fn assert_parameters<T: Parameters>(key_type: KeyType) {
@@ -699,7 +877,32 @@ mod tests {
         #[cfg(feature = "kyber768")]
         assert_parameters::<kyber768::Parameters>(KeyType::Kyber768);
 
-        #[cfg(feature = "mlkem1024")]
         assert_parameters::<mlkem1024::Parameters>(KeyType::MLKEM1024);
     }
+
+    #[test]
+    fn test_kem_policy() {
+        let policy = KemPolicy::new(KeyType::MLKEM1024, [KeyType::Kyber1024, KeyType::MLKEM1024]);
+        assert_eq!(policy.preferred(), KeyType::MLKEM1024);
+        assert_eq!(
+            policy.acceptable(),
+            [KeyType::MLKEM1024, KeyType::Kyber1024]
+        );
+        assert_eq!(
+            policy.select(&[KeyType::Kyber1024, KeyType::MLKEM1024]),
+            Some(KeyType::MLKEM1024)
+        );
+        assert_eq!(
+            policy.select(&[KeyType::Kyber1024]),
+            Some(KeyType::Kyber1024)
+        );
+        assert_eq!(policy.select(&[]), None);
+
+        let strict = KemPolicy::only(KeyType::MLKEM1024);
+        assert!(!strict.is_acceptable(KeyType::Kyber1024));
+        assert_eq!(strict.select(&[KeyType::Kyber1024]), None);
+
+        assert_eq!(KemPolicy::default().preferred(), KeyType::Kyber1024);
+        assert!(KemPolicy::default().is_acceptable(KeyType::MLKEM1024));
+    }
 }
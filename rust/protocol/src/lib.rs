@@ -22,6 +22,7 @@
 // https://doc.rust-lang.org/rustdoc/what-to-include.html for background.
 // #![warn(missing_docs)]
 
+pub mod attachment;
 mod consts;
 mod crypto;
 mod double_ratchet;
@@ -33,34 +34,47 @@ mod identity_key;
 pub mod incremental_mac;
 pub mod kem;
 pub mod pqxdh;
+mod prekey_inventory;
 mod proto;
 mod protocol;
 mod ratchet;
+mod retry;
 mod sealed_sender;
+mod sealed_sender_certificates;
 mod sender_keys;
 mod session;
 #[cfg(test)]
 mod session_cipher_legacy;
+mod session_diagnostics;
 mod session_management;
 mod state;
 mod storage;
 mod timestamp;
+pub mod transcript;
 mod triple_ratchet;
 
 use error::Result;
 pub use error::{SessionNotFound, SignalProtocolError};
 pub use fingerprint::{
-    DisplayableFingerprint, Error as FingerprintError, Fingerprint, ScannableFingerprint,
+    AccountIdentities, DisplayableFingerprint, Error as FingerprintError, Fingerprint,
+    FingerprintComponent, MultiIdentityFingerprint, ScannableFingerprint,
+    ScannableMultiIdentityFingerprint,
 };
 pub use group_cipher::{
     create_sender_key_distribution_message, group_decrypt, group_encrypt,
-    process_sender_key_distribution_message,
+    mark_sender_key_distributed, process_sender_key_distribution_message,
+    recipients_needing_sender_key, retire_sender_key, rotate_sender_key,
 };
-pub use identity_key::{IdentityKey, IdentityKeyPair};
+pub use identity_key::{IdentityKey, IdentityKeyPair, IdentityKeyTransition};
 pub use libsignal_core::curve::{KeyPair, PrivateKey, PublicKey};
 pub use libsignal_core::{
     Aci, DeviceId, Pni, ProtocolAddress, ServiceId, ServiceIdFixedWidthBinaryBytes, ServiceIdKind,
 };
+pub use prekey_inventory::{
+    AvailablePreKeyCounts, InventoryKyberPreKeyStore, MAX_PRE_KEY_ID, PreKeyInventory,
+    PreKeyInventoryConfig, PreKeyMaintenance, PreKeyStoreMutation, PreKeyUpload,
+    SignedPublicPreKey,
+};
 pub use protocol::{
     CiphertextMessage, CiphertextMessageType, DecryptionErrorMessage, KyberPayload,
     PlaintextContent, PreKeySignalMessage, SenderKeyDistributionMessage, SenderKeyMessage,
@@ -71,21 +85,37 @@ pub use ratchet::{
     AliceSignalProtocolParameters, BobSignalProtocolParameters, initialize_alice_session_record,
     initialize_bob_session_record,
 };
+pub use retry::{
+    DecryptionErrorAction, InMemSentMessageLog, SentMessage, SentMessageLog,
+    handle_decryption_error_message,
+};
 pub use sealed_sender::{
-    ContentHint, SealedSenderDecryptionResult, SealedSenderV2SentMessage,
+    ContentHint, SealedSenderDecryptionResult, SealedSenderV2MessageKey, SealedSenderV2SentMessage,
     SealedSenderV2SentMessageRecipient, SenderCertificate, ServerCertificate,
     UnidentifiedSenderMessageContent, sealed_sender_decrypt, sealed_sender_decrypt_to_usmc,
     sealed_sender_encrypt, sealed_sender_encrypt_from_usmc, sealed_sender_multi_recipient_encrypt,
+    sealed_sender_multi_recipient_encrypt_retargetable, sealed_sender_multi_recipient_retarget,
+};
+pub use sealed_sender_certificates::{
+    SenderCertificateIssuer, SenderCertificateVerifier, ValidityWindow,
 };
 pub use sender_keys::SenderKeyRecord;
-pub use session::{process_prekey, process_prekey_bundle};
+pub use session::{
+    process_identity_key_transition, process_prekey, process_prekey_bundle,
+    process_prekey_bundle_with_kem_policy,
+};
+pub use session_diagnostics::{
+    KemKeyTypeDiagnostics, PendingPreKeyDiagnostics, ReceiverChainDiagnostics, SessionDiagnostics,
+    SessionStateDiagnostics, session_diagnostics,
+};
 pub use session_management::{
-    message_decrypt, message_decrypt_prekey, message_decrypt_signal, message_encrypt,
+    PendingStoreChanges, message_decrypt, message_decrypt_deferred, message_decrypt_prekey,
+    message_decrypt_signal, message_decrypt_with_policy, message_encrypt,
 };
 pub use state::{
-    GenericSignedPreKey, KyberPreKeyId, KyberPreKeyRecord, PreKeyBundle, PreKeyBundleContent,
-    PreKeyId, PreKeyRecord, SessionRecord, SessionUsabilityRequirements, SignedPreKeyId,
-    SignedPreKeyRecord,
+    CachedMessageKey, GenericSignedPreKey, KyberPreKeyId, KyberPreKeyRecord, MessageKeyPruning,
+    PreKeyBundle, PreKeyBundleContent, PreKeyId, PreKeyRecord, SessionPolicy, SessionRecord,
+    SessionUsabilityRequirements, SignedPreKeyId, SignedPreKeyRecord,
 };
 pub use storage::{
     Direction, IdentityChange, IdentityKeyStore, InMemIdentityKeyStore, InMemKyberPreKeyStore,
@@ -93,4 +123,9 @@ pub use storage::{
     InMemSignedPreKeyStore, KyberPreKeyStore, PreKeyStore, ProtocolStore, SenderKeyStore,
     SessionStore, SignedPreKeyStore,
 };
+#[cfg(feature = "sqlite")]
+pub use storage::{
+    SqliteIdentityKeyStore, SqliteKyberPreKeyStore, SqlitePreKeyStore, SqliteSenderKeyStore,
+    SqliteSessionStore, SqliteSignalProtocolStore, SqliteSignedPreKeyStore, SqliteStoreError,
+};
 pub use timestamp::Timestamp;
@@ -0,0 +1,800 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Keeping this device's pre-keys stocked, rotated, and cleaned up.
+//!
+//! A [`PreKeyInventory`] remembers which pre-keys this device has generated and when. Each call to
+//! [`PreKeyInventory::maintain`] does whatever is due: generating a new batch of one-time pre-keys
+//! when the server is running low, replacing the signed pre-key and last-resort Kyber pre-key
+//! once they are old enough, and deleting keys that were replaced long enough ago that no
+//! in-flight message could still use them. The result is a [`PreKeyUpload`] for the server and a
+//! list of [`PreKeyStoreMutation`]s for the local stores; the inventory itself must be persisted
+//! with [`PreKeyInventory::serialize`] alongside them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use prost::Message;
+use rand::{CryptoRng, Rng};
+
+use crate::proto::storage::PreKeyInventoryStructure;
+use crate::proto::storage::pre_key_inventory_structure::{
+    BaseKeysSeen, OneTimeKey, TimestampedKey,
+};
+use crate::state::GenericSignedPreKey;
+use crate::{
+    CiphertextMessageType, IdentityKeyPair, KeyPair, KyberPreKeyId, KyberPreKeyRecord,
+    KyberPreKeyStore, PreKeyId, PreKeyRecord, PrivateKey, PublicKey, Result, SignalProtocolError,
+    SignedPreKeyId, SignedPreKeyRecord, Timestamp, kem,
+};
+
+/// The largest pre-key ID a [`PreKeyInventory`] hands out; IDs wrap around to 1 after this.
+pub const MAX_PRE_KEY_ID: u32 = 0xFF_FFFF;
+
+/// When a [`PreKeyInventory`] generates, replaces, and deletes keys.
+#[derive(Clone, Debug)]
+pub struct PreKeyInventoryConfig {
+    /// How many one-time pre-keys of each kind to generate at once.
+    pub batch_size: u32,
+    /// Generate more one-time pre-keys of a kind once the server has fewer than this many left.
+    pub refill_threshold: u32,
+    /// How long a signed pre-key is used before it is replaced.
+    pub signed_pre_key_rotation_interval: Duration,
+    /// How long a last-resort Kyber pre-key is used before it is replaced.
+    pub last_resort_kyber_pre_key_rotation_interval: Duration,
+    /// How long replaced keys are kept, so that messages sent before the replacement can still be
+    /// decrypted.
+    pub grace_period: Duration,
+    /// Which KEM to use for new Kyber pre-keys.
+    pub kem_policy: kem::KemPolicy,
+}
+
+impl Default for PreKeyInventoryConfig {
+    fn default() -> Self {
+        const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+        Self {
+            batch_size: 100,
+            refill_threshold: 10,
+            signed_pre_key_rotation_interval: 2 * DAY,
+            last_resort_kyber_pre_key_rotation_interval: 2 * DAY,
+            grace_period: 30 * DAY,
+            kem_policy: kem::KemPolicy::default(),
+        }
+    }
+}
+
+/// How many one-time pre-keys the server still has available for this device.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct AvailablePreKeyCounts {
+    pub ec_pre_keys: u32,
+    pub kyber_pre_keys: u32,
+}
+
+/// The public half of a signed pre-key, as uploaded to the server.
+#[derive(Clone, Debug)]
+pub struct SignedPublicPreKey<Id, Key> {
+    pub id: Id,
+    pub public_key: Key,
+    /// The signature over the serialized public key by the identity key.
+    pub signature: Box<[u8]>,
+}
+
+/// Keys to upload to the server after [`PreKeyInventory::maintain`].
+#[derive(Clone, Debug, Default)]
+pub struct PreKeyUpload {
+    /// New one-time EC pre-keys, replacing any the server still has.
+    pub pre_keys: Vec<(PreKeyId, PublicKey)>,
+    pub signed_pre_key: Option<SignedPublicPreKey<SignedPreKeyId, PublicKey>>,
+    /// New one-time Kyber pre-keys, replacing any the server still has.
+    pub kyber_pre_keys: Vec<SignedPublicPreKey<KyberPreKeyId, kem::PublicKey>>,
+    pub last_resort_kyber_pre_key: Option<SignedPublicPreKey<KyberPreKeyId, kem::PublicKey>>,
+}
+
+impl PreKeyUpload {
+    /// Returns `true` if there is nothing to upload.
+    pub fn is_empty(&self) -> bool {
+        self.pre_keys.is_empty()
+            && self.signed_pre_key.is_none()
+            && self.kyber_pre_keys.is_empty()
+            && self.last_resort_kyber_pre_key.is_none()
+    }
+}
+
+/// A change to make to the local pre-key stores.
+///
+/// Removals of signed and Kyber pre-keys aren't part of [`SignedPreKeyStore`] and
+/// [`KyberPreKeyStore`], so applying them is up to the store implementation.
+///
+/// [`SignedPreKeyStore`]: crate::SignedPreKeyStore
+#[derive(Clone, Debug)]
+pub enum PreKeyStoreMutation {
+    SavePreKey(PreKeyId, PreKeyRecord),
+    RemovePreKey(PreKeyId),
+    SaveSignedPreKey(SignedPreKeyId, SignedPreKeyRecord),
+    RemoveSignedPreKey(SignedPreKeyId),
+    SaveKyberPreKey {
+        id: KyberPreKeyId,
+        record: KyberPreKeyRecord,
+        /// Whether the key may be used more than once, and so must not be deleted when used.
+        last_resort: bool,
+    },
+    RemoveKyberPreKey(KyberPreKeyId),
+}
+
+/// The result of [`PreKeyInventory::maintain`].
+#[derive(Clone, Debug)]
+pub struct PreKeyMaintenance {
+    pub upload: PreKeyUpload,
+    pub mutations: Vec<PreKeyStoreMutation>,
+}
+
+/// A key that is replaced on a schedule, along with its predecessors that are still kept around.
+#[derive(Clone, Debug)]
+struct RotatedKeys<Id> {
+    /// The key in use and when it was created.
+    current: Option<(Id, Timestamp)>,
+    /// Replaced keys and when they were replaced.
+    retired: BTreeMap<Id, Timestamp>,
+}
+
+impl<Id: Copy + Ord> RotatedKeys<Id> {
+    fn needs_rotation(&self, now: Timestamp, interval: Duration) -> bool {
+        self.current
+            .is_none_or(|(_, created)| elapsed(created, now) >= interval)
+    }
+
+    fn rotate(&mut self, id: Id, now: Timestamp) {
+        if let Some((previous, _)) = self.current.replace((id, now)) {
+            self.retired.insert(previous, now);
+        }
+    }
+
+    fn contains(&self, id: Id) -> bool {
+        self.current.is_some_and(|(current, _)| current == id) || self.retired.contains_key(&id)
+    }
+
+    fn remove_expired(&mut self, now: Timestamp, grace_period: Duration) -> Vec<Id> {
+        remove_expired(&mut self.retired, |retired_at| {
+            elapsed(retired_at, now) >= grace_period
+        })
+    }
+}
+
+/// Tracks this device's pre-keys and decides when to generate, replace, and delete them.
+#[derive(Clone, Debug)]
+pub struct PreKeyInventory {
+    config: PreKeyInventoryConfig,
+    next_pre_key_id: u32,
+    next_signed_pre_key_id: u32,
+    next_kyber_pre_key_id: u32,
+    /// One-time EC pre-keys, each with when it was replaced by a newer batch, if it has been.
+    pre_keys: BTreeMap<PreKeyId, Option<Timestamp>>,
+    /// One-time Kyber pre-keys, each with when it was replaced by a newer batch, if it has been.
+    kyber_pre_keys: BTreeMap<KyberPreKeyId, Option<Timestamp>>,
+    signed_pre_keys: RotatedKeys<SignedPreKeyId>,
+    last_resort_kyber_pre_keys: RotatedKeys<KyberPreKeyId>,
+    /// Base keys already used with each combination of last-resort and signed pre-key.
+    base_keys_seen: HashMap<(KyberPreKeyId, SignedPreKeyId), Vec<PublicKey>>,
+}
+
+impl PreKeyInventory {
+    /// Creates an empty inventory, with key IDs starting at random points.
+    pub fn new<R: Rng + CryptoRng>(config: PreKeyInventoryConfig, csprng: &mut R) -> Self {
+        Self {
+            config,
+            next_pre_key_id: csprng.random_range(1..=MAX_PRE_KEY_ID),
+            next_signed_pre_key_id: csprng.random_range(1..=MAX_PRE_KEY_ID),
+            next_kyber_pre_key_id: csprng.random_range(1..=MAX_PRE_KEY_ID),
+            pre_keys: BTreeMap::new(),
+            kyber_pre_keys: BTreeMap::new(),
+            signed_pre_keys: RotatedKeys {
+                current: None,
+                retired: BTreeMap::new(),
+            },
+            last_resort_kyber_pre_keys: RotatedKeys {
+                current: None,
+                retired: BTreeMap::new(),
+            },
+            base_keys_seen: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &PreKeyInventoryConfig {
+        &self.config
+    }
+
+    /// The signed pre-key currently in use, if one has been generated.
+    pub fn current_signed_pre_key_id(&self) -> Option<SignedPreKeyId> {
+        self.signed_pre_keys.current.map(|(id, _)| id)
+    }
+
+    /// The last-resort Kyber pre-key currently in use, if one has been generated.
+    pub fn current_last_resort_kyber_pre_key_id(&self) -> Option<KyberPreKeyId> {
+        self.last_resort_kyber_pre_keys.current.map(|(id, _)| id)
+    }
+
+    /// Does whatever maintenance is due at `now`.
+    ///
+    /// `available` is the number of one-time pre-keys the server reports it still has for this
+    /// device. New keys are signed with `identity_key_pair`.
+    ///
+    /// The inventory is updated immediately; callers should apply the returned mutations, upload
+    /// the new keys, and persist the inventory together.
+    pub fn maintain<R: Rng + CryptoRng>(
+        &mut self,
+        identity_key_pair: &IdentityKeyPair,
+        available: AvailablePreKeyCounts,
+        now: SystemTime,
+        csprng: &mut R,
+    ) -> Result<PreKeyMaintenance> {
+        let now = Timestamp::from_epoch_millis(
+            now.duration_since(SystemTime::UNIX_EPOCH)
+                .expect("Time should move forward")
+                .as_millis()
+                .try_into()
+                .expect("Timestamp too large"),
+        );
+        let signing_key = identity_key_pair.private_key();
+        let mut upload = PreKeyUpload::default();
+        let mut mutations = Vec::new();
+
+        if self
+            .signed_pre_keys
+            .needs_rotation(now, self.config.signed_pre_key_rotation_interval)
+        {
+            let id = SignedPreKeyId::from(next_id(&mut self.next_signed_pre_key_id));
+            let key_pair = KeyPair::generate(csprng);
+            let signature =
+                signing_key.calculate_signature(&key_pair.public_key.serialize(), csprng)?;
+            mutations.push(PreKeyStoreMutation::SaveSignedPreKey(
+                id,
+                SignedPreKeyRecord::new(id, now, &key_pair, &signature),
+            ));
+            upload.signed_pre_key = Some(SignedPublicPreKey {
+                id,
+                public_key: key_pair.public_key,
+                signature,
+            });
+            self.signed_pre_keys.rotate(id, now);
+        }
+
+        if self
+            .last_resort_kyber_pre_keys
+            .needs_rotation(now, self.config.last_resort_kyber_pre_key_rotation_interval)
+        {
+            let (record, public) = self.generate_kyber_pre_key(signing_key, now, csprng)?;
+            self.last_resort_kyber_pre_keys.rotate(public.id, now);
+            mutations.push(PreKeyStoreMutation::SaveKyberPreKey {
+                id: public.id,
+                record,
+                last_resort: true,
+            });
+            upload.last_resort_kyber_pre_key = Some(public);
+        }
+
+        if available.ec_pre_keys < self.config.refill_threshold {
+            // The upload replaces whatever the server still has, but those keys may already have
+            // been handed out, so keep them for the grace period.
+            mark_stale(&mut self.pre_keys, now);
+            for _ in 0..self.config.batch_size {
+                let id = PreKeyId::from(next_id(&mut self.next_pre_key_id));
+                let key_pair = KeyPair::generate(csprng);
+                self.pre_keys.insert(id, None);
+                mutations.push(PreKeyStoreMutation::SavePreKey(
+                    id,
+                    PreKeyRecord::new(id, &key_pair),
+                ));
+                upload.pre_keys.push((id, key_pair.public_key));
+            }
+        }
+
+        if available.kyber_pre_keys < self.config.refill_threshold {
+            mark_stale(&mut self.kyber_pre_keys, now);
+            for _ in 0..self.config.batch_size {
+                let (record, public) = self.generate_kyber_pre_key(signing_key, now, csprng)?;
+                self.kyber_pre_keys.insert(public.id, None);
+                mutations.push(PreKeyStoreMutation::SaveKyberPreKey {
+                    id: public.id,
+                    record,
+                    last_resort: false,
+                });
+                upload.kyber_pre_keys.push(public);
+            }
+        }
+
+        let grace_period = self.config.grace_period;
+        let is_expired = |since: Timestamp| elapsed(since, now) >= grace_period;
+        for id in self.signed_pre_keys.remove_expired(now, grace_period) {
+            self.base_keys_seen
+                .retain(|(_, signed_pre_key_id), _| *signed_pre_key_id != id);
+            mutations.push(PreKeyStoreMutation::RemoveSignedPreKey(id));
+        }
+        for id in self
+            .last_resort_kyber_pre_keys
+            .remove_expired(now, grace_period)
+        {
+            self.base_keys_seen
+                .retain(|(kyber_pre_key_id, _), _| *kyber_pre_key_id != id);
+            mutations.push(PreKeyStoreMutation::RemoveKyberPreKey(id));
+        }
+        for id in remove_expired(&mut self.pre_keys, |stale_since| {
+            stale_since.is_some_and(is_expired)
+        }) {
+            mutations.push(PreKeyStoreMutation::RemovePreKey(id));
+        }
+        for id in remove_expired(&mut self.kyber_pre_keys, |stale_since| {
+            stale_since.is_some_and(is_expired)
+        }) {
+            mutations.push(PreKeyStoreMutation::RemoveKyberPreKey(id));
+        }
+
+        Ok(PreKeyMaintenance { upload, mutations })
+    }
+
+    /// Records that a Kyber pre-key was used to start a session with `base_key`.
+    ///
+    /// This has the semantics of [`KyberPreKeyStore::mark_kyber_pre_key_used`]: a one-time key is
+    /// forgotten, and the mutation deleting it is returned; a last-resort key produces an error if
+    /// it was already used with the same signed pre-key and base key. Keys the inventory doesn't
+    /// know about, including ones already used or expired, produce
+    /// [`SignalProtocolError::InvalidKyberPreKeyId`].
+    pub fn mark_kyber_pre_key_used(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        ec_prekey_id: SignedPreKeyId,
+        base_key: &PublicKey,
+    ) -> Result<Option<PreKeyStoreMutation>> {
+        if self.kyber_pre_keys.remove(&kyber_prekey_id).is_some() {
+            return Ok(Some(PreKeyStoreMutation::RemoveKyberPreKey(
+                kyber_prekey_id,
+            )));
+        }
+        if !self.last_resort_kyber_pre_keys.contains(kyber_prekey_id) {
+            return Err(SignalProtocolError::InvalidKyberPreKeyId);
+        }
+
+        let base_keys_seen = self
+            .base_keys_seen
+            .entry((kyber_prekey_id, ec_prekey_id))
+            .or_default();
+        if base_keys_seen.contains(base_key) {
+            return Err(SignalProtocolError::InvalidMessage(
+                CiphertextMessageType::PreKey,
+                "reused base key".to_owned(),
+            ));
+        }
+        base_keys_seen.push(*base_key);
+        Ok(None)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        fn one_time_keys<Id: Copy + Into<u32>>(
+            keys: &BTreeMap<Id, Option<Timestamp>>,
+        ) -> Vec<OneTimeKey> {
+            keys.iter()
+                .map(|(id, stale_since)| OneTimeKey {
+                    id: (*id).into(),
+                    stale_since: stale_since.map(|t| t.epoch_millis()),
+                })
+                .collect()
+        }
+        fn timestamped_key<Id: Copy + Into<u32>>(
+            (id, timestamp): (&Id, &Timestamp),
+        ) -> TimestampedKey {
+            TimestampedKey {
+                id: (*id).into(),
+                timestamp: timestamp.epoch_millis(),
+            }
+        }
+
+        PreKeyInventoryStructure {
+            next_pre_key_id: self.next_pre_key_id,
+            next_signed_pre_key_id: self.next_signed_pre_key_id,
+            next_kyber_pre_key_id: self.next_kyber_pre_key_id,
+            pre_keys: one_time_keys(&self.pre_keys),
+            kyber_pre_keys: one_time_keys(&self.kyber_pre_keys),
+            current_signed_pre_key: self
+                .signed_pre_keys
+                .current
+                .as_ref()
+                .map(|(id, created)| timestamped_key((id, created))),
+            retired_signed_pre_keys: self
+                .signed_pre_keys
+                .retired
+                .iter()
+                .map(timestamped_key)
+                .collect(),
+            current_last_resort_kyber_pre_key: self
+                .last_resort_kyber_pre_keys
+                .current
+                .as_ref()
+                .map(|(id, created)| timestamped_key((id, created))),
+            retired_last_resort_kyber_pre_keys: self
+                .last_resort_kyber_pre_keys
+                .retired
+                .iter()
+                .map(timestamped_key)
+                .collect(),
+            base_keys_seen: self
+                .base_keys_seen
+                .iter()
+                .map(
+                    |((kyber_pre_key_id, signed_pre_key_id), base_keys)| BaseKeysSeen {
+                        kyber_pre_key_id: (*kyber_pre_key_id).into(),
+                        signed_pre_key_id: (*signed_pre_key_id).into(),
+                        base_keys: base_keys
+                            .iter()
+                            .map(|key| key.serialize().into_vec())
+                            .collect(),
+                    },
+                )
+                .collect(),
+        }
+        .encode_to_vec()
+    }
+
+    /// Restores an inventory saved with [`Self::serialize`], to be maintained according to
+    /// `config`.
+    pub fn deserialize(config: PreKeyInventoryConfig, data: &[u8]) -> Result<Self> {
+        fn one_time_keys<Id: From<u32> + Ord>(
+            keys: Vec<OneTimeKey>,
+        ) -> BTreeMap<Id, Option<Timestamp>> {
+            keys.into_iter()
+                .map(|key| {
+                    (
+                        key.id.into(),
+                        key.stale_since.map(Timestamp::from_epoch_millis),
+                    )
+                })
+                .collect()
+        }
+        fn timestamped_key<Id: From<u32>>(key: TimestampedKey) -> (Id, Timestamp) {
+            (key.id.into(), Timestamp::from_epoch_millis(key.timestamp))
+        }
+
+        let structure = PreKeyInventoryStructure::decode(data)
+            .map_err(|_| SignalProtocolError::InvalidProtobufEncoding)?;
+
+        let mut base_keys_seen = HashMap::new();
+        for entry in structure.base_keys_seen {
+            let base_keys = entry
+                .base_keys
+                .iter()
+                .map(|key| PublicKey::deserialize(key))
+                .collect::<std::result::Result<Vec<_>, _>>()?;
+            base_keys_seen.insert(
+                (
+                    entry.kyber_pre_key_id.into(),
+                    entry.signed_pre_key_id.into(),
+                ),
+                base_keys,
+            );
+        }
+
+        Ok(Self {
+            config,
+            next_pre_key_id: structure.next_pre_key_id,
+            next_signed_pre_key_id: structure.next_signed_pre_key_id,
+            next_kyber_pre_key_id: structure.next_kyber_pre_key_id,
+            pre_keys: one_time_keys(structure.pre_keys),
+            kyber_pre_keys: one_time_keys(structure.kyber_pre_keys),
+            signed_pre_keys: RotatedKeys {
+                current: structure.current_signed_pre_key.map(timestamped_key),
+                retired: structure
+                    .retired_signed_pre_keys
+                    .into_iter()
+                    .map(timestamped_key)
+                    .collect(),
+            },
+            last_resort_kyber_pre_keys: RotatedKeys {
+                current: structure
+                    .current_last_resort_kyber_pre_key
+                    .map(timestamped_key),
+                retired: structure
+                    .retired_last_resort_kyber_pre_keys
+                    .into_iter()
+                    .map(timestamped_key)
+                    .collect(),
+            },
+            base_keys_seen,
+        })
+    }
+
+    fn generate_kyber_pre_key<R: Rng + CryptoRng>(
+        &mut self,
+        signing_key: &PrivateKey,
+        now: Timestamp,
+        csprng: &mut R,
+    ) -> Result<(
+        KyberPreKeyRecord,
+        SignedPublicPreKey<KyberPreKeyId, kem::PublicKey>,
+    )> {
+        let id = KyberPreKeyId::from(next_id(&mut self.next_kyber_pre_key_id));
+        let key_pair = kem::KeyPair::generate(self.config.kem_policy.preferred(), csprng);
+        let signature =
+            signing_key.calculate_signature(&key_pair.public_key.serialize(), csprng)?;
+        let record = KyberPreKeyRecord::new(id, now, &key_pair, &signature);
+        Ok((
+            record,
+            SignedPublicPreKey {
+                id,
+                public_key: key_pair.public_key,
+                signature,
+            },
+        ))
+    }
+}
+
+/// A [`KyberPreKeyStore`] that tracks pre-key use in a [`PreKeyInventory`].
+///
+/// Loading and saving keys goes to the wrapped store. Marking a key as used goes to
+/// [`PreKeyInventory::mark_kyber_pre_key_used`] instead, so that replays are rejected based on
+/// the inventory's records; the resulting deletions are collected for
+/// [`Self::into_mutations`].
+pub struct InventoryKyberPreKeyStore<'a> {
+    inventory: &'a mut PreKeyInventory,
+    store: &'a mut dyn KyberPreKeyStore,
+    mutations: Vec<PreKeyStoreMutation>,
+}
+
+impl<'a> InventoryKyberPreKeyStore<'a> {
+    pub fn new(inventory: &'a mut PreKeyInventory, store: &'a mut dyn KyberPreKeyStore) -> Self {
+        Self {
+            inventory,
+            store,
+            mutations: Vec::new(),
+        }
+    }
+
+    /// The store changes resulting from keys marked as used.
+    pub fn into_mutations(self) -> Vec<PreKeyStoreMutation> {
+        self.mutations
+    }
+}
+
+#[async_trait(?Send)]
+impl KyberPreKeyStore for InventoryKyberPreKeyStore<'_> {
+    async fn get_kyber_pre_key(&self, kyber_prekey_id: KyberPreKeyId) -> Result<KyberPreKeyRecord> {
+        self.store.get_kyber_pre_key(kyber_prekey_id).await
+    }
+
+    async fn save_kyber_pre_key(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        record: &KyberPreKeyRecord,
+    ) -> Result<()> {
+        self.store.save_kyber_pre_key(kyber_prekey_id, record).await
+    }
+
+    async fn mark_kyber_pre_key_used(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        ec_prekey_id: SignedPreKeyId,
+        base_key: &PublicKey,
+    ) -> Result<()> {
+        self.mutations
+            .extend(self.inventory.mark_kyber_pre_key_used(
+                kyber_prekey_id,
+                ec_prekey_id,
+                base_key,
+            )?);
+        Ok(())
+    }
+}
+
+fn next_id(next: &mut u32) -> u32 {
+    let id = *next;
+    *next = id % MAX_PRE_KEY_ID + 1;
+    id
+}
+
+fn elapsed(since: Timestamp, now: Timestamp) -> Duration {
+    Duration::from_millis(now.epoch_millis().saturating_sub(since.epoch_millis()))
+}
+
+fn mark_stale<Id>(keys: &mut BTreeMap<Id, Option<Timestamp>>, now: Timestamp) {
+    for stale_since in keys.values_mut() {
+        stale_since.get_or_insert(now);
+    }
+}
+
+/// Removes and returns the keys whose value satisfies `is_expired`.
+fn remove_expired<Id: Copy + Ord, T: Copy>(
+    keys: &mut BTreeMap<Id, T>,
+    is_expired: impl Fn(T) -> bool,
+) -> Vec<Id> {
+    let expired: Vec<Id> = keys
+        .iter()
+        .filter(|(_, value)| is_expired(**value))
+        .map(|(id, _)| *id)
+        .collect();
+    for id in &expired {
+        keys.remove(id);
+    }
+    expired
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use rand::TryRngCore as _;
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+    const FULL: AvailablePreKeyCounts = AvailablePreKeyCounts {
+        ec_pre_keys: 5,
+        kyber_pre_keys: 5,
+    };
+
+    fn config() -> PreKeyInventoryConfig {
+        PreKeyInventoryConfig {
+            batch_size: 5,
+            refill_threshold: 2,
+            ..Default::default()
+        }
+    }
+
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + 1000 * DAY
+    }
+
+    #[test]
+    fn maintenance_schedule() -> Result<()> {
+        let mut csprng = OsRng.unwrap_err();
+        let identity = IdentityKeyPair::generate(&mut csprng);
+        let mut inventory = PreKeyInventory::new(config(), &mut csprng);
+
+        let first = inventory.maintain(
+            &identity,
+            AvailablePreKeyCounts::default(),
+            start(),
+            &mut csprng,
+        )?;
+        assert_eq!(first.upload.pre_keys.len(), 5);
+        assert_eq!(first.upload.kyber_pre_keys.len(), 5);
+        assert_eq!(first.mutations.len(), 12);
+        let signed = first.upload.signed_pre_key.as_ref().expect("generated");
+        assert!(
+            identity
+                .public_key()
+                .verify_signature(&signed.public_key.serialize(), &signed.signature)
+        );
+        let last_resort = first
+            .upload
+            .last_resort_kyber_pre_key
+            .as_ref()
+            .expect("generated");
+        assert!(
+            identity
+                .public_key()
+                .verify_signature(&last_resort.public_key.serialize(), &last_resort.signature)
+        );
+
+        // Nothing is due yet.
+        let second = inventory.maintain(&identity, FULL, start() + DAY, &mut csprng)?;
+        assert!(second.upload.is_empty());
+        assert!(second.mutations.is_empty());
+
+        // The server is running low on EC pre-keys, and the signed keys are due to be replaced.
+        let third = inventory.maintain(
+            &identity,
+            AvailablePreKeyCounts {
+                ec_pre_keys: 1,
+                kyber_pre_keys: 5,
+            },
+            start() + 2 * DAY,
+            &mut csprng,
+        )?;
+        assert_eq!(third.upload.pre_keys.len(), 5);
+        assert!(third.upload.kyber_pre_keys.is_empty());
+        assert_ne!(
+            third.upload.signed_pre_key.as_ref().expect("rotated").id,
+            signed.id
+        );
+        assert_ne!(
+            third
+                .upload
+                .last_resort_kyber_pre_key
+                .as_ref()
+                .expect("rotated")
+                .id,
+            last_resort.id
+        );
+        assert_eq!(third.mutations.len(), 7);
+
+        // The keys replaced on the third round are deleted once the grace period is over.
+        let fourth = inventory.maintain(&identity, FULL, start() + 32 * DAY, &mut csprng)?;
+        let mut removed_pre_keys = vec![];
+        let mut removed_signed_pre_keys = vec![];
+        let mut removed_kyber_pre_keys = vec![];
+        for mutation in fourth.mutations {
+            match mutation {
+                PreKeyStoreMutation::RemovePreKey(id) => removed_pre_keys.push(id),
+                PreKeyStoreMutation::RemoveSignedPreKey(id) => removed_signed_pre_keys.push(id),
+                PreKeyStoreMutation::RemoveKyberPreKey(id) => removed_kyber_pre_keys.push(id),
+                PreKeyStoreMutation::SaveSignedPreKey(..)
+                | PreKeyStoreMutation::SaveKyberPreKey {
+                    last_resort: true, ..
+                } => {}
+                other => panic!("unexpected mutation {other:?}"),
+            }
+        }
+        let mut first_pre_keys: Vec<_> = first.upload.pre_keys.iter().map(|(id, _)| *id).collect();
+        first_pre_keys.sort();
+        assert_eq!(removed_pre_keys, first_pre_keys);
+        assert_eq!(removed_signed_pre_keys, [signed.id]);
+        assert_eq!(removed_kyber_pre_keys, [last_resort.id]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn kyber_pre_key_use() -> Result<()> {
+        let mut csprng = OsRng.unwrap_err();
+        let identity = IdentityKeyPair::generate(&mut csprng);
+        let mut inventory = PreKeyInventory::new(config(), &mut csprng);
+        let first = inventory.maintain(
+            &identity,
+            AvailablePreKeyCounts::default(),
+            start(),
+            &mut csprng,
+        )?;
+
+        let signed_id = inventory.current_signed_pre_key_id().expect("generated");
+        let last_resort_id = inventory
+            .current_last_resort_kyber_pre_key_id()
+            .expect("generated");
+        let one_time_id = first.upload.kyber_pre_keys[0].id;
+        let base_key = KeyPair::generate(&mut csprng).public_key;
+
+        assert_matches!(
+            inventory.mark_kyber_pre_key_used(one_time_id, signed_id, &base_key),
+            Ok(Some(PreKeyStoreMutation::RemoveKyberPreKey(id))) if id == one_time_id
+        );
+        assert_matches!(
+            inventory.mark_kyber_pre_key_used(one_time_id, signed_id, &base_key),
+            Err(SignalProtocolError::InvalidKyberPreKeyId)
+        );
+
+        assert_matches!(
+            inventory.mark_kyber_pre_key_used(last_resort_id, signed_id, &base_key),
+            Ok(None)
+        );
+
+        // Replays are still caught after the inventory is saved and restored.
+        let mut inventory = PreKeyInventory::deserialize(config(), &inventory.serialize())?;
+        assert_matches!(
+            inventory.mark_kyber_pre_key_used(last_resort_id, signed_id, &base_key),
+            Err(SignalProtocolError::InvalidMessage(
+                CiphertextMessageType::PreKey,
+                _
+            ))
+        );
+        let other_base_key = KeyPair::generate(&mut csprng).public_key;
+        assert_matches!(
+            inventory.mark_kyber_pre_key_used(last_resort_id, signed_id, &other_base_key),
+            Ok(None)
+        );
+
+        // A replaced last-resort key can be used until the grace period is over.
+        inventory.maintain(&identity, FULL, start() + 2 * DAY, &mut csprng)?;
+        let base_key = KeyPair::generate(&mut csprng).public_key;
+        assert_matches!(
+            inventory.mark_kyber_pre_key_used(last_resort_id, signed_id, &base_key),
+            Ok(None)
+        );
+        inventory.maintain(&identity, FULL, start() + 32 * DAY, &mut csprng)?;
+        let base_key = KeyPair::generate(&mut csprng).public_key;
+        assert_matches!(
+            inventory.mark_kyber_pre_key_used(last_resort_id, signed_id, &base_key),
+            Err(SignalProtocolError::InvalidKyberPreKeyId)
+        );
+
+        Ok(())
+    }
+}
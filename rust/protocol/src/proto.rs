@@ -7,4 +7,5 @@ pub mod fingerprint;
 pub mod sealed_sender;
 pub mod service;
 pub mod storage;
+pub mod transcript;
 pub mod wire;
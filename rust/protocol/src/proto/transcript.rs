@@ -0,0 +1,6 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+include!(concat!(env!("OUT_DIR"), "/signal.proto.transcript.rs"));
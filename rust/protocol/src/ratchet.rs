@@ -107,6 +107,7 @@ fn initialize_initiator_session<R: Rng + CryptoRng>(
     .with_sender_chain(&sending_ratchet_key, &sending_chain_chain_key);
 
     session.set_kyber_ciphertext(kyber_ciphertext);
+    session.set_kem_key_type(parameters.their_kyber_pre_key().key_type());
 
     Ok(session)
 }
@@ -158,7 +159,7 @@ fn initialize_recipient_session(
         ))
     })?;
 
-    let session = SessionState::new(
+    let mut session = SessionState::new(
         CIPHERTEXT_MESSAGE_CURRENT_VERSION,
         local_identity,
         parameters.their_identity_key(),
@@ -168,6 +169,8 @@ fn initialize_recipient_session(
     )
     .with_sender_chain(our_ratchet_key_pair, &chain_key);
 
+    session.set_kem_key_type(parameters.our_kyber_pre_key_pair().public_key.key_type());
+
     Ok(session)
 }
 
@@ -0,0 +1,188 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Deciding how to respond to a [`DecryptionErrorMessage`].
+//!
+//! When a recipient fails to decrypt one of our messages, it sends back a DecryptionErrorMessage
+//! (a "retry request") naming the failed message by timestamp. Answering it means finding the
+//! original message, working out whether the session it was sent on is broken, and resending it
+//! in a way the recipient can decrypt. [`handle_decryption_error_message`] does the first two
+//! steps and returns a [`DecryptionErrorAction`] describing the third.
+
+use std::collections::{BTreeMap, HashSet};
+
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::{
+    ContentHint, DecryptionErrorMessage, DeviceId, ProtocolAddress, Result, SessionStore, Timestamp,
+};
+
+/// A message we sent that may need to be resent in response to a retry request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SentMessage {
+    /// The timestamp the message was sent with.
+    pub timestamp: Timestamp,
+    /// The serialized, unencrypted content of the message.
+    pub content: Vec<u8>,
+    /// The hint the message was sent with, telling the recipient how to treat it if it can't be
+    /// decrypted (for instance, whether the sender can resend it).
+    ///
+    /// Messages sent without sealed sender carry no hint; record those as [`ContentHint::Default`].
+    pub content_hint: ContentHint,
+    /// The sender key distribution the message was encrypted with, if it was a group message.
+    pub distribution_id: Option<Uuid>,
+    /// The group the message was sent to, if any.
+    pub group_id: Option<Vec<u8>>,
+}
+
+/// A log of recently sent messages, used to answer retry requests.
+///
+/// Clients generally only need to keep messages for a limited time (a day, for instance), and
+/// only those that are safe to send again (see [`ContentHint::Resendable`]).
+#[async_trait(?Send)]
+pub trait SentMessageLog {
+    /// Returns the message sent to `recipient` at `timestamp`, if it is still in the log.
+    async fn sent_message(
+        &self,
+        recipient: &ProtocolAddress,
+        timestamp: Timestamp,
+    ) -> Result<Option<SentMessage>>;
+}
+
+/// Reference implementation of [`SentMessageLog`].
+#[derive(Clone, Debug, Default)]
+pub struct InMemSentMessageLog {
+    /// Sent messages by timestamp, each with the devices it was sent to.
+    messages: BTreeMap<Timestamp, Vec<(SentMessage, HashSet<ProtocolAddress>)>>,
+}
+
+impl InMemSentMessageLog {
+    /// Create an empty log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that `message` was sent to `recipients`.
+    pub fn record(
+        &mut self,
+        message: SentMessage,
+        recipients: impl IntoIterator<Item = ProtocolAddress>,
+    ) {
+        self.messages
+            .entry(message.timestamp)
+            .or_default()
+            .push((message, recipients.into_iter().collect()));
+    }
+
+    /// Forgets all messages sent before `cutoff`.
+    pub fn remove_messages_before(&mut self, cutoff: Timestamp) {
+        self.messages = self.messages.split_off(&cutoff);
+    }
+}
+
+#[async_trait(?Send)]
+impl SentMessageLog for InMemSentMessageLog {
+    async fn sent_message(
+        &self,
+        recipient: &ProtocolAddress,
+        timestamp: Timestamp,
+    ) -> Result<Option<SentMessage>> {
+        Ok(self.messages.get(&timestamp).and_then(|messages| {
+            messages
+                .iter()
+                .find(|(_, recipients)| recipients.contains(recipient))
+                .map(|(message, _)| message.clone())
+        }))
+    }
+}
+
+/// What to do about a [`DecryptionErrorMessage`], as decided by
+/// [`handle_decryption_error_message`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecryptionErrorAction {
+    /// Resend the message over the existing session, which has already moved on from the one the
+    /// failed message was sent on.
+    Resend(SentMessage),
+    /// The session the failed message was sent on was still current, and has been archived.
+    ///
+    /// Fetch a new pre-key bundle for the requester to start a new session, then resend the
+    /// message if it is available, or a null message otherwise so that the requester learns about
+    /// the new session.
+    ResetSession { resend: Option<SentMessage> },
+    /// A sender key message failed to decrypt.
+    ///
+    /// Send the requester a new SenderKeyDistributionMessage for `distribution_id` (individually,
+    /// not as a group message), then resend the message.
+    ResendSenderKey {
+        distribution_id: Uuid,
+        resend: SentMessage,
+    },
+    /// Nothing needs to be done: the failed message was sent by another of our devices, or it is
+    /// no longer available and the session is fine.
+    Ignore,
+}
+
+/// Decides how to answer `message`, a retry request received from `requester`.
+///
+/// `local_device_id` is the ID of this device; requests about messages sent by our other devices
+/// are ignored. If the failed message was sent on what is still the current session with
+/// `requester`, that session is archived in `session_store`, since the requester evidently can't
+/// use it.
+pub async fn handle_decryption_error_message(
+    message: &DecryptionErrorMessage,
+    requester: &ProtocolAddress,
+    local_device_id: DeviceId,
+    session_store: &mut dyn SessionStore,
+    sent_messages: &dyn SentMessageLog,
+) -> Result<DecryptionErrorAction> {
+    if message.device_id() != u32::from(local_device_id) {
+        log::info!(
+            "ignoring retry request from {requester} for message {} sent by device {}",
+            message.timestamp().epoch_millis(),
+            message.device_id()
+        );
+        return Ok(DecryptionErrorAction::Ignore);
+    }
+
+    let sent_message = sent_messages
+        .sent_message(requester, message.timestamp())
+        .await?;
+
+    let Some(ratchet_key) = message.ratchet_key() else {
+        // Only sender key messages have no ratchet key.
+        return Ok(match sent_message {
+            Some(sent_message) => match sent_message.distribution_id {
+                Some(distribution_id) => DecryptionErrorAction::ResendSenderKey {
+                    distribution_id,
+                    resend: sent_message,
+                },
+                None => DecryptionErrorAction::Resend(sent_message),
+            },
+            None => {
+                log::info!(
+                    "no sent message found for sender key retry request from {requester} ({})",
+                    message.timestamp().epoch_millis()
+                );
+                DecryptionErrorAction::Ignore
+            }
+        });
+    };
+
+    if let Some(mut session_record) = session_store.load_session(requester).await? {
+        if session_record.current_ratchet_key_matches(ratchet_key)? {
+            log::info!("archiving session with {requester} after retry request");
+            session_record.archive_current_state()?;
+            session_store
+                .store_session(requester, &session_record)
+                .await?;
+            return Ok(DecryptionErrorAction::ResetSession {
+                resend: sent_message,
+            });
+        }
+    }
+
+    Ok(sent_message.map_or(DecryptionErrorAction::Ignore, DecryptionErrorAction::Resend))
+}
@@ -1373,7 +1373,7 @@ pub async fn sealed_sender_multi_recipient_encrypt<
 where
     X::IntoIter: ExactSizeIterator,
 {
-    sealed_sender_multi_recipient_encrypt_impl(
+    let (serialized, _message_key) = sealed_sender_multi_recipient_encrypt_impl(
         destinations,
         destination_sessions,
         excluded_recipients,
@@ -1381,10 +1381,22 @@ where
         identity_store,
         rng,
     )
-    .await
+    .await?;
+    Ok(serialized)
 }
 
-async fn sealed_sender_multi_recipient_encrypt_impl<
+/// The random secret a Sealed Sender v2 message was encrypted with.
+///
+/// Holding on to this allows a sent message to be re-targeted at a different set of recipients
+/// with [`sealed_sender_multi_recipient_retarget`], without encrypting the message content again.
+/// It must be kept as secret as the message content itself.
+#[derive(Clone)]
+pub struct SealedSenderV2MessageKey([u8; sealed_sender_v2::MESSAGE_KEY_LEN]);
+
+/// Like [`sealed_sender_multi_recipient_encrypt`], but also returns the message's
+/// [`SealedSenderV2MessageKey`], so that the message can later be re-targeted with
+/// [`sealed_sender_multi_recipient_retarget`].
+pub async fn sealed_sender_multi_recipient_encrypt_retargetable<
     R: Rng + CryptoRng,
     X: IntoIterator<Item = ServiceId>,
 >(
@@ -1394,23 +1406,84 @@ async fn sealed_sender_multi_recipient_encrypt_impl<
     usmc: &UnidentifiedSenderMessageContent,
     identity_store: &dyn IdentityKeyStore,
     rng: &mut R,
+) -> Result<(Vec<u8>, SealedSenderV2MessageKey)>
+where
+    X::IntoIter: ExactSizeIterator,
+{
+    sealed_sender_multi_recipient_encrypt_impl(
+        destinations,
+        destination_sessions,
+        excluded_recipients,
+        usmc,
+        identity_store,
+        rng,
+    )
+    .await
+}
+
+/// Re-targets a Sealed Sender v2 SentMessage at a new set of recipients.
+///
+/// The result is a SentMessage for exactly `destinations` (and `excluded_recipients`), which may
+/// add or drop recipients and devices relative to `sent_message`. This is meant for resending after
+/// the server rejects a multi-recipient message because some devices were missing or stale.
+///
+/// The shared ciphertext of `sent_message` is reused as is; only the per-recipient key material
+/// (see [`SealedSenderV2SentMessage::range_for_recipient_key_material`]) is computed again, using
+/// the current identity keys in `identity_store`. `message_key` must be the key returned when
+/// `sent_message` was created by [`sealed_sender_multi_recipient_encrypt_retargetable`].
+pub async fn sealed_sender_multi_recipient_retarget<X: IntoIterator<Item = ServiceId>>(
+    sent_message: &[u8],
+    message_key: &SealedSenderV2MessageKey,
+    destinations: &[&ProtocolAddress],
+    destination_sessions: &[&SessionRecord],
+    excluded_recipients: X,
+    identity_store: &dyn IdentityKeyStore,
 ) -> Result<Vec<u8>>
 where
     X::IntoIter: ExactSizeIterator,
 {
-    if destinations.len() != destination_sessions.len() {
+    let sent_message = SealedSenderV2SentMessage::parse(sent_message)?;
+
+    let e = sealed_sender_v2::DerivedKeys::new(&message_key.0).derive_e();
+    let (sent_e_pub, _) = sent_message
+        .shared_bytes
+        .split_at(sealed_sender_v2::PUBLIC_KEY_LEN);
+    if sent_e_pub != e.public_key.public_key_bytes() {
         return Err(SignalProtocolError::InvalidArgument(
-            "must have the same number of destination sessions as addresses".to_string(),
+            "message key does not match the sent message".to_string(),
         ));
     }
 
-    let excluded_recipients = excluded_recipients.into_iter();
-    let our_identity = identity_store.get_identity_key_pair().await?;
+    let mut serialized = serialize_multi_recipient_header(
+        destinations,
+        destination_sessions,
+        excluded_recipients,
+        &message_key.0,
+        &e,
+        identity_store,
+    )
+    .await?;
+    serialized.extend_from_slice(sent_message.shared_bytes);
+    Ok(serialized)
+}
 
+async fn sealed_sender_multi_recipient_encrypt_impl<
+    R: Rng + CryptoRng,
+    X: IntoIterator<Item = ServiceId>,
+>(
+    destinations: &[&ProtocolAddress],
+    destination_sessions: &[&SessionRecord],
+    excluded_recipients: X,
+    usmc: &UnidentifiedSenderMessageContent,
+    identity_store: &dyn IdentityKeyStore,
+    rng: &mut R,
+) -> Result<(Vec<u8>, SealedSenderV2MessageKey)>
+where
+    X::IntoIter: ExactSizeIterator,
+{
     let m: [u8; sealed_sender_v2::MESSAGE_KEY_LEN] = rng.random();
     let keys = sealed_sender_v2::DerivedKeys::new(&m);
     let e = keys.derive_e();
-    let e_pub = &e.public_key;
 
     // Encrypt the shared ciphertext using AES-GCM-SIV.
     let ciphertext = {
@@ -1430,6 +1503,45 @@ where
         ciphertext
     };
 
+    let mut serialized = serialize_multi_recipient_header(
+        destinations,
+        destination_sessions,
+        excluded_recipients,
+        &m,
+        &e,
+        identity_store,
+    )
+    .await?;
+    serialized.extend_from_slice(e.public_key.public_key_bytes());
+    serialized.extend_from_slice(&ciphertext);
+
+    Ok((serialized, SealedSenderV2MessageKey(m)))
+}
+
+/// Serializes everything in a SentMessage that comes before the shared `e_pub` and `message`
+/// fields: the version byte and the per-recipient data for `destinations` and
+/// `excluded_recipients`.
+async fn serialize_multi_recipient_header<X: IntoIterator<Item = ServiceId>>(
+    destinations: &[&ProtocolAddress],
+    destination_sessions: &[&SessionRecord],
+    excluded_recipients: X,
+    m: &[u8; sealed_sender_v2::MESSAGE_KEY_LEN],
+    e: &KeyPair,
+    identity_store: &dyn IdentityKeyStore,
+) -> Result<Vec<u8>>
+where
+    X::IntoIter: ExactSizeIterator,
+{
+    if destinations.len() != destination_sessions.len() {
+        return Err(SignalProtocolError::InvalidArgument(
+            "must have the same number of destination sessions as addresses".to_string(),
+        ));
+    }
+
+    let excluded_recipients = excluded_recipients.into_iter();
+    let our_identity = identity_store.get_identity_key_pair().await?;
+    let e_pub = &e.public_key;
+
     // Group the destinations by name, and fetch identity keys once for each name. This optimizes
     // for the common case where all of a recipient's devices are included contiguously in the
     // destination list. (If the caller *doesn't* do this, that's on them; the message will still be
@@ -1525,10 +1637,10 @@ where
         }
 
         let c_i = sealed_sender_v2::apply_agreement_xor(
-            &e,
+            e,
             their_identity.public_key(),
             Direction::Sending,
-            &m,
+            m,
         )?;
         serialized.extend_from_slice(&c_i);
 
@@ -1607,9 +1719,6 @@ where
         serialized.push(0);
     }
 
-    serialized.extend_from_slice(e_pub.public_key_bytes());
-    serialized.extend_from_slice(&ciphertext);
-
     Ok(serialized)
 }
 
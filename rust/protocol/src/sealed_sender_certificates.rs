@@ -0,0 +1,373 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Issuing and validating sealed sender certificates with more than one trust root.
+//!
+//! A [`SenderCertificateIssuer`] is the server side: it holds trust roots and a schedule of server
+//! keys, each with a [`ValidityWindow`], and issues [`SenderCertificate`]s signed by whichever
+//! server key is current. A [`SenderCertificateVerifier`] is the client side: it accepts a sender
+//! certificate if its server certificate was signed by a trust root valid at the time of
+//! validation and its server key ID has not been revoked. Signature checks are cached, since the
+//! same few certificates are seen over and over.
+//!
+//! Signal's own servers don't need any of this, but it's useful for deployments that run their own
+//! Signal-compatible servers and have to rotate keys themselves.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use rand::{CryptoRng, Rng};
+use subtle::Choice;
+
+use crate::{
+    DeviceId, KeyPair, PrivateKey, PublicKey, Result, SenderCertificate, ServerCertificate,
+    SignalProtocolError, Timestamp,
+};
+
+/// The span of time during which a key may be used, inclusive at both ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ValidityWindow {
+    pub not_before: Timestamp,
+    /// If `None`, the key is valid indefinitely.
+    pub not_after: Option<Timestamp>,
+}
+
+impl ValidityWindow {
+    pub fn new(not_before: Timestamp, not_after: Option<Timestamp>) -> Self {
+        Self {
+            not_before,
+            not_after,
+        }
+    }
+
+    pub fn contains(&self, time: Timestamp) -> bool {
+        self.not_before <= time && self.not_after.is_none_or(|not_after| time <= not_after)
+    }
+
+    fn contains_window(&self, other: &Self) -> bool {
+        self.contains(other.not_before)
+            && match (self.not_after, other.not_after) {
+                (None, _) => true,
+                (Some(_), None) => false,
+                (Some(ours), Some(theirs)) => theirs <= ours,
+            }
+    }
+}
+
+struct IssuerTrustRoot {
+    key_pair: KeyPair,
+    window: ValidityWindow,
+}
+
+struct IssuerServerKey {
+    key_id: u32,
+    certificate: ServerCertificate,
+    private_key: PrivateKey,
+    window: ValidityWindow,
+}
+
+/// Issues server certificates and sender certificates according to a key rotation schedule.
+///
+/// Server keys are added ahead of time with overlapping validity windows; at any given time, the
+/// one that became valid most recently is used. Each server certificate is signed by a trust root
+/// whose window covers the server key's entire window.
+#[derive(Default)]
+pub struct SenderCertificateIssuer {
+    trust_roots: Vec<IssuerTrustRoot>,
+    server_keys: Vec<IssuerServerKey>,
+    revoked_key_ids: BTreeSet<u32>,
+}
+
+impl SenderCertificateIssuer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_trust_root(&mut self, key_pair: KeyPair, window: ValidityWindow) {
+        self.trust_roots.push(IssuerTrustRoot { key_pair, window });
+    }
+
+    /// The trust roots clients should be configured with, along with their windows.
+    pub fn trust_roots(&self) -> impl Iterator<Item = (PublicKey, ValidityWindow)> + '_ {
+        self.trust_roots
+            .iter()
+            .map(|root| (root.key_pair.public_key, root.window))
+    }
+
+    /// Generates a new server key and its certificate.
+    ///
+    /// `key_id` must not have been used before, and there must be a trust root valid for all of
+    /// `window`. If several are, the one that became valid most recently signs the certificate.
+    pub fn add_server_key<R: Rng + CryptoRng>(
+        &mut self,
+        key_id: u32,
+        window: ValidityWindow,
+        rng: &mut R,
+    ) -> Result<&ServerCertificate> {
+        if self.revoked_key_ids.contains(&key_id)
+            || self.server_keys.iter().any(|key| key.key_id == key_id)
+        {
+            return Err(SignalProtocolError::InvalidArgument(format!(
+                "server key ID {key_id:x} has already been used"
+            )));
+        }
+
+        let trust_root = self
+            .trust_roots
+            .iter()
+            .filter(|root| root.window.contains_window(&window))
+            .max_by_key(|root| root.window.not_before)
+            .ok_or_else(|| {
+                SignalProtocolError::InvalidArgument(format!(
+                    "no trust root is valid for all of {window:?}"
+                ))
+            })?;
+
+        let server_key = KeyPair::generate(rng);
+        let certificate = ServerCertificate::new(
+            key_id,
+            server_key.public_key,
+            &trust_root.key_pair.private_key,
+            rng,
+        )?;
+        self.server_keys.push(IssuerServerKey {
+            key_id,
+            certificate,
+            private_key: server_key.private_key,
+            window,
+        });
+        Ok(&self.server_keys.last().expect("just added").certificate)
+    }
+
+    /// Stops using a server key, and adds it to the list returned by
+    /// [`revoked_key_ids`](Self::revoked_key_ids).
+    pub fn revoke_server_key(&mut self, key_id: u32) {
+        self.server_keys.retain(|key| key.key_id != key_id);
+        self.revoked_key_ids.insert(key_id);
+    }
+
+    /// The server key IDs clients should refuse to accept.
+    pub fn revoked_key_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.revoked_key_ids.iter().copied()
+    }
+
+    /// Forgets server keys whose windows ended before `now`.
+    pub fn remove_expired_server_keys(&mut self, now: Timestamp) {
+        self.server_keys.retain(|key| {
+            key.window
+                .not_after
+                .is_none_or(|not_after| now <= not_after)
+        });
+    }
+
+    fn current_server_key(&self, now: Timestamp) -> Option<&IssuerServerKey> {
+        self.server_keys
+            .iter()
+            .filter(|key| key.window.contains(now))
+            .max_by_key(|key| key.window.not_before)
+    }
+
+    /// The server certificate that will sign sender certificates issued at `now`.
+    pub fn current_server_certificate(&self, now: Timestamp) -> Option<&ServerCertificate> {
+        self.current_server_key(now).map(|key| &key.certificate)
+    }
+
+    /// Issues a sender certificate signed by the current server key.
+    ///
+    /// Fails if there is no current server key, or if `expiration` is after the end of its
+    /// window.
+    pub fn issue<R: Rng + CryptoRng>(
+        &self,
+        sender_uuid: String,
+        sender_e164: Option<String>,
+        key: PublicKey,
+        sender_device_id: DeviceId,
+        expiration: Timestamp,
+        now: Timestamp,
+        rng: &mut R,
+    ) -> Result<SenderCertificate> {
+        let server_key = self.current_server_key(now).ok_or_else(|| {
+            SignalProtocolError::InvalidState(
+                "issue",
+                format!("no server key is valid at {}", now.epoch_millis()),
+            )
+        })?;
+        if server_key
+            .window
+            .not_after
+            .is_some_and(|not_after| expiration > not_after)
+        {
+            return Err(SignalProtocolError::InvalidArgument(format!(
+                "expiration {} is after the end of server key {:x}",
+                expiration.epoch_millis(),
+                server_key.key_id
+            )));
+        }
+
+        SenderCertificate::new(
+            sender_uuid,
+            sender_e164,
+            key,
+            sender_device_id,
+            expiration,
+            server_key.certificate.clone(),
+            &server_key.private_key,
+            rng,
+        )
+    }
+
+    /// Creates a verifier that trusts this issuer's trust roots and respects its revocations.
+    pub fn verifier(&self) -> SenderCertificateVerifier {
+        let mut verifier = SenderCertificateVerifier::new();
+        for (key, window) in self.trust_roots() {
+            verifier.add_trust_root(key, window);
+        }
+        for key_id in self.revoked_key_ids() {
+            verifier.revoke_server_key(key_id);
+        }
+        verifier
+    }
+}
+
+/// Validates sender certificates against several trust roots, each with its own validity window.
+///
+/// The results of signature checks are cached, keyed by the serialized certificates; revocations
+/// and validity windows are checked on every call.
+pub struct SenderCertificateVerifier {
+    trust_roots: Vec<(PublicKey, ValidityWindow)>,
+    revoked_key_ids: BTreeSet<u32>,
+    cache_capacity: usize,
+    /// For each server certificate, which of `trust_roots` signed it.
+    server_certificate_cache: Mutex<HashMap<Vec<u8>, Vec<bool>>>,
+    /// For each sender certificate, whether it was signed by its server certificate's key.
+    sender_certificate_cache: Mutex<HashMap<Vec<u8>, bool>>,
+}
+
+impl Default for SenderCertificateVerifier {
+    fn default() -> Self {
+        Self::with_cache_capacity(Self::DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+impl SenderCertificateVerifier {
+    /// The number of certificates of each kind whose signature checks are remembered by default.
+    pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a verifier that remembers up to `cache_capacity` certificates of each kind.
+    ///
+    /// A capacity of zero disables caching.
+    pub fn with_cache_capacity(cache_capacity: usize) -> Self {
+        Self {
+            trust_roots: Vec::new(),
+            revoked_key_ids: BTreeSet::new(),
+            cache_capacity,
+            server_certificate_cache: Default::default(),
+            sender_certificate_cache: Default::default(),
+        }
+    }
+
+    pub fn add_trust_root(&mut self, key: PublicKey, window: ValidityWindow) {
+        self.trust_roots.push((key, window));
+        // The cached results are per trust root.
+        self.server_certificate_cache
+            .get_mut()
+            .expect("not poisoned")
+            .clear();
+    }
+
+    pub fn revoke_server_key(&mut self, key_id: u32) {
+        self.revoked_key_ids.insert(key_id);
+    }
+
+    /// Checks that `certificate` is valid at `validation_time`.
+    ///
+    /// This is [`SenderCertificate::validate_with_trust_roots`], except that each trust root is
+    /// only accepted within its window, and server certificates with revoked key IDs are rejected.
+    pub fn validate(
+        &self,
+        certificate: &SenderCertificate,
+        validation_time: Timestamp,
+    ) -> Result<bool> {
+        let signer = certificate.signer()?;
+
+        let key_id = signer.key_id()?;
+        if self.revoked_key_ids.contains(&key_id) {
+            log::error!("received server certificate with revoked ID {key_id:x}");
+            return Ok(false);
+        }
+
+        let signed_by =
+            self.cached(&self.server_certificate_cache, signer.serialized()?, || {
+                self.trust_roots
+                    .iter()
+                    .map(|(root, _window)| signer.validate(root))
+                    .collect()
+            })?;
+        // As in validate_with_trust_roots, check every trust root to hide which one was correct.
+        let mut any_valid = Choice::from(0u8);
+        for ((_root, window), signed) in self.trust_roots.iter().zip(signed_by) {
+            any_valid |= Choice::from(u8::from(signed && window.contains(validation_time)));
+        }
+        if !bool::from(any_valid) {
+            log::error!(
+                "sender certificate contained server certificate that wasn't signed by any trust root valid at {}",
+                validation_time.epoch_millis()
+            );
+            return Ok(false);
+        }
+
+        let signed = self.cached(
+            &self.sender_certificate_cache,
+            certificate.serialized()?,
+            || {
+                Ok(signer
+                    .public_key()?
+                    .verify_signature(certificate.certificate()?, certificate.signature()?))
+            },
+        )?;
+        if !signed {
+            log::error!("sender certificate not signed by server");
+            return Ok(false);
+        }
+
+        let expiration = certificate.expiration()?;
+        if validation_time > expiration {
+            log::error!(
+                "sender certificate is expired (expiration: {}, validation_time: {})",
+                expiration.epoch_millis(),
+                validation_time.epoch_millis()
+            );
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    fn cached<V: Clone>(
+        &self,
+        cache: &Mutex<HashMap<Vec<u8>, V>>,
+        key: &[u8],
+        compute: impl FnOnce() -> Result<V>,
+    ) -> Result<V> {
+        if let Some(value) = cache.lock().expect("not poisoned").get(key) {
+            return Ok(value.clone());
+        }
+
+        let value = compute()?;
+        if self.cache_capacity > 0 {
+            let mut cache = cache.lock().expect("not poisoned");
+            if cache.len() >= self.cache_capacity {
+                // Certificates tend to be seen in bursts, so starting over is good enough.
+                cache.clear();
+            }
+            cache.insert(key.to_vec(), value.clone());
+        }
+        Ok(value)
+    }
+}
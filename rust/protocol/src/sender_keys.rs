@@ -4,13 +4,14 @@
 //
 
 use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
 
 use itertools::Itertools;
 use prost::Message;
 
 use crate::crypto::hmac_sha256;
 use crate::proto::storage as storage_proto;
-use crate::{PrivateKey, PublicKey, SignalProtocolError, consts};
+use crate::{PrivateKey, ProtocolAddress, PublicKey, SignalProtocolError, consts};
 
 /// A distinct error type to keep from accidentally propagating deserialization errors.
 #[derive(Debug)]
@@ -158,6 +159,8 @@ impl SenderKeyState {
                 },
             ),
             sender_message_keys: vec![],
+            receive_only_until: 0,
+            distributed_to: vec![],
         };
 
         Self { state }
@@ -212,6 +215,52 @@ impl SenderKeyState {
         self.state.clone()
     }
 
+    /// Whether this state can only be used to decrypt, either because it was received from
+    /// another sender or because it was retired locally.
+    pub(crate) fn is_receive_only(&self) -> bool {
+        self.state
+            .sender_signing_key
+            .as_ref()
+            .is_none_or(|signing_key| signing_key.private.is_empty())
+    }
+
+    fn mark_receive_only(&mut self, expires_at: SystemTime) {
+        if let Some(signing_key) = &mut self.state.sender_signing_key {
+            signing_key.private = vec![];
+        }
+        let expires_at = expires_at
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .max(1);
+        if self.state.receive_only_until == 0 || expires_at < self.state.receive_only_until {
+            self.state.receive_only_until = expires_at;
+        }
+    }
+
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.state.receive_only_until != 0
+            && SystemTime::UNIX_EPOCH + Duration::from_secs(self.state.receive_only_until) <= now
+    }
+
+    fn was_distributed_to(&self, recipient: &ProtocolAddress) -> bool {
+        self.state
+            .distributed_to
+            .iter()
+            .any(|r| r.name == recipient.name() && r.device_id == u32::from(recipient.device_id()))
+    }
+
+    fn add_distributed_to(&mut self, recipient: &ProtocolAddress) {
+        if !self.was_distributed_to(recipient) {
+            self.state
+                .distributed_to
+                .push(storage_proto::sender_key_state_structure::Recipient {
+                    name: recipient.name().to_owned(),
+                    device_id: recipient.device_id().into(),
+                });
+        }
+    }
+
     pub(crate) fn add_sender_message_key(&mut self, sender_message_key: &SenderMessageKey) {
         self.state
             .sender_message_keys
@@ -347,6 +396,65 @@ impl SenderKeyRecord {
         initial_length - self.states.len()
     }
 
+    /// Stops using every state in this record for sending.
+    ///
+    /// Receive-only states can still decrypt messages until they are removed by
+    /// [`remove_expired_states`](Self::remove_expired_states) at or after `expires_at`. A state
+    /// that was already retired keeps the earlier of its existing expiry and `expires_at`.
+    pub fn mark_receive_only(&mut self, expires_at: SystemTime) {
+        for state in &mut self.states {
+            state.mark_receive_only(expires_at);
+        }
+    }
+
+    /// Discards receive-only states whose expiry is at or before `now`.
+    ///
+    /// Returns the number of removed states.
+    pub fn remove_expired_states(&mut self, now: SystemTime) -> usize {
+        let initial_length = self.states.len();
+        self.states.retain(|state| !state.is_expired(now));
+        initial_length - self.states.len()
+    }
+
+    /// Records that the distribution message for the current sending chain has been sent to each
+    /// of `recipients`.
+    pub fn mark_distributed_to<'a>(
+        &mut self,
+        recipients: impl IntoIterator<Item = &'a ProtocolAddress>,
+    ) -> Result<(), SignalProtocolError> {
+        let state = self
+            .sender_key_state_mut()
+            .ok()
+            .filter(|state| !state.is_receive_only())
+            .ok_or_else(|| {
+                SignalProtocolError::InvalidState(
+                    "mark_distributed_to",
+                    "no current sending chain".to_owned(),
+                )
+            })?;
+        for recipient in recipients {
+            state.add_distributed_to(recipient);
+        }
+        Ok(())
+    }
+
+    /// Returns the members of `recipients` that have not been sent the distribution message for
+    /// the current sending chain.
+    ///
+    /// If there is no current sending chain, all of `recipients` are returned.
+    pub fn recipients_needing_distribution<'a>(
+        &self,
+        recipients: &'a [ProtocolAddress],
+    ) -> Vec<&'a ProtocolAddress> {
+        match self.sender_key_state() {
+            Ok(state) if !state.is_receive_only() => recipients
+                .iter()
+                .filter(|recipient| !state.was_distributed_to(recipient))
+                .collect(),
+            _ => recipients.iter().collect(),
+        }
+    }
+
     pub(crate) fn as_protobuf(&self) -> storage_proto::SenderKeyRecordStructure {
         let mut states = Vec::with_capacity(self.states.len());
         for state in &self.states {
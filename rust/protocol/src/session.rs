@@ -7,14 +7,15 @@ use std::time::SystemTime;
 
 use rand::{CryptoRng, Rng};
 
+use crate::kem::KemPolicy;
 use crate::protocol::CIPHERTEXT_MESSAGE_PRE_KYBER_VERSION;
 use crate::ratchet::{AliceSignalProtocolParameters, BobSignalProtocolParameters};
 use crate::state::GenericSignedPreKey;
 use crate::{
-    CiphertextMessageType, Direction, IdentityKey, IdentityKeyStore, KeyPair, KyberPreKeyId,
-    KyberPreKeyStore, PreKeyBundle, PreKeyId, PreKeySignalMessage, PreKeyStore, ProtocolAddress,
-    Result, SessionRecord, SessionStore, SignalProtocolError, SignedPreKeyId, SignedPreKeyStore,
-    ratchet,
+    CiphertextMessageType, Direction, IdentityChange, IdentityKey, IdentityKeyStore,
+    IdentityKeyTransition, KeyPair, KyberPreKeyId, KyberPreKeyStore, PreKeyBundle, PreKeyId,
+    PreKeySignalMessage, PreKeyStore, ProtocolAddress, Result, SessionPolicy, SessionRecord,
+    SessionStore, SignalProtocolError, SignedPreKeyId, SignedPreKeyStore, consts, ratchet,
 };
 
 pub struct PreKeysUsed {
@@ -52,6 +53,32 @@ pub async fn process_prekey<'a>(
     pre_key_store: &dyn PreKeyStore,
     signed_prekey_store: &dyn SignedPreKeyStore,
     kyber_prekey_store: &dyn KyberPreKeyStore,
+) -> Result<(Option<PreKeysUsed>, IdentityToSave<'a>)> {
+    process_prekey_with_policy(
+        message,
+        remote_address,
+        local_address,
+        session_record,
+        identity_store,
+        pre_key_store,
+        signed_prekey_store,
+        kyber_prekey_store,
+        &SessionPolicy::default(),
+    )
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn process_prekey_with_policy<'a>(
+    message: &'a PreKeySignalMessage,
+    remote_address: &'a ProtocolAddress,
+    local_address: &ProtocolAddress,
+    session_record: &mut SessionRecord,
+    identity_store: &dyn IdentityKeyStore,
+    pre_key_store: &dyn PreKeyStore,
+    signed_prekey_store: &dyn SignedPreKeyStore,
+    kyber_prekey_store: &dyn KyberPreKeyStore,
+    policy: &SessionPolicy,
 ) -> Result<(Option<PreKeysUsed>, IdentityToSave<'a>)> {
     let their_identity_key = message.identity_key();
 
@@ -73,6 +100,7 @@ pub async fn process_prekey<'a>(
         kyber_prekey_store,
         pre_key_store,
         identity_store,
+        policy,
     )
     .await?;
 
@@ -93,10 +121,12 @@ async fn process_prekey_impl(
     kyber_prekey_store: &dyn KyberPreKeyStore,
     pre_key_store: &dyn PreKeyStore,
     identity_store: &dyn IdentityKeyStore,
+    policy: &SessionPolicy,
 ) -> Result<Option<PreKeysUsed>> {
     if session_record.promote_matching_session(
         message.message_version() as u32,
         &message.base_key().serialize(),
+        policy.max_archived_states,
     )? {
         // We've already set up a session for this message, we can exit early.
         return Ok(None);
@@ -168,7 +198,7 @@ async fn process_prekey_impl(
     new_session.set_local_registration_id(identity_store.get_local_registration_id().await?);
     new_session.set_remote_registration_id(message.registration_id());
 
-    session_record.promote_state(new_session);
+    session_record.promote_state(new_session, policy.max_archived_states);
 
     let pre_keys_used = PreKeysUsed {
         one_time_ec_pre_key_id: message.pre_key_id(),
@@ -185,10 +215,41 @@ pub async fn process_prekey_bundle<R: Rng + CryptoRng>(
     identity_store: &mut dyn IdentityKeyStore,
     bundle: &PreKeyBundle,
     now: SystemTime,
+    csprng: &mut R,
+) -> Result<()> {
+    process_prekey_bundle_with_kem_policy(
+        remote_address,
+        local_address,
+        session_store,
+        identity_store,
+        bundle,
+        now,
+        &KemPolicy::default(),
+        csprng,
+    )
+    .await
+}
+
+/// Like [`process_prekey_bundle`], but rejects bundles whose Kyber pre-key doesn't use a KEM
+/// acceptable to `kem_policy`.
+#[expect(clippy::too_many_arguments)]
+pub async fn process_prekey_bundle_with_kem_policy<R: Rng + CryptoRng>(
+    remote_address: &ProtocolAddress,
+    local_address: &ProtocolAddress,
+    session_store: &mut dyn SessionStore,
+    identity_store: &mut dyn IdentityKeyStore,
+    bundle: &PreKeyBundle,
+    now: SystemTime,
+    kem_policy: &KemPolicy,
     mut csprng: &mut R,
 ) -> Result<()> {
     let their_identity_key = bundle.identity_key()?;
 
+    let kem_key_type = bundle.kyber_pre_key_public()?.key_type();
+    if !kem_policy.is_acceptable(kem_key_type) {
+        return Err(SignalProtocolError::UnacceptableKEMKeyType(kem_key_type));
+    }
+
     if !identity_store
         .is_trusted_identity(remote_address, their_identity_key, Direction::Sending)
         .await?
@@ -265,7 +326,7 @@ pub async fn process_prekey_bundle<R: Rng + CryptoRng>(
         .save_identity(remote_address, their_identity_key)
         .await?;
 
-    session_record.promote_state(session);
+    session_record.promote_state(session, consts::ARCHIVED_STATES_MAX_LENGTH);
 
     session_store
         .store_session(remote_address, &session_record)
@@ -273,3 +334,37 @@ pub async fn process_prekey_bundle<R: Rng + CryptoRng>(
 
     Ok(())
 }
+
+/// Applies an [`IdentityKeyTransition`] received from `remote_address`.
+///
+/// The new identity is saved with [`IdentityKeyStore::save_identity_with_transition`]. If that
+/// replaced a stored identity, the current session with `remote_address` was established with the
+/// old key, so it is archived; the next message in either direction will need a new session.
+///
+/// Returns [`SignalProtocolError::SignatureValidationFailed`] without changing either store if the
+/// transition is not signed by its old identity key, or if that key is not the stored identity for
+/// `remote_address`.
+pub async fn process_identity_key_transition(
+    remote_address: &ProtocolAddress,
+    transition: &IdentityKeyTransition,
+    identity_store: &mut dyn IdentityKeyStore,
+    session_store: &mut dyn SessionStore,
+) -> Result<IdentityChange> {
+    let change = identity_store
+        .save_identity_with_transition(remote_address, transition)
+        .await?;
+
+    if change == IdentityChange::NewOrUnchanged {
+        return Ok(change);
+    }
+
+    if let Some(mut session_record) = session_store.load_session(remote_address).await? {
+        log::info!("archiving session for {remote_address} after identity change: {change:?}");
+        session_record.archive_current_state()?;
+        session_store
+            .store_session(remote_address, &session_record)
+            .await?;
+    }
+
+    Ok(change)
+}
@@ -17,7 +17,9 @@ use std::time::SystemTime;
 
 use rand::{CryptoRng, Rng};
 
-use crate::consts::{MAX_FORWARD_JUMPS, MAX_UNACKNOWLEDGED_SESSION_AGE};
+use crate::consts::{
+    ARCHIVED_STATES_MAX_LENGTH, MAX_FORWARD_JUMPS, MAX_UNACKNOWLEDGED_SESSION_AGE,
+};
 use crate::ratchet::{ChainKey, MessageKeyGenerator};
 use crate::state::{InvalidSessionError, SessionState};
 use crate::{
@@ -584,7 +586,7 @@ fn decrypt_message_with_record<R: Rng + CryptoRng>(
     }
 
     if let Some((ptext, idx, updated_session)) = updated_session {
-        record.promote_old_session(idx, updated_session);
+        record.promote_old_session(idx, updated_session, ARCHIVED_STATES_MAX_LENGTH);
         Ok(ptext)
     } else {
         let previous_state_count = || record.previous_session_states().len();
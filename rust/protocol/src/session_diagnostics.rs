@@ -0,0 +1,186 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Secret-free summaries of stored sessions, for investigating decryption failures.
+//!
+//! A [`SessionDiagnostics`] report contains only counters, identifiers, and public keys, so it is
+//! safe to log or attach to a bug report. It never includes root keys, chain keys, message keys,
+//! or private ratchet keys.
+
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+
+use crate::protocol::CIPHERTEXT_MESSAGE_PRE_KYBER_VERSION;
+use crate::state::{KyberPreKeyId, PreKeyId, SessionState, SignedPreKeyId};
+use crate::{ProtocolAddress, PublicKey, Result, SessionRecord, SessionStore, kem};
+
+/// A secret-free report on a [`SessionRecord`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionDiagnostics {
+    /// The current session, or `None` if the record is fresh or its current session was archived.
+    pub current_session: Option<SessionStateDiagnostics>,
+    /// The number of archived session states kept alongside the current one.
+    pub archived_state_count: usize,
+}
+
+/// The diagnosable parts of a single session state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionStateDiagnostics {
+    /// The session version (3 for X3DH, 4 for PQXDH).
+    pub session_version: u32,
+    /// Whether the session was established with PQXDH, and therefore a Kyber pre-key.
+    pub established_with_pqxdh: bool,
+    /// The KEM used for PQXDH, if the session recorded it.
+    pub kem_key_type: Option<KemKeyTypeDiagnostics>,
+    /// Whether the session has SPQR state, i.e. is running the triple ratchet.
+    pub has_pq_ratchet_state: bool,
+    /// The counter of the next message to be sent, or `None` if there is no sender chain.
+    pub sender_chain_index: Option<u32>,
+    /// The number of messages sent on the previous sender chain.
+    pub previous_counter: u32,
+    /// The receiver chains, oldest first.
+    pub receiver_chains: Vec<ReceiverChainDiagnostics>,
+    /// The pre-keys used to start this session, if the peer has not yet responded to it.
+    pub pending_pre_key: Option<PendingPreKeyDiagnostics>,
+    /// A SHA-256 hash of the peer's serialized identity key, hex-encoded.
+    pub remote_identity_fingerprint: Option<String>,
+    /// The peer's registration ID.
+    pub remote_registration_id: u32,
+    /// The local registration ID.
+    pub local_registration_id: u32,
+}
+
+/// The KEM recorded in a session state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KemKeyTypeDiagnostics {
+    Known(kem::KeyType),
+    /// A stored value that this version of the library doesn't recognize.
+    Unknown(u32),
+}
+
+/// The position of a single receiver chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReceiverChainDiagnostics {
+    /// The peer's ratchet key for this chain.
+    pub sender_ratchet_key: PublicKey,
+    /// The counter of the next message expected on this chain.
+    pub chain_index: u32,
+    /// The number of keys cached for skipped messages on this chain.
+    pub cached_message_key_count: usize,
+}
+
+/// The pre-keys referenced by an unacknowledged session.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingPreKeyDiagnostics {
+    /// The peer's one-time EC pre-key, if one was used.
+    pub pre_key_id: Option<PreKeyId>,
+    /// The peer's signed EC pre-key.
+    pub signed_pre_key_id: SignedPreKeyId,
+    /// The peer's Kyber pre-key, if the session was started with PQXDH.
+    pub kyber_pre_key_id: Option<KyberPreKeyId>,
+    /// When the session was started.
+    pub timestamp: SystemTime,
+}
+
+impl SessionDiagnostics {
+    /// Summarizes `record` without exposing any secret key material.
+    pub fn from_record(record: &SessionRecord) -> Result<Self> {
+        Ok(Self {
+            current_session: record
+                .session_state()
+                .map(SessionStateDiagnostics::from_state)
+                .transpose()?,
+            archived_state_count: record.archived_state_count(),
+        })
+    }
+}
+
+impl SessionStateDiagnostics {
+    fn from_state(state: &SessionState) -> Result<Self> {
+        let session_version = state.session_version()?;
+        let receiver_chains = state
+            .receiver_chain_counters()?
+            .into_iter()
+            .map(
+                |(sender_ratchet_key, chain_index, cached_message_key_count)| {
+                    ReceiverChainDiagnostics {
+                        sender_ratchet_key,
+                        chain_index,
+                        cached_message_key_count,
+                    }
+                },
+            )
+            .collect();
+        let pending_pre_key =
+            state
+                .unacknowledged_pre_key_message_items()?
+                .map(|items| PendingPreKeyDiagnostics {
+                    pre_key_id: items.pre_key_id(),
+                    signed_pre_key_id: items.signed_pre_key_id(),
+                    kyber_pre_key_id: items.kyber_pre_key_id(),
+                    timestamp: items.timestamp(),
+                });
+        let remote_identity_fingerprint = state
+            .remote_identity_key()?
+            .map(|identity| hex::encode(Sha256::digest(identity.serialize())));
+
+        Ok(Self {
+            session_version,
+            established_with_pqxdh: session_version > CIPHERTEXT_MESSAGE_PRE_KYBER_VERSION.into(),
+            kem_key_type: match state.kem_key_type() {
+                Ok(key_type) => key_type.map(KemKeyTypeDiagnostics::Known),
+                Err(_) => Some(KemKeyTypeDiagnostics::Unknown(state.raw_kem_key_type())),
+            },
+            has_pq_ratchet_state: !state.pq_ratchet_state().is_empty(),
+            sender_chain_index: state.sender_chain_index(),
+            previous_counter: state.previous_counter(),
+            receiver_chains,
+            pending_pre_key,
+            remote_identity_fingerprint,
+            remote_registration_id: state.remote_registration_id(),
+            local_registration_id: state.local_registration_id(),
+        })
+    }
+}
+
+/// Loads the session for `remote_address` and summarizes it.
+///
+/// Returns `Ok(None)` if `session_store` has no record for `remote_address`.
+pub async fn session_diagnostics(
+    remote_address: &ProtocolAddress,
+    session_store: &dyn SessionStore,
+) -> Result<Option<SessionDiagnostics>> {
+    session_store
+        .load_session(remote_address)
+        .await?
+        .map(|record| SessionDiagnostics::from_record(&record))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::storage::SessionStructure;
+
+    #[test]
+    fn unknown_kem_key_type() {
+        let state = SessionState::from_session_structure(SessionStructure {
+            session_version: 4,
+            previous_counter: 5,
+            remote_registration_id: 1234,
+            kem_key_type: 0xFF,
+            ..Default::default()
+        });
+        let diagnostics = SessionStateDiagnostics::from_state(&state).expect("can summarize");
+        assert_eq!(
+            diagnostics.kem_key_type,
+            Some(KemKeyTypeDiagnostics::Unknown(0xFF))
+        );
+        assert_eq!(diagnostics.session_version, 4);
+        assert_eq!(diagnostics.previous_counter, 5);
+        assert_eq!(diagnostics.remote_registration_id, 1234);
+    }
+}
@@ -10,6 +10,10 @@
 //! 1. **The public API** — [`message_encrypt`], [`message_decrypt`],
 //!    [`message_decrypt_signal`], [`message_decrypt_prekey`]. These are the
 //!    entry points used by the bridge layer and `sealed_sender`.
+//!    [`message_decrypt_deferred`] returns the store mutations as a
+//!    [`PendingStoreChanges`] instead of applying them, for callers that want
+//!    to commit them atomically. [`message_decrypt_with_policy`] lets callers
+//!    override the out-of-order limits in [`SessionPolicy`].
 //!
 //! 2. **Sesame session management** — the "which session do we use?" logic:
 //!    trial-decryption across current and previous sessions, session promotion
@@ -29,9 +33,10 @@ use crate::consts::MAX_UNACKNOWLEDGED_SESSION_AGE;
 use crate::state::{InvalidSessionError, SessionState};
 use crate::triple_ratchet::{OutgoingTripleRatchet, TripleRatchet};
 use crate::{
-    CiphertextMessage, CiphertextMessageType, Direction, IdentityKeyStore, KyberPayload,
-    KyberPreKeyStore, PreKeySignalMessage, PreKeyStore, ProtocolAddress, Result, SessionNotFound,
-    SessionRecord, SessionStore, SignalMessage, SignalProtocolError, SignedPreKeyStore, session,
+    CiphertextMessage, CiphertextMessageType, Direction, IdentityKey, IdentityKeyStore,
+    KyberPayload, KyberPreKeyId, KyberPreKeyStore, PreKeyId, PreKeySignalMessage, PreKeyStore,
+    ProtocolAddress, PublicKey, Result, SessionNotFound, SessionPolicy, SessionRecord,
+    SessionStore, SignalMessage, SignalProtocolError, SignedPreKeyId, SignedPreKeyStore, session,
 };
 // ── Public API ───────────────────────────────────────────────────────────────
 
@@ -165,21 +170,125 @@ pub async fn message_decrypt<R: Rng + CryptoRng>(
     signed_pre_key_store: &dyn SignedPreKeyStore,
     kyber_pre_key_store: &mut dyn KyberPreKeyStore,
     csprng: &mut R,
+) -> Result<Vec<u8>> {
+    message_decrypt_with_policy(
+        ciphertext,
+        remote_address,
+        local_address,
+        session_store,
+        identity_store,
+        pre_key_store,
+        signed_pre_key_store,
+        kyber_pre_key_store,
+        &SessionPolicy::default(),
+        csprng,
+    )
+    .await
+}
+
+/// Decrypt a [`CiphertextMessage`] from `remote_address`, using the out-of-order
+/// limits in `policy` instead of the defaults.
+///
+/// Otherwise identical to [`message_decrypt`].
+#[allow(clippy::too_many_arguments)]
+pub async fn message_decrypt_with_policy<R: Rng + CryptoRng>(
+    ciphertext: &CiphertextMessage,
+    remote_address: &ProtocolAddress,
+    local_address: &ProtocolAddress,
+    session_store: &mut dyn SessionStore,
+    identity_store: &mut dyn IdentityKeyStore,
+    pre_key_store: &mut dyn PreKeyStore,
+    signed_pre_key_store: &dyn SignedPreKeyStore,
+    kyber_pre_key_store: &mut dyn KyberPreKeyStore,
+    policy: &SessionPolicy,
+    csprng: &mut R,
 ) -> Result<Vec<u8>> {
     match ciphertext {
         CiphertextMessage::SignalMessage(m) => {
-            message_decrypt_signal(
+            let (ptext, changes) = decrypt_signal_deferred(
+                m,
+                remote_address,
+                local_address,
+                session_store,
+                identity_store,
+                policy,
+                csprng,
+            )
+            .await?;
+            changes
+                .apply_without_pre_keys(session_store, identity_store)
+                .await?;
+            Ok(ptext)
+        }
+        CiphertextMessage::PreKeySignalMessage(m) => {
+            let (ptext, changes) = decrypt_prekey_deferred(
                 m,
                 remote_address,
                 local_address,
                 session_store,
                 identity_store,
+                pre_key_store,
+                signed_pre_key_store,
+                kyber_pre_key_store,
+                policy,
+                csprng,
+            )
+            .await?;
+            changes
+                .apply(
+                    session_store,
+                    identity_store,
+                    pre_key_store,
+                    kyber_pre_key_store,
+                )
+                .await?;
+            Ok(ptext)
+        }
+        _ => Err(SignalProtocolError::InvalidArgument(format!(
+            "message_decrypt cannot be used to decrypt {:?} messages",
+            ciphertext.message_type()
+        ))),
+    }
+}
+
+/// Decrypt a [`CiphertextMessage`] from `remote_address` without modifying any store.
+///
+/// This performs the same checks as [`message_decrypt`], but instead of writing to the stores as
+/// it goes, it returns every mutation the decryption requires as a [`PendingStoreChanges`]. The
+/// caller is responsible for [applying](PendingStoreChanges::apply) them, typically inside a
+/// single database transaction, so that a crash can never leave the session updated but the
+/// pre-key still present (or vice versa).
+///
+/// The plaintext must not be considered delivered until the changes have been applied.
+/// `policy` controls the out-of-order limits, as in [`message_decrypt_with_policy`].
+#[allow(clippy::too_many_arguments)]
+pub async fn message_decrypt_deferred<R: Rng + CryptoRng>(
+    ciphertext: &CiphertextMessage,
+    remote_address: &ProtocolAddress,
+    local_address: &ProtocolAddress,
+    session_store: &dyn SessionStore,
+    identity_store: &dyn IdentityKeyStore,
+    pre_key_store: &dyn PreKeyStore,
+    signed_pre_key_store: &dyn SignedPreKeyStore,
+    kyber_pre_key_store: &dyn KyberPreKeyStore,
+    policy: &SessionPolicy,
+    csprng: &mut R,
+) -> Result<(Vec<u8>, PendingStoreChanges)> {
+    match ciphertext {
+        CiphertextMessage::SignalMessage(m) => {
+            decrypt_signal_deferred(
+                m,
+                remote_address,
+                local_address,
+                session_store,
+                identity_store,
+                policy,
                 csprng,
             )
             .await
         }
         CiphertextMessage::PreKeySignalMessage(m) => {
-            message_decrypt_prekey(
+            decrypt_prekey_deferred(
                 m,
                 remote_address,
                 local_address,
@@ -188,12 +297,13 @@ pub async fn message_decrypt<R: Rng + CryptoRng>(
                 pre_key_store,
                 signed_pre_key_store,
                 kyber_pre_key_store,
+                policy,
                 csprng,
             )
             .await
         }
         _ => Err(SignalProtocolError::InvalidArgument(format!(
-            "message_decrypt cannot be used to decrypt {:?} messages",
+            "message_decrypt_deferred cannot be used to decrypt {:?} messages",
             ciphertext.message_type()
         ))),
     }
@@ -215,13 +325,52 @@ pub async fn message_decrypt_prekey<R: Rng + CryptoRng>(
     kyber_pre_key_store: &mut dyn KyberPreKeyStore,
     csprng: &mut R,
 ) -> Result<Vec<u8>> {
+    let (ptext, changes) = decrypt_prekey_deferred(
+        ciphertext,
+        remote_address,
+        local_address,
+        session_store,
+        identity_store,
+        pre_key_store,
+        signed_pre_key_store,
+        kyber_pre_key_store,
+        &SessionPolicy::default(),
+        csprng,
+    )
+    .await?;
+
+    changes
+        .apply(
+            session_store,
+            identity_store,
+            pre_key_store,
+            kyber_pre_key_store,
+        )
+        .await?;
+
+    Ok(ptext)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn decrypt_prekey_deferred<R: Rng + CryptoRng>(
+    ciphertext: &PreKeySignalMessage,
+    remote_address: &ProtocolAddress,
+    local_address: &ProtocolAddress,
+    session_store: &dyn SessionStore,
+    identity_store: &dyn IdentityKeyStore,
+    pre_key_store: &dyn PreKeyStore,
+    signed_pre_key_store: &dyn SignedPreKeyStore,
+    kyber_pre_key_store: &dyn KyberPreKeyStore,
+    policy: &SessionPolicy,
+    csprng: &mut R,
+) -> Result<(Vec<u8>, PendingStoreChanges)> {
     let mut session_record = session_store
         .load_session(remote_address)
         .await?
         .unwrap_or_else(SessionRecord::new_fresh);
 
     // Make sure we log the session state if we fail to process the pre-key.
-    let process_prekey_result = session::process_prekey(
+    let process_prekey_result = session::process_prekey_with_policy(
         ciphertext,
         remote_address,
         local_address,
@@ -230,6 +379,7 @@ pub async fn message_decrypt_prekey<R: Rng + CryptoRng>(
         pre_key_store,
         signed_pre_key_store,
         kyber_pre_key_store,
+        policy,
     )
     .await;
 
@@ -257,37 +407,27 @@ pub async fn message_decrypt_prekey<R: Rng + CryptoRng>(
         local_address,
         ciphertext.message(),
         CiphertextMessageType::PreKey,
+        policy,
         csprng,
     )?;
 
-    identity_store
-        .save_identity(
-            identity_to_save.remote_address,
-            identity_to_save.their_identity_key,
-        )
-        .await?;
-
-    if let Some(pre_key_used) = pre_key_used {
-        if let Some(kyber_pre_key_id) = pre_key_used.kyber_pre_key_id {
-            kyber_pre_key_store
-                .mark_kyber_pre_key_used(
-                    kyber_pre_key_id,
-                    pre_key_used.signed_ec_pre_key_id,
-                    ciphertext.base_key(),
-                )
-                .await?;
-        }
-
-        if let Some(pre_key_id) = pre_key_used.one_time_ec_pre_key_id {
-            pre_key_store.remove_pre_key(pre_key_id).await?;
-        }
-    }
-
-    session_store
-        .store_session(remote_address, &session_record)
-        .await?;
+    let session::IdentityToSave {
+        remote_address: _,
+        their_identity_key,
+    } = identity_to_save;
+
+    let changes = PendingStoreChanges {
+        remote_address: remote_address.clone(),
+        session_record,
+        identity_to_save: *their_identity_key,
+        kyber_pre_key_used: pre_key_used.as_ref().and_then(|used| {
+            used.kyber_pre_key_id
+                .map(|id| (id, used.signed_ec_pre_key_id, *ciphertext.base_key()))
+        }),
+        one_time_pre_key_used: pre_key_used.and_then(|used| used.one_time_ec_pre_key_id),
+    };
 
-    Ok(ptext)
+    Ok((ptext, changes))
 }
 
 /// Decrypt a [`SignalMessage`] from `remote_address`.
@@ -302,6 +442,33 @@ pub async fn message_decrypt_signal<R: Rng + CryptoRng>(
     identity_store: &mut dyn IdentityKeyStore,
     csprng: &mut R,
 ) -> Result<Vec<u8>> {
+    let (ptext, changes) = decrypt_signal_deferred(
+        ciphertext,
+        remote_address,
+        local_address,
+        session_store,
+        identity_store,
+        &SessionPolicy::default(),
+        csprng,
+    )
+    .await?;
+
+    changes
+        .apply_without_pre_keys(session_store, identity_store)
+        .await?;
+
+    Ok(ptext)
+}
+
+async fn decrypt_signal_deferred<R: Rng + CryptoRng>(
+    ciphertext: &SignalMessage,
+    remote_address: &ProtocolAddress,
+    local_address: &ProtocolAddress,
+    session_store: &dyn SessionStore,
+    identity_store: &dyn IdentityKeyStore,
+    policy: &SessionPolicy,
+    csprng: &mut R,
+) -> Result<(Vec<u8>, PendingStoreChanges)> {
     let mut session_record = session_store
         .load_session(remote_address)
         .await?
@@ -318,6 +485,7 @@ pub async fn message_decrypt_signal<R: Rng + CryptoRng>(
         local_address,
         ciphertext,
         CiphertextMessageType::Whisper,
+        policy,
         csprng,
     )?;
 
@@ -343,15 +511,110 @@ pub async fn message_decrypt_signal<R: Rng + CryptoRng>(
         ));
     }
 
-    identity_store
-        .save_identity(remote_address, &their_identity_key)
-        .await?;
+    let changes = PendingStoreChanges {
+        remote_address: remote_address.clone(),
+        session_record,
+        identity_to_save: their_identity_key,
+        kyber_pre_key_used: None,
+        one_time_pre_key_used: None,
+    };
 
-    session_store
-        .store_session(remote_address, &session_record)
-        .await?;
+    Ok((ptext, changes))
+}
 
-    Ok(ptext)
+// ── Deferred store changes ───────────────────────────────────────────────────
+
+/// Every store mutation required to complete a successful decryption.
+///
+/// Produced by [`message_decrypt_deferred`]. Nothing has been written to any store until
+/// [`apply`](Self::apply) is called; if the changes are dropped instead, the message is treated
+/// as never having been received.
+#[must_use]
+pub struct PendingStoreChanges {
+    remote_address: ProtocolAddress,
+    session_record: SessionRecord,
+    identity_to_save: IdentityKey,
+    kyber_pre_key_used: Option<(KyberPreKeyId, SignedPreKeyId, PublicKey)>,
+    one_time_pre_key_used: Option<PreKeyId>,
+}
+
+impl PendingStoreChanges {
+    /// The address whose session (and identity) will be updated.
+    pub fn remote_address(&self) -> &ProtocolAddress {
+        &self.remote_address
+    }
+
+    /// The session record to store for [`remote_address`](Self::remote_address).
+    pub fn session_record(&self) -> &SessionRecord {
+        &self.session_record
+    }
+
+    /// The identity key to save for [`remote_address`](Self::remote_address).
+    pub fn identity_to_save(&self) -> &IdentityKey {
+        &self.identity_to_save
+    }
+
+    /// The Kyber pre-key to mark as used, along with the signed pre-key and base key it was used
+    /// with.
+    pub fn kyber_pre_key_used(&self) -> Option<(KyberPreKeyId, SignedPreKeyId, &PublicKey)> {
+        self.kyber_pre_key_used
+            .as_ref()
+            .map(|(kyber_id, signed_id, base_key)| (*kyber_id, *signed_id, base_key))
+    }
+
+    /// The one-time pre-key to remove, if one was used.
+    pub fn one_time_pre_key_used(&self) -> Option<PreKeyId> {
+        self.one_time_pre_key_used
+    }
+
+    /// Write all changes to the given stores.
+    ///
+    /// The Kyber pre-key is marked used first, since that is where a replayed message is
+    /// rejected; that way a replay does not leave any partial changes behind even if the stores
+    /// are not transactional.
+    pub async fn apply(
+        self,
+        session_store: &mut dyn SessionStore,
+        identity_store: &mut dyn IdentityKeyStore,
+        pre_key_store: &mut dyn PreKeyStore,
+        kyber_pre_key_store: &mut dyn KyberPreKeyStore,
+    ) -> Result<()> {
+        if let Some((kyber_pre_key_id, signed_pre_key_id, base_key)) = &self.kyber_pre_key_used {
+            kyber_pre_key_store
+                .mark_kyber_pre_key_used(*kyber_pre_key_id, *signed_pre_key_id, base_key)
+                .await?;
+        }
+
+        identity_store
+            .save_identity(&self.remote_address, &self.identity_to_save)
+            .await?;
+
+        if let Some(pre_key_id) = self.one_time_pre_key_used {
+            pre_key_store.remove_pre_key(pre_key_id).await?;
+        }
+
+        session_store
+            .store_session(&self.remote_address, &self.session_record)
+            .await
+    }
+
+    /// Like [`apply`](Self::apply), for changes that are known not to involve pre-keys.
+    async fn apply_without_pre_keys(
+        self,
+        session_store: &mut dyn SessionStore,
+        identity_store: &mut dyn IdentityKeyStore,
+    ) -> Result<()> {
+        debug_assert!(self.kyber_pre_key_used.is_none());
+        debug_assert!(self.one_time_pre_key_used.is_none());
+
+        identity_store
+            .save_identity(&self.remote_address, &self.identity_to_save)
+            .await?;
+
+        session_store
+            .store_session(&self.remote_address, &self.session_record)
+            .await
+    }
 }
 
 // ── Session management (Sesame) ──────────────────────────────────────────────
@@ -371,6 +634,7 @@ pub(crate) fn try_decrypt_from_record<R: Rng + CryptoRng>(
     local_address: &ProtocolAddress,
     ciphertext: &SignalMessage,
     original_message_type: CiphertextMessageType,
+    policy: &SessionPolicy,
     csprng: &mut R,
 ) -> Result<Vec<u8>> {
     debug_assert!(matches!(
@@ -415,6 +679,7 @@ pub(crate) fn try_decrypt_from_record<R: Rng + CryptoRng>(
                 ciphertext,
                 original_message_type,
                 CurrentOrPrevious::Current,
+                policy,
                 csprng,
             ) {
                 Ok(ptext) => {
@@ -497,6 +762,7 @@ pub(crate) fn try_decrypt_from_record<R: Rng + CryptoRng>(
             ciphertext,
             original_message_type,
             CurrentOrPrevious::Previous,
+            policy,
             csprng,
         ) {
             Ok(ptext) => {
@@ -522,7 +788,7 @@ pub(crate) fn try_decrypt_from_record<R: Rng + CryptoRng>(
     if let Some((ptext, idx, updated)) = promoted {
         // Sesame: promote the successful previous session to current.
         // The upcoming session management update will remove this promotion.
-        record.promote_old_session(idx, updated);
+        record.promote_old_session(idx, updated, policy.max_archived_states);
         Ok(ptext)
     } else {
         let previous_state_count = || record.previous_session_states().len();
@@ -563,6 +829,7 @@ pub(crate) fn try_decrypt_from_record<R: Rng + CryptoRng>(
 ///
 /// Constructs a [`TripleRatchet`], delegates the actual decryption, and writes
 /// updated state back on success. On failure, `state` is unchanged.
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn try_decrypt_with_state<R: Rng + CryptoRng>(
     state: &mut SessionState,
     remote_address: &ProtocolAddress,
@@ -570,6 +837,7 @@ pub(crate) fn try_decrypt_with_state<R: Rng + CryptoRng>(
     ciphertext: &SignalMessage,
     original_message_type: CiphertextMessageType,
     curr_or_prev_for_logging: CurrentOrPrevious,
+    policy: &SessionPolicy,
     csprng: &mut R,
 ) -> Result<Vec<u8>> {
     debug_assert_eq!(
@@ -588,7 +856,7 @@ pub(crate) fn try_decrypt_with_state<R: Rng + CryptoRng>(
     })
     .inspect_err(|e| log::warn!("Failed to determine self_session: {}", e))
     .unwrap_or_default();
-    let mut session = TripleRatchet::from_session_state(state, self_session)?;
+    let mut session = TripleRatchet::from_session_state(state, self_session, policy)?;
 
     let ptext = session.decrypt(
         remote_address,
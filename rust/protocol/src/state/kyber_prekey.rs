@@ -71,4 +71,13 @@ impl KyberPreKeyRecord {
             &signature,
         ))
     }
+
+    /// Generates a pre-key using the KEM preferred by `policy`.
+    pub fn generate_with_policy(
+        policy: &kem::KemPolicy,
+        id: KyberPreKeyId,
+        signing_key: &PrivateKey,
+    ) -> Result<KyberPreKeyRecord> {
+        Self::generate(policy.preferred(), id, signing_key)
+    }
 }
@@ -133,6 +133,80 @@ bitflags! {
     }
 }
 
+/// A skipped message key held by a session for an out-of-order message, described without its key
+/// material.
+#[derive(Clone, Debug)]
+pub struct CachedMessageKey {
+    sender_ratchet_key: PublicKey,
+    counter: u32,
+    chain_index: u32,
+}
+
+impl CachedMessageKey {
+    /// The peer's ratchet key for the receiving chain this key belongs to.
+    pub fn sender_ratchet_key(&self) -> &PublicKey {
+        &self.sender_ratchet_key
+    }
+
+    /// The counter of the message this key will decrypt.
+    pub fn counter(&self) -> u32 {
+        self.counter
+    }
+
+    /// The counter of the next message expected on the same receiving chain.
+    pub fn chain_index(&self) -> u32 {
+        self.chain_index
+    }
+
+    /// How far the receiving chain has advanced past this key.
+    ///
+    /// Message keys don't record wall-clock time, so this is the closest thing a session has to
+    /// the age of a cached key.
+    pub fn messages_behind(&self) -> u32 {
+        self.chain_index.saturating_sub(self.counter)
+    }
+}
+
+/// Limits on how much out-of-order state a session keeps while decrypting.
+///
+/// [`Default`] gives the limits libsignal has always used, which suit a typical phone. Bots and
+/// relays on lossy transports may want a wider window; memory-constrained clients a narrower one.
+///
+/// Sessions using SPQR also enforce the forward-jump and message-key limits that were in effect
+/// when the session was created, so raising them above the defaults only affects the classical
+/// Double Ratchet layer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SessionPolicy {
+    /// The furthest a message counter may be ahead of its receiving chain.
+    ///
+    /// Ignored for sessions with oneself, which never limit forward jumps.
+    pub max_forward_jumps: usize,
+    /// The number of skipped message keys kept per receiving chain.
+    pub max_message_keys: usize,
+    /// The number of archived session states kept alongside the current one.
+    pub max_archived_states: usize,
+}
+
+impl Default for SessionPolicy {
+    fn default() -> Self {
+        Self {
+            max_forward_jumps: consts::MAX_FORWARD_JUMPS,
+            max_message_keys: consts::MAX_MESSAGE_KEYS,
+            max_archived_states: consts::ARCHIVED_STATES_MAX_LENGTH,
+        }
+    }
+}
+
+/// Selects which cached message keys [`SessionRecord::prune_message_keys`] discards.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageKeyPruning {
+    /// Keep at most this many keys on each receiving chain, discarding the oldest first.
+    MaxPerChain(usize),
+    /// Discard keys that are more than this many messages behind their receiving chain (see
+    /// [`CachedMessageKey::messages_behind`]).
+    MaxMessagesBehind(u32),
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct SessionState {
     session: SessionStructure,
@@ -166,6 +240,7 @@ impl SessionState {
                 local_registration_id: 0,
                 alice_base_key: alice_base_key.serialize().into_vec(),
                 pq_ratchet_state,
+                kem_key_type: 0,
             },
         }
     }
@@ -303,6 +378,81 @@ impl SessionState {
         results
     }
 
+    pub(crate) fn sender_chain_index(&self) -> Option<u32> {
+        self.session
+            .sender_chain
+            .as_ref()
+            .and_then(|chain| chain.chain_key.as_ref())
+            .map(|chain_key| chain_key.index)
+    }
+
+    /// Returns each receiver chain's ratchet key, chain index, and number of cached message keys.
+    pub(crate) fn receiver_chain_counters(
+        &self,
+    ) -> Result<Vec<(PublicKey, u32, usize)>, InvalidSessionError> {
+        self.session
+            .receiver_chains
+            .iter()
+            .map(|chain| {
+                let sender_ratchet_key = PublicKey::deserialize(&chain.sender_ratchet_key)
+                    .map_err(|_| InvalidSessionError("invalid receiver chain ratchet key"))?;
+                let chain_index = chain
+                    .chain_key
+                    .as_ref()
+                    .ok_or(InvalidSessionError("missing receiver chain key"))?
+                    .index;
+                Ok((sender_ratchet_key, chain_index, chain.message_keys.len()))
+            })
+            .collect()
+    }
+
+    pub(crate) fn cached_message_keys(&self) -> Result<Vec<CachedMessageKey>, InvalidSessionError> {
+        let mut results = vec![];
+        for chain in self.session.receiver_chains.iter() {
+            let sender_ratchet_key = PublicKey::deserialize(&chain.sender_ratchet_key)
+                .map_err(|_| InvalidSessionError("invalid receiver chain ratchet key"))?;
+            let chain_index = chain
+                .chain_key
+                .as_ref()
+                .ok_or(InvalidSessionError("missing receiver chain key"))?
+                .index;
+
+            results.extend(chain.message_keys.iter().map(|key| CachedMessageKey {
+                sender_ratchet_key,
+                counter: key.index,
+                chain_index,
+            }));
+        }
+        Ok(results)
+    }
+
+    /// Returns the number of message keys removed.
+    pub(crate) fn prune_message_keys(
+        &mut self,
+        pruning: MessageKeyPruning,
+    ) -> Result<usize, InvalidSessionError> {
+        let mut removed = 0;
+        for chain in self.session.receiver_chains.iter_mut() {
+            let before = chain.message_keys.len();
+            match pruning {
+                // Keys are stored newest-first, so the oldest are at the end.
+                MessageKeyPruning::MaxPerChain(max) => chain.message_keys.truncate(max),
+                MessageKeyPruning::MaxMessagesBehind(max) => {
+                    let chain_index = chain
+                        .chain_key
+                        .as_ref()
+                        .ok_or(InvalidSessionError("missing receiver chain key"))?
+                        .index;
+                    chain
+                        .message_keys
+                        .retain(|key| chain_index.saturating_sub(key.index) <= max);
+                }
+            }
+            removed += before - chain.message_keys.len();
+        }
+        Ok(removed)
+    }
+
     pub(crate) fn get_receiver_chain(
         &self,
         sender: &PublicKey,
@@ -580,6 +730,7 @@ impl SessionState {
             local_registration_id: _local_registration_id,
             alice_base_key: _alice_base_key,
             pq_ratchet_state: _pq_ratchet_state,
+            kem_key_type: _kem_key_type,
         } = &self.session;
         // ####### IMPORTANT #######
         // Don't forget to clean up new pending fields.
@@ -588,6 +739,26 @@ impl SessionState {
         self.session.pending_kyber_pre_key = None;
     }
 
+    pub(crate) fn set_kem_key_type(&mut self, key_type: kem::KeyType) {
+        self.session.kem_key_type = key_type.value().into();
+    }
+
+    pub(crate) fn kem_key_type(&self) -> Result<Option<kem::KeyType>, InvalidSessionError> {
+        match self.session.kem_key_type {
+            0 => Ok(None),
+            value => u8::try_from(value)
+                .ok()
+                .and_then(|value| kem::KeyType::try_from(value).ok())
+                .map(Some)
+                .ok_or(InvalidSessionError("unknown KEM key type")),
+        }
+    }
+
+    /// The stored KEM key type, even if it isn't one this version recognizes.
+    pub(crate) fn raw_kem_key_type(&self) -> u32 {
+        self.session.kem_key_type
+    }
+
     pub(crate) fn set_remote_registration_id(&mut self, registration_id: u32) {
         self.session.remote_registration_id = registration_id;
     }
@@ -625,12 +796,14 @@ impl SessionState {
     pub(crate) fn take_ratchet_state(
         &mut self,
         self_session: bool,
+        policy: &SessionPolicy,
     ) -> crate::error::Result<crate::double_ratchet::RatchetState> {
         let receiver_chains = std::mem::take(&mut self.session.receiver_chains);
         Ok(crate::double_ratchet::RatchetState::from_pb(
             &self.session,
             self_session,
             receiver_chains,
+            policy,
         )?)
     }
 
@@ -742,6 +915,7 @@ impl SessionRecord {
         &mut self,
         version: u32,
         alice_base_key: &[u8],
+        max_archived_states: usize,
     ) -> Result<bool, InvalidSessionError> {
         if let Some(current_session) = &self.current_session {
             if current_session.session_version()? == version
@@ -765,7 +939,7 @@ impl SessionRecord {
         }
 
         if let Some((i, state)) = session_to_promote {
-            self.promote_old_session(i, state);
+            self.promote_old_session(i, state, max_archived_states);
             return Ok(true);
         }
 
@@ -798,27 +972,26 @@ impl SessionRecord {
         &mut self,
         old_session: usize,
         updated_session: SessionState,
+        max_archived_states: usize,
     ) {
         self.previous_sessions.remove(old_session);
-        self.promote_state(updated_session)
+        self.promote_state(updated_session, max_archived_states)
     }
 
-    pub(crate) fn promote_state(&mut self, new_state: SessionState) {
-        self.archive_current_state_inner();
+    pub(crate) fn promote_state(&mut self, new_state: SessionState, max_archived_states: usize) {
+        self.archive_current_state_inner(max_archived_states);
         self.current_session = Some(new_state);
     }
 
     // A non-fallible version of archive_current_state.
     //
     // Returns `true` if there was a session to archive, `false` if not.
-    fn archive_current_state_inner(&mut self) -> bool {
+    fn archive_current_state_inner(&mut self, max_archived_states: usize) -> bool {
         if let Some(mut current_session) = self.current_session.take() {
-            if self.previous_sessions.len() >= consts::ARCHIVED_STATES_MAX_LENGTH {
-                self.previous_sessions.pop();
-            }
             current_session.clear_unacknowledged_pre_key_message();
             self.previous_sessions
                 .insert(0, current_session.session.encode_to_vec());
+            self.previous_sessions.truncate(max_archived_states);
             true
         } else {
             false
@@ -826,12 +999,52 @@ impl SessionRecord {
     }
 
     pub fn archive_current_state(&mut self) -> Result<(), SignalProtocolError> {
-        if !self.archive_current_state_inner() {
+        if !self.archive_current_state_inner(consts::ARCHIVED_STATES_MAX_LENGTH) {
             log::info!("Skipping archive, current session state is fresh");
         }
         Ok(())
     }
 
+    /// Returns the number of archived (previous) session states kept in this record.
+    pub fn archived_state_count(&self) -> usize {
+        self.previous_sessions.len()
+    }
+
+    /// Discards all but the `max_count` most recently archived session states.
+    ///
+    /// Returns the number of states removed. Messages sent on a removed state can no longer be
+    /// decrypted.
+    pub fn prune_archived_states(&mut self, max_count: usize) -> usize {
+        let before = self.previous_sessions.len();
+        self.previous_sessions.truncate(max_count);
+        before - self.previous_sessions.len()
+    }
+
+    /// Lists the message keys the current session has cached for messages that have not arrived
+    /// yet.
+    ///
+    /// Returns an empty list if there is no current session. Archived states are not included.
+    pub fn cached_message_keys(&self) -> Result<Vec<CachedMessageKey>, SignalProtocolError> {
+        match &self.current_session {
+            Some(session) => Ok(session.cached_message_keys()?),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Discards cached message keys from the current session according to `pruning`.
+    ///
+    /// Returns the number of keys removed. Messages whose keys are removed will be rejected as
+    /// duplicates if they arrive later.
+    pub fn prune_message_keys(
+        &mut self,
+        pruning: MessageKeyPruning,
+    ) -> Result<usize, SignalProtocolError> {
+        match &mut self.current_session {
+            Some(session) => Ok(session.prune_message_keys(pruning)?),
+            None => Ok(0),
+        }
+    }
+
     pub fn serialize(&self) -> Result<Vec<u8>, SignalProtocolError> {
         let record = RecordStructure {
             current_session: self.current_session.as_ref().map(|s| s.into()),
@@ -877,6 +1090,20 @@ impl SessionRecord {
             .session_version()?)
     }
 
+    /// The KEM used to establish the current session.
+    ///
+    /// Returns `None` for sessions established before the KEM was recorded.
+    pub fn kem_key_type(&self) -> Result<Option<kem::KeyType>, SignalProtocolError> {
+        Ok(self
+            .session_state()
+            .ok_or_else(|| {
+                SignalProtocolError::SessionNotFound(SessionNotFound::without_address(
+                    "kem_key_type",
+                ))
+            })?
+            .kem_key_type()?)
+    }
+
     pub fn local_identity_key_bytes(&self) -> Result<Vec<u8>, SignalProtocolError> {
         Ok(self
             .session_state()
@@ -4,16 +4,25 @@
 //
 
 //! Interfaces in [traits] and reference implementations in [inmem] for various mutable stores.
+//!
+//! With the `sqlite` feature enabled, the `sqlite` module provides durable implementations as well.
 
 #![warn(missing_docs)]
 
 mod inmem;
+#[cfg(feature = "sqlite")]
+mod sqlite;
 mod traits;
 
 pub use inmem::{
     InMemIdentityKeyStore, InMemKyberPreKeyStore, InMemPreKeyStore, InMemSenderKeyStore,
     InMemSessionStore, InMemSignalProtocolStore, InMemSignedPreKeyStore,
 };
+#[cfg(feature = "sqlite")]
+pub use sqlite::{
+    SqliteIdentityKeyStore, SqliteKyberPreKeyStore, SqlitePreKeyStore, SqliteSenderKeyStore,
+    SqliteSessionStore, SqliteSignalProtocolStore, SqliteSignedPreKeyStore, SqliteStoreError,
+};
 pub use traits::{
     Direction, IdentityChange, IdentityKeyStore, KyberPreKeyStore, PreKeyStore, ProtocolStore,
     SenderKeyStore, SessionStore, SignedPreKeyStore,
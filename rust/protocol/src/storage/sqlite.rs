@@ -0,0 +1,978 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Durable implementations for stores defined in [super::traits], backed by SQLite.
+//!
+//! All of the stores created by a [SqliteSignalProtocolStore] share a single database connection,
+//! so a transaction started with [SqliteSignalProtocolStore::transaction] covers writes made
+//! through any of them. This makes it possible to commit the session update and pre-key removal
+//! performed by a single [`message_decrypt`](crate::message_decrypt) call atomically.
+//!
+//! The schema is versioned using SQLite's `user_version` pragma and is migrated forward
+//! automatically whenever a store is opened.
+
+use std::rc::Rc;
+
+use async_trait::async_trait;
+use rusqlite::{Connection, OptionalExtension as _, params};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::storage::traits::{self, IdentityChange};
+use crate::{
+    CiphertextMessageType, GenericSignedPreKey as _, IdentityKey, IdentityKeyPair, KyberPreKeyId,
+    KyberPreKeyRecord, PreKeyId, PreKeyRecord, ProtocolAddress, PublicKey, Result, SenderKeyRecord,
+    SessionRecord, SignalProtocolError, SignedPreKeyId, SignedPreKeyRecord,
+};
+
+/// Schema migrations, applied in order.
+///
+/// The `user_version` pragma records how many of these have been applied. Never change an entry
+/// once it has been released; add a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // Version 1: initial schema.
+    "CREATE TABLE local_identity (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        key_pair BLOB NOT NULL,
+        registration_id INTEGER NOT NULL
+    );
+    CREATE TABLE identities (
+        name TEXT NOT NULL,
+        device_id INTEGER NOT NULL,
+        identity_key BLOB NOT NULL,
+        PRIMARY KEY (name, device_id)
+    );
+    CREATE TABLE pre_keys (
+        id INTEGER PRIMARY KEY,
+        record BLOB NOT NULL
+    );
+    CREATE TABLE signed_pre_keys (
+        id INTEGER PRIMARY KEY,
+        record BLOB NOT NULL
+    );
+    CREATE TABLE kyber_pre_keys (
+        id INTEGER PRIMARY KEY,
+        record BLOB NOT NULL,
+        is_last_resort INTEGER NOT NULL
+    );
+    CREATE TABLE kyber_base_keys_seen (
+        kyber_pre_key_id INTEGER NOT NULL,
+        signed_pre_key_id INTEGER NOT NULL,
+        base_key BLOB NOT NULL,
+        PRIMARY KEY (kyber_pre_key_id, signed_pre_key_id, base_key)
+    );
+    CREATE TABLE sessions (
+        name TEXT NOT NULL,
+        device_id INTEGER NOT NULL,
+        record BLOB NOT NULL,
+        PRIMARY KEY (name, device_id)
+    );
+    CREATE TABLE sender_keys (
+        name TEXT NOT NULL,
+        device_id INTEGER NOT NULL,
+        distribution_id BLOB NOT NULL,
+        record BLOB NOT NULL,
+        PRIMARY KEY (name, device_id, distribution_id)
+    );",
+];
+
+/// The savepoint used by [SqliteSignalProtocolStore::transaction].
+///
+/// Savepoints (unlike `BEGIN`) can be nested, so transactions can be as well.
+const SAVEPOINT_NAME: &str = "libsignal_store";
+
+/// An error reported by the underlying SQLite database.
+///
+/// Store methods wrap this in [`SignalProtocolError::ApplicationCallbackError`].
+#[derive(Debug, Error)]
+#[error("database error: {0}")]
+pub struct SqliteStoreError(String);
+
+fn db_error(method: &'static str) -> impl FnOnce(rusqlite::Error) -> SignalProtocolError {
+    move |e| {
+        SignalProtocolError::ApplicationCallbackError(
+            method,
+            Box::new(SqliteStoreError(e.to_string())),
+        )
+    }
+}
+
+/// Brings the schema in `conn` up to date, returning the resulting schema version.
+fn migrate(conn: &Connection) -> Result<u32> {
+    let current: u32 = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(db_error("migrate"))?;
+
+    let mut version = 0;
+    for (target, migration) in (1u32..).zip(MIGRATIONS) {
+        version = target;
+        if target <= current {
+            continue;
+        }
+        log::info!("migrating protocol store schema to version {target}");
+        let tx = conn.unchecked_transaction().map_err(db_error("migrate"))?;
+        tx.execute_batch(migration).map_err(db_error("migrate"))?;
+        tx.pragma_update(None, "user_version", target)
+            .map_err(db_error("migrate"))?;
+        tx.commit().map_err(db_error("migrate"))?;
+    }
+
+    if current > version {
+        return Err(SignalProtocolError::InvalidState(
+            "migrate",
+            format!("database schema version {current} is newer than supported version {version}"),
+        ));
+    }
+    Ok(version)
+}
+
+/// SQLite implementation of [traits::IdentityKeyStore].
+pub struct SqliteIdentityKeyStore {
+    conn: Rc<Connection>,
+    key_pair: IdentityKeyPair,
+    registration_id: u32,
+}
+
+#[async_trait(?Send)]
+impl traits::IdentityKeyStore for SqliteIdentityKeyStore {
+    async fn get_identity_key_pair(&self) -> Result<IdentityKeyPair> {
+        Ok(self.key_pair)
+    }
+
+    async fn get_local_registration_id(&self) -> Result<u32> {
+        Ok(self.registration_id)
+    }
+
+    async fn save_identity(
+        &mut self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+    ) -> Result<IdentityChange> {
+        let existing = self.get_identity(address).await?;
+        if existing.as_ref() == Some(identity) {
+            return Ok(IdentityChange::NewOrUnchanged);
+        }
+        self.conn
+            .execute(
+                "INSERT INTO identities (name, device_id, identity_key) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (name, device_id) DO UPDATE SET identity_key = excluded.identity_key",
+                params![
+                    address.name(),
+                    u32::from(address.device_id()),
+                    &identity.serialize()[..]
+                ],
+            )
+            .map_err(db_error("save_identity"))?;
+        Ok(IdentityChange::from_changed(existing.is_some()))
+    }
+
+    async fn is_trusted_identity(
+        &self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+        _direction: traits::Direction,
+    ) -> Result<bool> {
+        match self.get_identity(address).await? {
+            None => Ok(true), // first use
+            Some(k) => Ok(&k == identity),
+        }
+    }
+
+    async fn get_identity(&self, address: &ProtocolAddress) -> Result<Option<IdentityKey>> {
+        self.conn
+            .query_row(
+                "SELECT identity_key FROM identities WHERE name = ?1 AND device_id = ?2",
+                params![address.name(), u32::from(address.device_id())],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(db_error("get_identity"))?
+            .map(|bytes| IdentityKey::decode(&bytes))
+            .transpose()
+    }
+}
+
+/// SQLite implementation of [traits::PreKeyStore].
+pub struct SqlitePreKeyStore {
+    conn: Rc<Connection>,
+}
+
+impl SqlitePreKeyStore {
+    /// Returns all registered pre-key ids.
+    pub fn all_pre_key_ids(&self) -> Result<Vec<PreKeyId>> {
+        all_ids(
+            &self.conn,
+            "SELECT id FROM pre_keys ORDER BY id",
+            "all_pre_key_ids",
+        )
+    }
+}
+
+#[async_trait(?Send)]
+impl traits::PreKeyStore for SqlitePreKeyStore {
+    async fn get_pre_key(&self, id: PreKeyId) -> Result<PreKeyRecord> {
+        let record = get_record(
+            &self.conn,
+            "SELECT record FROM pre_keys WHERE id = ?1",
+            id.into(),
+            "get_pre_key",
+        )?
+        .ok_or(SignalProtocolError::InvalidPreKeyId)?;
+        PreKeyRecord::deserialize(&record)
+    }
+
+    async fn save_pre_key(&mut self, id: PreKeyId, record: &PreKeyRecord) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO pre_keys (id, record) VALUES (?1, ?2)",
+                params![u32::from(id), record.serialize()?],
+            )
+            .map_err(db_error("save_pre_key"))?;
+        Ok(())
+    }
+
+    async fn remove_pre_key(&mut self, id: PreKeyId) -> Result<()> {
+        // If id does not exist this silently does nothing, like the in-memory store.
+        self.conn
+            .execute("DELETE FROM pre_keys WHERE id = ?1", params![u32::from(id)])
+            .map_err(db_error("remove_pre_key"))?;
+        Ok(())
+    }
+}
+
+/// SQLite implementation of [traits::SignedPreKeyStore].
+pub struct SqliteSignedPreKeyStore {
+    conn: Rc<Connection>,
+}
+
+impl SqliteSignedPreKeyStore {
+    /// Returns all registered signed pre-key ids.
+    pub fn all_signed_pre_key_ids(&self) -> Result<Vec<SignedPreKeyId>> {
+        all_ids(
+            &self.conn,
+            "SELECT id FROM signed_pre_keys ORDER BY id",
+            "all_signed_pre_key_ids",
+        )
+    }
+
+    /// Remove the entry for `id`, typically once a newer signed pre-key has been uploaded.
+    pub fn remove_signed_pre_key(&mut self, id: SignedPreKeyId) -> Result<()> {
+        self.conn
+            .execute(
+                "DELETE FROM signed_pre_keys WHERE id = ?1",
+                params![u32::from(id)],
+            )
+            .map_err(db_error("remove_signed_pre_key"))?;
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl traits::SignedPreKeyStore for SqliteSignedPreKeyStore {
+    async fn get_signed_pre_key(&self, id: SignedPreKeyId) -> Result<SignedPreKeyRecord> {
+        let record = get_record(
+            &self.conn,
+            "SELECT record FROM signed_pre_keys WHERE id = ?1",
+            id.into(),
+            "get_signed_pre_key",
+        )?
+        .ok_or(SignalProtocolError::InvalidSignedPreKeyId)?;
+        SignedPreKeyRecord::deserialize(&record)
+    }
+
+    async fn save_signed_pre_key(
+        &mut self,
+        id: SignedPreKeyId,
+        record: &SignedPreKeyRecord,
+    ) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO signed_pre_keys (id, record) VALUES (?1, ?2)",
+                params![u32::from(id), record.serialize()?],
+            )
+            .map_err(db_error("save_signed_pre_key"))?;
+        Ok(())
+    }
+}
+
+/// SQLite implementation of [traits::KyberPreKeyStore].
+///
+/// Unlike [InMemKyberPreKeyStore](crate::InMemKyberPreKeyStore), this store distinguishes between
+/// one-time and last-resort keys. Keys saved through the [traits::KyberPreKeyStore] interface are
+/// treated as last-resort keys, since that is the only safe assumption; use
+/// [save_one_time_kyber_pre_key](Self::save_one_time_kyber_pre_key) for keys that should be
+/// deleted once used.
+pub struct SqliteKyberPreKeyStore {
+    conn: Rc<Connection>,
+}
+
+impl SqliteKyberPreKeyStore {
+    /// Set the entry for `kyber_prekey_id` to a one-time key, which will be removed by
+    /// [traits::KyberPreKeyStore::mark_kyber_pre_key_used].
+    pub fn save_one_time_kyber_pre_key(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        record: &KyberPreKeyRecord,
+    ) -> Result<()> {
+        self.save(kyber_prekey_id, record, false)
+    }
+
+    /// Returns all registered Kyber pre-key ids.
+    pub fn all_kyber_pre_key_ids(&self) -> Result<Vec<KyberPreKeyId>> {
+        all_ids(
+            &self.conn,
+            "SELECT id FROM kyber_pre_keys ORDER BY id",
+            "all_kyber_pre_key_ids",
+        )
+    }
+
+    /// Remove the entry for `kyber_prekey_id`, along with any base keys recorded for it.
+    pub fn remove_kyber_pre_key(&mut self, kyber_prekey_id: KyberPreKeyId) -> Result<()> {
+        let id = u32::from(kyber_prekey_id);
+        self.conn
+            .execute("DELETE FROM kyber_pre_keys WHERE id = ?1", params![id])
+            .map_err(db_error("remove_kyber_pre_key"))?;
+        self.conn
+            .execute(
+                "DELETE FROM kyber_base_keys_seen WHERE kyber_pre_key_id = ?1",
+                params![id],
+            )
+            .map_err(db_error("remove_kyber_pre_key"))?;
+        Ok(())
+    }
+
+    fn save(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        record: &KyberPreKeyRecord,
+        is_last_resort: bool,
+    ) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO kyber_pre_keys (id, record, is_last_resort)
+                 VALUES (?1, ?2, ?3)",
+                params![
+                    u32::from(kyber_prekey_id),
+                    record.serialize()?,
+                    is_last_resort
+                ],
+            )
+            .map_err(db_error("save_kyber_pre_key"))?;
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl traits::KyberPreKeyStore for SqliteKyberPreKeyStore {
+    async fn get_kyber_pre_key(&self, kyber_prekey_id: KyberPreKeyId) -> Result<KyberPreKeyRecord> {
+        let record = get_record(
+            &self.conn,
+            "SELECT record FROM kyber_pre_keys WHERE id = ?1",
+            kyber_prekey_id.into(),
+            "get_kyber_pre_key",
+        )?
+        .ok_or(SignalProtocolError::InvalidKyberPreKeyId)?;
+        KyberPreKeyRecord::deserialize(&record)
+    }
+
+    async fn save_kyber_pre_key(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        record: &KyberPreKeyRecord,
+    ) -> Result<()> {
+        self.save(kyber_prekey_id, record, true)
+    }
+
+    async fn mark_kyber_pre_key_used(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        ec_prekey_id: SignedPreKeyId,
+        base_key: &PublicKey,
+    ) -> Result<()> {
+        let id = u32::from(kyber_prekey_id);
+        let is_last_resort: Option<bool> = self
+            .conn
+            .query_row(
+                "SELECT is_last_resort FROM kyber_pre_keys WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db_error("mark_kyber_pre_key_used"))?;
+
+        match is_last_resort {
+            None => Err(SignalProtocolError::InvalidKyberPreKeyId),
+            Some(false) => {
+                self.conn
+                    .execute("DELETE FROM kyber_pre_keys WHERE id = ?1", params![id])
+                    .map_err(db_error("mark_kyber_pre_key_used"))?;
+                Ok(())
+            }
+            Some(true) => {
+                let inserted = self
+                    .conn
+                    .execute(
+                        "INSERT OR IGNORE INTO kyber_base_keys_seen
+                         (kyber_pre_key_id, signed_pre_key_id, base_key) VALUES (?1, ?2, ?3)",
+                        params![id, u32::from(ec_prekey_id), &base_key.serialize()[..]],
+                    )
+                    .map_err(db_error("mark_kyber_pre_key_used"))?;
+                if inserted == 0 {
+                    return Err(SignalProtocolError::InvalidMessage(
+                        CiphertextMessageType::PreKey,
+                        "reused base key".to_owned(),
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// SQLite implementation of [traits::SessionStore].
+pub struct SqliteSessionStore {
+    conn: Rc<Connection>,
+}
+
+#[async_trait(?Send)]
+impl traits::SessionStore for SqliteSessionStore {
+    async fn load_session(&self, address: &ProtocolAddress) -> Result<Option<SessionRecord>> {
+        self.conn
+            .query_row(
+                "SELECT record FROM sessions WHERE name = ?1 AND device_id = ?2",
+                params![address.name(), u32::from(address.device_id())],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(db_error("load_session"))?
+            .map(|bytes| SessionRecord::deserialize(&bytes))
+            .transpose()
+    }
+
+    async fn store_session(
+        &mut self,
+        address: &ProtocolAddress,
+        record: &SessionRecord,
+    ) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO sessions (name, device_id, record) VALUES (?1, ?2, ?3)",
+                params![
+                    address.name(),
+                    u32::from(address.device_id()),
+                    record.serialize()?
+                ],
+            )
+            .map_err(db_error("store_session"))?;
+        Ok(())
+    }
+}
+
+/// SQLite implementation of [traits::SenderKeyStore].
+pub struct SqliteSenderKeyStore {
+    conn: Rc<Connection>,
+}
+
+#[async_trait(?Send)]
+impl traits::SenderKeyStore for SqliteSenderKeyStore {
+    async fn store_sender_key(
+        &mut self,
+        sender: &ProtocolAddress,
+        distribution_id: Uuid,
+        record: &SenderKeyRecord,
+    ) -> Result<()> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO sender_keys (name, device_id, distribution_id, record)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    sender.name(),
+                    u32::from(sender.device_id()),
+                    &distribution_id.as_bytes()[..],
+                    record.serialize()?
+                ],
+            )
+            .map_err(db_error("store_sender_key"))?;
+        Ok(())
+    }
+
+    async fn load_sender_key(
+        &mut self,
+        sender: &ProtocolAddress,
+        distribution_id: Uuid,
+    ) -> Result<Option<SenderKeyRecord>> {
+        self.conn
+            .query_row(
+                "SELECT record FROM sender_keys
+                 WHERE name = ?1 AND device_id = ?2 AND distribution_id = ?3",
+                params![
+                    sender.name(),
+                    u32::from(sender.device_id()),
+                    &distribution_id.as_bytes()[..]
+                ],
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .optional()
+            .map_err(db_error("load_sender_key"))?
+            .map(|bytes| SenderKeyRecord::deserialize(&bytes))
+            .transpose()
+    }
+}
+
+fn get_record(
+    conn: &Connection,
+    query: &str,
+    id: u32,
+    method: &'static str,
+) -> Result<Option<Vec<u8>>> {
+    conn.query_row(query, params![id], |row| row.get(0))
+        .optional()
+        .map_err(db_error(method))
+}
+
+fn all_ids<T: From<u32>>(conn: &Connection, query: &str, method: &'static str) -> Result<Vec<T>> {
+    let mut statement = conn.prepare(query).map_err(db_error(method))?;
+    let ids = statement
+        .query_map([], |row| row.get::<_, u32>(0))
+        .map_err(db_error(method))?
+        .map(|id| id.map(T::from))
+        .collect::<rusqlite::Result<Vec<T>>>()
+        .map_err(db_error(method))?;
+    Ok(ids)
+}
+
+/// SQLite implementation of [traits::ProtocolStore].
+///
+/// The individual stores are exposed as fields so that they can be passed separately to
+/// functions like [`message_decrypt`](crate::message_decrypt), exactly like
+/// [InMemSignalProtocolStore](crate::InMemSignalProtocolStore). They all share one connection.
+#[allow(missing_docs)]
+pub struct SqliteSignalProtocolStore {
+    pub session_store: SqliteSessionStore,
+    pub pre_key_store: SqlitePreKeyStore,
+    pub signed_pre_key_store: SqliteSignedPreKeyStore,
+    pub kyber_pre_key_store: SqliteKyberPreKeyStore,
+    pub identity_store: SqliteIdentityKeyStore,
+    pub sender_key_store: SqliteSenderKeyStore,
+    conn: Rc<Connection>,
+}
+
+impl SqliteSignalProtocolStore {
+    /// Set up a new store in `connection` for the given identity `key_pair` and
+    /// `registration_id`, creating or migrating the schema as necessary.
+    ///
+    /// Fails if the database already belongs to a different identity.
+    pub fn create(
+        connection: Connection,
+        key_pair: IdentityKeyPair,
+        registration_id: u32,
+    ) -> Result<Self> {
+        migrate(&connection)?;
+        match Self::load_local_identity(&connection)? {
+            Some((existing_key_pair, existing_registration_id)) => {
+                if existing_key_pair.identity_key() != key_pair.identity_key()
+                    || existing_registration_id != registration_id
+                {
+                    return Err(SignalProtocolError::InvalidState(
+                        "create",
+                        "database belongs to a different local identity".to_owned(),
+                    ));
+                }
+            }
+            None => {
+                connection
+                    .execute(
+                        "INSERT INTO local_identity (id, key_pair, registration_id)
+                         VALUES (0, ?1, ?2)",
+                        params![&key_pair.serialize()[..], registration_id],
+                    )
+                    .map_err(db_error("create"))?;
+            }
+        }
+        Ok(Self::with_connection(
+            Rc::new(connection),
+            key_pair,
+            registration_id,
+        ))
+    }
+
+    /// Open a store previously set up with [create](Self::create), migrating the schema if
+    /// necessary.
+    pub fn open(connection: Connection) -> Result<Self> {
+        migrate(&connection)?;
+        let (key_pair, registration_id) =
+            Self::load_local_identity(&connection)?.ok_or_else(|| {
+                SignalProtocolError::InvalidState(
+                    "open",
+                    "database has no local identity".to_owned(),
+                )
+            })?;
+        Ok(Self::with_connection(
+            Rc::new(connection),
+            key_pair,
+            registration_id,
+        ))
+    }
+
+    fn with_connection(
+        conn: Rc<Connection>,
+        key_pair: IdentityKeyPair,
+        registration_id: u32,
+    ) -> Self {
+        Self {
+            session_store: SqliteSessionStore { conn: conn.clone() },
+            pre_key_store: SqlitePreKeyStore { conn: conn.clone() },
+            signed_pre_key_store: SqliteSignedPreKeyStore { conn: conn.clone() },
+            kyber_pre_key_store: SqliteKyberPreKeyStore { conn: conn.clone() },
+            identity_store: SqliteIdentityKeyStore {
+                conn: conn.clone(),
+                key_pair,
+                registration_id,
+            },
+            sender_key_store: SqliteSenderKeyStore { conn: conn.clone() },
+            conn,
+        }
+    }
+
+    fn load_local_identity(conn: &Connection) -> Result<Option<(IdentityKeyPair, u32)>> {
+        conn.query_row(
+            "SELECT key_pair, registration_id FROM local_identity WHERE id = 0",
+            [],
+            |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, u32>(1)?)),
+        )
+        .optional()
+        .map_err(db_error("load_local_identity"))?
+        .map(|(key_pair, registration_id)| {
+            Ok((IdentityKeyPair::try_from(&key_pair[..])?, registration_id))
+        })
+        .transpose()
+    }
+
+    /// Returns the schema version of the underlying database.
+    pub fn schema_version(&self) -> Result<u32> {
+        self.conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(db_error("schema_version"))
+    }
+
+    /// Run `f` inside a database transaction.
+    ///
+    /// If `f` returns `Ok`, every store write made inside it is committed together; if it returns
+    /// `Err`, they are all rolled back. Transactions may be nested.
+    ///
+    /// ```ignore
+    /// let ptext = store
+    ///     .transaction(async |store| {
+    ///         message_decrypt(
+    ///             &ciphertext,
+    ///             &remote_address,
+    ///             &local_address,
+    ///             &mut store.session_store,
+    ///             &mut store.identity_store,
+    ///             &mut store.pre_key_store,
+    ///             &store.signed_pre_key_store,
+    ///             &mut store.kyber_pre_key_store,
+    ///             &mut rng,
+    ///         )
+    ///         .await
+    ///     })
+    ///     .await?;
+    /// ```
+    pub async fn transaction<T>(
+        &mut self,
+        f: impl AsyncFnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        self.conn
+            .execute_batch(&format!("SAVEPOINT {SAVEPOINT_NAME}"))
+            .map_err(db_error("transaction"))?;
+        match f(self).await {
+            Ok(result) => {
+                self.conn
+                    .execute_batch(&format!("RELEASE {SAVEPOINT_NAME}"))
+                    .map_err(db_error("transaction"))?;
+                Ok(result)
+            }
+            Err(e) => {
+                if let Err(rollback_error) = self.conn.execute_batch(&format!(
+                    "ROLLBACK TO {SAVEPOINT_NAME}; RELEASE {SAVEPOINT_NAME}"
+                )) {
+                    log::warn!("failed to roll back protocol store transaction: {rollback_error}");
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl traits::IdentityKeyStore for SqliteSignalProtocolStore {
+    async fn get_identity_key_pair(&self) -> Result<IdentityKeyPair> {
+        self.identity_store.get_identity_key_pair().await
+    }
+
+    async fn get_local_registration_id(&self) -> Result<u32> {
+        self.identity_store.get_local_registration_id().await
+    }
+
+    async fn save_identity(
+        &mut self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+    ) -> Result<IdentityChange> {
+        self.identity_store.save_identity(address, identity).await
+    }
+
+    async fn is_trusted_identity(
+        &self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+        direction: traits::Direction,
+    ) -> Result<bool> {
+        self.identity_store
+            .is_trusted_identity(address, identity, direction)
+            .await
+    }
+
+    async fn get_identity(&self, address: &ProtocolAddress) -> Result<Option<IdentityKey>> {
+        self.identity_store.get_identity(address).await
+    }
+}
+
+#[async_trait(?Send)]
+impl traits::PreKeyStore for SqliteSignalProtocolStore {
+    async fn get_pre_key(&self, id: PreKeyId) -> Result<PreKeyRecord> {
+        self.pre_key_store.get_pre_key(id).await
+    }
+
+    async fn save_pre_key(&mut self, id: PreKeyId, record: &PreKeyRecord) -> Result<()> {
+        self.pre_key_store.save_pre_key(id, record).await
+    }
+
+    async fn remove_pre_key(&mut self, id: PreKeyId) -> Result<()> {
+        self.pre_key_store.remove_pre_key(id).await
+    }
+}
+
+#[async_trait(?Send)]
+impl traits::SignedPreKeyStore for SqliteSignalProtocolStore {
+    async fn get_signed_pre_key(&self, id: SignedPreKeyId) -> Result<SignedPreKeyRecord> {
+        self.signed_pre_key_store.get_signed_pre_key(id).await
+    }
+
+    async fn save_signed_pre_key(
+        &mut self,
+        id: SignedPreKeyId,
+        record: &SignedPreKeyRecord,
+    ) -> Result<()> {
+        self.signed_pre_key_store
+            .save_signed_pre_key(id, record)
+            .await
+    }
+}
+
+#[async_trait(?Send)]
+impl traits::KyberPreKeyStore for SqliteSignalProtocolStore {
+    async fn get_kyber_pre_key(&self, kyber_prekey_id: KyberPreKeyId) -> Result<KyberPreKeyRecord> {
+        self.kyber_pre_key_store
+            .get_kyber_pre_key(kyber_prekey_id)
+            .await
+    }
+
+    async fn save_kyber_pre_key(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        record: &KyberPreKeyRecord,
+    ) -> Result<()> {
+        self.kyber_pre_key_store
+            .save_kyber_pre_key(kyber_prekey_id, record)
+            .await
+    }
+
+    async fn mark_kyber_pre_key_used(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        ec_prekey_id: SignedPreKeyId,
+        base_key: &PublicKey,
+    ) -> Result<()> {
+        self.kyber_pre_key_store
+            .mark_kyber_pre_key_used(kyber_prekey_id, ec_prekey_id, base_key)
+            .await
+    }
+}
+
+#[async_trait(?Send)]
+impl traits::SessionStore for SqliteSignalProtocolStore {
+    async fn load_session(&self, address: &ProtocolAddress) -> Result<Option<SessionRecord>> {
+        self.session_store.load_session(address).await
+    }
+
+    async fn store_session(
+        &mut self,
+        address: &ProtocolAddress,
+        record: &SessionRecord,
+    ) -> Result<()> {
+        self.session_store.store_session(address, record).await
+    }
+}
+
+#[async_trait(?Send)]
+impl traits::SenderKeyStore for SqliteSignalProtocolStore {
+    async fn store_sender_key(
+        &mut self,
+        sender: &ProtocolAddress,
+        distribution_id: Uuid,
+        record: &SenderKeyRecord,
+    ) -> Result<()> {
+        self.sender_key_store
+            .store_sender_key(sender, distribution_id, record)
+            .await
+    }
+
+    async fn load_sender_key(
+        &mut self,
+        sender: &ProtocolAddress,
+        distribution_id: Uuid,
+    ) -> Result<Option<SenderKeyRecord>> {
+        self.sender_key_store
+            .load_sender_key(sender, distribution_id)
+            .await
+    }
+}
+
+impl traits::ProtocolStore for SqliteSignalProtocolStore {}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use futures_util::FutureExt;
+    use rand::TryRngCore as _;
+    use rand::rngs::OsRng;
+
+    use super::*;
+    use crate::storage::traits::{KyberPreKeyStore as _, PreKeyStore as _};
+    use crate::{GenericSignedPreKey as _, KeyPair, Timestamp, kem};
+
+    fn new_store() -> SqliteSignalProtocolStore {
+        let identity = IdentityKeyPair::generate(&mut OsRng.unwrap_err());
+        SqliteSignalProtocolStore::create(
+            Connection::open_in_memory().expect("can open in-memory database"),
+            identity,
+            42,
+        )
+        .expect("can create store")
+    }
+
+    fn kyber_record(id: KyberPreKeyId, identity: &IdentityKeyPair) -> KyberPreKeyRecord {
+        let mut rng = OsRng.unwrap_err();
+        let key_pair = kem::KeyPair::generate(kem::KeyType::Kyber1024, &mut rng);
+        let signature = identity
+            .private_key()
+            .calculate_signature(&key_pair.public_key.serialize(), &mut rng)
+            .expect("can sign");
+        KyberPreKeyRecord::new(id, Timestamp::from_epoch_millis(42), &key_pair, &signature)
+    }
+
+    #[test]
+    fn migration_is_idempotent() {
+        let conn = Connection::open_in_memory().expect("can open in-memory database");
+        let version = migrate(&conn).expect("can migrate");
+        assert_eq!(usize::try_from(version).expect("small"), MIGRATIONS.len());
+        assert_eq!(migrate(&conn).expect("can migrate again"), version);
+    }
+
+    #[test]
+    fn rejects_newer_schema() {
+        let conn = Connection::open_in_memory().expect("can open in-memory database");
+        conn.pragma_update(None, "user_version", 1000)
+            .expect("can set version");
+        assert_matches!(
+            migrate(&conn),
+            Err(SignalProtocolError::InvalidState("migrate", _))
+        );
+    }
+
+    #[test]
+    fn transaction_rolls_back_on_error() {
+        let mut store = new_store();
+        let id = PreKeyId::from(7);
+        let record = PreKeyRecord::new(id, &KeyPair::generate(&mut OsRng.unwrap_err()));
+
+        let result: Result<()> = store
+            .transaction(async |store| {
+                store.save_pre_key(id, &record).await?;
+                Err(SignalProtocolError::InvalidArgument("abort".to_owned()))
+            })
+            .now_or_never()
+            .expect("sync");
+        assert_matches!(result, Err(SignalProtocolError::InvalidArgument(_)));
+        assert_matches!(
+            store.get_pre_key(id).now_or_never().expect("sync"),
+            Err(SignalProtocolError::InvalidPreKeyId)
+        );
+
+        store
+            .transaction(async |store| store.save_pre_key(id, &record).await)
+            .now_or_never()
+            .expect("sync")
+            .expect("can commit");
+        assert_eq!(
+            store.pre_key_store.all_pre_key_ids().expect("can list"),
+            vec![id]
+        );
+    }
+
+    #[test]
+    fn kyber_one_time_and_last_resort_keys() {
+        let mut store = new_store();
+        let identity = store.identity_store.key_pair;
+        let base_key = KeyPair::generate(&mut OsRng.unwrap_err()).public_key;
+        let signed_pre_key_id = SignedPreKeyId::from(1);
+
+        let one_time_id = KyberPreKeyId::from(1);
+        store
+            .kyber_pre_key_store
+            .save_one_time_kyber_pre_key(one_time_id, &kyber_record(one_time_id, &identity))
+            .expect("can save");
+        store
+            .mark_kyber_pre_key_used(one_time_id, signed_pre_key_id, &base_key)
+            .now_or_never()
+            .expect("sync")
+            .expect("can use once");
+        assert_matches!(
+            store
+                .get_kyber_pre_key(one_time_id)
+                .now_or_never()
+                .expect("sync"),
+            Err(SignalProtocolError::InvalidKyberPreKeyId)
+        );
+
+        let last_resort_id = KyberPreKeyId::from(2);
+        store
+            .save_kyber_pre_key(last_resort_id, &kyber_record(last_resort_id, &identity))
+            .now_or_never()
+            .expect("sync")
+            .expect("can save");
+        store
+            .mark_kyber_pre_key_used(last_resort_id, signed_pre_key_id, &base_key)
+            .now_or_never()
+            .expect("sync")
+            .expect("can use once");
+        assert_matches!(
+            store
+                .mark_kyber_pre_key_used(last_resort_id, signed_pre_key_id, &base_key)
+                .now_or_never()
+                .expect("sync"),
+            Err(SignalProtocolError::InvalidMessage(
+                CiphertextMessageType::PreKey,
+                _
+            ))
+        );
+        store
+            .get_kyber_pre_key(last_resort_id)
+            .now_or_never()
+            .expect("sync")
+            .expect("last-resort key is kept");
+    }
+}
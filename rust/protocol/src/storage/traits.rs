@@ -14,7 +14,10 @@ use crate::state::{
     KyberPreKeyId, KyberPreKeyRecord, PreKeyId, PreKeyRecord, SessionRecord, SignedPreKeyId,
     SignedPreKeyRecord,
 };
-use crate::{IdentityKey, IdentityKeyPair, ProtocolAddress, PublicKey};
+use crate::{
+    IdentityKey, IdentityKeyPair, IdentityKeyTransition, ProtocolAddress, PublicKey,
+    SignalProtocolError,
+};
 
 // TODO: consider moving this enum into utils.rs?
 /// Each Signal message can be considered to have exactly two participants, a sender and receiver.
@@ -38,6 +41,11 @@ pub enum IdentityChange {
     NewOrUnchanged,
     /// The new identity key replaced a different key for the protocol address.
     ReplacedExisting,
+    /// The new identity key replaced the previous key for the protocol address, and the previous
+    /// key signed off on the change with an [`IdentityKeyTransition`].
+    ///
+    /// Only produced by [`IdentityKeyStore::save_identity_with_transition`].
+    RotatedVerified,
 }
 
 /// Interface defining the identity store, which may be in-memory, on-disk, etc.
@@ -79,6 +87,34 @@ pub trait IdentityKeyStore {
 
     /// Return the public identity for the given `address`, if known.
     async fn get_identity(&self, address: &ProtocolAddress) -> Result<Option<IdentityKey>>;
+
+    /// Record the new identity from `transition` into the store.
+    ///
+    /// The transition must start from the identity currently stored for `address`; the result is
+    /// then [`IdentityChange::RotatedVerified`]. If the new identity is already stored, nothing
+    /// changes and the result is [`IdentityChange::NewOrUnchanged`]. Otherwise, including when
+    /// the transition's signature does not verify or no identity is stored, the transition is
+    /// rejected with [`SignalProtocolError::SignatureValidationFailed`], and nothing is saved.
+    async fn save_identity_with_transition(
+        &mut self,
+        address: &ProtocolAddress,
+        transition: &IdentityKeyTransition,
+    ) -> Result<IdentityChange> {
+        if !transition.verify()? {
+            return Err(SignalProtocolError::SignatureValidationFailed);
+        }
+        let current = self.get_identity(address).await?;
+        if current.as_ref() == Some(transition.new_identity_key()) {
+            return Ok(IdentityChange::NewOrUnchanged);
+        }
+        if current.as_ref() != Some(transition.old_identity_key()) {
+            log::warn!("identity key transition for {address} does not start from the stored key");
+            return Err(SignalProtocolError::SignatureValidationFailed);
+        }
+        self.save_identity(address, transition.new_identity_key())
+            .await?;
+        Ok(IdentityChange::RotatedVerified)
+    }
 }
 
 /// Interface for storing pre-keys downloaded from a server.
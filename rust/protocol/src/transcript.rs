@@ -0,0 +1,1104 @@
+//
+// Copyright 2026 Signal Messenger, LLC.
+// SPDX-License-Identifier: AGPL-3.0-only
+//
+
+//! Recording and replaying the store traffic of [`message_encrypt`] and [`message_decrypt`].
+//!
+//! A [`TranscriptRecorder`] wraps a [`ProtocolStore`] and logs every store call made through it,
+//! along with the inputs to each encrypt or decrypt it runs. The randomness for those operations
+//! comes from a logged seed, so [`replay_transcript`] can later run the same operations offline,
+//! answering store reads from the log, and report the first point where anything differs.
+//!
+//! A transcript holds session state, private keys, and message contents. These are either sealed
+//! under a [`TranscriptDebugKey`] or redacted; a redacted transcript shows which calls were made,
+//! but cannot be replayed.
+//!
+//! Everything else is stored in plaintext, including the addresses of both parties, the identity
+//! keys passed to and returned from the store, and the ciphertexts of received messages. Treat a
+//! transcript as revealing who was talking to whom and when, even if its secrets are redacted.
+
+#![warn(missing_docs)]
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use aes::Aes256;
+use aes::cipher::{KeyIvInit, StreamCipher};
+use aes_gcm_siv::aead::Aead;
+use aes_gcm_siv::{Aes256GcmSiv, KeyInit};
+use async_trait::async_trait;
+use futures_util::lock::Mutex;
+use prost::Message;
+use rand::{CryptoRng, Rng, RngCore};
+
+use crate::proto::transcript::store_event::{self, Call};
+use crate::proto::transcript::{self as proto, secret, step};
+use crate::state::GenericSignedPreKey;
+use crate::{
+    CiphertextMessage, CiphertextMessageType, DeviceId, Direction, IdentityChange, IdentityKey,
+    IdentityKeyPair, IdentityKeyStore, KyberPreKeyId, KyberPreKeyRecord, KyberPreKeyStore,
+    PlaintextContent, PreKeyId, PreKeyRecord, PreKeySignalMessage, PreKeyStore, ProtocolAddress,
+    ProtocolStore, PublicKey, Result, SenderKeyMessage, SessionRecord, SessionStore, SignalMessage,
+    SignalProtocolError, SignedPreKeyId, SignedPreKeyRecord, SignedPreKeyStore, message_decrypt,
+    message_encrypt,
+};
+
+const NONCE_LEN: usize = 12;
+
+/// A symmetric key protecting the secrets in a transcript.
+///
+/// Anyone holding both the key and a transcript can recover the recorded sessions and messages,
+/// so the key should go only to whoever is debugging the failure.
+#[derive(Clone)]
+pub struct TranscriptDebugKey([u8; 32]);
+
+impl TranscriptDebugKey {
+    /// Generate a new random key.
+    pub fn generate<R: Rng + CryptoRng>(csprng: &mut R) -> Self {
+        Self(csprng.random())
+    }
+
+    /// Use existing key bytes, such as a key previously returned by [`Self::as_bytes`].
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Return the raw key bytes.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    fn cipher(&self) -> Aes256GcmSiv {
+        Aes256GcmSiv::new(&self.0.into())
+    }
+}
+
+/// How a [`TranscriptRecorder`] treats values that would reveal keys or message contents.
+pub enum TranscriptSecrets {
+    /// Record only the length of each secret value. The transcript cannot be replayed.
+    Redact,
+    /// Encrypt each secret value under the given key, which is needed to replay the transcript.
+    Seal(TranscriptDebugKey),
+}
+
+/// A log of protocol operations and the store calls they made.
+///
+/// Produced by [`TranscriptRecorder`] and consumed by [`replay_transcript`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transcript(proto::Transcript);
+
+impl Transcript {
+    /// Return a byte slice which can later be deserialized with [`Self::deserialize`].
+    pub fn serialize(&self) -> Vec<u8> {
+        self.0.encode_to_vec()
+    }
+
+    /// Deserialize a transcript from a byte slice.
+    pub fn deserialize(bytes: &[u8]) -> Result<Self> {
+        Ok(Self(proto::Transcript::decode(bytes).map_err(|_| {
+            SignalProtocolError::InvalidProtobufEncoding
+        })?))
+    }
+
+    /// Return the number of steps in the transcript.
+    ///
+    /// Each encrypt or decrypt is one step, as is each run of store calls made between them.
+    pub fn len(&self) -> usize {
+        self.0.steps.len()
+    }
+
+    /// Return `true` if nothing has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.0.steps.is_empty()
+    }
+
+    /// Return the indexes of the steps whose operation failed when recorded.
+    pub fn failed_steps(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0
+            .steps
+            .iter()
+            .enumerate()
+            .filter(|(_, step)| matches!(step.outcome, Some(step::Outcome::Error(_))))
+            .map(|(index, _)| index)
+    }
+}
+
+/// Wraps a [`ProtocolStore`], recording every call made through it.
+///
+/// The store traits are implemented for `&TranscriptRecorder`, so a shared reference can be passed
+/// as each of the stores an operation needs. [`Self::message_encrypt`] and
+/// [`Self::message_decrypt`] do this, additionally recording their inputs and drawing their
+/// randomness from a recorded seed; only operations run through them can be replayed. Store calls
+/// made otherwise are still recorded, as steps with no operation.
+///
+/// Operations on one recorder must not run concurrently, or their steps will be mixed together.
+pub struct TranscriptRecorder<S> {
+    store: Mutex<S>,
+    log: RefCell<Log>,
+}
+
+impl<S> TranscriptRecorder<S> {
+    /// Start recording calls to `store`.
+    pub fn new<R: Rng + CryptoRng>(store: S, secrets: TranscriptSecrets, csprng: &mut R) -> Self {
+        let sealer = match secrets {
+            TranscriptSecrets::Redact => None,
+            TranscriptSecrets::Seal(key) => Some(Sealer {
+                cipher: key.cipher(),
+                nonce_prefix: csprng.random(),
+                counter: 0,
+            }),
+        };
+        Self {
+            store: Mutex::new(store),
+            log: RefCell::new(Log {
+                sealer,
+                steps: Vec::new(),
+                current: None,
+            }),
+        }
+    }
+
+    /// Return a copy of everything recorded so far.
+    pub fn transcript(&self) -> Transcript {
+        Transcript(proto::Transcript {
+            steps: self.log.borrow().steps.clone(),
+        })
+    }
+
+    /// Stop recording, returning the wrapped store and the transcript.
+    pub fn into_parts(self) -> (S, Transcript) {
+        let log = self.log.into_inner();
+        (
+            self.store.into_inner(),
+            Transcript(proto::Transcript { steps: log.steps }),
+        )
+    }
+
+    fn push_event<T>(&self, mut event: proto::StoreEvent, result: &Result<T>) {
+        if let Err(e) = result {
+            event.failure = Some(failure(e));
+        }
+        self.log.borrow_mut().push_event(event);
+    }
+}
+
+impl<S: ProtocolStore> TranscriptRecorder<S> {
+    /// Like [`message_encrypt`], using the wrapped store and recording the step.
+    pub async fn message_encrypt<R: Rng + CryptoRng>(
+        &self,
+        ptext: &[u8],
+        remote_address: &ProtocolAddress,
+        local_address: &ProtocolAddress,
+        now: SystemTime,
+        csprng: &mut R,
+    ) -> Result<CiphertextMessage> {
+        let seed = csprng.random();
+        {
+            let mut log = self.log.borrow_mut();
+            let operation = step::Operation::Encrypt(proto::Encrypt {
+                remote: Some(address_to_proto(remote_address)),
+                local: Some(address_to_proto(local_address)),
+                plaintext: Some(log.secret(ptext)),
+                now_millis: now
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .expect("Time should move forward")
+                    .as_millis()
+                    .try_into()
+                    .expect("Timestamp too large"),
+            });
+            log.begin(operation, &seed);
+        }
+
+        let (mut session_store, mut identity_store) = (self, self);
+        let result = message_encrypt(
+            ptext,
+            remote_address,
+            local_address,
+            &mut session_store,
+            &mut identity_store,
+            now,
+            &mut TranscriptRng::new(&seed),
+        )
+        .await;
+
+        self.log
+            .borrow_mut()
+            .finish(result.as_ref().map(CiphertextMessage::serialize));
+        result
+    }
+
+    /// Like [`message_decrypt`], using the wrapped store and recording the step.
+    pub async fn message_decrypt<R: Rng + CryptoRng>(
+        &self,
+        ciphertext: &CiphertextMessage,
+        remote_address: &ProtocolAddress,
+        local_address: &ProtocolAddress,
+        csprng: &mut R,
+    ) -> Result<Vec<u8>> {
+        let seed = csprng.random();
+        self.log.borrow_mut().begin(
+            step::Operation::Decrypt(proto::Decrypt {
+                remote: Some(address_to_proto(remote_address)),
+                local: Some(address_to_proto(local_address)),
+                message_type: (ciphertext.message_type() as u8).into(),
+                ciphertext: ciphertext.serialize().to_vec(),
+            }),
+            &seed,
+        );
+
+        let (mut session_store, mut identity_store, mut pre_key_store, mut kyber_pre_key_store) =
+            (self, self, self, self);
+        let result = message_decrypt(
+            ciphertext,
+            remote_address,
+            local_address,
+            &mut session_store,
+            &mut identity_store,
+            &mut pre_key_store,
+            &self,
+            &mut kyber_pre_key_store,
+            &mut TranscriptRng::new(&seed),
+        )
+        .await;
+
+        self.log
+            .borrow_mut()
+            .finish(result.as_ref().map(Vec::as_slice));
+        result
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a, S: IdentityKeyStore> IdentityKeyStore for &'a TranscriptRecorder<S> {
+    async fn get_identity_key_pair(&self) -> Result<IdentityKeyPair> {
+        let result = self.store.lock().await.get_identity_key_pair().await;
+        let mut event = event(Call::GetIdentityKeyPair, None);
+        if let Ok(key_pair) = &result {
+            event.record = Some(self.log.borrow_mut().secret(&key_pair.serialize()));
+        }
+        self.push_event(event, &result);
+        result
+    }
+
+    async fn get_local_registration_id(&self) -> Result<u32> {
+        let result = self.store.lock().await.get_local_registration_id().await;
+        let mut event = event(Call::GetLocalRegistrationId, None);
+        if let Ok(id) = &result {
+            event.result = *id;
+        }
+        self.push_event(event, &result);
+        result
+    }
+
+    async fn save_identity(
+        &mut self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+    ) -> Result<IdentityChange> {
+        let result = self
+            .store
+            .lock()
+            .await
+            .save_identity(address, identity)
+            .await;
+        let mut event = event(Call::SaveIdentity, Some(address));
+        event.key = identity.serialize().into_vec();
+        if let Ok(change) = &result {
+            event.result = identity_change_to_proto(*change);
+        }
+        self.push_event(event, &result);
+        result
+    }
+
+    async fn is_trusted_identity(
+        &self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+        direction: Direction,
+    ) -> Result<bool> {
+        let result = self
+            .store
+            .lock()
+            .await
+            .is_trusted_identity(address, identity, direction.clone())
+            .await;
+        let mut event = event(Call::IsTrustedIdentity, Some(address));
+        event.key = identity.serialize().into_vec();
+        event.secondary_id = direction_to_proto(&direction);
+        if let Ok(trusted) = &result {
+            event.result = (*trusted).into();
+        }
+        self.push_event(event, &result);
+        result
+    }
+
+    async fn get_identity(&self, address: &ProtocolAddress) -> Result<Option<IdentityKey>> {
+        let result = self.store.lock().await.get_identity(address).await;
+        let mut event = event(Call::GetIdentity, Some(address));
+        if let Ok(Some(identity)) = &result {
+            event.found = true;
+            event.key = identity.serialize().into_vec();
+        }
+        self.push_event(event, &result);
+        result
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a, S: PreKeyStore> PreKeyStore for &'a TranscriptRecorder<S> {
+    async fn get_pre_key(&self, prekey_id: PreKeyId) -> Result<PreKeyRecord> {
+        let result = self.store.lock().await.get_pre_key(prekey_id).await;
+        let mut event = event(Call::GetPreKey, None);
+        event.id = prekey_id.into();
+        if let Ok(record) = &result {
+            event.record = Some(self.log.borrow_mut().secret(&record.serialize()?));
+        }
+        self.push_event(event, &result);
+        result
+    }
+
+    async fn save_pre_key(&mut self, prekey_id: PreKeyId, record: &PreKeyRecord) -> Result<()> {
+        let mut event = event(Call::SavePreKey, None);
+        event.id = prekey_id.into();
+        event.record = Some(self.log.borrow_mut().secret(&record.serialize()?));
+        let result = self
+            .store
+            .lock()
+            .await
+            .save_pre_key(prekey_id, record)
+            .await;
+        self.push_event(event, &result);
+        result
+    }
+
+    async fn remove_pre_key(&mut self, prekey_id: PreKeyId) -> Result<()> {
+        let result = self.store.lock().await.remove_pre_key(prekey_id).await;
+        let mut event = event(Call::RemovePreKey, None);
+        event.id = prekey_id.into();
+        self.push_event(event, &result);
+        result
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a, S: SignedPreKeyStore> SignedPreKeyStore for &'a TranscriptRecorder<S> {
+    async fn get_signed_pre_key(
+        &self,
+        signed_prekey_id: SignedPreKeyId,
+    ) -> Result<SignedPreKeyRecord> {
+        let result = self
+            .store
+            .lock()
+            .await
+            .get_signed_pre_key(signed_prekey_id)
+            .await;
+        let mut event = event(Call::GetSignedPreKey, None);
+        event.id = signed_prekey_id.into();
+        if let Ok(record) = &result {
+            event.record = Some(self.log.borrow_mut().secret(&record.serialize()?));
+        }
+        self.push_event(event, &result);
+        result
+    }
+
+    async fn save_signed_pre_key(
+        &mut self,
+        signed_prekey_id: SignedPreKeyId,
+        record: &SignedPreKeyRecord,
+    ) -> Result<()> {
+        let mut event = event(Call::SaveSignedPreKey, None);
+        event.id = signed_prekey_id.into();
+        event.record = Some(self.log.borrow_mut().secret(&record.serialize()?));
+        let result = self
+            .store
+            .lock()
+            .await
+            .save_signed_pre_key(signed_prekey_id, record)
+            .await;
+        self.push_event(event, &result);
+        result
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a, S: KyberPreKeyStore> KyberPreKeyStore for &'a TranscriptRecorder<S> {
+    async fn get_kyber_pre_key(&self, kyber_prekey_id: KyberPreKeyId) -> Result<KyberPreKeyRecord> {
+        let result = self
+            .store
+            .lock()
+            .await
+            .get_kyber_pre_key(kyber_prekey_id)
+            .await;
+        let mut event = event(Call::GetKyberPreKey, None);
+        event.id = kyber_prekey_id.into();
+        if let Ok(record) = &result {
+            event.record = Some(self.log.borrow_mut().secret(&record.serialize()?));
+        }
+        self.push_event(event, &result);
+        result
+    }
+
+    async fn save_kyber_pre_key(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        record: &KyberPreKeyRecord,
+    ) -> Result<()> {
+        let mut event = event(Call::SaveKyberPreKey, None);
+        event.id = kyber_prekey_id.into();
+        event.record = Some(self.log.borrow_mut().secret(&record.serialize()?));
+        let result = self
+            .store
+            .lock()
+            .await
+            .save_kyber_pre_key(kyber_prekey_id, record)
+            .await;
+        self.push_event(event, &result);
+        result
+    }
+
+    async fn mark_kyber_pre_key_used(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        ec_prekey_id: SignedPreKeyId,
+        base_key: &PublicKey,
+    ) -> Result<()> {
+        let result = self
+            .store
+            .lock()
+            .await
+            .mark_kyber_pre_key_used(kyber_prekey_id, ec_prekey_id, base_key)
+            .await;
+        let mut event = event(Call::MarkKyberPreKeyUsed, None);
+        event.id = kyber_prekey_id.into();
+        event.secondary_id = ec_prekey_id.into();
+        event.key = base_key.serialize().into_vec();
+        self.push_event(event, &result);
+        result
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a, S: SessionStore> SessionStore for &'a TranscriptRecorder<S> {
+    async fn load_session(&self, address: &ProtocolAddress) -> Result<Option<SessionRecord>> {
+        let result = self.store.lock().await.load_session(address).await;
+        let mut event = event(Call::LoadSession, Some(address));
+        if let Ok(Some(record)) = &result {
+            event.found = true;
+            event.record = Some(self.log.borrow_mut().secret(&record.serialize()?));
+        }
+        self.push_event(event, &result);
+        result
+    }
+
+    async fn store_session(
+        &mut self,
+        address: &ProtocolAddress,
+        record: &SessionRecord,
+    ) -> Result<()> {
+        let mut event = event(Call::StoreSession, Some(address));
+        event.record = Some(self.log.borrow_mut().secret(&record.serialize()?));
+        let result = self.store.lock().await.store_session(address, record).await;
+        self.push_event(event, &result);
+        result
+    }
+}
+
+impl<'a, S: ProtocolStore> ProtocolStore for &'a TranscriptRecorder<S> {}
+
+/// The result of [`replay_transcript`].
+#[derive(Debug)]
+pub struct ReplayReport {
+    /// The number of encrypt and decrypt steps that were replayed.
+    pub steps_replayed: usize,
+    /// The first difference from the recording, after which replay stopped.
+    pub divergence: Option<ReplayDivergence>,
+}
+
+/// A point where replaying a transcript did not match the recording.
+#[derive(Debug)]
+pub struct ReplayDivergence {
+    /// The index of the step, as counted by [`Transcript::len`].
+    pub step: usize,
+    /// What differed.
+    pub description: String,
+}
+
+impl fmt::Display for ReplayDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "step {}: {}", self.step, self.description)
+    }
+}
+
+/// Re-runs each encrypt and decrypt recorded in `transcript`.
+///
+/// Each step is replayed on its own: store reads are answered from the recording, store writes
+/// and the final result are compared against it, and randomness comes from the recorded seed. So
+/// a step whose recorded outcome was an error (see [`Transcript::failed_steps`]) will fail the
+/// same way, unless the protocol code has changed since it was recorded, in which case the report
+/// points at the first store call or result that came out differently.
+///
+/// Returns an error if the transcript is malformed, was redacted, or was sealed under a different
+/// key.
+pub async fn replay_transcript(
+    transcript: &Transcript,
+    debug_key: &TranscriptDebugKey,
+) -> Result<ReplayReport> {
+    let cipher = debug_key.cipher();
+    let mut steps_replayed = 0;
+
+    for (index, step) in transcript.0.steps.iter().enumerate() {
+        let Some(operation) = &step.operation else {
+            continue;
+        };
+        let seed: [u8; 32] = open(&cipher, step.rng_seed.as_ref())?
+            .try_into()
+            .map_err(|_| SignalProtocolError::InvalidProtobufEncoding)?;
+        let mut csprng = TranscriptRng::new(&seed);
+        let store = ReplayStore {
+            events: &step.events,
+            cipher: &cipher,
+            next: Cell::new(0),
+            divergence: RefCell::new(None),
+            approximate_failure: Cell::new(false),
+        };
+
+        let result = match operation {
+            step::Operation::Encrypt(encrypt) => {
+                let ptext = open(&cipher, encrypt.plaintext.as_ref())?;
+                let (mut session_store, mut identity_store) = (&store, &store);
+                message_encrypt(
+                    &ptext,
+                    &address_from_proto(encrypt.remote.as_ref())?,
+                    &address_from_proto(encrypt.local.as_ref())?,
+                    &mut session_store,
+                    &mut identity_store,
+                    SystemTime::UNIX_EPOCH + Duration::from_millis(encrypt.now_millis),
+                    &mut csprng,
+                )
+                .await
+                .map(|message| message.serialize().to_vec())
+            }
+            step::Operation::Decrypt(decrypt) => {
+                let ciphertext = ciphertext_from_proto(decrypt)?;
+                let (
+                    mut session_store,
+                    mut identity_store,
+                    mut pre_key_store,
+                    mut kyber_pre_key_store,
+                ) = (&store, &store, &store, &store);
+                message_decrypt(
+                    &ciphertext,
+                    &address_from_proto(decrypt.remote.as_ref())?,
+                    &address_from_proto(decrypt.local.as_ref())?,
+                    &mut session_store,
+                    &mut identity_store,
+                    &mut pre_key_store,
+                    &&store,
+                    &mut kyber_pre_key_store,
+                    &mut csprng,
+                )
+                .await
+            }
+        };
+        steps_replayed += 1;
+
+        if let Some(description) = store.compare(step.outcome.as_ref(), result)? {
+            return Ok(ReplayReport {
+                steps_replayed,
+                divergence: Some(ReplayDivergence {
+                    step: index,
+                    description,
+                }),
+            });
+        }
+    }
+
+    Ok(ReplayReport {
+        steps_replayed,
+        divergence: None,
+    })
+}
+
+struct Sealer {
+    cipher: Aes256GcmSiv,
+    nonce_prefix: [u8; 4],
+    counter: u64,
+}
+
+struct Log {
+    sealer: Option<Sealer>,
+    steps: Vec<proto::Step>,
+    current: Option<proto::Step>,
+}
+
+impl Log {
+    fn secret(&mut self, value: &[u8]) -> proto::Secret {
+        let value = match &mut self.sealer {
+            None => secret::Value::RedactedLength(value.len().try_into().unwrap_or(u32::MAX)),
+            Some(sealer) => {
+                let mut nonce = [0; NONCE_LEN];
+                nonce[..4].copy_from_slice(&sealer.nonce_prefix);
+                nonce[4..].copy_from_slice(&sealer.counter.to_be_bytes());
+                sealer.counter += 1;
+                let ciphertext = sealer
+                    .cipher
+                    .encrypt(&nonce.into(), value)
+                    .expect("AES-GCM-SIV encryption should not fail for in-memory values");
+                secret::Value::Sealed([&nonce[..], &ciphertext].concat())
+            }
+        };
+        proto::Secret { value: Some(value) }
+    }
+
+    fn begin(&mut self, operation: step::Operation, seed: &[u8; 32]) {
+        // An operation that never finished (because its future was dropped) is kept as-is.
+        self.steps.extend(self.current.take());
+        self.current = Some(proto::Step {
+            operation: Some(operation),
+            rng_seed: Some(self.secret(seed)),
+            ..Default::default()
+        });
+    }
+
+    fn finish(&mut self, outcome: std::result::Result<&[u8], &SignalProtocolError>) {
+        let outcome = match outcome {
+            Ok(output) => step::Outcome::Output(self.secret(output)),
+            Err(e) => step::Outcome::Error(e.to_string()),
+        };
+        if let Some(mut step) = self.current.take() {
+            step.outcome = Some(outcome);
+            self.steps.push(step);
+        }
+    }
+
+    fn push_event(&mut self, event: proto::StoreEvent) {
+        if let Some(step) = &mut self.current {
+            step.events.push(event);
+            return;
+        }
+        match self.steps.last_mut() {
+            Some(step) if step.operation.is_none() => step.events.push(event),
+            _ => self.steps.push(proto::Step {
+                events: vec![event],
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+/// Serves store calls for one step of [`replay_transcript`] from the recording.
+struct ReplayStore<'a> {
+    events: &'a [proto::StoreEvent],
+    cipher: &'a Aes256GcmSiv,
+    next: Cell<usize>,
+    divergence: RefCell<Option<String>>,
+    // Set when a recorded store failure couldn't be reproduced exactly, so the operation's error
+    // message may legitimately differ from the recorded one.
+    approximate_failure: Cell<bool>,
+}
+
+impl<'a> ReplayStore<'a> {
+    /// Checks a store call against the next recorded one, and returns the recorded call.
+    ///
+    /// If the recorded call failed, returns an equivalent error instead.
+    fn next_call(&self, call: &proto::StoreEvent) -> Result<&'a proto::StoreEvent> {
+        let index = self.next.get();
+        let recorded = self.events.get(index);
+        let Some(recorded) = recorded.filter(|recorded| same_request(recorded, call)) else {
+            return Err(self.diverge(format!(
+                "store call {index} was {}, but {} was recorded",
+                describe(Some(call)),
+                describe(recorded)
+            )));
+        };
+        self.next.set(index + 1);
+
+        let Some(failure) = &recorded.failure else {
+            return Ok(recorded);
+        };
+        Err(match failure.kind() {
+            store_event::failure::Kind::InvalidPreKeyId => SignalProtocolError::InvalidPreKeyId,
+            store_event::failure::Kind::InvalidSignedPreKeyId => {
+                SignalProtocolError::InvalidSignedPreKeyId
+            }
+            store_event::failure::Kind::InvalidKyberPreKeyId => {
+                SignalProtocolError::InvalidKyberPreKeyId
+            }
+            store_event::failure::Kind::Other => {
+                self.approximate_failure.set(true);
+                SignalProtocolError::InvalidState("transcript replay", failure.description.clone())
+            }
+        })
+    }
+
+    /// Like [`Self::next_call`], also checking that the same record was written.
+    fn next_write(&self, call: &proto::StoreEvent, record: &[u8]) -> Result<()> {
+        let index = self.next.get();
+        let recorded = self.next_call(call)?;
+        if open(self.cipher, recorded.record.as_ref())? != record {
+            return Err(self.diverge(format!(
+                "store call {index} ({}) wrote a different record",
+                describe(Some(call))
+            )));
+        }
+        Ok(())
+    }
+
+    fn recorded_record(&self, recorded: &proto::StoreEvent) -> Result<Vec<u8>> {
+        open(self.cipher, recorded.record.as_ref())
+    }
+
+    fn diverge(&self, description: String) -> SignalProtocolError {
+        // Only the first difference matters; the operation may report more as it unwinds.
+        self.divergence.borrow_mut().get_or_insert(description);
+        SignalProtocolError::InvalidState("transcript replay", "diverged from recording".into())
+    }
+
+    /// Returns a description of the first difference from the recording, if there was one.
+    fn compare(
+        &self,
+        recorded: Option<&step::Outcome>,
+        actual: Result<Vec<u8>>,
+    ) -> Result<Option<String>> {
+        if let Some(description) = self.divergence.take() {
+            return Ok(Some(description));
+        }
+        let calls_made = self.next.get();
+        if calls_made != self.events.len() {
+            return Ok(Some(format!(
+                "made {calls_made} store calls, but {} were recorded",
+                self.events.len()
+            )));
+        }
+
+        Ok(match (recorded, actual) {
+            // The operation never finished when recorded.
+            (None, _) => None,
+            (Some(step::Outcome::Output(recorded)), Ok(output)) => {
+                (open(self.cipher, Some(recorded))? != output)
+                    .then(|| "produced different output".to_owned())
+            }
+            (Some(step::Outcome::Output(_)), Err(e)) => {
+                Some(format!("failed with {e}, but succeeded when recorded"))
+            }
+            (Some(step::Outcome::Error(recorded)), Ok(_)) => Some(format!(
+                "succeeded, but failed with {recorded} when recorded"
+            )),
+            (Some(step::Outcome::Error(recorded)), Err(e)) => {
+                let actual = e.to_string();
+                (actual != *recorded && !self.approximate_failure.get()).then(|| {
+                    format!("failed with {actual}, but failed with {recorded} when recorded")
+                })
+            }
+        })
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a, 'b> IdentityKeyStore for &'a ReplayStore<'b> {
+    async fn get_identity_key_pair(&self) -> Result<IdentityKeyPair> {
+        let recorded = self.next_call(&event(Call::GetIdentityKeyPair, None))?;
+        IdentityKeyPair::try_from(&self.recorded_record(recorded)?[..])
+    }
+
+    async fn get_local_registration_id(&self) -> Result<u32> {
+        let recorded = self.next_call(&event(Call::GetLocalRegistrationId, None))?;
+        Ok(recorded.result)
+    }
+
+    async fn save_identity(
+        &mut self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+    ) -> Result<IdentityChange> {
+        let mut call = event(Call::SaveIdentity, Some(address));
+        call.key = identity.serialize().into_vec();
+        let recorded = self.next_call(&call)?;
+        identity_change_from_proto(recorded.result)
+    }
+
+    async fn is_trusted_identity(
+        &self,
+        address: &ProtocolAddress,
+        identity: &IdentityKey,
+        direction: Direction,
+    ) -> Result<bool> {
+        let mut call = event(Call::IsTrustedIdentity, Some(address));
+        call.key = identity.serialize().into_vec();
+        call.secondary_id = direction_to_proto(&direction);
+        let recorded = self.next_call(&call)?;
+        Ok(recorded.result != 0)
+    }
+
+    async fn get_identity(&self, address: &ProtocolAddress) -> Result<Option<IdentityKey>> {
+        let recorded = self.next_call(&event(Call::GetIdentity, Some(address)))?;
+        if !recorded.found {
+            return Ok(None);
+        }
+        Ok(Some(IdentityKey::decode(&recorded.key)?))
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a, 'b> PreKeyStore for &'a ReplayStore<'b> {
+    async fn get_pre_key(&self, prekey_id: PreKeyId) -> Result<PreKeyRecord> {
+        let mut call = event(Call::GetPreKey, None);
+        call.id = prekey_id.into();
+        let recorded = self.next_call(&call)?;
+        PreKeyRecord::deserialize(&self.recorded_record(recorded)?)
+    }
+
+    async fn save_pre_key(&mut self, prekey_id: PreKeyId, record: &PreKeyRecord) -> Result<()> {
+        let mut call = event(Call::SavePreKey, None);
+        call.id = prekey_id.into();
+        self.next_write(&call, &record.serialize()?)
+    }
+
+    async fn remove_pre_key(&mut self, prekey_id: PreKeyId) -> Result<()> {
+        let mut call = event(Call::RemovePreKey, None);
+        call.id = prekey_id.into();
+        self.next_call(&call)?;
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a, 'b> SignedPreKeyStore for &'a ReplayStore<'b> {
+    async fn get_signed_pre_key(
+        &self,
+        signed_prekey_id: SignedPreKeyId,
+    ) -> Result<SignedPreKeyRecord> {
+        let mut call = event(Call::GetSignedPreKey, None);
+        call.id = signed_prekey_id.into();
+        let recorded = self.next_call(&call)?;
+        SignedPreKeyRecord::deserialize(&self.recorded_record(recorded)?)
+    }
+
+    async fn save_signed_pre_key(
+        &mut self,
+        signed_prekey_id: SignedPreKeyId,
+        record: &SignedPreKeyRecord,
+    ) -> Result<()> {
+        let mut call = event(Call::SaveSignedPreKey, None);
+        call.id = signed_prekey_id.into();
+        self.next_write(&call, &record.serialize()?)
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a, 'b> KyberPreKeyStore for &'a ReplayStore<'b> {
+    async fn get_kyber_pre_key(&self, kyber_prekey_id: KyberPreKeyId) -> Result<KyberPreKeyRecord> {
+        let mut call = event(Call::GetKyberPreKey, None);
+        call.id = kyber_prekey_id.into();
+        let recorded = self.next_call(&call)?;
+        KyberPreKeyRecord::deserialize(&self.recorded_record(recorded)?)
+    }
+
+    async fn save_kyber_pre_key(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        record: &KyberPreKeyRecord,
+    ) -> Result<()> {
+        let mut call = event(Call::SaveKyberPreKey, None);
+        call.id = kyber_prekey_id.into();
+        self.next_write(&call, &record.serialize()?)
+    }
+
+    async fn mark_kyber_pre_key_used(
+        &mut self,
+        kyber_prekey_id: KyberPreKeyId,
+        ec_prekey_id: SignedPreKeyId,
+        base_key: &PublicKey,
+    ) -> Result<()> {
+        let mut call = event(Call::MarkKyberPreKeyUsed, None);
+        call.id = kyber_prekey_id.into();
+        call.secondary_id = ec_prekey_id.into();
+        call.key = base_key.serialize().into_vec();
+        self.next_call(&call)?;
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl<'a, 'b> SessionStore for &'a ReplayStore<'b> {
+    async fn load_session(&self, address: &ProtocolAddress) -> Result<Option<SessionRecord>> {
+        let recorded = self.next_call(&event(Call::LoadSession, Some(address)))?;
+        if !recorded.found {
+            return Ok(None);
+        }
+        Ok(Some(SessionRecord::deserialize(
+            &self.recorded_record(recorded)?,
+        )?))
+    }
+
+    async fn store_session(
+        &mut self,
+        address: &ProtocolAddress,
+        record: &SessionRecord,
+    ) -> Result<()> {
+        self.next_write(
+            &event(Call::StoreSession, Some(address)),
+            &record.serialize()?,
+        )
+    }
+}
+
+/// Deterministic randomness for a recorded operation: the AES-256-CTR keystream under its seed.
+struct TranscriptRng(ctr::Ctr64BE<Aes256>);
+
+impl TranscriptRng {
+    fn new(seed: &[u8; 32]) -> Self {
+        Self(ctr::Ctr64BE::new(seed[..].into(), [0u8; 16][..].into()))
+    }
+}
+
+impl RngCore for TranscriptRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        dst.fill(0);
+        self.0.apply_keystream(dst);
+    }
+}
+
+impl CryptoRng for TranscriptRng {}
+
+fn event(call: Call, address: Option<&ProtocolAddress>) -> proto::StoreEvent {
+    proto::StoreEvent {
+        call: call.into(),
+        address: address.map(address_to_proto),
+        ..Default::default()
+    }
+}
+
+/// Compares the arguments of two store calls, ignoring their results.
+fn same_request(a: &proto::StoreEvent, b: &proto::StoreEvent) -> bool {
+    // For GET_IDENTITY, `key` is a result rather than an argument.
+    let key_is_argument = a.call() != Call::GetIdentity;
+    a.call == b.call
+        && a.address == b.address
+        && a.id == b.id
+        && a.secondary_id == b.secondary_id
+        && (!key_is_argument || a.key == b.key)
+}
+
+fn describe(event: Option<&proto::StoreEvent>) -> String {
+    let Some(event) = event else {
+        return "nothing".to_owned();
+    };
+    match &event.address {
+        Some(address) => format!(
+            "{:?} for {}.{}",
+            event.call(),
+            address.name,
+            address.device_id
+        ),
+        None => format!("{:?} for ID {}", event.call(), event.id),
+    }
+}
+
+fn failure(e: &SignalProtocolError) -> store_event::Failure {
+    let kind = match e {
+        SignalProtocolError::InvalidPreKeyId => store_event::failure::Kind::InvalidPreKeyId,
+        SignalProtocolError::InvalidSignedPreKeyId => {
+            store_event::failure::Kind::InvalidSignedPreKeyId
+        }
+        SignalProtocolError::InvalidKyberPreKeyId => {
+            store_event::failure::Kind::InvalidKyberPreKeyId
+        }
+        _ => store_event::failure::Kind::Other,
+    };
+    store_event::Failure {
+        kind: kind.into(),
+        description: e.to_string(),
+    }
+}
+
+fn open(cipher: &Aes256GcmSiv, secret: Option<&proto::Secret>) -> Result<Vec<u8>> {
+    match secret.and_then(|secret| secret.value.as_ref()) {
+        Some(secret::Value::Sealed(sealed)) if sealed.len() >= NONCE_LEN => {
+            let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+            cipher.decrypt(nonce.into(), ciphertext).map_err(|_| {
+                SignalProtocolError::InvalidArgument(
+                    "transcript was not sealed with this debug key".to_owned(),
+                )
+            })
+        }
+        Some(secret::Value::RedactedLength(_)) => Err(SignalProtocolError::InvalidArgument(
+            "transcript secrets were redacted".to_owned(),
+        )),
+        _ => Err(SignalProtocolError::InvalidProtobufEncoding),
+    }
+}
+
+fn address_to_proto(address: &ProtocolAddress) -> proto::Address {
+    proto::Address {
+        name: address.name().to_owned(),
+        device_id: address.device_id().into(),
+    }
+}
+
+fn address_from_proto(address: Option<&proto::Address>) -> Result<ProtocolAddress> {
+    let address = address.ok_or(SignalProtocolError::InvalidProtobufEncoding)?;
+    let device_id = DeviceId::try_from(address.device_id)
+        .map_err(|_| SignalProtocolError::InvalidProtobufEncoding)?;
+    Ok(ProtocolAddress::new(address.name.clone(), device_id))
+}
+
+fn ciphertext_from_proto(decrypt: &proto::Decrypt) -> Result<CiphertextMessage> {
+    let message_type = u8::try_from(decrypt.message_type)
+        .ok()
+        .and_then(|message_type| CiphertextMessageType::try_from(message_type).ok())
+        .ok_or(SignalProtocolError::InvalidProtobufEncoding)?;
+    let bytes = &decrypt.ciphertext[..];
+    Ok(match message_type {
+        CiphertextMessageType::Whisper => {
+            CiphertextMessage::SignalMessage(SignalMessage::try_from(bytes)?)
+        }
+        CiphertextMessageType::PreKey => {
+            CiphertextMessage::PreKeySignalMessage(PreKeySignalMessage::try_from(bytes)?)
+        }
+        CiphertextMessageType::SenderKey => {
+            CiphertextMessage::SenderKeyMessage(SenderKeyMessage::try_from(bytes)?)
+        }
+        CiphertextMessageType::Plaintext => {
+            CiphertextMessage::PlaintextContent(PlaintextContent::try_from(bytes)?)
+        }
+    })
+}
+
+fn direction_to_proto(direction: &Direction) -> u32 {
+    match direction {
+        Direction::Sending => 0,
+        Direction::Receiving => 1,
+    }
+}
+
+fn identity_change_to_proto(change: IdentityChange) -> u32 {
+    match change {
+        IdentityChange::NewOrUnchanged => 0,
+        IdentityChange::ReplacedExisting => 1,
+        IdentityChange::RotatedVerified => 2,
+    }
+}
+
+fn identity_change_from_proto(value: u32) -> Result<IdentityChange> {
+    match value {
+        0 => Ok(IdentityChange::NewOrUnchanged),
+        1 => Ok(IdentityChange::ReplacedExisting),
+        2 => Ok(IdentityChange::RotatedVerified),
+        _ => Err(SignalProtocolError::InvalidProtobufEncoding),
+    }
+}
@@ -18,7 +18,7 @@ use rand::{CryptoRng, Rng};
 use crate::double_ratchet::RatchetState;
 use crate::ratchet::ChainKey;
 use crate::session_management::CurrentOrPrevious;
-use crate::state::SessionState;
+use crate::state::{SessionPolicy, SessionState};
 use crate::{
     CiphertextMessageType, IdentityKey, KeyPair, ProtocolAddress, Result, SignalMessage,
     SignalProtocolError,
@@ -173,8 +173,12 @@ impl TripleRatchet {
     /// Fails if the session is missing required fields (root key, identity
     /// keys, etc.). The caller should map the error appropriately for the
     /// context (e.g., "no session available to decrypt").
-    pub(crate) fn from_session_state(state: &mut SessionState, self_session: bool) -> Result<Self> {
-        let ratchet = state.take_ratchet_state(self_session)?;
+    pub(crate) fn from_session_state(
+        state: &mut SessionState,
+        self_session: bool,
+        policy: &SessionPolicy,
+    ) -> Result<Self> {
+        let ratchet = state.take_ratchet_state(self_session, policy)?;
         let pqr_state = state.take_pq_ratchet_state();
         let local_identity_key = state.local_identity_key()?;
         let remote_identity_key =
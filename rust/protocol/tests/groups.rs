@@ -5,7 +5,7 @@
 
 mod support;
 
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use futures_util::FutureExt;
 use libsignal_protocol::*;
@@ -1299,3 +1299,188 @@ fn group_message_key_limit() -> Result<(), SignalProtocolError> {
     .now_or_never()
     .expect("sync")
 }
+
+#[test]
+fn group_rotation_after_member_removal() -> Result<(), SignalProtocolError> {
+    async {
+        let mut csprng = OsRng.unwrap_err();
+
+        let sender_address =
+            ProtocolAddress::new("+14159999111".to_owned(), DeviceId::new(1).unwrap());
+        let bob_address =
+            ProtocolAddress::new("+14159999222".to_owned(), DeviceId::new(1).unwrap());
+        let carol_address =
+            ProtocolAddress::new("+14159999333".to_owned(), DeviceId::new(1).unwrap());
+        let distribution_id = Uuid::from_u128(0xd1d1d1d1_7000_11eb_b32a_33b8a8a487a6);
+
+        let mut alice_store = test_in_memory_protocol_store()?;
+        let mut bob_store = test_in_memory_protocol_store()?;
+        let mut carol_store = test_in_memory_protocol_store()?;
+
+        let members = [bob_address.clone(), carol_address.clone()];
+        assert_eq!(
+            recipients_needing_sender_key(
+                &sender_address,
+                distribution_id,
+                &mut alice_store,
+                &members
+            )
+            .await?,
+            members
+        );
+
+        let first_distribution_message = create_sender_key_distribution_message(
+            &sender_address,
+            distribution_id,
+            &mut alice_store,
+            &mut csprng,
+        )
+        .await?;
+        for store in [&mut bob_store, &mut carol_store] {
+            process_sender_key_distribution_message(
+                &sender_address,
+                &first_distribution_message,
+                store,
+            )
+            .await?;
+        }
+        mark_sender_key_distributed(&sender_address, distribution_id, &mut alice_store, &members)
+            .await?;
+        assert!(
+            recipients_needing_sender_key(
+                &sender_address,
+                distribution_id,
+                &mut alice_store,
+                &members
+            )
+            .await?
+            .is_empty()
+        );
+
+        let before_rotation = group_encrypt(
+            &mut alice_store,
+            &sender_address,
+            distribution_id,
+            "before".as_bytes(),
+            &mut csprng,
+        )
+        .await?;
+
+        // Carol leaves the group.
+        let now = SystemTime::now();
+        let expires_at = now + Duration::from_secs(60 * 60 * 24);
+        let second_distribution_message = rotate_sender_key(
+            &sender_address,
+            distribution_id,
+            &mut alice_store,
+            expires_at,
+            &mut csprng,
+        )
+        .await?;
+        assert_ne!(
+            first_distribution_message.chain_id()?,
+            second_distribution_message.chain_id()?
+        );
+
+        let remaining_members = [bob_address.clone()];
+        assert_eq!(
+            recipients_needing_sender_key(
+                &sender_address,
+                distribution_id,
+                &mut alice_store,
+                &remaining_members
+            )
+            .await?,
+            remaining_members
+        );
+        process_sender_key_distribution_message(
+            &sender_address,
+            &second_distribution_message,
+            &mut bob_store,
+        )
+        .await?;
+        mark_sender_key_distributed(
+            &sender_address,
+            distribution_id,
+            &mut alice_store,
+            &remaining_members,
+        )
+        .await?;
+
+        let after_rotation = group_encrypt(
+            &mut alice_store,
+            &sender_address,
+            distribution_id,
+            "after".as_bytes(),
+            &mut csprng,
+        )
+        .await?;
+
+        assert_eq!(
+            group_decrypt(after_rotation.serialized(), &mut bob_store, &sender_address).await?,
+            b"after"
+        );
+        assert_eq!(
+            group_decrypt(
+                before_rotation.serialized(),
+                &mut bob_store,
+                &sender_address
+            )
+            .await?,
+            b"before"
+        );
+        assert!(matches!(
+            group_decrypt(
+                after_rotation.serialized(),
+                &mut carol_store,
+                &sender_address
+            )
+            .await,
+            Err(SignalProtocolError::NoSenderKeyState { .. })
+        ));
+
+        let mut alice_record = alice_store
+            .load_sender_key(&sender_address, distribution_id)
+            .await?
+            .expect("record exists");
+        assert_eq!(alice_record.remove_expired_states(now), 0);
+        assert_eq!(
+            alice_record.remove_expired_states(expires_at + Duration::from_secs(1)),
+            1
+        );
+
+        // Moving to a new distribution ID retires the old one entirely.
+        retire_sender_key(
+            &sender_address,
+            distribution_id,
+            &mut alice_store,
+            expires_at,
+        )
+        .await?;
+        assert!(
+            group_encrypt(
+                &mut alice_store,
+                &sender_address,
+                distribution_id,
+                "retired".as_bytes(),
+                &mut csprng,
+            )
+            .await
+            .is_err()
+        );
+        assert_eq!(
+            recipients_needing_sender_key(
+                &sender_address,
+                distribution_id,
+                &mut alice_store,
+                &remaining_members
+            )
+            .await?,
+            remaining_members
+        );
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
@@ -153,6 +153,102 @@ fn test_sender_cert() -> Result<(), SignalProtocolError> {
     Ok(())
 }
 
+#[test]
+fn test_sender_cert_issuer_and_verifier() -> Result<(), SignalProtocolError> {
+    let mut rng = OsRng.unwrap_err();
+    let t = Timestamp::from_epoch_millis;
+
+    let mut issuer = SenderCertificateIssuer::new();
+    issuer.add_trust_root(
+        KeyPair::generate(&mut rng),
+        ValidityWindow::new(t(0), Some(t(2000))),
+    );
+    issuer.add_trust_root(
+        KeyPair::generate(&mut rng),
+        ValidityWindow::new(t(1000), None),
+    );
+
+    // The first server key can only be signed by the first trust root, and the second by the
+    // second one.
+    issuer.add_server_key(1, ValidityWindow::new(t(0), Some(t(1500))), &mut rng)?;
+    issuer.add_server_key(2, ValidityWindow::new(t(1200), None), &mut rng)?;
+    assert!(matches!(
+        issuer.add_server_key(2, ValidityWindow::new(t(1200), None), &mut rng),
+        Err(SignalProtocolError::InvalidArgument(_))
+    ));
+    assert_eq!(
+        issuer
+            .current_server_certificate(t(1100))
+            .map(|c| c.key_id().unwrap()),
+        Some(1)
+    );
+    assert_eq!(
+        issuer
+            .current_server_certificate(t(1300))
+            .map(|c| c.key_id().unwrap()),
+        Some(2)
+    );
+
+    let sender = KeyPair::generate(&mut rng).public_key;
+    let device_id = DeviceId::new(1).unwrap();
+    let issue = |now: Timestamp, expiration: Timestamp, rng: &mut _| {
+        issuer.issue(
+            "9d0652a3-dcc3-4d11-975f-74d61598733f".to_string(),
+            None,
+            sender,
+            device_id,
+            expiration,
+            now,
+            rng,
+        )
+    };
+    let old_cert = issue(t(100), t(1400), &mut rng)?;
+    let new_cert = issue(t(1300), t(5000), &mut rng)?;
+    assert_eq!(old_cert.signer()?.key_id()?, 1);
+    assert_eq!(new_cert.signer()?.key_id()?, 2);
+    // Sender certificates can't outlive their server key.
+    assert!(matches!(
+        issue(t(100), t(1600), &mut rng),
+        Err(SignalProtocolError::InvalidArgument(_))
+    ));
+
+    let verifier = issuer.verifier();
+    for _ in 0..2 {
+        // The second time around, the signature checks are cached.
+        assert!(verifier.validate(&old_cert, t(1400))?);
+        assert!(verifier.validate(&new_cert, t(1400))?);
+        assert!(!verifier.validate(&new_cert, t(5001))?); // expired
+    }
+    assert!(!verifier.validate(&new_cert, t(500))?); // before the second trust root
+
+    // A verifier that only knows the second trust root rejects certificates from the first.
+    let mut other_verifier = SenderCertificateVerifier::with_cache_capacity(0);
+    let (second_root, second_window) = issuer.trust_roots().nth(1).expect("two roots");
+    other_verifier.add_trust_root(second_root, second_window);
+    assert!(!other_verifier.validate(&old_cert, t(1400))?);
+    assert!(other_verifier.validate(&new_cert, t(1400))?);
+
+    // Revoking a server key stops both issuing and accepting certificates signed by it.
+    issuer.revoke_server_key(2);
+    assert_eq!(issuer.revoked_key_ids().collect::<Vec<_>>(), [2]);
+    // The first server key takes over until its window ends.
+    assert_eq!(
+        issuer
+            .current_server_certificate(t(1300))
+            .map(|c| c.key_id().unwrap()),
+        Some(1)
+    );
+    assert!(issuer.current_server_certificate(t(1600)).is_none());
+    assert!(!issuer.verifier().validate(&new_cert, t(1400))?);
+    assert!(issuer.verifier().validate(&old_cert, t(1400))?);
+    assert!(matches!(
+        issuer.add_server_key(2, ValidityWindow::new(t(1200), None), &mut rng),
+        Err(SignalProtocolError::InvalidArgument(_))
+    ));
+
+    Ok(())
+}
+
 #[test]
 fn test_sealed_sender() -> Result<(), SignalProtocolError> {
     async {
@@ -901,6 +997,174 @@ fn test_sealed_sender_multi_recipient_encrypt_with_bad_registration_id()
     .expect("sync")
 }
 
+#[test]
+fn test_sealed_sender_multi_recipient_retarget() -> Result<(), SignalProtocolError> {
+    async {
+        let mut rng = OsRng.unwrap_err();
+
+        let alice_device_id = DeviceId::new(23).unwrap();
+        let bob_device_id = DeviceId::new(42).unwrap();
+        let carol_device_id = DeviceId::new(3).unwrap();
+
+        let alice_uuid = "9d0652a3-dcc3-4d11-975f-74d61598733f".to_string();
+        let bob_uuid = "796abedb-ca4e-4f18-8803-1fde5b921f9f".to_string();
+        let carol_uuid = "38381c3b-2606-4ca7-9310-7cb927f2ab4a".to_string();
+
+        let alice_uuid_address = ProtocolAddress::new(alice_uuid.clone(), alice_device_id);
+        let bob_uuid_address = ProtocolAddress::new(bob_uuid.clone(), bob_device_id);
+        let carol_uuid_address = ProtocolAddress::new(carol_uuid.clone(), carol_device_id);
+
+        let mut alice_store = support::test_in_memory_protocol_store()?;
+        let mut bob_store = support::test_in_memory_protocol_store()?;
+        let mut carol_store = support::test_in_memory_protocol_store()?;
+
+        let alice_pubkey = *alice_store.get_identity_key_pair().await?.public_key();
+
+        for (address, store) in [
+            (&bob_uuid_address, &mut bob_store),
+            (&carol_uuid_address, &mut carol_store),
+        ] {
+            let pre_key_bundle = create_pre_key_bundle(store, &mut rng).await?;
+            process_prekey_bundle(
+                address,
+                &alice_uuid_address,
+                &mut alice_store.session_store,
+                &mut alice_store.identity_store,
+                &pre_key_bundle,
+                SystemTime::now(),
+                &mut rng,
+            )
+            .await?;
+        }
+
+        let trust_root = KeyPair::generate(&mut rng);
+        let server_key = KeyPair::generate(&mut rng);
+        let server_cert =
+            ServerCertificate::new(1, server_key.public_key, &trust_root.private_key, &mut rng)?;
+        let sender_cert = SenderCertificate::new(
+            alice_uuid.clone(),
+            None,
+            alice_pubkey,
+            alice_device_id,
+            Timestamp::from_epoch_millis(1605722925),
+            server_cert,
+            &server_key.private_key,
+            &mut rng,
+        )?;
+
+        let alice_usmc = UnidentifiedSenderMessageContent::new(
+            CiphertextMessageType::SenderKey,
+            sender_cert,
+            vec![1, 2, 3, 23, 99],
+            ContentHint::Resendable,
+            Some([42].to_vec()),
+        )?;
+
+        let recipients = [&bob_uuid_address];
+        let (alice_ctext, message_key) = sealed_sender_multi_recipient_encrypt_retargetable(
+            &recipients,
+            &alice_store
+                .session_store
+                .load_existing_sessions(&recipients)?,
+            [],
+            &alice_usmc,
+            &alice_store.identity_store,
+            &mut rng,
+        )
+        .await?;
+
+        // The server reports that Carol is missing; add her without re-encrypting the content.
+        let recipients = [&bob_uuid_address, &carol_uuid_address];
+        let retargeted = sealed_sender_multi_recipient_retarget(
+            &alice_ctext,
+            &message_key,
+            &recipients,
+            &alice_store
+                .session_store
+                .load_existing_sessions(&recipients)?,
+            [],
+            &alice_store.identity_store,
+        )
+        .await?;
+
+        let original = SealedSenderV2SentMessage::parse(&alice_ctext)?;
+        let parsed = SealedSenderV2SentMessage::parse(&retargeted)?;
+        assert_eq!(parsed.recipients.len(), 2);
+        assert_eq!(
+            &retargeted[parsed.offset_of_shared_bytes()..],
+            &alice_ctext[original.offset_of_shared_bytes()..],
+        );
+
+        for (uuid, store) in [(&bob_uuid, &bob_store), (&carol_uuid, &carol_store)] {
+            let service_id = ServiceId::parse_from_service_id_string(uuid).unwrap();
+            let recipient = &parsed.recipients[&service_id];
+            let received = parsed
+                .received_message_parts_for_recipient(recipient)
+                .as_ref()
+                .concat();
+            let usmc = sealed_sender_decrypt_to_usmc(&received, &store.identity_store).await?;
+            assert_eq!(usmc.contents()?, alice_usmc.contents()?);
+        }
+
+        // Bob is dropped and only kept as an excluded recipient.
+        let recipients = [&carol_uuid_address];
+        let retargeted = sealed_sender_multi_recipient_retarget(
+            &alice_ctext,
+            &message_key,
+            &recipients,
+            &alice_store
+                .session_store
+                .load_existing_sessions(&recipients)?,
+            [ServiceId::parse_from_service_id_string(&bob_uuid).unwrap()],
+            &alice_store.identity_store,
+        )
+        .await?;
+        let parsed = SealedSenderV2SentMessage::parse(&retargeted)?;
+        let bob_service_id = ServiceId::parse_from_service_id_string(&bob_uuid).unwrap();
+        assert!(parsed.recipients[&bob_service_id].devices.is_empty());
+        let carol_service_id = ServiceId::parse_from_service_id_string(&carol_uuid).unwrap();
+        assert_eq!(
+            parsed.recipients[&carol_service_id].devices,
+            [(
+                carol_device_id,
+                u16::try_from(carol_store.get_local_registration_id().await?).unwrap()
+            )]
+        );
+
+        // A key from a different message is rejected.
+        let (_, other_message_key) = sealed_sender_multi_recipient_encrypt_retargetable(
+            &recipients,
+            &alice_store
+                .session_store
+                .load_existing_sessions(&recipients)?,
+            [],
+            &alice_usmc,
+            &alice_store.identity_store,
+            &mut rng,
+        )
+        .await?;
+        let result = sealed_sender_multi_recipient_retarget(
+            &alice_ctext,
+            &other_message_key,
+            &recipients,
+            &alice_store
+                .session_store
+                .load_existing_sessions(&recipients)?,
+            [],
+            &alice_store.identity_store,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(SignalProtocolError::InvalidArgument(_))
+        ));
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
 #[test]
 fn test_decryption_error_in_sealed_sender() -> Result<(), SignalProtocolError> {
     async {
@@ -470,6 +470,267 @@ fn test_bad_signed_pre_key_signature() -> TestResult {
     .expect("sync")
 }
 
+#[test]
+fn test_kem_policy() -> TestResult {
+    async {
+        let mut csprng = OsRng.unwrap_err();
+        let alice_address =
+            ProtocolAddress::new("+14151111111".to_owned(), DeviceId::new(1).unwrap());
+        let bob_address =
+            ProtocolAddress::new("+14151111112".to_owned(), DeviceId::new(1).unwrap());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store_builder = TestStoreBuilder::new()
+            .with_pre_key(1.into())
+            .with_signed_pre_key(2.into());
+        let mlkem_only = kem::KemPolicy::only(kem::KeyType::MLKEM1024);
+        let kyber_pre_key = KyberPreKeyRecord::generate_with_policy(
+            &mlkem_only,
+            3.into(),
+            bob_store_builder
+                .store
+                .get_identity_key_pair()
+                .await?
+                .private_key(),
+        )?;
+        bob_store_builder
+            .store
+            .save_kyber_pre_key(3.into(), &kyber_pre_key)
+            .await?;
+        let bundle = bob_store_builder.make_bundle_with_latest_keys(DeviceId::new(1).unwrap());
+        assert_eq!(
+            bundle.kyber_pre_key_public()?.key_type(),
+            kem::KeyType::MLKEM1024
+        );
+
+        assert_matches!(
+            process_prekey_bundle_with_kem_policy(
+                &bob_address,
+                &alice_address,
+                &mut alice_store.session_store,
+                &mut alice_store.identity_store,
+                &bundle,
+                SystemTime::now(),
+                &kem::KemPolicy::only(kem::KeyType::Kyber1024),
+                &mut csprng,
+            )
+            .await,
+            Err(SignalProtocolError::UnacceptableKEMKeyType(
+                kem::KeyType::MLKEM1024
+            ))
+        );
+        assert!(alice_store.load_session(&bob_address).await?.is_none());
+
+        process_prekey_bundle_with_kem_policy(
+            &bob_address,
+            &alice_address,
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            &bundle,
+            SystemTime::now(),
+            &mlkem_only,
+            &mut csprng,
+        )
+        .await?;
+        assert_eq!(
+            alice_store
+                .load_session(&bob_address)
+                .await?
+                .expect("session established")
+                .kem_key_type()?,
+            Some(kem::KeyType::MLKEM1024)
+        );
+
+        let mut bob_store = bob_store_builder.store;
+        let message = encrypt(&mut alice_store, &bob_address, &alice_address, "hi").await?;
+        assert_eq!(
+            decrypt(&mut bob_store, &alice_address, &bob_address, &message).await?,
+            b"hi"
+        );
+        assert_eq!(
+            bob_store
+                .load_session(&alice_address)
+                .await?
+                .expect("session established")
+                .kem_key_type()?,
+            Some(kem::KeyType::MLKEM1024)
+        );
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_identity_key_transition() -> TestResult {
+    async {
+        let mut csprng = OsRng.unwrap_err();
+
+        let alice_address =
+            ProtocolAddress::new("+14151111111".to_owned(), DeviceId::new(1).unwrap());
+        let bob_address =
+            ProtocolAddress::new("+14151111112".to_owned(), DeviceId::new(1).unwrap());
+
+        let bob_store_builder = TestStoreBuilder::new()
+            .with_pre_key(1.into())
+            .with_signed_pre_key(2.into())
+            .with_kyber_pre_key(3.into());
+        let mut alice_store = TestStoreBuilder::new().store;
+
+        process_prekey_bundle(
+            &bob_address,
+            &alice_address,
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            &bob_store_builder.make_bundle_with_latest_keys(DeviceId::new(1).unwrap()),
+            SystemTime::now(),
+            &mut csprng,
+        )
+        .await?;
+
+        let bob_identity = bob_store_builder.store.get_identity_key_pair().await?;
+        let (bob_new_identity, transition) = bob_identity.rotate(&mut csprng)?;
+
+        // A transition not signed by the old key is rejected without touching the stores.
+        let (_, unrelated_transition) =
+            IdentityKeyPair::generate(&mut csprng).rotate(&mut csprng)?;
+        let forged = IdentityKeyTransition::new(
+            *bob_identity.identity_key(),
+            *bob_new_identity.identity_key(),
+            unrelated_transition.signature().into(),
+        );
+        assert_matches!(
+            process_identity_key_transition(
+                &bob_address,
+                &forged,
+                &mut alice_store.identity_store,
+                &mut alice_store.session_store,
+            )
+            .await,
+            Err(SignalProtocolError::SignatureValidationFailed)
+        );
+        assert_eq!(
+            alice_store.get_identity(&bob_address).await?,
+            Some(*bob_identity.identity_key())
+        );
+        let session = alice_store
+            .load_session(&bob_address)
+            .await?
+            .expect("session found");
+        assert_eq!(session.archived_state_count(), 0);
+        assert!(
+            session
+                .has_usable_sender_chain(SystemTime::now(), SessionUsabilityRequirements::all())?
+        );
+
+        assert_eq!(
+            process_identity_key_transition(
+                &bob_address,
+                &transition,
+                &mut alice_store.identity_store,
+                &mut alice_store.session_store,
+            )
+            .await?,
+            IdentityChange::RotatedVerified
+        );
+        assert_eq!(
+            alice_store.get_identity(&bob_address).await?,
+            Some(*bob_new_identity.identity_key())
+        );
+        let session = alice_store
+            .load_session(&bob_address)
+            .await?
+            .expect("session found");
+        assert_eq!(session.archived_state_count(), 1);
+        assert!(
+            !session
+                .has_usable_sender_chain(SystemTime::now(), SessionUsabilityRequirements::all())?
+        );
+
+        // Seeing the same transition again is not a change.
+        assert_eq!(
+            alice_store
+                .identity_store
+                .save_identity_with_transition(&bob_address, &transition)
+                .await?,
+            IdentityChange::NewOrUnchanged
+        );
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_identity_key_transition_from_untrusted_key() -> TestResult {
+    async {
+        let mut csprng = OsRng.unwrap_err();
+
+        let alice_address =
+            ProtocolAddress::new("+14151111111".to_owned(), DeviceId::new(1).unwrap());
+        let bob_address =
+            ProtocolAddress::new("+14151111112".to_owned(), DeviceId::new(1).unwrap());
+
+        let bob_store_builder = TestStoreBuilder::new()
+            .with_pre_key(1.into())
+            .with_signed_pre_key(2.into())
+            .with_kyber_pre_key(3.into());
+        let mut alice_store = TestStoreBuilder::new().store;
+
+        // With no stored identity, there is nothing for a transition to start from.
+        let (_, unrelated_transition) =
+            IdentityKeyPair::generate(&mut csprng).rotate(&mut csprng)?;
+        assert_matches!(
+            alice_store
+                .identity_store
+                .save_identity_with_transition(&bob_address, &unrelated_transition)
+                .await,
+            Err(SignalProtocolError::SignatureValidationFailed)
+        );
+        assert_eq!(alice_store.get_identity(&bob_address).await?, None);
+
+        process_prekey_bundle(
+            &bob_address,
+            &alice_address,
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            &bob_store_builder.make_bundle_with_latest_keys(DeviceId::new(1).unwrap()),
+            SystemTime::now(),
+            &mut csprng,
+        )
+        .await?;
+        let bob_identity = bob_store_builder.store.get_identity_key_pair().await?;
+
+        // A correctly signed transition that starts from some other key doesn't replace the
+        // trusted one.
+        assert_matches!(
+            process_identity_key_transition(
+                &bob_address,
+                &unrelated_transition,
+                &mut alice_store.identity_store,
+                &mut alice_store.session_store,
+            )
+            .await,
+            Err(SignalProtocolError::SignatureValidationFailed)
+        );
+        assert_eq!(
+            alice_store.get_identity(&bob_address).await?,
+            Some(*bob_identity.identity_key())
+        );
+        let session = alice_store
+            .load_session(&bob_address)
+            .await?
+            .expect("session found");
+        assert_eq!(session.archived_state_count(), 0);
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
 #[test]
 fn test_repeat_bundle_message() -> TestResult {
     let mut alice_store_builder = TestStoreBuilder::new();
@@ -2337,6 +2598,127 @@ fn prekey_message_failed_decryption_does_not_update_stores() -> TestResult {
     .expect("sync")
 }
 
+#[test]
+fn prekey_message_deferred_decryption_updates_stores_only_when_applied() -> TestResult {
+    async {
+        let mut csprng = OsRng.unwrap_err();
+        let alice_address =
+            ProtocolAddress::new("+14151111111".to_owned(), DeviceId::new(1).unwrap());
+        let bob_address =
+            ProtocolAddress::new("+14151111112".to_owned(), DeviceId::new(1).unwrap());
+
+        let alice_store_builder = TestStoreBuilder::new()
+            .with_pre_key(0.into())
+            .with_signed_pre_key(0.into())
+            .with_kyber_pre_key(0.into());
+        let alice_pre_key_bundle =
+            alice_store_builder.make_bundle_with_latest_keys(DeviceId::new(1).unwrap());
+
+        let mut alice_store = alice_store_builder.store;
+
+        let mut bob_store = TestStoreBuilder::new().store;
+        process_prekey_bundle(
+            &alice_address,
+            &bob_address,
+            &mut bob_store.session_store,
+            &mut bob_store.identity_store,
+            &alice_pre_key_bundle,
+            SystemTime::now(),
+            &mut csprng,
+        )
+        .await?;
+
+        let original_message = "from Bob";
+        let pre_key_message = encrypt(
+            &mut bob_store,
+            &alice_address,
+            &bob_address,
+            original_message,
+        )
+        .await?;
+        assert_eq!(
+            pre_key_message.message_type(),
+            CiphertextMessageType::PreKey
+        );
+
+        let (ptext, changes) = message_decrypt_deferred(
+            &pre_key_message,
+            &bob_address,
+            &alice_address,
+            &alice_store.session_store,
+            &alice_store.identity_store,
+            &alice_store.pre_key_store,
+            &alice_store.signed_pre_key_store,
+            &alice_store.kyber_pre_key_store,
+            &SessionPolicy::default(),
+            &mut csprng,
+        )
+        .await?;
+        assert_eq!(
+            String::from_utf8(ptext).expect("valid utf8"),
+            original_message
+        );
+        assert_eq!(changes.remote_address(), &bob_address);
+        assert_eq!(changes.one_time_pre_key_used(), Some(0.into()));
+        assert_matches!(
+            changes.kyber_pre_key_used(),
+            Some((kyber_id, signed_id, _)) if kyber_id == 0.into() && signed_id == 0.into()
+        );
+
+        // Nothing has been written yet.
+        assert_eq!(
+            alice_store
+                .identity_store
+                .get_identity(&bob_address)
+                .await?,
+            None
+        );
+        assert!(
+            alice_store
+                .session_store
+                .load_session(&bob_address)
+                .await?
+                .is_none()
+        );
+        assert_eq!(alice_store.all_pre_key_ids().count(), 1);
+
+        changes
+            .apply(
+                &mut alice_store.session_store,
+                &mut alice_store.identity_store,
+                &mut alice_store.pre_key_store,
+                &mut alice_store.kyber_pre_key_store,
+            )
+            .await?;
+
+        assert_eq!(
+            alice_store
+                .identity_store
+                .get_identity(&bob_address)
+                .await?,
+            Some(*bob_store.get_identity_key_pair().await?.identity_key())
+        );
+        assert!(
+            alice_store
+                .session_store
+                .load_session(&bob_address)
+                .await?
+                .is_some()
+        );
+        assert_eq!(alice_store.all_pre_key_ids().count(), 0);
+
+        // The session is now usable for replies.
+        let reply = encrypt(&mut alice_store, &bob_address, &alice_address, "from Alice").await?;
+        assert_eq!(reply.message_type(), CiphertextMessageType::Whisper);
+        let ptext = decrypt(&mut bob_store, &alice_address, &bob_address, &reply).await?;
+        assert_eq!(String::from_utf8(ptext).expect("valid utf8"), "from Alice");
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
 #[test]
 fn prekey_message_failed_decryption_does_not_update_stores_even_when_previously_archived()
 -> TestResult {
@@ -3281,6 +3663,568 @@ fn test_duplicate_message_error_returned() -> TestResult {
     .expect("sync")
 }
 
+#[test]
+fn test_transcript_replay() -> TestResult {
+    use libsignal_protocol::transcript::{
+        Transcript, TranscriptDebugKey, TranscriptRecorder, TranscriptSecrets, replay_transcript,
+    };
+
+    async {
+        let mut csprng = OsRng.unwrap_err();
+
+        let alice_address =
+            ProtocolAddress::new("+14151111111".to_owned(), DeviceId::new(1).unwrap());
+        let bob_address =
+            ProtocolAddress::new("+14151111112".to_owned(), DeviceId::new(1).unwrap());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let bob_store_builder = TestStoreBuilder::new()
+            .with_pre_key(1.into())
+            .with_signed_pre_key(2.into())
+            .with_kyber_pre_key(3.into());
+
+        process_prekey_bundle(
+            &bob_address,
+            &alice_address,
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            &bob_store_builder.make_bundle_with_latest_keys(DeviceId::new(1).unwrap()),
+            SystemTime::now(),
+            &mut csprng,
+        )
+        .await?;
+        let msg = encrypt(&mut alice_store, &bob_address, &alice_address, "hello").await?;
+
+        let debug_key = TranscriptDebugKey::generate(&mut csprng);
+        let bob = TranscriptRecorder::new(
+            bob_store_builder.store,
+            TranscriptSecrets::Seal(debug_key.clone()),
+            &mut csprng,
+        );
+        let ptext = bob
+            .message_decrypt(&msg, &alice_address, &bob_address, &mut csprng)
+            .await?;
+        assert_eq!(ptext, b"hello");
+        assert_matches!(
+            bob.message_decrypt(&msg, &alice_address, &bob_address, &mut csprng)
+                .await,
+            Err(SignalProtocolError::DuplicatedMessage(_, _))
+        );
+        let reply = bob
+            .message_encrypt(
+                b"hi",
+                &alice_address,
+                &bob_address,
+                SystemTime::now(),
+                &mut csprng,
+            )
+            .await?;
+
+        let (mut bob_store, transcript) = bob.into_parts();
+        assert_eq!(transcript.len(), 3);
+        assert_eq!(transcript.failed_steps().collect::<Vec<_>>(), [1]);
+        let transcript = Transcript::deserialize(&transcript.serialize())?;
+
+        let report = replay_transcript(&transcript, &debug_key).await?;
+        assert_eq!(report.steps_replayed, 3);
+        assert!(report.divergence.is_none(), "{:?}", report.divergence);
+
+        // Recorded ciphertexts aren't sealed, so one can be changed in place. The first copy of
+        // `msg` belongs to the first step.
+        let mut tampered = transcript.serialize();
+        let msg_bytes = msg.serialize();
+        let msg_start = tampered
+            .windows(msg_bytes.len())
+            .position(|window| window == msg_bytes)
+            .expect("ciphertext is recorded");
+        tampered[msg_start + msg_bytes.len() - 1] ^= 0x01;
+        let tampered = Transcript::deserialize(&tampered)?;
+        let report = replay_transcript(&tampered, &debug_key).await?;
+        assert_eq!(report.steps_replayed, 1);
+        assert_eq!(report.divergence.expect("diverges").step, 0);
+
+        assert_matches!(
+            replay_transcript(&transcript, &TranscriptDebugKey::generate(&mut csprng)).await,
+            Err(SignalProtocolError::InvalidArgument(_))
+        );
+
+        // The store keeps working once unwrapped.
+        assert_eq!(
+            decrypt(&mut alice_store, &bob_address, &alice_address, &reply).await?,
+            b"hi"
+        );
+        let msg = encrypt(&mut alice_store, &bob_address, &alice_address, "again").await?;
+        assert_eq!(
+            decrypt(&mut bob_store, &alice_address, &bob_address, &msg).await?,
+            b"again"
+        );
+
+        // Redacted transcripts can't be replayed.
+        let recorder = TranscriptRecorder::new(bob_store, TranscriptSecrets::Redact, &mut csprng);
+        let msg = encrypt(&mut alice_store, &bob_address, &alice_address, "redacted").await?;
+        recorder
+            .message_decrypt(&msg, &alice_address, &bob_address, &mut csprng)
+            .await?;
+        assert_matches!(
+            replay_transcript(&recorder.transcript(), &debug_key).await,
+            Err(SignalProtocolError::InvalidArgument(_))
+        );
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_cached_message_key_inspection_and_pruning() -> TestResult {
+    async {
+        let (alice_session_record, bob_session_record) = initialize_sessions_v4()?;
+
+        let alice_address =
+            ProtocolAddress::new("+14159999999".to_owned(), DeviceId::new(1).unwrap());
+        let bob_address =
+            ProtocolAddress::new("+14158888888".to_owned(), DeviceId::new(1).unwrap());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        let mut inflight = vec![];
+        for i in 0..10 {
+            inflight.push(
+                encrypt(
+                    &mut alice_store,
+                    &bob_address,
+                    &alice_address,
+                    &format!("message {i}"),
+                )
+                .await?,
+            );
+        }
+        decrypt(&mut bob_store, &alice_address, &bob_address, &inflight[9]).await?;
+
+        let mut bob_record = bob_store
+            .load_session(&alice_address)
+            .await?
+            .expect("session found");
+        let cached = bob_record.cached_message_keys()?;
+        assert_eq!(
+            cached.iter().map(|key| key.counter()).collect::<Vec<_>>(),
+            (0..9).rev().collect::<Vec<_>>()
+        );
+        assert!(cached.iter().all(|key| key.chain_index() == 10));
+        assert_eq!(cached[0].messages_behind(), 2);
+
+        assert_eq!(
+            bob_record.prune_message_keys(MessageKeyPruning::MaxMessagesBehind(5))?,
+            5
+        );
+        assert_eq!(
+            bob_record.prune_message_keys(MessageKeyPruning::MaxPerChain(2))?,
+            2
+        );
+        assert_eq!(
+            bob_record
+                .cached_message_keys()?
+                .iter()
+                .map(|key| key.counter())
+                .collect::<Vec<_>>(),
+            [8, 7]
+        );
+        bob_store.store_session(&alice_address, &bob_record).await?;
+
+        decrypt(&mut bob_store, &alice_address, &bob_address, &inflight[7]).await?;
+        let err = decrypt(&mut bob_store, &alice_address, &bob_address, &inflight[5])
+            .await
+            .expect_err("key was pruned");
+        assert_matches!(err, SignalProtocolError::DuplicatedMessage(10, 5));
+
+        let mut bob_record = bob_store
+            .load_session(&alice_address)
+            .await?
+            .expect("session found");
+        assert_eq!(bob_record.archived_state_count(), 0);
+        bob_record.archive_current_state()?;
+        assert_eq!(bob_record.archived_state_count(), 1);
+        assert!(bob_record.cached_message_keys()?.is_empty());
+        assert_eq!(bob_record.prune_archived_states(1), 0);
+        assert_eq!(bob_record.prune_archived_states(0), 1);
+        assert_eq!(bob_record.archived_state_count(), 0);
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_session_diagnostics() -> TestResult {
+    async {
+        let mut csprng = OsRng.unwrap_err();
+
+        let alice_device_id = DeviceId::new(1).unwrap();
+        let bob_device_id = DeviceId::new(1).unwrap();
+
+        let alice_address = ProtocolAddress::new("+14151111111".to_owned(), alice_device_id);
+        let bob_address = ProtocolAddress::new("+14151111112".to_owned(), bob_device_id);
+
+        let mut alice_store_builder = TestStoreBuilder::new();
+        let mut bob_store_builder = TestStoreBuilder::new();
+        bob_store_builder.add_pre_key(IdChoice::Next);
+        bob_store_builder.add_signed_pre_key(IdChoice::Next);
+        bob_store_builder.add_kyber_pre_key(IdChoice::Next);
+
+        let bob_pre_key_bundle = bob_store_builder.make_bundle_with_latest_keys(bob_device_id);
+
+        let alice_store = &mut alice_store_builder.store;
+        let bob_store = &mut bob_store_builder.store;
+
+        assert_eq!(
+            session_diagnostics(&bob_address, &alice_store.session_store).await?,
+            None
+        );
+
+        process_prekey_bundle(
+            &bob_address,
+            &alice_address,
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            &bob_pre_key_bundle,
+            SystemTime::now(),
+            &mut csprng,
+        )
+        .await?;
+
+        for i in 0..3 {
+            let msg = encrypt(
+                alice_store,
+                &bob_address,
+                &alice_address,
+                &format!("msg {i}"),
+            )
+            .await?;
+            if i != 1 {
+                decrypt(bob_store, &alice_address, &bob_address, &msg).await?;
+            }
+        }
+
+        let alice_report = session_diagnostics(&bob_address, &alice_store.session_store)
+            .await?
+            .expect("session exists");
+        assert_eq!(alice_report.archived_state_count, 0);
+        let alice_state = alice_report.current_session.expect("current session");
+        assert_eq!(alice_state.session_version, 4);
+        assert!(alice_state.established_with_pqxdh);
+        assert_eq!(
+            alice_state.kem_key_type,
+            Some(KemKeyTypeDiagnostics::Known(kem::KeyType::Kyber1024))
+        );
+        assert!(alice_state.has_pq_ratchet_state);
+        assert_eq!(alice_state.sender_chain_index, Some(3));
+        let pending = alice_state.pending_pre_key.expect("unacknowledged");
+        assert_eq!(pending.pre_key_id, bob_pre_key_bundle.pre_key_id()?);
+        assert_eq!(
+            pending.signed_pre_key_id,
+            bob_pre_key_bundle.signed_pre_key_id()?
+        );
+        assert_eq!(
+            pending.kyber_pre_key_id,
+            Some(bob_pre_key_bundle.kyber_pre_key_id()?)
+        );
+
+        let bob_report = session_diagnostics(&alice_address, &bob_store.session_store)
+            .await?
+            .expect("session exists");
+        let bob_state = bob_report.current_session.expect("current session");
+        assert!(bob_state.pending_pre_key.is_none());
+        assert_eq!(bob_state.receiver_chains.len(), 1);
+        assert_eq!(bob_state.receiver_chains[0].chain_index, 3);
+        assert_eq!(bob_state.receiver_chains[0].cached_message_key_count, 1);
+        assert_eq!(
+            bob_state
+                .remote_identity_fingerprint
+                .expect("has identity")
+                .len(),
+            64
+        );
+
+        let mut alice_record = alice_store
+            .load_session(&bob_address)
+            .await?
+            .expect("session exists");
+        alice_record.archive_current_state()?;
+        let archived_report = SessionDiagnostics::from_record(&alice_record)?;
+        assert_eq!(archived_report.current_session, None);
+        assert_eq!(archived_report.archived_state_count, 1);
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_session_policy_limits() -> TestResult {
+    async {
+        let mut csprng = OsRng.unwrap_err();
+        let (alice_session_record, bob_session_record) = initialize_sessions_v4()?;
+
+        let alice_address =
+            ProtocolAddress::new("+14159999999".to_owned(), DeviceId::new(1).unwrap());
+        let bob_address =
+            ProtocolAddress::new("+14158888888".to_owned(), DeviceId::new(1).unwrap());
+
+        let mut alice_store = TestStoreBuilder::new().store;
+        let mut bob_store = TestStoreBuilder::new().store;
+
+        alice_store
+            .store_session(&bob_address, &alice_session_record)
+            .await?;
+        bob_store
+            .store_session(&alice_address, &bob_session_record)
+            .await?;
+
+        let policy = SessionPolicy {
+            max_forward_jumps: 10,
+            max_message_keys: 3,
+            ..SessionPolicy::default()
+        };
+
+        let mut inflight = vec![];
+        for i in 0..20 {
+            inflight.push(
+                encrypt(
+                    &mut alice_store,
+                    &bob_address,
+                    &alice_address,
+                    &format!("message {i}"),
+                )
+                .await?,
+            );
+        }
+
+        let err = message_decrypt_with_policy(
+            &inflight[19],
+            &alice_address,
+            &bob_address,
+            &mut bob_store.session_store,
+            &mut bob_store.identity_store,
+            &mut bob_store.pre_key_store,
+            &bob_store.signed_pre_key_store,
+            &mut bob_store.kyber_pre_key_store,
+            &policy,
+            &mut csprng,
+        )
+        .await
+        .expect_err("jump exceeds policy");
+        assert_matches!(err, SignalProtocolError::InvalidMessage(_, _));
+
+        let ptext = message_decrypt_with_policy(
+            &inflight[5],
+            &alice_address,
+            &bob_address,
+            &mut bob_store.session_store,
+            &mut bob_store.identity_store,
+            &mut bob_store.pre_key_store,
+            &bob_store.signed_pre_key_store,
+            &mut bob_store.kyber_pre_key_store,
+            &policy,
+            &mut csprng,
+        )
+        .await?;
+        assert_eq!(String::from_utf8(ptext).expect("valid utf8"), "message 5");
+
+        let bob_record = bob_store
+            .load_session(&alice_address)
+            .await?
+            .expect("session found");
+        assert_eq!(
+            bob_record
+                .cached_message_keys()?
+                .iter()
+                .map(|key| key.counter())
+                .collect::<Vec<_>>(),
+            [4, 3, 2]
+        );
+
+        let err = decrypt(&mut bob_store, &alice_address, &bob_address, &inflight[1])
+            .await
+            .expect_err("key was not cached");
+        assert_matches!(err, SignalProtocolError::DuplicatedMessage(6, 1));
+
+        // The default policy still accepts the larger jump.
+        decrypt(&mut bob_store, &alice_address, &bob_address, &inflight[19]).await?;
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
+#[test]
+fn test_decryption_error_retry_actions() -> TestResult {
+    async {
+        let mut csprng = OsRng.unwrap_err();
+
+        let alice_device_id = DeviceId::new(1).unwrap();
+        let bob_device_id = DeviceId::new(1).unwrap();
+
+        let alice_address = ProtocolAddress::new("+14151111111".to_owned(), alice_device_id);
+        let bob_address = ProtocolAddress::new("+14151111112".to_owned(), bob_device_id);
+
+        let mut alice_store_builder = TestStoreBuilder::new();
+        let mut bob_store_builder = TestStoreBuilder::new();
+        bob_store_builder.add_pre_key(IdChoice::Next);
+        bob_store_builder.add_signed_pre_key(IdChoice::Next);
+        bob_store_builder.add_kyber_pre_key(IdChoice::Next);
+        let bob_pre_key_bundle = bob_store_builder.make_bundle_with_latest_keys(bob_device_id);
+
+        let alice_store = &mut alice_store_builder.store;
+        let bob_store = &mut bob_store_builder.store;
+
+        process_prekey_bundle(
+            &bob_address,
+            &alice_address,
+            &mut alice_store.session_store,
+            &mut alice_store.identity_store,
+            &bob_pre_key_bundle,
+            SystemTime::now(),
+            &mut csprng,
+        )
+        .await?;
+
+        let mut sent_log = InMemSentMessageLog::new();
+        let sent = |millis: u64, distribution_id: Option<uuid::Uuid>| SentMessage {
+            timestamp: Timestamp::from_epoch_millis(millis),
+            content: format!("message {millis}").into_bytes(),
+            content_hint: ContentHint::Resendable,
+            distribution_id,
+            group_id: distribution_id.map(|_| vec![42]),
+        };
+
+        let first = encrypt(alice_store, &bob_address, &alice_address, "first").await?;
+        sent_log.record(sent(1000, None), [bob_address.clone()]);
+        decrypt(bob_store, &alice_address, &bob_address, &first).await?;
+
+        // Bob replies, so Alice's next message is sent on a new chain.
+        let reply = encrypt(bob_store, &alice_address, &bob_address, "reply").await?;
+        decrypt(alice_store, &bob_address, &alice_address, &reply).await?;
+        let second = encrypt(alice_store, &bob_address, &alice_address, "second").await?;
+        sent_log.record(sent(2000, None), [bob_address.clone()]);
+
+        let retry_request = |message: &CiphertextMessage, millis: u64, device_id: u32| {
+            DecryptionErrorMessage::for_original(
+                message.serialize(),
+                message.message_type(),
+                Timestamp::from_epoch_millis(millis),
+                device_id,
+            )
+        };
+
+        // A request about a message sent by another of Alice's devices is ignored.
+        let action = handle_decryption_error_message(
+            &retry_request(&second, 2000, 2)?,
+            &bob_address,
+            alice_device_id,
+            &mut alice_store.session_store,
+            &sent_log,
+        )
+        .await?;
+        assert_eq!(action, DecryptionErrorAction::Ignore);
+
+        // The first message's chain is no longer current, so it's simply resent.
+        let action = handle_decryption_error_message(
+            &retry_request(&first, 1000, 1)?,
+            &bob_address,
+            alice_device_id,
+            &mut alice_store.session_store,
+            &sent_log,
+        )
+        .await?;
+        assert_eq!(action, DecryptionErrorAction::Resend(sent(1000, None)));
+        assert!(
+            alice_store
+                .load_session(&bob_address)
+                .await?
+                .expect("session exists")
+                .has_usable_sender_chain(SystemTime::now(), SessionUsabilityRequirements::all())?
+        );
+
+        // The second message was sent on the current session, which gets reset.
+        let action = handle_decryption_error_message(
+            &retry_request(&second, 2000, 1)?,
+            &bob_address,
+            alice_device_id,
+            &mut alice_store.session_store,
+            &sent_log,
+        )
+        .await?;
+        assert_eq!(
+            action,
+            DecryptionErrorAction::ResetSession {
+                resend: Some(sent(2000, None))
+            }
+        );
+        let record = alice_store
+            .load_session(&bob_address)
+            .await?
+            .expect("session exists");
+        assert!(
+            !record
+                .has_usable_sender_chain(SystemTime::now(), SessionUsabilityRequirements::all())?
+        );
+        assert_eq!(record.archived_state_count(), 1);
+
+        // Sender key messages call for a new SenderKeyDistributionMessage.
+        let distribution_id = uuid::Uuid::from_u128(0xd1d1);
+        sent_log.record(sent(3000, Some(distribution_id)), [bob_address.clone()]);
+        let sender_key_request = DecryptionErrorMessage::for_original(
+            &[],
+            CiphertextMessageType::SenderKey,
+            Timestamp::from_epoch_millis(3000),
+            1,
+        )?;
+        let action = handle_decryption_error_message(
+            &sender_key_request,
+            &bob_address,
+            alice_device_id,
+            &mut alice_store.session_store,
+            &sent_log,
+        )
+        .await?;
+        assert_eq!(
+            action,
+            DecryptionErrorAction::ResendSenderKey {
+                distribution_id,
+                resend: sent(3000, Some(distribution_id)),
+            }
+        );
+
+        // Once the log has forgotten a message, there's nothing to resend.
+        sent_log.remove_messages_before(Timestamp::from_epoch_millis(4000));
+        let action = handle_decryption_error_message(
+            &sender_key_request,
+            &bob_address,
+            alice_device_id,
+            &mut alice_store.session_store,
+            &sent_log,
+        )
+        .await?;
+        assert_eq!(action, DecryptionErrorAction::Ignore);
+
+        Ok(())
+    }
+    .now_or_never()
+    .expect("sync")
+}
+
 #[test]
 fn test_pqr_state_and_message_contents_nonempty() -> TestResult {
     async {
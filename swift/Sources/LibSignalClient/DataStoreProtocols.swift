@@ -14,6 +14,7 @@ public enum Direction: Sendable {
 public enum IdentityChange: Sendable {
     case newOrUnchanged
     case replacedExisting
+    case rotatedVerified
 }
 
 /// A marker protocol, which must be downcast to use in any particular store.
@@ -72,6 +72,7 @@ internal func withIdentityKeyStore<Result>(
                 switch try store.saveIdentity(identity, for: address, context: context) {
                 case .newOrUnchanged: UInt8(SignalIdentityChangeNewOrUnchanged.rawValue)
                 case .replacedExisting: UInt8(SignalIdentityChangeReplacedExisting.rawValue)
+                case .rotatedVerified: UInt8(SignalIdentityChangeRotatedVerified.rawValue)
                 }
         }
     }
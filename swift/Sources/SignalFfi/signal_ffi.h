@@ -185,6 +185,13 @@ typedef enum {
    * The new identity key replaced a different key for the protocol address.
    */
   SignalIdentityChangeReplacedExisting,
+  /**
+   * The new identity key replaced the previous key for the protocol address, and the previous
+   * key signed off on the change with an [`IdentityKeyTransition`].
+   *
+   * Only produced by [`IdentityKeyStore::save_identity_with_transition`].
+   */
+  SignalIdentityChangeRotatedVerified,
 } SignalIdentityChange;
 
 typedef enum {